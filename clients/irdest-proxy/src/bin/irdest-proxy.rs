@@ -9,65 +9,119 @@
 //!
 //! - Simple socket + port mapped to a Ratman address
 //!
-//! - Tap device mapping a whole IP space to a Ratman address
+//! - TUN device mapping an IPv6 prefix onto Ratman addresses, with
+//!   exit nodes for any other destination
 //!
 //! - Tap device mapping different IP spaces to different Ratman
 //!   addresses, using dynamic route announcements (a la BGP).
 //!
-//! Currently ___0___ of these mechanisms are implemented :)
+//! Currently only the first two of these mechanisms are implemented.
 
-// use clap::{App, Arg, ArgMatches};
-// use irdest_proxy::{parse_routes_file, Config};
-// use std::path::PathBuf;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use libratman::{
+    api::default_api_bind,
+    tokio::runtime::{Builder, Runtime},
+};
+use std::{net::SocketAddr, path::PathBuf};
 
-// fn setup_cli() -> ArgMatches {
-//     App::new("irdest-proxy")
-//         .version(env!("CARGO_PKG_VERSION"))
-//         .about("A TCP proxy to tunnel connections through a Ratman network")
-//         .after_help(r#"By default irdest-proxy(1) stores its configuration files in $XDG_CONFIG_HOME/irdest-proxy.  You can override this behaviour via the --cfg-dir parameter.
+fn setup_cli() -> ArgMatches {
+    Command::new("irdest-proxy")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("A proxy to tunnel TCP connections or IP traffic through a Ratman network")
+        .after_help(r#"By default irdest-proxy(1) stores its configuration files in $XDG_CONFIG_HOME/irdest-proxy.  You can override this behaviour via the --cfg-dir parameter.
 
-// Check the user manual for instructions on setting up this program."#)
-//         .arg(
-//             Arg::new("VERBOSITY")
-//                 .takes_value(true)
-//                 .short('v')
-//                 .long("verbosity")
-//                 .help("Specify to which degree this service should log")
-//                 .default_value("info")
-//                 .possible_values(["trace", "debug", "info", "warn", "error"]),
-//         )
-//         .arg(
-//             Arg::new("CONFIG_DIR")
-//                 .takes_value(true)
-//                 .short('d')
-//                 .long("cfg-dir")
-//                 .help("Override the default configuration directory location.  The directory must contain a `routes.pm` file"),
-//         )
-//         .arg(
-//             Arg::new("API_BIND")
-//                 .takes_value(true)
-//                 .long("ipc")
-//                 .help("Override the default bind address of the Ratman IPC socket"),
-//         )
-//         .get_matches()
-// }
+With --tun irdest-proxy(1) creates a TUN interface instead of reading `routes.pm`, which requires the CAP_NET_ADMIN capability.
 
-#[async_std::main]
-async fn main() {
-    // let m = setup_cli();
+Check the user manual for instructions on setting up this program."#)
+        .max_term_width(110)
+        .args([
+            Arg::new("VERBOSITY")
+                .action(ArgAction::Set)
+                .short('v')
+                .long("verbosity")
+                .help("Specify to which degree this service should log")
+                .default_value("info")
+                .value_parser(["trace", "debug", "info", "warn", "error"]),
+            Arg::new("CONFIG_DIR")
+                .action(ArgAction::Set)
+                .short('d')
+                .long("cfg-dir")
+                .help("Override the default configuration directory location.  Unless --tun is given the directory must contain a `routes.pm` file"),
+            Arg::new("API_BIND")
+                .action(ArgAction::Set)
+                .long("ipc")
+                .help("Override the default bind address of the Ratman IPC socket"),
+            Arg::new("TUN")
+                .action(ArgAction::SetTrue)
+                .long("tun")
+                .help("Tunnel IP traffic through a TUN interface instead of the routes in `routes.pm`"),
+            Arg::new("EXIT")
+                .action(ArgAction::SetTrue)
+                .long("exit")
+                .requires("TUN")
+                .help("Act as an exit node for IP traffic leaving the Ratman network"),
+        ])
+        .get_matches()
+}
+
+#[cfg(target_os = "linux")]
+fn start_tun_proxy(
+    runtime: &Runtime,
+    verbosity: &str,
+    api_bind: SocketAddr,
+    cfg_dir: PathBuf,
+    exit: bool,
+) -> libratman::Result<()> {
+    runtime.block_on(irdest_proxy::start_tun_proxy(
+        verbosity, api_bind, cfg_dir, exit,
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn start_tun_proxy(
+    _: &Runtime,
+    _: &str,
+    _: SocketAddr,
+    _: PathBuf,
+    _: bool,
+) -> libratman::Result<()> {
+    eprintln!("TUN mode is only supported on Linux");
+    std::process::exit(1);
+}
+
+fn main() {
+    let m = setup_cli();
 
-    println!("Down for maintenance :(");
+    let cfg_dir = m
+        .get_one::<String>("CONFIG_DIR")
+        .map(|s| PathBuf::new().join(s))
+        .unwrap_or_else(irdest_proxy::get_config_path);
 
-    // let cfg_dir = m
-    //     .value_of("CONFIG_DIR")
-    //     .map(|s| PathBuf::new().join(s))
-    //     .unwrap_or_else(|| irdest_proxy::get_config_path());
+    let verbosity = m.get_one::<String>("VERBOSITY").unwrap();
+    let api_bind = match m.get_one::<String>("API_BIND") {
+        Some(bind) => match bind.parse::<SocketAddr>() {
+            Ok(bind) => bind,
+            Err(e) => {
+                eprintln!("Invalid IPC socket address '{}': {}", bind, e);
+                std::process::exit(1);
+            }
+        },
+        None => default_api_bind(),
+    };
 
-    // let verbosity = m.value_of("VERBOSITY").unwrap();
-    // let bind = m.value_of("API_BIND");
+    let runtime = Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("failed to start async runtime");
 
-    // let routes = parse_routes_file(&cfg_dir);
-    // let config = Config::load_and_update(&cfg_dir, &routes);
+    let res = if m.get_flag("TUN") {
+        start_tun_proxy(&runtime, verbosity, api_bind, cfg_dir, m.get_flag("EXIT"))
+    } else {
+        runtime.block_on(irdest_proxy::start_proxy(verbosity, api_bind, cfg_dir))
+    };
 
-    // irdest_proxy::start_proxy(verbosity, bind, config, routes).await;
+    if let Err(e) = res {
+        eprintln!("irdest-proxy encountered an error: {}", e);
+        std::process::exit(2);
+    }
 }
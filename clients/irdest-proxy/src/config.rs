@@ -2,14 +2,23 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
 
-use async_std::net::SocketAddr;
-use libratman::types::Address;
+use libratman::{
+    api::{RatmanIpc, RatmanIpcExtV1},
+    types::{AddrAuth, Address, Ident32},
+    Result,
+};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::BTreeMap,
+    collections::{btree_map::Entry, BTreeMap, BTreeSet},
+    convert::TryFrom,
+    fmt,
     fs::File,
-    io::{Read, Write},
-    path::PathBuf,
+    io::Read,
+    net::{Ipv6Addr, SocketAddr},
+    path::Path,
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
 };
 
 pub type Routes = BTreeMap<IpSpace, (InOrOut, Address)>;
@@ -19,34 +28,190 @@ pub type Routes = BTreeMap<IpSpace, (InOrOut, Address)>;
 /// The `addresses` field is only relevant for Inlets and is
 /// automatically populated.  Each "bind" is registered as a new
 /// address.  These addresses are then be re-used between runs.
+///
+/// Outlet addresses are created manually (for example via `ratctl
+/// addr create`), and their authentication tokens must be added to
+/// the `outlets` field.
+///
+/// The `tun` field configures the TUN mode, and its address is
+/// populated on the first start in this mode.
 #[derive(Default, Serialize, Deserialize)]
 pub struct Config {
     /// Inlet addresses per-route
-    pub addresses: BTreeMap<String, Address>,
+    pub addresses: BTreeMap<String, (Address, AddrAuth)>,
+    /// Authentication tokens per outlet address
+    #[serde(default)]
+    pub outlets: BTreeMap<String, AddrAuth>,
+    /// Settings for the TUN mode
+    #[serde(default)]
+    pub tun: TunConfig,
+}
+
+/// Settings for the TUN mode
+///
+/// Every Ratman address is mapped to an IPv6 address inside `prefix`.
+/// Traffic to other destinations is sent to an exit node.  Exit nodes
+/// announce themselves in `exit_space`, but only the ones listed in
+/// `trusted_exits` are used.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TunConfig {
+    /// Name of the TUN interface
+    pub interface: String,
+    /// The IPv6 prefix that Ratman addresses are mapped into
+    pub prefix: Prefix,
+    /// The address of this TUN endpoint
+    pub address: Option<(Address, AddrAuth)>,
+    /// The namespace that exit nodes announce themselves in
+    pub exit_space: Option<Address>,
+    /// The private key of `exit_space`, which only exit nodes need
+    pub exit_space_key: Option<Ident32>,
+    /// Exit nodes that traffic may be sent to
+    pub trusted_exits: BTreeSet<Address>,
+}
+
+impl Default for TunConfig {
+    fn default() -> Self {
+        Self {
+            interface: "irdest0".into(),
+            prefix: "fd69:7264:6573::/48".parse().unwrap(),
+            address: None,
+            exit_space: None,
+            exit_space_key: None,
+            trusted_exits: BTreeSet::new(),
+        }
+    }
+}
+
+impl TunConfig {
+    /// Pick the fastest trusted exit node from the answers to a probe
+    ///
+    /// Any node can announce itself in the exit namespace, so exits
+    /// which aren't trusted are ignored.
+    pub fn choose_exit(&self, exits: &[(Address, Duration)]) -> Option<Address> {
+        exits
+            .iter()
+            .filter(|(exit, _)| self.trusted_exits.contains(exit))
+            .min_by_key(|(_, rtt)| *rtt)
+            .map(|(exit, _)| *exit)
+    }
+}
+
+/// An IPv6 prefix that Ratman addresses are mapped into
+///
+/// The prefix length must be a multiple of 8 and at most 64 bits,
+/// because the remaining bytes of an IP address are taken from the
+/// start of the Ratman address.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Prefix {
+    addr: Ipv6Addr,
+    len: u8,
+}
+
+impl Prefix {
+    /// The length of this prefix in bits
+    pub fn bits(&self) -> u8 {
+        self.len
+    }
+
+    /// Check whether an IP address is part of this prefix
+    pub fn contains(&self, ip: &Ipv6Addr) -> bool {
+        let bytes = self.len as usize / 8;
+        self.addr.octets()[..bytes] == ip.octets()[..bytes]
+    }
+
+    /// Map a Ratman address to its IP address inside this prefix
+    pub fn map(&self, addr: Address) -> Ipv6Addr {
+        let bytes = self.len as usize / 8;
+        let mut octets = self.addr.octets();
+        octets[bytes..].copy_from_slice(&addr.slice()[..16 - bytes]);
+        octets.into()
+    }
+}
+
+impl fmt::Display for Prefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.len)
+    }
+}
+
+impl FromStr for Prefix {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (addr, len) = s
+            .split_once('/')
+            .ok_or_else(|| format!("prefix '{}' has no length", s))?;
+        let addr: Ipv6Addr = addr
+            .parse()
+            .map_err(|e| format!("invalid prefix address '{}': {}", addr, e))?;
+        let len: u8 = len
+            .parse()
+            .map_err(|e| format!("invalid prefix length '{}': {}", len, e))?;
+
+        if len == 0 || len > 64 || !len.is_multiple_of(8) {
+            return Err(format!(
+                "prefix length must be a multiple of 8 between 8 and 64, not {}",
+                len
+            ));
+        }
+
+        // Clear the host part so that equal prefixes compare equal
+        let bytes = len as usize / 8;
+        let mut octets = addr.octets();
+        octets[bytes..].iter_mut().for_each(|b| *b = 0);
+
+        Ok(Self {
+            addr: octets.into(),
+            len,
+        })
+    }
+}
+
+impl TryFrom<String> for Prefix {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Prefix> for String {
+    fn from(prefix: Prefix) -> Self {
+        prefix.to_string()
+    }
 }
 
 impl Config {
-    pub fn get_address(&self, ip: &IpSpace) -> Address {
-        *self
-            .addresses
-            .get(&ip.to_string())
-            .expect("failed to load address from config")
+    /// Get the address and authentication token of an inlet route
+    pub fn inlet_address(&self, ip: &IpSpace) -> Option<(Address, AddrAuth)> {
+        self.addresses.get(&ip.to_string()).copied()
+    }
+
+    /// Get the authentication token for an outlet address
+    pub fn outlet_auth(&self, addr: Address) -> Option<AddrAuth> {
+        self.outlets.get(&addr.to_string()).copied()
     }
 
     /// Load the current configuration (creating it if none exists)
     /// and generating new addresses for any additional Inlet route
     /// that exists.
-    pub fn load_and_update(dir: &PathBuf, routes: &Routes) -> Self {
+    ///
+    /// When `tun` is set an address for the TUN mode is generated as
+    /// well, if none exists yet.
+    pub async fn load_and_update(
+        dir: &Path,
+        routes: &Routes,
+        tun: bool,
+        ipc: &Arc<RatmanIpc>,
+    ) -> Result<Self> {
         let path = dir.join("config.json");
 
-        let mut f = File::open(&path)
-            .unwrap_or_else(|_| File::create(&path).expect("failed to create config file"));
-
-        let mut buf = String::new();
-        f.read_to_string(&mut buf)
-            .expect("failed to read config file");
-
-        let mut cfg = serde_json::from_str(&buf).unwrap_or_else(|_| Config::default());
+        let mut cfg: Config = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|buf| serde_json::from_str(&buf).ok())
+            .unwrap_or_default();
 
         for (ip, (io, _)) in routes {
             // We only care about Inlet routes
@@ -55,22 +220,29 @@ impl Config {
             }
 
             // If this inlet route is new we generate a unique address for it
-            let ip_str = ip.to_string();
-            if !cfg.addresses.contains_key(&ip_str) {
-                cfg.addresses.insert(ip_str, Address::random());
+            if let Entry::Vacant(entry) = cfg.addresses.entry(ip.to_string()) {
+                let (addr, auth) = ipc.addr_create(None).await?;
+                info!("Created address {} for inlet {}", addr, entry.key());
+                entry.insert((addr, auth));
             }
         }
 
+        if tun && cfg.tun.address.is_none() {
+            let (addr, auth) = ipc.addr_create(None).await?;
+            info!("Created address {} for the TUN interface", addr);
+            cfg.tun.address = Some((addr, auth));
+        }
+
         // After generating new addresses for inlet routes we save
         // this to the configuration
-        f.write_all(&serde_json::to_string_pretty(&cfg).unwrap().as_bytes())
-            .unwrap();
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(&path, serde_json::to_string_pretty(&cfg)?)?;
 
-        cfg
+        Ok(cfg)
     }
 }
 
-pub fn parse_routes_file(dir: &PathBuf) -> Routes {
+pub fn parse_routes_file(dir: &Path) -> Routes {
     let path = dir.join("routes.pm");
 
     let mut f = File::open(&path)
@@ -93,15 +265,15 @@ pub fn parse_routes_file(dir: &PathBuf) -> Routes {
 }
 
 /// Represent some kind of IP space information
-#[derive(Debug, Ord, PartialOrd, PartialEq, Eq)]
+#[derive(Clone, Debug, Ord, PartialOrd, PartialEq, Eq)]
 pub enum IpSpace {
     Single(SocketAddr),
 }
 
-impl ToString for IpSpace {
-    fn to_string(&self) -> String {
+impl fmt::Display for IpSpace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Single(addr) => addr.to_string(),
+            Self::Single(addr) => write!(f, "{}", addr),
         }
     }
 }
@@ -115,7 +287,7 @@ impl IpSpace {
 }
 
 /// An enum that's either `In` or `Out`
-#[derive(Debug, Ord, PartialOrd, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, Ord, PartialOrd, PartialEq, Eq)]
 pub enum InOrOut {
     In,
     Out,
@@ -135,14 +307,14 @@ fn parse_line(line: &str) -> Option<(IpSpace, (InOrOut, Address))> {
 
 fn parse_outgoing(line: &str) -> Option<(IpSpace, (InOrOut, Address))> {
     let split: Vec<_> = line.split("<-").collect();
-    let socket = IpSpace::Single(split.get(0)?.trim().parse().ok()?);
+    let socket = IpSpace::Single(split.first()?.trim().parse().ok()?);
     let id = Address::from_string(&split.get(1)?.trim().to_string());
     Some((socket, (InOrOut::Out, id)))
 }
 
 fn parse_incoming(line: &str) -> Option<(IpSpace, (InOrOut, Address))> {
     let split: Vec<_> = line.split("->").collect();
-    let socket = IpSpace::Single(split.get(0)?.trim().parse().ok()?);
+    let socket = IpSpace::Single(split.first()?.trim().parse().ok()?);
     let id = Address::from_string(&split.get(1)?.trim().to_string());
     Some((socket, (InOrOut::In, id)))
 }
@@ -190,3 +362,53 @@ fn test_parse_line_in() {
         _ => panic!("invalid parse"),
     }
 }
+
+#[test]
+fn test_parse_prefix() {
+    let prefix: Prefix = "fd00:1234:5678:9abc::1/48".parse().unwrap();
+    assert_eq!(prefix.to_string(), "fd00:1234:5678::/48");
+    assert_eq!(prefix.bits(), 48);
+
+    assert!("fd00::/0".parse::<Prefix>().is_err());
+    assert!("fd00::/50".parse::<Prefix>().is_err());
+    assert!("fd00::/72".parse::<Prefix>().is_err());
+    assert!("fd00::".parse::<Prefix>().is_err());
+}
+
+#[test]
+fn test_prefix_map() {
+    let prefix: Prefix = "fd00:1234::/32".parse().unwrap();
+    let addr = Address::from_string(
+        &"7053-2C1D-15D9-4D30-4FC5-4663-28BD-2E0C-F33D-0D49-2E28-6C1F-5649-6922-7DA8-B7A5"
+            .to_owned(),
+    );
+
+    let ip = prefix.map(addr);
+    assert_eq!(
+        ip,
+        "fd00:1234:7053:2c1d:15d9:4d30:4fc5:4663"
+            .parse::<Ipv6Addr>()
+            .unwrap()
+    );
+    assert!(prefix.contains(&ip));
+    assert!(!prefix.contains(&"fd00:1235::1".parse().unwrap()));
+}
+
+#[test]
+fn reject_untrusted_exits() {
+    let trusted = Address::random();
+    let rogue = Address::random();
+    let cfg = TunConfig {
+        trusted_exits: vec![trusted].into_iter().collect(),
+        ..Default::default()
+    };
+
+    // A faster exit which isn't trusted is never picked
+    let exits = [
+        (rogue, Duration::from_millis(5)),
+        (trusted, Duration::from_millis(50)),
+    ];
+    assert_eq!(cfg.choose_exit(&exits), Some(trusted));
+    assert_eq!(cfg.choose_exit(&exits[..1]), None);
+    assert_eq!(TunConfig::default().choose_exit(&exits), None);
+}
@@ -1,76 +1,94 @@
+// SPDX-FileCopyrightText: 2022 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
 use crate::{
     config::IpSpace,
-    io::{connect_with_address, from_tcp_to_ratman, terminate_session},
+    io::{from_tcp_to_ratman, next_envelope},
+    server::{Session, SessionMap},
+};
+use libratman::{
+    api::{RatmanIpc, RatmanIpcExtV1, RatmanStreamExtV1},
+    tokio::{net::TcpListener, task},
+    types::{AddrAuth, Address, Ident32, Recipient},
+    Result,
 };
-use async_std::{io, net::TcpListener, stream::StreamExt, task};
-use libratman::client::{Address, Id};
+use std::{net::SocketAddr, sync::Arc};
 
 /// An inlet takes data from a socket and maps it into a Ratman
-/// message to a particular peer (provided to the `new` function).
+/// message to a particular peer (provided to the `spawn` function).
 ///
-/// Otherwise the Inlet is completely stateless.  It can deduce its
-/// state from incoming connections and exsiting tasks.
+/// Each incoming connection is a new session.  Responses from the
+/// peer are matched to the connection via their session ID.
 pub struct Inlet;
 
 impl Inlet {
     /// Spawn an inlet listener
-    pub fn new(
-        bind: Option<&str>,
+    pub async fn spawn(
+        api_bind: SocketAddr,
         ip: &IpSpace,
         peer_addr: Address,
         self_addr: Address,
-        token: Id,
-    ) -> io::Result<()> {
-        task::block_on(Self.spawn(bind, ip, peer_addr, self_addr, token))
+        auth: AddrAuth,
+    ) -> Result<()> {
+        let tcp = TcpListener::bind(*ip.socket_addr()).await?;
+        Self::listen(api_bind, tcp, peer_addr, self_addr, auth).await
     }
 
-    async fn spawn(
-        self,
-        bind: Option<&str>,
-        ip: &IpSpace,
+    /// Spawn an inlet on an already bound listener
+    pub async fn listen(
+        api_bind: SocketAddr,
+        tcp: TcpListener,
         peer_addr: Address,
         self_addr: Address,
-        token: Id,
-    ) -> io::Result<()> {
-        let socket_addr = ip.socket_addr().clone();
-        let tcp = TcpListener::bind(&socket_addr).await?;
-        let ipc = connect_with_address(bind, self_addr, token).await?;
+        auth: AddrAuth,
+    ) -> Result<()> {
+        let socket_addr = tcp.local_addr()?;
+
+        // Receiving streams blocks an API connection, so responses
+        // are handled on a second connection
+        let ipc = RatmanIpc::start(api_bind).await?;
+        ipc.addr_up(auth, self_addr).await?;
+        let recv_ipc = RatmanIpc::start(api_bind).await?;
+        let map = SessionMap::default();
 
         debug!("Starting inlet loop");
 
+        let sessions = Arc::clone(&map);
         task::spawn(async move {
-            let mut inc = tcp.incoming();
+            if let Err(e) = Self::to_tcp(recv_ipc, auth, self_addr, peer_addr, sessions).await {
+                error!("inlet {} stopped receiving: {}", socket_addr, e);
+            }
+        });
 
+        task::spawn(async move {
             // A new stream means a new session, so given that the
             // stream is valid we first generate a session ID
-            while let Some(stream) = inc.next().await {
-                let mut stream = match stream {
-                    Ok(s) => s,
+            loop {
+                let stream = match tcp.accept().await {
+                    Ok((stream, _)) => stream,
                     Err(e) => {
                         warn!("invalid stream tried to connect to {}: {}", socket_addr, e);
                         continue;
                     }
                 };
 
-                debug!("Accepted new stream!");
+                let session = Ident32::random();
+                debug!("Accepted new stream for session {}", session);
+
+                let (read, write) = stream.into_split();
+                map.lock().await.insert(session, Session::new(write));
 
                 // Then spawn a new task for this session.  We keep
                 // reading messages from the TCP stream until we no
                 // longer get any (i.e. the socket collapses or
                 // something)
-                let ipc = ipc.clone();
+                let ipc = Arc::clone(&ipc);
                 task::spawn(async move {
-                    let session = Address::random();
-                    while let Ok(_) =
-                        from_tcp_to_ratman(peer_addr, session, &mut stream, &ipc).await
+                    if let Err(e) =
+                        from_tcp_to_ratman(read, &ipc, auth, self_addr, peer_addr, session).await
                     {
-                    }
-
-                    // Before we kill the task we send one last
-                    // message to the peer to terminate the session on
-                    // their end
-                    if let Err(e) = terminate_session(peer_addr, session, &ipc).await {
-                        error!("failed to terminate session: {}", e);
+                        error!("failed to forward session {}: {}", session, e);
                     }
                 });
             }
@@ -78,4 +96,47 @@ impl Inlet {
 
         Ok(())
     }
+
+    /// Write responses from the peer to their TCP streams
+    async fn to_tcp(
+        ipc: Arc<RatmanIpc>,
+        auth: AddrAuth,
+        self_addr: Address,
+        peer_addr: Address,
+        map: SessionMap,
+    ) -> Result<()> {
+        let mut gen = ipc
+            .recv_many(auth, self_addr, Recipient::Address(self_addr), None)
+            .await?;
+
+        loop {
+            let (from, env) = next_envelope(&mut gen).await?;
+            if from != peer_addr {
+                warn!("Ignoring envelope from unknown address {}", from);
+                continue;
+            }
+
+            let session = env.session;
+            let mut map = map.lock().await;
+            let res = match map.get_mut(&session) {
+                Some(s) => s.deliver(env).await,
+                None => {
+                    trace!("Ignoring envelope for closed session {}", session);
+                    continue;
+                }
+            };
+
+            match res {
+                Ok(false) => {}
+                Ok(true) => {
+                    debug!("Clearing session: {}", session);
+                    map.remove(&session);
+                }
+                Err(e) => {
+                    error!("failed to send message for session {}: {}", session, e);
+                    map.remove(&session);
+                }
+            }
+        }
+    }
 }
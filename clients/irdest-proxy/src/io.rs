@@ -1,58 +1,80 @@
+// SPDX-FileCopyrightText: 2022 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
 use crate::proto::Envelope;
-use async_std::{
-    io::{self, ReadExt, WriteExt},
-    net::TcpStream,
+use libratman::{
+    api::{RatmanIpc, RatmanStreamExtV1, StreamGenerator},
+    tokio::{io::AsyncReadExt, net::tcp::OwnedReadHalf},
+    types::{AddrAuth, Address, Ident32, LetterheadV1, Recipient},
+    Result,
 };
-use libratman::client::{Address, Id, RatmanIpc, Receive_Type};
-
-/// Connect to the router with a fixed address
-pub async fn connect_with_address(
-    bind: Option<&str>,
-    addr: Address,
-    token: Id,
-) -> io::Result<RatmanIpc> {
-    Ok(match bind {
-        Some(bind) => RatmanIpc::connect(bind, Some(addr), Some(token)).await,
-        None => RatmanIpc::default_with_addr(addr, token).await,
-    }?)
-}
+use std::sync::Arc;
 
-pub async fn terminate_session(addr: Address, session: Address, ipc: &RatmanIpc) -> io::Result<()> {
-    let env = Envelope::end(session).encode();
-    ipc.send_to(addr, env).await?;
-    Ok(())
+/// The largest amount of data read from a TCP socket into a single envelope
+pub const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Send an envelope to a remote proxy address
+pub async fn send_envelope(
+    ipc: &Arc<RatmanIpc>,
+    auth: AddrAuth,
+    from: Address,
+    to: Address,
+    env: Envelope,
+) -> Result<()> {
+    let buf = env.encode();
+    let mut letterhead = LetterheadV1::send(from, Recipient::Address(to));
+    letterhead.stream_size = buf.len() as u64;
+    ipc.send_to(auth, letterhead, buf.as_slice()).await
 }
 
-pub async fn from_tcp_to_ratman(
-    addr: Address,
-    session: Address,
-    tcp: &mut TcpStream,
-    ipc: &RatmanIpc,
-) -> io::Result<()> {
-    let mut buffer = vec![0; 1024]; // TODO: get this size from IPC interface
-    tcp.read(&mut buffer).await?;
+/// Wait for the next envelope and the address that sent it
+pub async fn next_envelope(gen: &mut StreamGenerator<'_>) -> Result<(Address, Envelope)> {
+    let letterhead = gen.wait_for_manifest().await?;
 
-    debug!("Read message from TCP, wrapping into Ratman envelope...");
+    let mut buf = vec![0; letterhead.stream_size as usize];
+    gen.inner.as_reader().read_exact(&mut buf).await?;
+    Ok((letterhead.from, Envelope::decode(&buf)?))
+}
 
-    // Encode data into envelope
-    let env = Envelope::with_session(session, buffer);
+/// Forward everything read from a TCP stream to a remote proxy address
+///
+/// When the TCP stream is closed, the session is terminated on the
+/// remote end.
+pub async fn from_tcp_to_ratman(
+    mut tcp: OwnedReadHalf,
+    ipc: &Arc<RatmanIpc>,
+    auth: AddrAuth,
+    from: Address,
+    to: Address,
+    session: Ident32,
+) -> Result<()> {
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let mut seq = 0;
 
-    ipc.send_to(addr, env.encode()).await?;
-    Ok(())
-}
+    loop {
+        let read = match tcp.read(&mut buffer).await {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) => {
+                debug!(
+                    "failed to read from TCP stream for session {}: {}",
+                    session, e
+                );
+                break;
+            }
+        };
 
-/// Get a message for a session from Ratman
-pub async fn from_ratman(ipc: &RatmanIpc) -> Option<(Address, Option<Vec<u8>>)> {
-    ipc.next()
-        .await
-        .filter(|(t, _)| t == &Receive_Type::DEFAULT)
-        .map(|(_, msg)| {
-            let env = Envelope::decode(&msg.get_payload());
-            (env.session, env.data)
-        })
-}
+        trace!(
+            "Read {} bytes from TCP, wrapping into Ratman envelope...",
+            read
+        );
+        let env = Envelope::with_session(session, seq, buffer[..read].to_vec());
+        send_envelope(ipc, auth, from, to, env).await?;
+        seq += 1;
+    }
 
-pub async fn to_tcp(tcp: &mut TcpStream, data: Vec<u8>) -> io::Result<()> {
-    tcp.write_all(&data).await?;
-    Ok(())
+    // Before we stop we send one last message to the peer to
+    // terminate the session on their end
+    send_envelope(ipc, auth, from, to, Envelope::end(session, seq)).await
 }
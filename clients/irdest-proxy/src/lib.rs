@@ -1,3 +1,7 @@
+// SPDX-FileCopyrightText: 2022 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
 //! Irdest Proxy allows you to send IP data through an Irdest network
 //!
 //! It uses (at some point in the future) a few different mechanisms
@@ -5,61 +9,95 @@
 //!
 //! - Simple socket + port mapped to a Ratman address
 //!
-//! - Tap device mapping a whole IP space to a Ratman address
+//! - TUN device mapping an IPv6 prefix onto Ratman addresses, with
+//!   exit nodes for any other destination
 //!
 //! - Tap device mapping different IP spaces to different Ratman
 //!   addresses, using dynamic route announcements (a la BGP).
 //!
-//! Currently ___0___ of these mechanisms are implemented :)
+//! Currently only the first two of these mechanisms are implemented.
 
 #[macro_use]
 extern crate tracing;
 
-// mod config;
-// mod inlet;
-// mod io;
-// mod outlet;
-// mod proto;
-// mod server;
+mod config;
+mod inlet;
+mod io;
+mod outlet;
+mod proto;
+mod server;
+#[cfg(target_os = "linux")]
+mod tun;
+
+#[cfg(test)]
+mod test;
+
+pub use config::{parse_routes_file, Config, InOrOut, IpSpace, Prefix, Routes, TunConfig};
+pub use server::Server;
+
+use directories::ProjectDirs;
+use libratman::{
+    api::{RatmanIpc, RatmanIpcExtV1},
+    Result,
+};
+use std::{net::SocketAddr, path::PathBuf};
+use tracing_subscriber::{filter::LevelFilter, fmt, EnvFilter};
+
+pub fn setup_logging(lvl: &str) {
+    let filter = EnvFilter::default()
+        .add_directive(match lvl {
+            "trace" => LevelFilter::TRACE.into(),
+            "debug" => LevelFilter::DEBUG.into(),
+            "info" => LevelFilter::INFO.into(),
+            "warn" => LevelFilter::WARN.into(),
+            "error" => LevelFilter::ERROR.into(),
+            _ => unreachable!(),
+        })
+        .add_directive("mio=error".parse().unwrap());
+
+    // Initialise the logger
+    fmt().with_env_filter(filter).init();
+    info!("Initialised logger: welcome to irdest-proxy!");
+}
 
-// #[cfg(test)]
-// mod test;
+pub fn get_config_path() -> PathBuf {
+    ProjectDirs::from("org", "irdest", "irdest-proxy")
+        .expect("failed to determine configuration directory on your platform")
+        .config_dir()
+        .to_path_buf()
+}
 
-// pub use config::{parse_routes_file, Config, Routes};
+pub async fn start_proxy(verbosity: &str, api_bind: SocketAddr, cfg_dir: PathBuf) -> Result<()> {
+    setup_logging(verbosity);
 
-// use directories::ProjectDirs;
-// use server::Server;
-// use std::path::PathBuf;
-// use tracing_subscriber::{filter::LevelFilter, fmt, EnvFilter};
+    let routes = parse_routes_file(&cfg_dir);
+    let ipc = RatmanIpc::start(api_bind).await?;
+    let config = Config::load_and_update(&cfg_dir, &routes, false, &ipc).await?;
 
-// pub fn setup_logging(lvl: &str) {
-//     let filter = EnvFilter::default()
-//         .add_directive(match lvl {
-//             "trace" => LevelFilter::TRACE.into(),
-//             "debug" => LevelFilter::DEBUG.into(),
-//             "info" => LevelFilter::INFO.into(),
-//             "warn" => LevelFilter::WARN.into(),
-//             "error" => LevelFilter::ERROR.into(),
-//             _ => unreachable!(),
-//         })
-//         .add_directive("async_std=error".parse().unwrap())
-//         .add_directive("async_io=error".parse().unwrap())
-//         .add_directive("polling=error".parse().unwrap())
-//         .add_directive("mio=error".parse().unwrap());
+    Server::new(config, routes).run(api_bind).await;
+    Ok(())
+}
 
-//     // Initialise the logger
-//     fmt().with_env_filter(filter).init();
-//     info!("Initialised logger: welcome to irdest-proxy!");
-// }
+/// Start the proxy in TUN mode
+///
+/// Instead of the routes file this creates a TUN interface as
+/// configured in the `tun` section of config.json.  With `exit` set,
+/// this proxy acts as an exit node for the rest of the network.
+#[cfg(target_os = "linux")]
+pub async fn start_tun_proxy(
+    verbosity: &str,
+    api_bind: SocketAddr,
+    cfg_dir: PathBuf,
+    exit: bool,
+) -> Result<()> {
+    setup_logging(verbosity);
 
-// pub fn get_config_path() -> PathBuf {
-//     ProjectDirs::from("org", "irdest", "irdest-proxy")
-//         .expect("failed to determine configuration directory on your platform")
-//         .config_dir()
-//         .to_path_buf()
-// }
+    let ipc = RatmanIpc::start(api_bind).await?;
+    let config = Config::load_and_update(&cfg_dir, &Routes::new(), true, &ipc).await?;
+    let (addr, auth) = config
+        .tun
+        .address
+        .expect("no address was created for the TUN interface");
 
-// pub async fn start_proxy(verbosity: &str, bind: Option<&str>, config: Config, routes: Routes) {
-//     setup_logging(verbosity);
-//     Server::new(config, routes).run(bind).await
-// }
+    tun::TunProxy::run(api_bind, &config.tun, addr, auth, exit).await
+}
@@ -1,88 +1,122 @@
+// SPDX-FileCopyrightText: 2022 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
 use crate::{
     config::IpSpace,
-    io::{connect_with_address, from_ratman, to_tcp},
-    server::SessionMap,
+    io::{from_tcp_to_ratman, next_envelope, send_envelope},
+    proto::Envelope,
+    server::Session,
+};
+use libratman::{
+    api::{RatmanIpc, RatmanIpcExtV1, RatmanStreamExtV1},
+    tokio::{net::TcpStream, task},
+    types::{AddrAuth, Address, Recipient},
+    Result,
+};
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    net::SocketAddr,
+    sync::Arc,
 };
-use async_std::{io, net::TcpStream, task};
-use libratman::client::{Address, Id};
 
-pub struct Outlet {
-    map: SessionMap,
-}
+/// An outlet receives sessions on its own Ratman address and opens a
+/// connection to its destination for each of them.
+///
+/// Data read from the destination is sent back to the address that
+/// started the session.
+pub struct Outlet;
 
 impl Outlet {
-    pub fn new(
-        map: &SessionMap,
-        bind: Option<&str>,
+    /// Spawn an outlet for an address
+    pub async fn spawn(
+        api_bind: SocketAddr,
         ip: &IpSpace,
         addr: Address,
-        token: Id,
-    ) -> io::Result<()> {
-        task::block_on(Self { map: map.clone() }.spawn(bind, ip, addr, token))
-    }
+        auth: AddrAuth,
+    ) -> Result<()> {
+        let socket_addr = *ip.socket_addr();
 
-    async fn spawn(
-        self,
-        bind: Option<&str>,
-        ip: &IpSpace,
-        addr: Address,
-        token: Id,
-    ) -> io::Result<()> {
-        let socket_addr = ip.socket_addr().clone();
-        let ipc = connect_with_address(bind, addr, token).await?;
+        // Receiving streams blocks an API connection, so responses
+        // are sent on a second connection
+        let ipc = RatmanIpc::start(api_bind).await?;
+        ipc.addr_up(auth, addr).await?;
+        let recv_ipc = RatmanIpc::start(api_bind).await?;
 
         debug!("Starting the outlet loop");
 
-        // We don't set up a lot of state for this type.  We do
-        // however move Self into the task so it doesn't go out of
-        // scope.
         task::spawn(async move {
-            let this = self;
+            if let Err(e) = Self::run(ipc, recv_ipc, socket_addr, addr, auth).await {
+                error!("outlet to {} stopped receiving: {}", socket_addr, e);
+            }
+        });
 
-            while let Some((session, data)) = from_ratman(&ipc).await {
-                let session_exists = this.map.read().await.contains_key(&session);
+        Ok(())
+    }
 
-                match (session_exists, data) {
-                    // Session exists, no data sent --> drop session
-                    (true, None) => {
-                        debug!("Clearing session: {:?}", session);
-                        this.map.write().await.remove(&session);
-                    }
-                    // Session exists and we received data --> forward
-                    (true, Some(data)) => {
-                        let mut tcp = this.map.read().await.get(&session).unwrap().clone();
-                        trace!("Received data, forwarding to {:?}", tcp.peer_addr());
-                        if let Err(e) = to_tcp(&mut tcp, data).await {
-                            error!("failed to send message for session {}: {}", session, e);
-                            this.map.write().await.remove(&session);
+    async fn run(
+        ipc: Arc<RatmanIpc>,
+        recv_ipc: Arc<RatmanIpc>,
+        socket_addr: SocketAddr,
+        addr: Address,
+        auth: AddrAuth,
+    ) -> Result<()> {
+        let mut map: BTreeMap<_, Session> = BTreeMap::new();
+        let mut gen = recv_ipc
+            .recv_many(auth, addr, Recipient::Address(addr), None)
+            .await?;
+
+        loop {
+            let (from, env) = next_envelope(&mut gen).await?;
+            let session = env.session;
+
+            let res = match map.entry(session) {
+                Entry::Occupied(mut entry) => entry.get_mut().deliver(env).await,
+                // No session exists and no data was sent --> ignore
+                Entry::Vacant(_) if env.seq == 0 && env.data.is_none() => continue,
+                // No session exists, but we received data --> create session
+                Entry::Vacant(entry) => {
+                    debug!("Creating new session: {}", session);
+                    let (read, write) = match TcpStream::connect(socket_addr).await {
+                        Ok(tcp) => tcp.into_split(),
+                        Err(e) => {
+                            error!(
+                                "failed to establish outbound connection to {}: {}",
+                                socket_addr, e
+                            );
+
+                            // Let the inlet know that this session is over
+                            let end = Envelope::end(session, 0);
+                            send_envelope(&ipc, auth, addr, from, end).await?;
+                            continue;
                         }
-                    }
-                    // No session exists, but we received data --> create session
-                    (false, Some(data)) => {
-                        debug!("Creating new session: {:?}", session);
-                        let mut tcp = match TcpStream::connect(&socket_addr).await {
-                            Ok(tcp) => tcp,
-                            Err(e) => {
-                                error!(
-                                    "failed to establish outbound connection to {}: {}",
-                                    socket_addr, e
-                                );
-                                break;
-                            }
-                        };
+                    };
 
-                        if let Err(e) = to_tcp(&mut tcp, data).await {
-                            error!("failed to send message for session {}: {}", session, e);
+                    let ipc = Arc::clone(&ipc);
+                    task::spawn(async move {
+                        if let Err(e) =
+                            from_tcp_to_ratman(read, &ipc, auth, addr, from, session).await
+                        {
+                            error!("failed to send response for session {}: {}", session, e);
                         }
+                    });
 
-                        this.map.write().await.insert(session, tcp);
-                    }
-                    // No session exists and no data was sent --> ignore
-                    (false, None) => continue,
+                    entry.insert(Session::new(write)).deliver(env).await
                 }
-            }
-        });
+            };
 
-        Ok(())
+            match res {
+                Ok(false) => {}
+                // Session was terminated by the inlet --> drop session
+                Ok(true) => {
+                    debug!("Clearing session: {}", session);
+                    map.remove(&session);
+                }
+                Err(e) => {
+                    error!("failed to send message for session {}: {}", session, e);
+                    map.remove(&session);
+                }
+            }
+        }
     }
 }
@@ -1,23 +1,36 @@
-use libratman::client::Address;
+// SPDX-FileCopyrightText: 2022 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+use libratman::{types::Ident32, Result};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+/// A single chunk of data for a proxy session
+///
+/// Every envelope is sent as its own message stream.  Because streams
+/// can overtake each other in the network, envelopes are numbered per
+/// session and direction, so that the receiver can put them back into
+/// order.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Envelope {
-    pub session: Address,
+    pub session: Ident32,
+    pub seq: u64,
     pub data: Option<Vec<u8>>,
 }
 
 impl Envelope {
-    pub fn with_session(session: Address, data: Vec<u8>) -> Self {
+    pub fn with_session(session: Ident32, seq: u64, data: Vec<u8>) -> Self {
         Self {
             session,
+            seq,
             data: Some(data),
         }
     }
 
-    pub fn end(session: Address) -> Self {
+    pub fn end(session: Ident32, seq: u64) -> Self {
         Self {
             session,
+            seq,
             data: None,
         }
     }
@@ -26,7 +39,17 @@ impl Envelope {
         bincode::serialize(self).expect("failed to encode envelope")
     }
 
-    pub fn decode(buf: &Vec<u8>) -> Self {
-        bincode::deserialize(buf).expect("failed to decode envelope")
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        Ok(bincode::deserialize(buf)?)
     }
 }
+
+#[test]
+fn envelope_roundtrip() {
+    let session = Ident32::random();
+    let env = Envelope::with_session(session, 3, b"GET / HTTP/1.1".to_vec());
+    assert_eq!(Envelope::decode(&env.encode()).unwrap(), env);
+
+    let end = Envelope::end(session, 4);
+    assert_eq!(Envelope::decode(&end.encode()).unwrap(), end);
+}
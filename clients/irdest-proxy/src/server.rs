@@ -6,41 +6,122 @@ use crate::{
     config::{Config, InOrOut, Routes},
     inlet::Inlet,
     outlet::Outlet,
+    proto::Envelope,
 };
-use async_std::{
-    net::TcpStream,
-    sync::{Arc, RwLock},
+use libratman::{
+    tokio::{
+        io::{self, AsyncWriteExt},
+        net::tcp::OwnedWriteHalf,
+        sync::Mutex,
+    },
+    types::Ident32,
 };
-use libratman::client::{Address, Id};
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, net::SocketAddr, sync::Arc};
 
-pub type SessionMap = Arc<RwLock<BTreeMap<Address, TcpStream>>>;
+pub type SessionMap = Arc<Mutex<BTreeMap<Ident32, Session>>>;
+
+/// How far envelopes may arrive ahead of the next one to be written
+///
+/// This bounds the number of envelopes a session keeps in memory.
+const MAX_PENDING: u64 = 256;
+
+/// The receiving half of a proxy session
+///
+/// Envelopes are written to the TCP stream in the order they were
+/// sent in.  Envelopes that arrive early are kept until all previous
+/// envelopes have been written, up to `MAX_PENDING` envelopes ahead.
+pub struct Session {
+    tcp: OwnedWriteHalf,
+    next_seq: u64,
+    pending: BTreeMap<u64, Option<Vec<u8>>>,
+}
+
+impl Session {
+    pub fn new(tcp: OwnedWriteHalf) -> Self {
+        Self {
+            tcp,
+            next_seq: 0,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Write an envelope to the TCP stream
+    ///
+    /// Returns `true` when the remote end has terminated the session,
+    /// after which the session should be removed.  Envelopes that were
+    /// already written are ignored, and envelopes that are too far
+    /// ahead fail the session.
+    pub async fn deliver(&mut self, env: Envelope) -> io::Result<bool> {
+        if env.seq < self.next_seq {
+            trace!("Ignoring duplicate envelope {}", env.seq);
+            return Ok(false);
+        }
+
+        if env.seq - self.next_seq >= MAX_PENDING {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "envelope {} is too far ahead of envelope {}",
+                    env.seq, self.next_seq
+                ),
+            ));
+        }
+
+        self.pending.insert(env.seq, env.data);
+
+        while let Some(data) = self.pending.remove(&self.next_seq) {
+            self.next_seq += 1;
+            match data {
+                Some(data) => self.tcp.write_all(&data).await?,
+                None => {
+                    self.tcp.shutdown().await?;
+                    return Ok(true);
+                }
+            }
+        }
+
+        Ok(false)
+    }
+}
 
 /// The main proxy server state
 pub struct Server {
     cfg: Config,
     routes: Routes,
-    map: SessionMap,
 }
 
 impl Server {
     pub fn new(cfg: Config, routes: Routes) -> Self {
-        Self {
-            cfg,
-            routes,
-            map: SessionMap::default(),
-        }
+        Self { cfg, routes }
     }
 
-    /// Run this server
-    pub async fn run(&self, bind: Option<&str>) {
+    /// Start all inlets and outlets
+    ///
+    /// Routes that fail to initialise are logged and skipped.
+    pub async fn start(&self, api_bind: SocketAddr) {
         for (ip, (io, addr)) in self.routes.iter() {
             debug!("Loading: {:?} // {:?} // {}", ip, io, addr);
 
             if let Err(e) = match io {
-                // FIXME: these token will be rejected
-                InOrOut::In => Inlet::new(bind, ip, *addr, self.cfg.get_address(&ip), Id::random()),
-                InOrOut::Out => Outlet::new(&self.map, bind, ip, *addr, Id::random()),
+                InOrOut::In => match self.cfg.inlet_address(ip) {
+                    Some((self_addr, auth)) => {
+                        Inlet::spawn(api_bind, ip, *addr, self_addr, auth).await
+                    }
+                    None => {
+                        error!("no address was created for inlet {}", ip.to_string());
+                        continue;
+                    }
+                },
+                InOrOut::Out => match self.cfg.outlet_auth(*addr) {
+                    Some(auth) => Outlet::spawn(api_bind, ip, *addr, auth).await,
+                    None => {
+                        error!(
+                            "no authentication token for outlet address {}: add it to the `outlets` section of config.json",
+                            addr
+                        );
+                        continue;
+                    }
+                },
             } {
                 error!(
                     "failed to initialise {}: {}",
@@ -52,8 +133,45 @@ impl Server {
                 );
             }
         }
+    }
 
-        // wowow this is a hack ;_;
-        async_std::future::pending().await
+    /// Run this server
+    pub async fn run(&self, api_bind: SocketAddr) {
+        self.start(api_bind).await;
+        std::future::pending().await
     }
 }
+
+#[cfg(test)]
+use libratman::tokio;
+
+#[libratman::tokio::test]
+async fn bound_pending_envelopes() {
+    use libratman::tokio::{
+        io::AsyncReadExt,
+        net::{TcpListener, TcpStream},
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let tcp = TcpStream::connect(listener.local_addr().unwrap())
+        .await
+        .unwrap();
+    let (mut remote, _) = listener.accept().await.unwrap();
+    let (_read, write) = tcp.into_split();
+
+    let session = Ident32::random();
+    let mut s = Session::new(write);
+    let env = |seq: u64| Envelope::with_session(session, seq, vec![seq as u8]);
+
+    assert!(!s.deliver(env(1)).await.unwrap());
+    assert!(!s.deliver(env(0)).await.unwrap());
+    assert!(!s.deliver(env(0)).await.unwrap());
+    assert!(s.pending.is_empty());
+
+    let mut buf = [0; 2];
+    remote.read_exact(&mut buf).await.unwrap();
+    assert_eq!(buf, [0, 1]);
+
+    assert!(s.deliver(env(2 + MAX_PENDING)).await.is_err());
+    assert!(s.pending.is_empty());
+}
@@ -1,38 +1,137 @@
-use ratmand::{config::ConfigTree, start_with_configuration};
-
-#[test]
-fn integration() {
-    // Setup router A
-    std::thread::spawn(|| {
-        let cfg = ConfigTree::default_in_memory()
-            .patch("ratmand/api_bind", "127.0.0.1:10999")
-            .patch("ratmand/ephemeral", true)
-            .patch("inet/bind", "[::]:10900")
-            .patch("ratmand/enable_dashboard", false)
-            .patch("ratmand/accept_unknown_peers", true)
-            .patch("lan/enable", false);
-
-        start_with_configuration(cfg);
-    });
+// SPDX-FileCopyrightText: 2022 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+use crate::{inlet::Inlet, Config, InOrOut, IpSpace, Routes, Server};
+use libratman::{
+    api::RatmanIpcExtV1,
+    tokio::{
+        self,
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        time::{sleep, timeout, Instant},
+    },
+    types::Address,
+};
+use ratman_harness::Router;
+use ratmand::{config::ConfigTree, routes::ScorerRegistry};
+use std::{net::SocketAddr, time::Duration};
+
+/// Start a router with an inet link on a free loopback port
+///
+/// netmod-inet only supports IPv6 binds.  The link reports the address
+/// it was bound to as its identifier.
+async fn start_router() -> (Router, SocketAddr) {
+    // The routers don't know each other's keys
+    let router = Router::start(
+        ConfigTree::default_in_memory().patch("ratmand/accept_unknown_peers", true),
+        ScorerRegistry::default(),
+        vec![],
+    )
+    .await
+    .unwrap();
+
+    let (_, auth) = router.create_address().await.unwrap();
+    let links = router
+        .ipc()
+        .link_up(auth, r#"settings "inet" { bind "[::1]:0"; }"#.into())
+        .await
+        .unwrap();
+    let inet_bind = links[0].identifier.parse().unwrap();
 
-    #[allow(deprecated)]
-    std::thread::sleep_ms(100);
+    (router, inet_bind)
+}
+
+async fn wait_for_peer(router: &Router, addr: Address) {
+    let started = Instant::now();
+    while !router.known_peers().await.unwrap().contains(&addr) {
+        assert!(
+            started.elapsed() < Duration::from_secs(30),
+            "router never learned about {}",
+            addr
+        );
+        sleep(Duration::from_millis(100)).await;
+    }
+}
 
-    std::thread::spawn(|| {
-        let cfg = ConfigTree::default_in_memory()
-            .patch_list("ratmand/peers", "inet:localhost:10900")
-            .patch("ratmand/api_bind", "127.0.0.1:11999")
-            .patch("ratmand/ephemeral", true)
-            .patch("inet/bind", "[::]:11900")
-            .patch("ratmand/enable_dashboard", false)
-            .patch("ratmand/accept_unknown_peers", true)
-            .patch("lan/enable", false);
+/// A TCP service that sends back everything it receives
+async fn echo_service() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let bind = listener.local_addr().unwrap();
 
-        start_with_configuration(cfg);
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (mut read, mut write) = stream.split();
+                let _ = tokio::io::copy(&mut read, &mut write).await;
+            });
+        }
     });
 
-    #[allow(deprecated)]
-    std::thread::sleep_ms(100);
+    bind
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn tunnel_tcp_through_inet() {
+    let (inlet_router, inlet_inet) = start_router().await;
+    let (outlet_router, _) = start_router().await;
+
+    let (_, auth) = outlet_router.create_address().await.unwrap();
+    outlet_router
+        .ipc()
+        .peers_add(auth, format!("inet:{}", inlet_inet))
+        .await
+        .unwrap();
+
+    // Configure the outlet side with a manually created address
+    let echo = echo_service().await;
+    let (outlet_addr, outlet_auth) = outlet_router.ipc().addr_create(None).await.unwrap();
+    let mut outlet_routes = Routes::new();
+    outlet_routes.insert(IpSpace::Single(echo), (InOrOut::Out, outlet_addr));
+    let mut outlet_cfg = Config::default();
+    outlet_cfg
+        .outlets
+        .insert(outlet_addr.to_string(), outlet_auth);
+
+    Server::new(outlet_cfg, outlet_routes)
+        .start(outlet_router.api_bind())
+        .await;
+
+    // The inlet side gets its own address, and listens on a socket that
+    // is bound here so that its port can't be taken in the meantime
+    let inlet_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let inlet_bind = inlet_listener.local_addr().unwrap();
+    let (inlet_addr, inlet_auth) = inlet_router.ipc().addr_create(None).await.unwrap();
+    Inlet::listen(
+        inlet_router.api_bind(),
+        inlet_listener,
+        outlet_addr,
+        inlet_addr,
+        inlet_auth,
+    )
+    .await
+    .unwrap();
+
+    wait_for_peer(&inlet_router, outlet_addr).await;
+    wait_for_peer(&outlet_router, inlet_addr).await;
+
+    let mut tcp = TcpStream::connect(inlet_bind).await.unwrap();
+    let request = b"Hello through the mesh!";
+    tcp.write_all(request).await.unwrap();
+
+    let mut response = vec![0; request.len()];
+    timeout(Duration::from_secs(30), tcp.read_exact(&mut response))
+        .await
+        .expect("no response from the outlet")
+        .unwrap();
+    assert_eq!(response, request);
 
-    // Now do other magic
+    // Closing the connection ends the session on both sides
+    tcp.shutdown().await.unwrap();
+    let mut rest = vec![];
+    timeout(Duration::from_secs(30), tcp.read_to_end(&mut rest))
+        .await
+        .expect("session was not closed by the outlet")
+        .unwrap();
+    assert!(rest.is_empty());
 }
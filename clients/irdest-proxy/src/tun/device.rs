@@ -0,0 +1,156 @@
+// SPDX-FileCopyrightText: 2022 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+use libratman::tokio::io::unix::AsyncFd;
+use std::{
+    ffi::CStr,
+    io, mem,
+    net::Ipv6Addr,
+    os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+};
+
+/// `_IOW('T', 202, int)` from `linux/if_tun.h`
+const TUNSETIFF: libc::c_ulong = 0x400454ca;
+
+/// `struct in6_ifreq` from `linux/ipv6.h`
+#[repr(C)]
+struct In6Ifreq {
+    addr: libc::in6_addr,
+    prefix_len: u32,
+    ifindex: libc::c_int,
+}
+
+/// A TUN interface without packet information headers
+///
+/// Creating and configuring the interface requires `CAP_NET_ADMIN`.
+pub struct TunDevice {
+    fd: AsyncFd<OwnedFd>,
+    name: String,
+}
+
+impl TunDevice {
+    /// Create a TUN interface and bring it up with an IPv6 address
+    pub fn create(name: &str, addr: Ipv6Addr, prefix_len: u8, mtu: u16) -> io::Result<Self> {
+        let fd = cvt(unsafe {
+            libc::open(
+                b"/dev/net/tun\0".as_ptr() as *const _,
+                libc::O_RDWR | libc::O_NONBLOCK | libc::O_CLOEXEC,
+            )
+        })?;
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let mut req = ifreq(name)?;
+        req.ifr_ifru.ifru_flags = (libc::IFF_TUN | libc::IFF_NO_PI) as libc::c_short;
+        ioctl(fd.as_raw_fd(), TUNSETIFF, &mut req)?;
+
+        // The kernel may have picked a different name (e.g. for "tun%d")
+        let name = unsafe { CStr::from_ptr(req.ifr_name.as_ptr()) }
+            .to_string_lossy()
+            .into_owned();
+        configure(&name, addr, prefix_len, mtu)?;
+
+        Ok(Self {
+            fd: AsyncFd::new(fd)?,
+            name,
+        })
+    }
+
+    /// The name of this interface
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Read the next IP packet from the interface
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let mut guard = self.fd.readable().await?;
+            match guard.try_io(|fd| {
+                cvt_size(unsafe {
+                    libc::read(fd.as_raw_fd(), buf.as_mut_ptr() as *mut _, buf.len())
+                })
+            }) {
+                Ok(res) => return res,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Write an IP packet to the interface
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            let mut guard = self.fd.writable().await?;
+            match guard.try_io(|fd| {
+                cvt_size(unsafe {
+                    libc::write(fd.as_raw_fd(), buf.as_ptr() as *const _, buf.len())
+                })
+            }) {
+                Ok(res) => return res,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+/// Set the MTU and address of an interface and bring it up
+fn configure(name: &str, addr: Ipv6Addr, prefix_len: u8, mtu: u16) -> io::Result<()> {
+    let sock =
+        cvt(unsafe { libc::socket(libc::AF_INET6, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) })?;
+    let sock = unsafe { OwnedFd::from_raw_fd(sock) };
+
+    let mut req = ifreq(name)?;
+    req.ifr_ifru.ifru_mtu = mtu as libc::c_int;
+    ioctl(sock.as_raw_fd(), libc::SIOCSIFMTU, &mut req)?;
+
+    let mut req = ifreq(name)?;
+    ioctl(sock.as_raw_fd(), libc::SIOCGIFINDEX, &mut req)?;
+    let mut addr_req = In6Ifreq {
+        addr: libc::in6_addr {
+            s6_addr: addr.octets(),
+        },
+        prefix_len: prefix_len as u32,
+        ifindex: unsafe { req.ifr_ifru.ifru_ifindex },
+    };
+    ioctl(sock.as_raw_fd(), libc::SIOCSIFADDR, &mut addr_req)?;
+
+    let mut req = ifreq(name)?;
+    ioctl(sock.as_raw_fd(), libc::SIOCGIFFLAGS, &mut req)?;
+    unsafe { req.ifr_ifru.ifru_flags |= (libc::IFF_UP | libc::IFF_RUNNING) as libc::c_short };
+    ioctl(sock.as_raw_fd(), libc::SIOCSIFFLAGS, &mut req)
+}
+
+/// Create an interface request for an interface name
+fn ifreq(name: &str) -> io::Result<libc::ifreq> {
+    if name.is_empty() || name.len() >= libc::IFNAMSIZ {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid interface name '{}'", name),
+        ));
+    }
+
+    let mut req: libc::ifreq = unsafe { mem::zeroed() };
+    for (dst, src) in req.ifr_name.iter_mut().zip(name.bytes()) {
+        *dst = src as libc::c_char;
+    }
+    Ok(req)
+}
+
+fn ioctl<T>(fd: RawFd, request: libc::c_ulong, arg: &mut T) -> io::Result<()> {
+    cvt(unsafe { libc::ioctl(fd, request as _, arg as *mut T) }).map(|_| ())
+}
+
+fn cvt(res: libc::c_int) -> io::Result<libc::c_int> {
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(res)
+    }
+}
+
+fn cvt_size(res: libc::ssize_t) -> io::Result<usize> {
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(res as usize)
+    }
+}
@@ -0,0 +1,288 @@
+// SPDX-FileCopyrightText: 2022 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+//! Tunnel IP packets through a Ratman network via a TUN interface
+//!
+//! Every Ratman address is mapped into an IPv6 prefix, and each IP
+//! packet is sent as a single stream to the address it maps to.
+//! Packets for destinations outside of the prefix are sent to an exit
+//! node, which is discovered via an anycast probe on the configured
+//! exit namespace.
+
+mod device;
+mod routes;
+
+pub use device::TunDevice;
+pub use routes::{ipv6_endpoints, TunRoutes};
+
+use crate::config::TunConfig;
+use libratman::{
+    api::{NamespaceAnycastExtV1, RatmanIpc, RatmanIpcExtV1, RatmanStreamExtV1, StreamGenerator},
+    tokio::{
+        io::{self, AsyncReadExt},
+        task,
+        time::{sleep, Duration, Instant},
+    },
+    types::error::UserError,
+    types::{AddrAuth, Address, LetterheadV1, Recipient},
+    ClientError, RatmanError, Result,
+};
+use std::{net::SocketAddr, sync::Arc};
+
+/// The MTU of the TUN interface, which is the IPv6 minimum
+pub const MTU: u16 = 1280;
+
+/// The largest packet that is accepted from the network
+const MAX_PACKET: usize = u16::MAX as usize;
+
+/// How often the list of peers is checked for new addresses
+const PEER_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often a new exit node is searched for
+const EXIT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long to wait for exit nodes to answer a probe
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+fn missing_setting(name: &str) -> RatmanError {
+    ClientError::User(UserError::MissingInput(format!(
+        "{} must be set in config.json to run an exit node",
+        name
+    )))
+    .into()
+}
+
+/// Forward packets between a TUN interface and a Ratman address
+pub struct TunProxy;
+
+impl TunProxy {
+    /// Create the TUN interface and forward packets until an error
+    /// occurs
+    ///
+    /// An exit node announces itself in the exit namespace and writes
+    /// packets for destinations outside of the prefix to its
+    /// interface, from where the kernel can forward them.
+    pub async fn run(
+        api_bind: SocketAddr,
+        cfg: &TunConfig,
+        addr: Address,
+        auth: AddrAuth,
+        exit: bool,
+    ) -> Result<()> {
+        // Receiving streams blocks an API connection, so sending,
+        // receiving and route updates each get their own connection
+        let ipc = RatmanIpc::start(api_bind).await?;
+        ipc.addr_up(auth, addr).await?;
+        let recv_ipc = RatmanIpc::start(api_bind).await?;
+        let update_ipc = RatmanIpc::start(api_bind).await?;
+
+        let ip = cfg.prefix.map(addr);
+        let device = Arc::new(TunDevice::create(
+            &cfg.interface,
+            ip,
+            cfg.prefix.bits(),
+            MTU,
+        )?);
+        info!(
+            "Created interface {} with address {}/{}",
+            device.name(),
+            ip,
+            cfg.prefix.bits()
+        );
+
+        let routes = Arc::new(TunRoutes::new(cfg.prefix));
+        routes.learn(addr).await;
+
+        if exit {
+            let space = cfg
+                .exit_space
+                .ok_or_else(|| missing_setting("tun.exit_space"))?;
+            if !ipc.namespace_list().await?.contains(&space) {
+                let key = cfg
+                    .exit_space_key
+                    .ok_or_else(|| missing_setting("tun.exit_space_key"))?;
+                ipc.namespace_register(auth, space, key).await?;
+            }
+            ipc.namespace_up(addr, auth, space).await?;
+            info!("Announcing {} as an exit node", addr);
+        }
+
+        {
+            let routes = Arc::clone(&routes);
+            let cfg = cfg.clone();
+            task::spawn(async move {
+                Self::update_routes(update_ipc, routes, &cfg, addr, auth, exit).await
+            });
+        }
+
+        {
+            let device = Arc::clone(&device);
+            let routes = Arc::clone(&routes);
+            task::spawn(async move {
+                if let Err(e) = Self::to_tun(recv_ipc, device, routes, addr, auth).await {
+                    error!("TUN interface stopped receiving: {}", e);
+                }
+            });
+        }
+
+        Self::from_tun(ipc, device, routes, addr, auth).await
+    }
+
+    /// Send packets read from the interface to their Ratman address
+    async fn from_tun(
+        ipc: Arc<RatmanIpc>,
+        device: Arc<TunDevice>,
+        routes: Arc<TunRoutes>,
+        addr: Address,
+        auth: AddrAuth,
+    ) -> Result<()> {
+        let mut buf = vec![0; MAX_PACKET];
+
+        loop {
+            let len = device.recv(&mut buf).await?;
+            let packet = &buf[..len];
+
+            let dst = match ipv6_endpoints(packet) {
+                Some((_, dst)) => dst,
+                None => {
+                    trace!("Ignoring non-IPv6 packet");
+                    continue;
+                }
+            };
+
+            let to = match routes.next_hop(&dst).await {
+                Some(to) => to,
+                None => {
+                    trace!("No route to {}, dropping packet", dst);
+                    continue;
+                }
+            };
+
+            trace!("Sending {} byte packet for {} to {}", len, dst, to);
+            let mut letterhead = LetterheadV1::send(addr, Recipient::Address(to));
+            letterhead.stream_size = len as u64;
+            if let Err(e) = ipc.send_to(auth, letterhead, packet).await {
+                warn!("failed to send packet to {}: {}", to, e);
+            }
+        }
+    }
+
+    /// Write packets received on our address to the interface
+    async fn to_tun(
+        ipc: Arc<RatmanIpc>,
+        device: Arc<TunDevice>,
+        routes: Arc<TunRoutes>,
+        addr: Address,
+        auth: AddrAuth,
+    ) -> Result<()> {
+        let mut gen = ipc
+            .recv_many(auth, addr, Recipient::Address(addr), None)
+            .await?;
+
+        loop {
+            let (from, packet) = match next_packet(&mut gen).await? {
+                Some(next) => next,
+                None => continue,
+            };
+
+            let src = match ipv6_endpoints(&packet) {
+                Some((src, _)) => src,
+                None => {
+                    warn!("Ignoring non-IPv6 packet from {}", from);
+                    continue;
+                }
+            };
+
+            if !routes.accept(from, &src).await {
+                warn!("Dropping packet from {} with source address {}", from, src);
+                continue;
+            }
+
+            if routes.learn(from).await {
+                debug!("Learned new address {}", from);
+            }
+
+            device.send(&packet).await?;
+        }
+    }
+
+    /// Periodically learn addresses from the router and look for an
+    /// exit node
+    async fn update_routes(
+        ipc: Arc<RatmanIpc>,
+        routes: Arc<TunRoutes>,
+        cfg: &TunConfig,
+        addr: Address,
+        auth: AddrAuth,
+        exit: bool,
+    ) {
+        // Exit nodes don't need another exit node
+        let space = cfg.exit_space.filter(|_| !exit);
+        if space.is_none() && !exit {
+            warn!("No exit namespace is configured, traffic outside the prefix is dropped");
+        }
+        let mut last_probe: Option<Instant> = None;
+
+        loop {
+            match ipc.peers_list().await {
+                Ok(peers) => {
+                    for peer in peers {
+                        if routes.learn(peer.addr).await {
+                            debug!("Learned new address {}", peer.addr);
+                        }
+                    }
+                }
+                Err(e) => warn!("failed to list peers: {}", e),
+            }
+
+            let probe_due = last_probe.is_none_or(|t| t.elapsed() >= EXIT_INTERVAL);
+            if let Some(space) = space.filter(|_| probe_due) {
+                last_probe = Some(Instant::now());
+                match ipc
+                    .namespace_anycast_probe(addr, auth, space, PROBE_TIMEOUT)
+                    .await
+                {
+                    Ok(exits) => {
+                        let next = cfg.choose_exit(&exits);
+                        if next.is_none() && !exits.is_empty() {
+                            warn!("Ignoring {} untrusted exit node(s)", exits.len());
+                        }
+                        if next != routes.exit().await {
+                            match next {
+                                Some(exit) => info!("Using exit node {}", exit),
+                                None => warn!("No exit node is reachable"),
+                            }
+                            routes.set_exit(next).await;
+                        }
+
+                        if let Some(exit) = next {
+                            routes.learn(exit).await;
+                        }
+                    }
+                    Err(e) => warn!("failed to probe for exit nodes: {}", e),
+                }
+            }
+
+            sleep(PEER_INTERVAL).await;
+        }
+    }
+}
+
+/// Wait for the next packet and the address that sent it
+///
+/// Streams that are too large to be an IP packet are skipped.
+async fn next_packet(gen: &mut StreamGenerator<'_>) -> Result<Option<(Address, Vec<u8>)>> {
+    let letterhead = gen.wait_for_manifest().await?;
+    let size = letterhead.stream_size;
+
+    if size > MAX_PACKET as u64 {
+        warn!("Ignoring {} byte stream from {}", size, letterhead.from);
+        io::copy(&mut gen.inner.as_reader().take(size), &mut io::sink()).await?;
+        return Ok(None);
+    }
+
+    let mut buf = vec![0; size as usize];
+    gen.inner.as_reader().read_exact(&mut buf).await?;
+    Ok(Some((letterhead.from, buf)))
+}
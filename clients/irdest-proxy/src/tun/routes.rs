@@ -0,0 +1,145 @@
+// SPDX-FileCopyrightText: 2022 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+use crate::config::Prefix;
+use libratman::{tokio::sync::RwLock, types::Address};
+use std::{collections::BTreeMap, convert::TryInto, net::Ipv6Addr};
+
+/// Read the source and destination address of an IPv6 packet
+///
+/// Returns `None` for anything that isn't an IPv6 packet.
+pub fn ipv6_endpoints(packet: &[u8]) -> Option<(Ipv6Addr, Ipv6Addr)> {
+    if packet.len() < 40 || packet[0] >> 4 != 6 {
+        return None;
+    }
+
+    let src: [u8; 16] = packet[8..24].try_into().ok()?;
+    let dst: [u8; 16] = packet[24..40].try_into().ok()?;
+    Some((src.into(), dst.into()))
+}
+
+/// Map IP addresses inside the mesh prefix to Ratman addresses
+///
+/// Because an IP address only contains the first few bytes of a
+/// Ratman address, the table has to learn about addresses before it
+/// can send packets to them.  Addresses outside of the prefix are
+/// sent to the current exit node.
+pub struct TunRoutes {
+    prefix: Prefix,
+    addrs: RwLock<BTreeMap<Ipv6Addr, Address>>,
+    exit: RwLock<Option<Address>>,
+}
+
+impl TunRoutes {
+    pub fn new(prefix: Prefix) -> Self {
+        Self {
+            prefix,
+            addrs: RwLock::new(BTreeMap::new()),
+            exit: RwLock::new(None),
+        }
+    }
+
+    /// Remember a Ratman address, returning `true` if it was new
+    pub async fn learn(&self, addr: Address) -> bool {
+        let ip = self.prefix.map(addr);
+        self.addrs.write().await.insert(ip, addr) != Some(addr)
+    }
+
+    /// Change the exit node used for destinations outside the prefix
+    pub async fn set_exit(&self, exit: Option<Address>) {
+        *self.exit.write().await = exit;
+    }
+
+    pub async fn exit(&self) -> Option<Address> {
+        *self.exit.read().await
+    }
+
+    /// Find the Ratman address that a packet should be sent to
+    pub async fn next_hop(&self, dst: &Ipv6Addr) -> Option<Address> {
+        if self.prefix.contains(dst) {
+            self.addrs.read().await.get(dst).copied()
+        } else {
+            self.exit().await
+        }
+    }
+
+    /// Check whether a packet received from `from` may be delivered
+    ///
+    /// Packets must come from the mesh address of their sender.  Only
+    /// the exit node is allowed to send packets from outside the
+    /// prefix, for which it acts as a gateway.
+    pub async fn accept(&self, from: Address, src: &Ipv6Addr) -> bool {
+        if *src == self.prefix.map(from) {
+            return true;
+        }
+
+        !self.prefix.contains(src) && self.exit().await == Some(from)
+    }
+}
+
+#[cfg(test)]
+use libratman::tokio;
+
+#[cfg(test)]
+fn ipv6_packet(src: Ipv6Addr, dst: Ipv6Addr) -> Vec<u8> {
+    let mut packet = vec![0; 48];
+    packet[0] = 6 << 4;
+    packet[8..24].copy_from_slice(&src.octets());
+    packet[24..40].copy_from_slice(&dst.octets());
+    packet
+}
+
+#[test]
+fn parse_ipv6_endpoints() {
+    let src = "fd00::1".parse().unwrap();
+    let dst = "2001:db8::2".parse().unwrap();
+    let packet = ipv6_packet(src, dst);
+
+    assert_eq!(ipv6_endpoints(&packet), Some((src, dst)));
+    assert_eq!(ipv6_endpoints(&packet[..39]), None);
+
+    // An IPv4 header is ignored
+    let mut packet = packet;
+    packet[0] = 4 << 4;
+    assert_eq!(ipv6_endpoints(&packet), None);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn route_through_prefix_and_exit() {
+    let prefix: Prefix = "fd00:1234::/32".parse().unwrap();
+    let routes = TunRoutes::new(prefix);
+    let peer = Address::random();
+    let exit = Address::random();
+
+    // Unknown addresses inside the prefix can't be routed
+    assert_eq!(routes.next_hop(&prefix.map(peer)).await, None);
+    assert!(routes.learn(peer).await);
+    assert!(!routes.learn(peer).await);
+    assert_eq!(routes.next_hop(&prefix.map(peer)).await, Some(peer));
+
+    let internet = "2001:db8::1".parse().unwrap();
+    assert_eq!(routes.next_hop(&internet).await, None);
+    routes.set_exit(Some(exit)).await;
+    assert_eq!(routes.next_hop(&internet).await, Some(exit));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn reject_spoofed_sources() {
+    let prefix: Prefix = "fd00:1234::/32".parse().unwrap();
+    let routes = TunRoutes::new(prefix);
+    let peer = Address::random();
+    let exit = Address::random();
+    let internet = "2001:db8::1".parse().unwrap();
+
+    assert!(routes.accept(peer, &prefix.map(peer)).await);
+    assert!(!routes.accept(peer, &prefix.map(exit)).await);
+    assert!(!routes.accept(peer, &internet).await);
+
+    routes.set_exit(Some(exit)).await;
+    assert!(routes.accept(exit, &internet).await);
+    assert!(!routes.accept(exit, &prefix.map(peer)).await);
+    assert!(!routes.accept(peer, &internet).await);
+}
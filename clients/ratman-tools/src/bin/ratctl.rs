@@ -87,6 +87,82 @@ fn setup_cli() -> Command {
                     Command::new("list")
                         .about("List available local addresses")
                 ]),
+            //// =^-^= Per-address contact books
+            ////
+            //// Every local address has its own contact book, which stores
+            //// private notes, tags, and a trust level for other addresses.
+            Command::new("contact")
+                .about("Manage the contact book of the current identity")
+                .arg_required_else_help(true)
+                .subcommands([
+                    Command::new("add")
+                        .about("Add a new contact book entry")
+                        .args([
+                            Arg::new("address")
+                                .long("addr")
+                                .short('a')
+                                .help("The address to add as a contact")
+                                .required(true)
+                                .action(ArgAction::Set),
+                            Arg::new("note")
+                                .long("note")
+                                .help("A private note about this contact")
+                                .action(ArgAction::Set),
+                            Arg::new("tag")
+                                .long("tag")
+                                .help("Add a key=value tag to this contact (can be repeated)")
+                                .action(ArgAction::Append),
+                            Arg::new("trust")
+                                .long("trust")
+                                .help("A trust level between 1 (lowest) and 7 (highest)")
+                                .value_parser(value_parser!(u8).range(1..=7))
+                                .default_value("1")
+                                .action(ArgAction::Set),
+                        ]),
+                    Command::new("list")
+                        .alias("ls")
+                        .about("List contact book entries, optionally filtered")
+                        .args(contact_filter_args()),
+                    Command::new("modify")
+                        .about("Change all contact book entries selected by the given filters")
+                        .args(contact_filter_args())
+                        .args([
+                            Arg::new("note")
+                                .long("note")
+                                .help("Replace the note of all selected contacts")
+                                .conflicts_with("clear-note")
+                                .action(ArgAction::Set),
+                            Arg::new("clear-note")
+                                .long("clear-note")
+                                .help("Remove the note of all selected contacts")
+                                .action(ArgAction::SetTrue),
+                            Arg::new("tag")
+                                .long("tag")
+                                .help("Set a key=value tag on all selected contacts")
+                                .conflicts_with("remove-tag")
+                                .action(ArgAction::Set),
+                            Arg::new("remove-tag")
+                                .long("remove-tag")
+                                .help("Remove the tag with this key from all selected contacts")
+                                .action(ArgAction::Set),
+                            Arg::new("trust")
+                                .long("trust")
+                                .help("Set a new trust level between 1 (lowest) and 7 (highest)")
+                                .value_parser(value_parser!(u8).range(1..=7))
+                                .action(ArgAction::Set),
+                        ]),
+                    Command::new("delete")
+                        .alias("del")
+                        .about("Delete all contact book entries for an address")
+                        .args([
+                            Arg::new("address")
+                                .long("addr")
+                                .short('a')
+                                .help("The address to delete contact entries for")
+                                .required(true)
+                                .action(ArgAction::Set),
+                        ]),
+                ]),
             //// =^-^= Stream subscriptions & more
             ////
             //// A subscription listens to all incoming messages for a given
@@ -135,6 +211,48 @@ fn setup_cli() -> Command {
                                 .help("Specify the subscription to restore")
                                 .action(ArgAction::Set)
                         ),
+                    //// Fetch a stream from neighbouring routers and print it
+                    Command::new("fetch")
+                        .about("Fetch a stream by its read capability and write it to stdout")
+                        .arg_required_else_help(true)
+                        .args([
+                            Arg::new("read_cap")
+                                .help("The read capability of the stream (urn:erisx2:...)")
+                                .required(true)
+                                .action(ArgAction::Set),
+                            Arg::new("timeout")
+                                .long("timeout")
+                                .help("Give up if the stream isn't complete after this many seconds")
+                                .value_parser(value_parser!(u64).range(1..))
+                                .default_value("60")
+                                .action(ArgAction::Set),
+                        ]),
+                    //// Linked streams are never evicted from the journal
+                    Command::new("link")
+                        .about("Tag a stream, which keeps it in the router's journal")
+                        .arg_required_else_help(true)
+                        .args([
+                            Arg::new("stream_id")
+                                .help("The stream (manifest) ID to link")
+                                .required(true)
+                                .action(ArgAction::Set),
+                            Arg::new("tag")
+                                .long("tag")
+                                .help("Add a tag to this stream (can be repeated)")
+                                .action(ArgAction::Append),
+                            Arg::new("with")
+                                .long("with")
+                                .help("Associate another stream ID with this stream (can be repeated)")
+                                .action(ArgAction::Append),
+                        ]),
+                    Command::new("unlink")
+                        .about("Remove the tags of a stream, so that it can be evicted again")
+                        .arg(
+                            Arg::new("stream_id")
+                                .help("The stream (manifest) ID to unlink")
+                                .required(true)
+                                .action(ArgAction::Set)
+                        ),
                 ]),
             //// Query various types of status output
             Command::new("status")
@@ -152,6 +270,87 @@ fn setup_cli() -> Command {
                 .arg_required_else_help(true)
                 .subcommands([
                     Command::new("list").about("List all available peers on the network along some metadata about them"),
+                    Command::new("add")
+                        .about("Connect to a new peer and remember it across router restarts")
+                        .args([
+                            Arg::new("peer")
+                                .help("The peer to connect to, in the '<netmod>:<address>' peer syntax (for example 'inet:example.org:9001')")
+                                .required(true)
+                                .action(ArgAction::Set),
+                        ]),
+                    Command::new("remove")
+                        .alias("rm")
+                        .about("Stop peering with a previously added peer")
+                        .args([
+                            Arg::new("peer")
+                                .help("The peer to disconnect from, as given to 'peers add' or in the router configuration")
+                                .required(true)
+                                .action(ArgAction::Set),
+                        ]),
+                    Command::new("prune")
+                        .about("Drop stale links and forget old peers from the routing table")
+                        .args([
+                            Arg::new("lost-after")
+                                .long("lost-after")
+                                .help("Drop links that haven't been seen for this many hours (default: router configuration)")
+                                .value_parser(value_parser!(u32).range(1..))
+                                .action(ArgAction::Set),
+                            Arg::new("forget-after")
+                                .long("forget-after")
+                                .help("Forget peers that haven't been seen for this many days (default: router configuration)")
+                                .value_parser(value_parser!(u32).range(1..))
+                                .action(ArgAction::Set),
+                            Arg::new("follow")
+                                .long("follow")
+                                .short('f')
+                                .help("Keep running and print peers that are pruned by the router's expiry policy later on")
+                                .action(ArgAction::SetTrue),
+                        ]),
+                ]),
+            //// Netmod management commands
+            ////
+            //// Links can be started and stopped at runtime, without
+            //// restarting the router.
+            Command::new("link")
+                .about("Manage the netmod drivers attached to the router")
+                .arg_required_else_help(true)
+                .subcommands([
+                    Command::new("list")
+                        .alias("ls")
+                        .about("List all active links along with their status"),
+                    Command::new("up")
+                        .about("Start new links from a netmod configuration snippet")
+                        .args([
+                            Arg::new("config")
+                                .help("Netmod configuration in the ratmand KDL syntax, for example 'settings \"inet\" { bind \"[::]:9001\"; }'")
+                                .required_unless_present("file")
+                                .action(ArgAction::Set),
+                            Arg::new("file")
+                                .long("file")
+                                .short('f')
+                                .help("Read the netmod configuration from a file instead")
+                                .conflicts_with("config")
+                                .action(ArgAction::Set),
+                        ]),
+                    Command::new("down")
+                        .about("Stop a link and remove it from the router")
+                        .args([
+                            Arg::new("id")
+                                .help("The link ID, as shown by 'link list'")
+                                .required(true)
+                                .value_parser(value_parser!(u32))
+                                .action(ArgAction::Set),
+                        ]),
+                ]),
+            ////
+            //// Apply changes to the router configuration file without
+            //// restarting the router.
+            Command::new("config")
+                .about("Manage the router configuration")
+                .arg_required_else_help(true)
+                .subcommands([
+                    Command::new("reload")
+                        .about("Re-read the configuration file and apply what changed; settings that need a router restart are listed"),
                 ]),
             //// Namespace management commands
             Command::new("space")
@@ -167,29 +366,58 @@ fn setup_cli() -> Command {
                                 .required(true)
                                 .action(ArgAction::Set)
                         ]),
-                    Command::new("up")
-                        .about("Mark a given namespace as 'up', enabling the router to respond to anycast pings and other protocols")
+                    Command::new("create")
+                        .about("Create a new namespace with a key that is held by the router")
                         .args([
+                            Arg::new("priv-name")
+                                .long("name")
+                                .help("A private namespace name")
+                                .action(ArgAction::Set),
+                        ]),
+                    Command::new("list")
+                        .alias("ls")
+                        .about("List namespaces which this router holds keys for"),
+                    Command::new("export")
+                        .about("Write a namespace key to a file so it can be imported on another router")
+                        .args([
+                            space_arg().required(true),
                             Arg::new("file_name")
-                                .help("Specify the key file created by 'register'")
+                                .help("Specify the output file name for the namespace key")
                                 .short('f')
+                                .required(true)
                                 .action(ArgAction::Set)
                         ]),
-                    Command::new("down")
-                        .about("Mark a given namespace as 'down'")
+                    Command::new("import")
+                        .about("Import a namespace key file created by 'register' or 'export'")
                         .args([
                             Arg::new("file_name")
-                                .help("Specify the key file created by 'register'")
+                                .help("Specify the namespace key file to import")
                                 .short('f')
+                                .required(true)
                                 .action(ArgAction::Set)
                         ]),
+                    Command::new("rotate")
+                        .about("Replace a namespace key with a new one, printing the new namespace address")
+                        .args([space_arg().required(true)]),
+                    Command::new("destroy")
+                        .about("Delete a namespace key from the router")
+                        .args([space_arg().required(true)]),
+                    Command::new("up")
+                        .about("Mark a given namespace as 'up', enabling the router to respond to anycast pings and other protocols")
+                        .args([space_arg(), key_file_arg()]),
+                    Command::new("down")
+                        .about("Mark a given namespace as 'down'")
+                        .args([space_arg(), key_file_arg()]),
                     Command::new("anycast")
                         .about("Send an anycast probe to this namespace, returning address responses ordered by time")
                         .args([
+                            space_arg(),
+                            key_file_arg(),
                             Arg::new("timeout")
                                 .help("Specify a timeout in milliseconds")
                                 .short('t')
                                 .value_parser(value_parser!(u64))
+                                .default_value("1000")
                                 .action(ArgAction::Set)
                         ])
                 ]),
@@ -199,6 +427,40 @@ fn setup_cli() -> Command {
         )
 }
 
+fn contact_filter_args() -> [Arg; 3] {
+    [
+        Arg::new("address")
+            .long("addr")
+            .short('a')
+            .help("Only select entries for this address (can be repeated)")
+            .action(ArgAction::Append),
+        Arg::new("note-filter")
+            .long("note-filter")
+            .help("Only select entries whose note contains this string")
+            .action(ArgAction::Set),
+        Arg::new("tag-filter")
+            .long("tag-filter")
+            .help("Only select entries with this key=value tag (can be repeated)")
+            .action(ArgAction::Append),
+    ]
+}
+
+fn space_arg() -> Arg {
+    Arg::new("space")
+        .long("space")
+        .short('s')
+        .help("Specify the namespace address")
+        .action(ArgAction::Set)
+}
+
+fn key_file_arg() -> Arg {
+    Arg::new("file_name")
+        .help("Specify the key file created by 'register' or 'export'")
+        .short('f')
+        .conflicts_with("space")
+        .action(ArgAction::Set)
+}
+
 async fn run_program(m: ArgMatches, base_args: BaseArgs) -> Result<()> {
     let api_bind = m.get_one::<String>("api-bind").map(|provided| {
         SocketAddr::from_str(provided.as_str()).map_err(|parse_err| {
@@ -0,0 +1,19 @@
+use crate::{base_args::BaseArgs, encode_list};
+use clap::ArgMatches;
+use libratman::{
+    api::{RatmanIpc, RatmanIpcExtV1},
+    Result,
+};
+use std::sync::Arc;
+
+pub async fn reload(
+    ipc: &Arc<RatmanIpc>,
+    base_args: BaseArgs,
+    _matches: &ArgMatches,
+) -> Result<()> {
+    let (_, auth) = base_args.identity_data?;
+
+    let changes = ipc.config_reload(auth).await?;
+    println!("{}", encode_list(changes, base_args.out_fmt));
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use crate::{base_args::BaseArgs, encode_list, reply_ok};
+use clap::ArgMatches;
+use libratman::{
+    api::{types::ContactFilter, RatmanIpc, RatmanIpcExtV1},
+    types::{error::UserError, to_cstring, Address, Modify},
+    Result,
+};
+use std::{collections::BTreeMap, sync::Arc};
+
+/// Parse a `key=value` tag argument
+fn parse_tag(tag: &str) -> Result<(String, String)> {
+    tag.split_once('=')
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .ok_or_else(|| UserError::InvalidInput(tag.to_string(), Some("key=value".into())).into())
+}
+
+fn parse_tags(matches: &ArgMatches, key: &str) -> Result<BTreeMap<String, String>> {
+    matches
+        .get_many::<String>(key)
+        .into_iter()
+        .flatten()
+        .map(|tag| parse_tag(tag))
+        .collect()
+}
+
+fn parse_filter(matches: &ArgMatches) -> Result<ContactFilter> {
+    Ok(ContactFilter {
+        addrs: matches
+            .get_many::<String>("address")
+            .into_iter()
+            .flatten()
+            .map(Address::from_string)
+            .collect(),
+        note: matches.get_one::<String>("note-filter").map(to_cstring),
+        tags: parse_tags(matches, "tag-filter")?
+            .iter()
+            .map(|(k, v)| (to_cstring(k), to_cstring(v)))
+            .collect(),
+    })
+}
+
+pub async fn add(ipc: &Arc<RatmanIpc>, base_args: BaseArgs, matches: &ArgMatches) -> Result<()> {
+    let (_, auth) = base_args.identity_data?;
+    let addr = Address::from_string(matches.get_one::<String>("address").unwrap());
+    let note = matches.get_one::<String>("note").cloned();
+    let tags = parse_tags(matches, "tag")?;
+    let trust = *matches.get_one::<u8>("trust").unwrap();
+
+    let id = ipc.contact_add(auth, addr, note, tags, trust).await?;
+    println!("{}", id);
+    Ok(())
+}
+
+pub async fn list(ipc: &Arc<RatmanIpc>, base_args: BaseArgs, matches: &ArgMatches) -> Result<()> {
+    let (_, auth) = base_args.identity_data?;
+    let filter = parse_filter(matches)?;
+
+    let contacts = ipc.contact_list(auth, filter).await?;
+    println!("{}", encode_list(contacts, base_args.out_fmt));
+    Ok(())
+}
+
+pub async fn modify(ipc: &Arc<RatmanIpc>, base_args: BaseArgs, matches: &ArgMatches) -> Result<()> {
+    let (_, auth) = base_args.identity_data?;
+    let filter = parse_filter(matches)?;
+
+    let note_modify = match (
+        matches.get_one::<String>("note"),
+        matches.get_flag("clear-note"),
+    ) {
+        (Some(note), _) => Modify::Change(note.clone()),
+        (None, true) => Modify::DeleteAll,
+        (None, false) => Modify::Keep,
+    };
+
+    let tags_modify = match (
+        matches.get_one::<String>("tag"),
+        matches.get_one::<String>("remove-tag"),
+    ) {
+        (Some(tag), _) => Modify::Change(parse_tag(tag)?),
+        (None, Some(key)) => Modify::DeleteOne((key.clone(), String::new())),
+        (None, None) => Modify::Keep,
+    };
+
+    let new_trust = matches.get_one::<u8>("trust").copied();
+
+    let ids = ipc
+        .contact_modify(auth, filter, note_modify, tags_modify, new_trust)
+        .await?;
+    println!("{}", encode_list(ids, base_args.out_fmt));
+    Ok(())
+}
+
+pub async fn delete(ipc: &Arc<RatmanIpc>, base_args: BaseArgs, matches: &ArgMatches) -> Result<()> {
+    let (_, auth) = base_args.identity_data?;
+    let addr = Address::from_string(matches.get_one::<String>("address").unwrap());
+
+    ipc.contact_delete(auth, addr).await?;
+    println!("{}", reply_ok(&base_args.out_fmt));
+    Ok(())
+}
@@ -13,6 +13,9 @@ use std::{any::Any, collections::BTreeMap, fmt::Display, sync::Arc};
 
 pub mod addr;
 pub mod base_args;
+pub mod config;
+pub mod contact;
+pub mod link;
 pub mod peers;
 pub mod recv;
 pub mod send;
@@ -138,12 +141,32 @@ pub async fn command_filter(
                 ("addr", "up") => addr::up(ipc, base_args, op_matches).await,
                 ("addr", "down") => addr::down(ipc, base_args, op_matches).await,
                 ("addr", "list") => addr::list(ipc, base_args, op_matches).await,
+                //// =^-^= Contact book commands (ctl)
+                ("contact", "add") => contact::add(ipc, base_args, op_matches).await,
+                ("contact", "list") => contact::list(ipc, base_args, op_matches).await,
+                ("contact", "modify") => contact::modify(ipc, base_args, op_matches).await,
+                ("contact", "delete") => contact::delete(ipc, base_args, op_matches).await,
                 //// =^-^= Status commands (ctl)
                 ("status", "system") => status::system(ipc, base_args, op_matches).await,
                 //// =^-^= Peer commands (ctl)
                 ("peers", "list") => peers::list(ipc, base_args, op_matches).await,
+                ("peers", "add") => peers::add(ipc, base_args, op_matches).await,
+                ("peers", "remove") => peers::remove(ipc, base_args, op_matches).await,
+                ("peers", "prune") => peers::prune(ipc, base_args, op_matches).await,
+                //// =^-^= Link commands (ctl)
+                ("link", "list") => link::list(ipc, base_args, op_matches).await,
+                ("link", "up") => link::up(ipc, base_args, op_matches).await,
+                ("link", "down") => link::down(ipc, base_args, op_matches).await,
+                //// =^-^= Configuration commands (ctl)
+                ("config", "reload") => config::reload(ipc, base_args, op_matches).await,
                 //// =^-^= Namespace commands (ctl)
                 ("space", "register") => space::register(ipc, base_args, op_matches).await,
+                ("space", "create") => space::create(ipc, base_args, op_matches).await,
+                ("space", "list") => space::list(ipc, base_args, op_matches).await,
+                ("space", "export") => space::export(ipc, base_args, op_matches).await,
+                ("space", "import") => space::import(ipc, base_args, op_matches).await,
+                ("space", "rotate") => space::rotate(ipc, base_args, op_matches).await,
+                ("space", "destroy") => space::destroy(ipc, base_args, op_matches).await,
                 ("space", "up") => space::up(ipc, base_args, op_matches).await,
                 ("space", "down") => space::down(ipc, base_args, op_matches).await,
                 ("space", "anycast") => space::anycast(ipc, base_args, op_matches).await,
@@ -151,6 +174,9 @@ pub async fn command_filter(
                 ("stream", "sub") => stream::subscribe(ipc, base_args, op_matches).await,
                 ("stream", "unsub") => stream::unsubscribe(ipc, base_args, op_matches).await,
                 ("stream", "resub") => stream::resubscribe(ipc, base_args, op_matches).await,
+                ("stream", "fetch") => stream::fetch(ipc, base_args, op_matches).await,
+                ("stream", "link") => stream::link(ipc, base_args, op_matches).await,
+                ("stream", "unlink") => stream::unlink(ipc, base_args, op_matches).await,
                 _ => unreachable!("oops! looks like the cli library didn't filter this"),
             },
             None => match cmd {
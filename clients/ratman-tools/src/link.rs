@@ -0,0 +1,46 @@
+use crate::{base_args::BaseArgs, encode_list, parse_field, reply_ok};
+use clap::ArgMatches;
+use libratman::{
+    api::{RatmanIpc, RatmanIpcExtV1},
+    tokio::{fs::File, io::AsyncReadExt},
+    types::error::UserError,
+    Result,
+};
+use std::sync::Arc;
+
+pub async fn list(ipc: &Arc<RatmanIpc>, base_args: BaseArgs, _matches: &ArgMatches) -> Result<()> {
+    let links = ipc.link_list().await?;
+    println!("{}", encode_list(links, base_args.out_fmt));
+    Ok(())
+}
+
+pub async fn up(ipc: &Arc<RatmanIpc>, base_args: BaseArgs, matches: &ArgMatches) -> Result<()> {
+    let (_, auth) = base_args.identity_data?;
+
+    let config = match (
+        matches.get_one::<String>("config"),
+        matches.get_one::<String>("file"),
+    ) {
+        (Some(config), _) => config.clone(),
+        (None, Some(path)) => {
+            let mut f = File::open(path).await?;
+            let mut buf = String::new();
+            f.read_to_string(&mut buf).await?;
+            buf
+        }
+        (None, None) => return Err(UserError::MissingInput("config or --file".into()).into()),
+    };
+
+    let started = ipc.link_up(auth, config).await?;
+    println!("{}", encode_list(started, base_args.out_fmt));
+    Ok(())
+}
+
+pub async fn down(ipc: &Arc<RatmanIpc>, base_args: BaseArgs, matches: &ArgMatches) -> Result<()> {
+    let (_, auth) = base_args.identity_data?;
+    let id = parse_field::<u32>(matches, "id")?;
+
+    ipc.link_down(auth, *id).await?;
+    println!("{}", reply_ok(&base_args.out_fmt));
+    Ok(())
+}
@@ -1,7 +1,7 @@
-use crate::{base_args::BaseArgs, encode_list};
+use crate::{base_args::BaseArgs, encode, encode_list, parse_field, reply_ok};
 use clap::ArgMatches;
 use libratman::{
-    api::{RatmanIpc, RatmanIpcExtV1},
+    api::{types::PeerEvent, RatmanIpc, RatmanIpcExtV1},
     Result,
 };
 use std::sync::Arc;
@@ -11,3 +11,43 @@ pub async fn list(ipc: &Arc<RatmanIpc>, base_args: BaseArgs, _matches: &ArgMatch
     println!("{}", encode_list(peers_list, base_args.out_fmt));
     Ok(())
 }
+
+pub async fn add(ipc: &Arc<RatmanIpc>, base_args: BaseArgs, matches: &ArgMatches) -> Result<()> {
+    let (_, auth) = base_args.identity_data?;
+    let peer = parse_field::<String>(matches, "peer")?;
+
+    ipc.peers_add(auth, peer.clone()).await?;
+    println!("{}", reply_ok(&base_args.out_fmt));
+    Ok(())
+}
+
+pub async fn remove(ipc: &Arc<RatmanIpc>, base_args: BaseArgs, matches: &ArgMatches) -> Result<()> {
+    let (_, auth) = base_args.identity_data?;
+    let peer = parse_field::<String>(matches, "peer")?;
+
+    ipc.peers_remove(auth, peer.clone()).await?;
+    println!("{}", reply_ok(&base_args.out_fmt));
+    Ok(())
+}
+
+pub async fn prune(ipc: &Arc<RatmanIpc>, base_args: BaseArgs, matches: &ArgMatches) -> Result<()> {
+    let (_, auth) = base_args.identity_data?;
+    let lost_after = matches.get_one::<u32>("lost-after").copied();
+    let forget_after = matches.get_one::<u32>("forget-after").copied();
+
+    if !matches.get_flag("follow") {
+        let pruned = ipc.peers_prune(auth, lost_after, forget_after).await?;
+        println!("{}", encode_list(pruned, base_args.out_fmt));
+        return Ok(());
+    }
+
+    // Subscribe first, so that the peers pruned right away are printed
+    // via the same events as the ones that are pruned later on
+    let mut events = ipc.peers_subscribe(auth).await?;
+    ipc.peers_prune(auth, lost_after, forget_after).await?;
+    loop {
+        if let PeerEvent::Pruned(pruned) = events.next_event().await? {
+            println!("{}", encode(pruned, base_args.out_fmt));
+        }
+    }
+}
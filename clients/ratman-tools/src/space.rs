@@ -1,4 +1,4 @@
-use crate::{base_args::BaseArgs, encode_map, OutputFormat};
+use crate::{base_args::BaseArgs, encode_list, encode_map, reply_ok, OutputFormat};
 use clap::ArgMatches;
 use libratman::{
     api::{NamespaceAnycastExtV1, RatmanIpc},
@@ -6,11 +6,86 @@ use libratman::{
         fs::File,
         io::{AsyncReadExt, AsyncWriteExt},
     },
-    types::Address,
+    types::{error::UserError, Address, Ident32},
     Result,
 };
 use std::{collections::BTreeMap, sync::Arc, time::Duration};
 
+/// Read a namespace key file created by 'register' or 'export'
+async fn read_key_file(space_file: &String, out_fmt: OutputFormat) -> Result<(Address, Ident32)> {
+    let mut f = File::open(space_file).await?;
+    let mut buf = String::new();
+    f.read_to_string(&mut buf).await?;
+
+    let invalid = || UserError::InvalidInput(space_file.clone(), Some("namespace key file".into()));
+
+    let (pubkey, privkey) = match out_fmt {
+        OutputFormat::Lines => {
+            let mut lines = buf.lines();
+            let mut next_value = || {
+                lines
+                    .next()
+                    .and_then(|line| line.split("=").last())
+                    .map(|value| value.trim().to_string())
+                    .ok_or_else(invalid)
+            };
+
+            (next_value()?, next_value()?)
+        }
+        OutputFormat::Json => {
+            let mut map: BTreeMap<String, String> =
+                serde_json::from_str(buf.as_str()).map_err(|_| invalid())?;
+            (
+                map.remove("pubkey").ok_or_else(invalid)?,
+                map.remove("privkey").ok_or_else(invalid)?,
+            )
+        }
+    };
+
+    Ok((
+        Address::from_string(&pubkey),
+        Ident32::from_string(&privkey),
+    ))
+}
+
+/// Write a namespace key file which can be read by 'import', 'up', etc
+async fn write_key_file(
+    space_file: &String,
+    pubkey: Address,
+    privkey: Ident32,
+    out_fmt: OutputFormat,
+) -> Result<()> {
+    let mut f = File::create(space_file).await?;
+    f.write_all(
+        encode_map(
+            vec![
+                ("pubkey", pubkey.to_string()),
+                ("privkey", privkey.to_string()),
+            ],
+            out_fmt,
+        )
+        .as_bytes(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Get a namespace address either from --space or a key file (-f)
+async fn namespace_addr(matches: &ArgMatches, out_fmt: OutputFormat) -> Result<Address> {
+    match (
+        matches.get_one::<String>("space"),
+        matches.get_one::<String>("file_name"),
+    ) {
+        (Some(space), _) => Ok(Address::from_string(space)),
+        (None, Some(space_file)) => Ok(read_key_file(space_file, out_fmt).await?.0),
+        (None, None) => Err(UserError::MissingInput(
+            "Must provide either --space or a key file (-f)".into(),
+        )
+        .into()),
+    }
+}
+
 pub async fn register(
     ipc: &Arc<RatmanIpc>,
     base_args: BaseArgs,
@@ -21,88 +96,86 @@ pub async fn register(
     let (pubkey, privkey) = libratman::generate_space_key();
 
     ipc.namespace_register(auth, pubkey, privkey).await?;
+    write_key_file(space_file, pubkey, privkey, base_args.out_fmt).await
+}
 
-    let mut f = File::open(space_file).await?;
-    f.write_all(
-        format!(
-            "{}",
-            encode_map(
-                vec![
-                    ("pubkey", pubkey.to_string()),
-                    ("privkey", privkey.to_string())
-                ],
-                base_args.out_fmt
-            )
-        )
-        .as_bytes(),
-    )
-    .await?;
+pub async fn create(ipc: &Arc<RatmanIpc>, base_args: BaseArgs, matches: &ArgMatches) -> Result<()> {
+    let (_, auth) = base_args.identity_data?;
+    let name = matches.get_one::<String>("priv-name");
 
+    let space = ipc.namespace_create(auth, name).await?;
+
+    println!(
+        "{}",
+        encode_map(vec![("space", space.to_string())], base_args.out_fmt)
+    );
     Ok(())
 }
 
-pub async fn up(ipc: &Arc<RatmanIpc>, base_args: BaseArgs, matches: &ArgMatches) -> Result<()> {
-    let (addr, auth) = base_args.identity_data?;
+pub async fn list(ipc: &Arc<RatmanIpc>, base_args: BaseArgs, _matches: &ArgMatches) -> Result<()> {
+    let spaces = ipc.namespace_list().await?;
+    println!("{}", encode_list(spaces, base_args.out_fmt));
+    Ok(())
+}
+
+pub async fn export(ipc: &Arc<RatmanIpc>, base_args: BaseArgs, matches: &ArgMatches) -> Result<()> {
+    let (_, auth) = base_args.identity_data?;
+    let space = Address::from_string(matches.get_one::<String>("space").unwrap());
     let space_file = matches.get_one::<String>("file_name").unwrap();
 
-    let mut f = File::open(space_file).await?;
-    let mut buf = String::new();
-    f.read_to_string(&mut buf).await?;
+    let (pubkey, privkey) = ipc.namespace_export(auth, space).await?;
+    write_key_file(space_file, pubkey, privkey, base_args.out_fmt).await
+}
 
-    let (pubkey, _) = match base_args.out_fmt {
-        OutputFormat::Lines => {
-            let mut lines = buf.lines();
+pub async fn import(ipc: &Arc<RatmanIpc>, base_args: BaseArgs, matches: &ArgMatches) -> Result<()> {
+    let (_, auth) = base_args.identity_data?;
+    let space_file = matches.get_one::<String>("file_name").unwrap();
 
-            let pubkey = lines.next().unwrap().split("=").last().unwrap().to_string();
-            let privkey = lines.next().unwrap().split("=").last().unwrap().to_string();
+    let (pubkey, privkey) = read_key_file(space_file, base_args.out_fmt).await?;
+    ipc.namespace_register(auth, pubkey, privkey).await?;
 
-            (
-                Address::from_string(&pubkey),
-                Address::from_string(&privkey),
-            )
-        }
-        OutputFormat::Json => {
-            let mut map: BTreeMap<String, String> = serde_json::from_str(buf.as_str()).unwrap();
-            (
-                Address::from_string(&map.remove("pubkey").unwrap()),
-                Address::from_string(&map.remove("privkey").unwrap()),
-            )
-        }
-    };
+    println!("{}", reply_ok(&base_args.out_fmt));
+    Ok(())
+}
 
-    ipc.namespace_up(addr, auth, pubkey).await?;
+pub async fn rotate(ipc: &Arc<RatmanIpc>, base_args: BaseArgs, matches: &ArgMatches) -> Result<()> {
+    let (_, auth) = base_args.identity_data?;
+    let space = Address::from_string(matches.get_one::<String>("space").unwrap());
 
+    let new_space = ipc.namespace_rotate(auth, space).await?;
+
+    println!(
+        "{}",
+        encode_map(vec![("space", new_space.to_string())], base_args.out_fmt)
+    );
     Ok(())
 }
 
-pub async fn down(ipc: &Arc<RatmanIpc>, base_args: BaseArgs, matches: &ArgMatches) -> Result<()> {
-    let (addr, auth) = base_args.identity_data?;
-    let space_file = matches.get_one::<String>("file_name").unwrap();
+pub async fn destroy(
+    ipc: &Arc<RatmanIpc>,
+    base_args: BaseArgs,
+    matches: &ArgMatches,
+) -> Result<()> {
+    let (_, auth) = base_args.identity_data?;
+    let space = Address::from_string(matches.get_one::<String>("space").unwrap());
 
-    let mut f = File::open(space_file).await?;
-    let mut buf = String::new();
-    f.read_to_string(&mut buf).await?;
+    ipc.namespace_destroy(auth, space).await?;
 
-    let (pubkey, _) = match base_args.out_fmt {
-        OutputFormat::Lines => {
-            let mut lines = buf.lines();
+    println!("{}", reply_ok(&base_args.out_fmt));
+    Ok(())
+}
 
-            let pubkey = lines.next().unwrap().split("=").last().unwrap().to_string();
-            let privkey = lines.next().unwrap().split("=").last().unwrap().to_string();
+pub async fn up(ipc: &Arc<RatmanIpc>, base_args: BaseArgs, matches: &ArgMatches) -> Result<()> {
+    let (addr, auth) = base_args.identity_data?;
+    let pubkey = namespace_addr(matches, base_args.out_fmt).await?;
 
-            (
-                Address::from_string(&pubkey),
-                Address::from_string(&privkey),
-            )
-        }
-        OutputFormat::Json => {
-            let mut map: BTreeMap<String, String> = serde_json::from_str(buf.as_str()).unwrap();
-            (
-                Address::from_string(&map.remove("pubkey").unwrap()),
-                Address::from_string(&map.remove("privkey").unwrap()),
-            )
-        }
-    };
+    ipc.namespace_up(addr, auth, pubkey).await?;
+    Ok(())
+}
+
+pub async fn down(ipc: &Arc<RatmanIpc>, base_args: BaseArgs, matches: &ArgMatches) -> Result<()> {
+    let (addr, auth) = base_args.identity_data?;
+    let pubkey = namespace_addr(matches, base_args.out_fmt).await?;
 
     ipc.namespace_down(addr, auth, pubkey).await?;
     Ok(())
@@ -114,33 +187,8 @@ pub async fn anycast(
     matches: &ArgMatches,
 ) -> Result<()> {
     let (addr, auth) = base_args.identity_data?;
-    let space_file = matches.get_one::<String>("file_name").unwrap();
     let timeout = matches.get_one::<u64>("timeout").unwrap();
-
-    let mut f = File::open(space_file).await?;
-    let mut buf = String::new();
-    f.read_to_string(&mut buf).await?;
-
-    let (pubkey, _) = match base_args.out_fmt {
-        OutputFormat::Lines => {
-            let mut lines = buf.lines();
-
-            let pubkey = lines.next().unwrap().split("=").last().unwrap().to_string();
-            let privkey = lines.next().unwrap().split("=").last().unwrap().to_string();
-
-            (
-                Address::from_string(&pubkey),
-                Address::from_string(&privkey),
-            )
-        }
-        OutputFormat::Json => {
-            let mut map: BTreeMap<String, String> = serde_json::from_str(buf.as_str()).unwrap();
-            (
-                Address::from_string(&map.remove("pubkey").unwrap()),
-                Address::from_string(&map.remove("privkey").unwrap()),
-            )
-        }
-    };
+    let pubkey = namespace_addr(matches, base_args.out_fmt).await?;
 
     let addrs = ipc
         .namespace_anycast_probe(addr, auth, pubkey, Duration::from_millis(*timeout))
@@ -1,11 +1,15 @@
 use crate::{base_args::BaseArgs, encode_list, encode_map, parse_ident32, reply_ok};
 use clap::ArgMatches;
 use libratman::{
-    api::{RatmanIpc, RatmanIpcExtV1},
-    types::{error::UserError, Address, Recipient},
-    Result,
+    api::{RatmanIpc, RatmanIpcExtV1, RatmanStreamExtV1},
+    tokio::{
+        self,
+        io::{AsyncReadExt, AsyncWriteExt},
+    },
+    types::{error::UserError, Address, Ident32, Recipient},
+    ReadCapability, Result,
 };
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 pub async fn subscribe(
     ipc: &Arc<RatmanIpc>,
@@ -88,3 +92,56 @@ pub async fn list(ipc: &Arc<RatmanIpc>, base_args: BaseArgs, _matches: &ArgMatch
     println!("{}", encode_list(available_subs, base_args.out_fmt));
     Ok(())
 }
+
+pub async fn fetch(ipc: &Arc<RatmanIpc>, base_args: BaseArgs, matches: &ArgMatches) -> Result<()> {
+    let (addr, auth) = base_args.identity_data?;
+    let urn = matches.get_one::<String>("read_cap").unwrap();
+    let read_cap = ReadCapability::from_urn(urn).ok_or_else(|| {
+        UserError::InvalidInput(urn.clone(), Some("urn:erisx2:<read capability>".into()))
+    })?;
+    let timeout = Duration::from_secs(*matches.get_one::<u64>("timeout").unwrap());
+
+    let (length, mut stream) = ipc.fetch_stream(auth, addr, read_cap, timeout).await?;
+
+    let mut stdout = tokio::io::stdout();
+    tokio::io::copy(&mut stream.as_reader().take(length), &mut stdout).await?;
+    stdout.flush().await?;
+    stream.drop().await
+}
+
+/// Parse a stream ID in its printed (hex) form
+fn parse_stream_id(id: &str) -> Result<Ident32> {
+    Ident32::try_from(id).map_err(|_| {
+        UserError::InvalidInput(id.to_string(), Some("a hex encoded stream ID".into())).into()
+    })
+}
+
+pub async fn link(ipc: &Arc<RatmanIpc>, base_args: BaseArgs, matches: &ArgMatches) -> Result<()> {
+    let (addr, auth) = base_args.identity_data?;
+    let stream_id = parse_stream_id(matches.get_one::<String>("stream_id").unwrap())?;
+    let streams = matches
+        .get_many::<String>("with")
+        .into_iter()
+        .flatten()
+        .map(|id| parse_stream_id(id))
+        .collect::<Result<Vec<_>>>()?;
+    let tags = matches
+        .get_many::<String>("tag")
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect();
+
+    ipc.stream_link(auth, addr, stream_id, streams, tags)
+        .await?;
+    println!("{}", reply_ok(&base_args.out_fmt));
+    Ok(())
+}
+
+pub async fn unlink(ipc: &Arc<RatmanIpc>, base_args: BaseArgs, matches: &ArgMatches) -> Result<()> {
+    let (addr, auth) = base_args.identity_data?;
+    let stream_id = parse_stream_id(matches.get_one::<String>("stream_id").unwrap())?;
+    ipc.stream_unlink(auth, addr, stream_id).await?;
+    println!("{}", reply_ok(&base_args.out_fmt));
+    Ok(())
+}
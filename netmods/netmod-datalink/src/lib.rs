@@ -8,6 +8,9 @@
 extern crate tracing;
 
 mod socket;
+
+/// Largest frame that fits into a single ethernet frame
+const MAX_FRAME_SIZE: usize = 1500;
 use std::{collections::HashMap, convert::TryInto, time::Duration};
 
 use libratman::{
@@ -20,11 +23,12 @@ pub(crate) use socket::Socket;
 
 use useful_netmod_bits::addrs::AddrTable;
 use useful_netmod_bits::framing::Envelope;
+use useful_netmod_bits::status::StatusTracker;
 
 use async_trait::async_trait;
 use libratman::{
     endpoint::EndpointExt,
-    types::{InMemoryEnvelope, Neighbour},
+    types::{CurrentStatus, InMemoryEnvelope, Neighbour},
     RatmanError, Result,
 };
 use pnet::util::MacAddr;
@@ -47,6 +51,7 @@ use zbus_nm::{
 pub struct Endpoint {
     socket: Arc<Socket>,
     addrs: Arc<AddrTable<MacAddr>>,
+    status: Arc<StatusTracker>,
     //Our network connection will terminate when this closes.
     #[allow(dead_code)]
     nmconn: Arc<Connection>,
@@ -273,6 +278,7 @@ impl Endpoint {
                 .await
                 .unwrap(),
             addrs,
+            status: Arc::default(),
             nmconn,
         })
     }
@@ -285,6 +291,22 @@ impl Endpoint {
 
 #[async_trait]
 impl EndpointExt for Endpoint {
+    fn size_hint(&self) -> usize {
+        MAX_FRAME_SIZE
+    }
+
+    async fn status(&self) -> CurrentStatus {
+        let peers = self.addrs.all().await.len() as u32;
+        self.status.snapshot(peers)
+    }
+
+    /// Stop receiving frames and close the ethernet channel
+    async fn shutdown(&self) -> Result<()> {
+        self.socket.shutdown().await;
+        self.status.set_down();
+        Ok(())
+    }
+
     async fn send(
         &self,
         InMemoryEnvelope { mut buffer, header }: InMemoryEnvelope,
@@ -295,10 +317,11 @@ impl EndpointExt for Endpoint {
         header.generate(&mut full_buffer)?;
         full_buffer.append(&mut buffer);
 
-        if full_buffer.len() > 1500 {
+        if full_buffer.len() > MAX_FRAME_SIZE {
             return Err(RatmanError::Netmod(NetmodError::FrameTooLarge));
         }
 
+        self.status.sent(full_buffer.len());
         let env = Envelope::Data(full_buffer);
 
         match neighbour {
@@ -334,6 +357,7 @@ impl EndpointExt for Endpoint {
 
     async fn next(&self) -> Result<(InMemoryEnvelope, Neighbour)> {
         let fe = self.socket.next().await;
+        self.status.received(fe.0.buffer.len());
         Ok((fe.0, fe.1))
     }
 }
@@ -10,7 +10,7 @@ use libratman::{
     futures::future,
     tokio::{
         sync::{Mutex, RwLock},
-        task::spawn,
+        task::{spawn, JoinHandle},
     },
     types::{Ident32, InMemoryEnvelope, Neighbour},
 };
@@ -20,10 +20,10 @@ use pnet::{
     packet::Packet,
     util::MacAddr,
 };
-use pnet_datalink::{channel, Channel, DataLinkReceiver, DataLinkSender, NetworkInterface};
-use std::sync::Arc;
+use pnet_datalink::{channel, Channel, Config, DataLinkReceiver, DataLinkSender, NetworkInterface};
 use std::{collections::VecDeque, pin::Pin, task::Poll};
 use std::{error::Error, future::Future};
+use std::{io::ErrorKind, sync::Arc, time::Duration};
 use task_notify::Notify;
 use useful_netmod_bits::addrs::AddrTable;
 use useful_netmod_bits::framing::{Envelope, FrameExt};
@@ -32,13 +32,19 @@ use useful_netmod_bits::framing::{Envelope, FrameExt};
 pub(crate) struct Socket {
     iface: NetworkInterface,
     self_rk_id: Ident32,
-    tx: Arc<Mutex<Box<dyn DataLinkSender>>>,
-    rx: Arc<Mutex<Box<dyn DataLinkReceiver>>>,
+    /// Both sides are taken when the socket is shut down
+    tx: Arc<Mutex<Option<Box<dyn DataLinkSender>>>>,
+    rx: Arc<Mutex<Option<Box<dyn DataLinkReceiver>>>>,
+    /// Task receiving incoming packets, until the socket is shut down
+    incoming: Mutex<Option<JoinHandle<()>>>,
     inbox: Arc<RwLock<Notify<VecDeque<FrameExt>>>>,
 }
 
 const CUSTOM_ETHERTYPE: EtherType = EtherType(0xDE57);
 
+/// How long a read may block before the receive task checks for shutdown
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
 impl Socket {
     /// Create a new socket handler and return a management reference
     pub(crate) async fn new(
@@ -46,7 +52,11 @@ impl Socket {
         table: Arc<AddrTable<MacAddr>>,
         self_rk_id: Ident32,
     ) -> Result<Arc<Self>, Box<dyn Error>> {
-        let (tx, rx) = match channel(&iface, Default::default()) {
+        let config = Config {
+            read_timeout: Some(READ_TIMEOUT),
+            ..Default::default()
+        };
+        let (tx, rx) = match channel(&iface, config) {
             Ok(Channel::Ethernet(tx, rx)) => (tx, rx),
             Ok(_) => Err("Invalid channel type")?,
             Err(e) => Err(e)?,
@@ -55,12 +65,14 @@ impl Socket {
         let arc = Arc::new(Self {
             iface,
             self_rk_id,
-            tx: Arc::new(Mutex::new(tx)),
-            rx: Arc::new(Mutex::new(rx)),
+            tx: Arc::new(Mutex::new(Some(tx))),
+            rx: Arc::new(Mutex::new(Some(rx))),
+            incoming: Default::default(),
             inbox: Default::default(),
         });
 
-        Self::incoming_handle(Arc::clone(&arc), table);
+        let incoming = Self::incoming_handle(Arc::clone(&arc), table);
+        *arc.incoming.lock().await = Some(incoming);
         arc.multicast(&Envelope::Announce(arc.self_rk_id)).await;
         info!("Sent multicast announcement");
         Ok(arc)
@@ -78,6 +90,10 @@ impl Socket {
 
     pub(crate) async fn send_multiple(&self, env: &Envelope, peers: &Vec<MacAddr>) {
         let mut tx = self.tx.lock().await;
+        let tx = match tx.as_mut() {
+            Some(tx) => tx,
+            None => return,
+        };
 
         let payload = env.as_bytes();
         let packet_size = payload.len() + EthernetPacket::minimum_packet_size();
@@ -98,6 +114,10 @@ impl Socket {
 
     async fn send_inner(&self, env: &Envelope, peer: MacAddr) {
         let mut tx = self.tx.lock().await;
+        let tx = match tx.as_mut() {
+            Some(tx) => tx,
+            None => return,
+        };
 
         let payload = env.as_bytes();
         let packet_size = payload.len() + EthernetPacket::minimum_packet_size();
@@ -112,6 +132,17 @@ impl Socket {
         });
     }
 
+    /// Stop receiving packets and close the ethernet channel
+    pub(crate) async fn shutdown(&self) {
+        if let Some(incoming) = self.incoming.lock().await.take() {
+            incoming.abort();
+            let _ = incoming.await;
+        }
+
+        self.rx.lock().await.take();
+        self.tx.lock().await.take();
+    }
+
     pub(crate) async fn next(&self) -> FrameExt {
         future::poll_fn(|ctx| {
             let lock = &mut self.inbox.write();
@@ -131,10 +162,14 @@ impl Socket {
     }
 
     #[instrument(skip(arc, table), level = "trace")]
-    fn incoming_handle(arc: Arc<Self>, table: Arc<AddrTable<MacAddr>>) {
+    fn incoming_handle(arc: Arc<Self>, table: Arc<AddrTable<MacAddr>>) -> JoinHandle<()> {
         spawn(async move {
             loop {
                 let mut rx = arc.rx.lock().await;
+                let rx = match rx.as_mut() {
+                    Some(rx) => rx,
+                    None => break,
+                };
 
                 match rx.next() {
                     Ok(packet) => {
@@ -181,6 +216,10 @@ impl Socket {
                             }
                         }
                     }
+                    // Give shutdown a chance to stop this task
+                    Err(error) if error.kind() == ErrorKind::TimedOut => {
+                        libratman::tokio::task::yield_now().await;
+                    }
                     Err(error) => {
                         //NOTE: See issue #86442. Nix hopefully won't be necessary for most of this
                         //in the future :D
@@ -196,6 +235,32 @@ impl Socket {
                     }
                 }
             }
-        });
+        })
     }
 }
+
+/// Opening an ethernet channel requires `CAP_NET_RAW`
+#[ignore]
+#[test]
+fn shutdown_stops_receiving() {
+    use libratman::rt::AsyncSystem;
+    let system = AsyncSystem::new("shutdown-stops-receiving".into(), 1);
+    system.exec(async {
+        let lo = pnet_datalink::interfaces()
+            .into_iter()
+            .find(|iface| iface.is_loopback())
+            .unwrap();
+
+        let sock = Socket::new(lo.clone(), Arc::new(AddrTable::new()), Ident32::random())
+            .await
+            .unwrap();
+        sock.shutdown().await;
+        assert!(sock.rx.lock().await.is_none());
+
+        // Sending after shutdown is ignored
+        sock.multicast(&Envelope::Announce(Ident32::random())).await;
+        Socket::new(lo, Arc::new(AddrTable::new()), Ident32::random())
+            .await
+            .unwrap();
+    })
+}
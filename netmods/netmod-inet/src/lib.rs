@@ -6,13 +6,18 @@
 #[macro_use]
 extern crate tracing;
 
+mod noise;
 mod peer;
+mod policy;
 mod proto;
 mod resolve;
 mod routes;
 mod server;
 mod session;
 
+pub use policy::PeerPolicy;
+
+use noise::RouterKey;
 use peer::{FrameReceiver, FrameSender};
 use routes::Routes;
 use session::{setup_cleanuptask, start_connection, SessionData};
@@ -23,13 +28,13 @@ use libratman::{
     endpoint::{EndpointExt, NeighbourMetrics},
     tokio::{
         sync::{mpsc::channel, Mutex},
-        task::spawn,
+        task::{spawn, JoinHandle},
     },
-    types::{Ident32, InMemoryEnvelope, Neighbour},
+    types::{CurrentStatus, Ident32, InMemoryEnvelope, LinkState, Neighbour},
     NetmodError, RatmanError, Result,
 };
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::{convert::TryFrom, net::SocketAddr, sync::Arc};
 
 /// The type of session being created
 #[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -49,38 +54,62 @@ pub(crate) enum Direction {
 }
 
 /// Internet overlay endpoint for Ratman
+///
+/// Sessions with peers are encrypted, and peers authenticate each
+/// other with their router keys.  Peers are identified by the public
+/// part of their key.  Which peers may connect is decided by a
+/// [`PeerPolicy`].
 pub struct InetEndpoint {
-    port: u16,
-    self_router_key_id: Ident32,
+    bind: SocketAddr,
+    self_router_key: RouterKey,
     routes: Arc<Routes>,
     channel: (FrameSender, Mutex<FrameReceiver>),
+    /// Task accepting incoming connections, until the endpoint is shut down
+    accept: Mutex<Option<JoinHandle<()>>>,
 }
 
 impl InetEndpoint {
     /// Start a basic inet endpoint on a particular bind address
-    pub async fn start(bind: &str, self_router_key_id: Ident32) -> Result<Arc<Self>> {
-        let server = Server::bind(bind, self_router_key_id).await?;
+    ///
+    /// `router_key` is the private key this router authenticates
+    /// itself with, and must be kept secret.  Incoming peers are
+    /// checked against `policy`.
+    pub async fn start(bind: &str, router_key: Ident32, policy: PeerPolicy) -> Result<Arc<Self>> {
+        let self_router_key = RouterKey::new(router_key);
+        let server = Server::bind(bind, self_router_key, policy).await?;
         let routes = Routes::new();
         let channel = channel(64); // TODO: constraint the channel?
-        let port = server.port(); // we don't store the server
+        let bind = server.local_addr(); // we don't store the server
 
         // Accept connections and spawn associated peers
-        {
+        let accept = {
             let sender = channel.0.clone();
-            spawn(server.run(sender, Arc::clone(&routes)));
-        }
+            spawn(server.run(sender, Arc::clone(&routes)))
+        };
+
+        info!(
+            "Listening for inet peers on {} with key {}",
+            bind,
+            self_router_key.public()
+        );
 
         Ok(Arc::new(Self {
-            port,
-            self_router_key_id,
+            bind,
+            self_router_key,
             routes,
             channel: (channel.0, Mutex::new(channel.1)),
+            accept: Mutex::new(Some(accept)),
         }))
     }
 
     /// Get the listening port for this server
     pub fn port(&self) -> u16 {
-        self.port
+        self.bind.port()
+    }
+
+    /// Get the public key that peers identify this endpoint by
+    pub fn public_key(&self) -> Ident32 {
+        self.self_router_key.public()
     }
 
     /// Insert a set of peers into the routing table
@@ -88,12 +117,14 @@ impl InetEndpoint {
     /// Each peer will spawn a worker that periodically attempts to
     /// connect to it.  At the moment all connections are "Standard"
     /// connections as outlined in the user manual.
+    ///
+    /// A peer line can pin the router id of the peer
+    /// (`<router id>@<host>:<port>`).  Otherwise the key presented
+    /// during the first handshake is trusted.
     async fn add_peer(&self, p: String) -> Result<u16> {
-        if p == "" {
-            return Err(RatmanError::Netmod(NetmodError::InvalidPeer(p.into())));
-        }
+        let (key, host) = parse_peer(&p)?;
 
-        let peer = match Resolver::resolve(&p) {
+        let peer = match Resolver::resolve(host) {
             Some(p) => p,
             None => {
                 warn!("Failed to parse peer: '{}'... skipping", p);
@@ -105,8 +136,8 @@ impl InetEndpoint {
         let id = self.routes.next_target();
         let session_data = SessionData {
             id,
-            self_router_key_id: self.self_router_key_id,
-            peer_router_key_id: Ident32::uninit(),
+            self_router_key: self.self_router_key,
+            peer_router_key_id: key.unwrap_or_else(Ident32::uninit),
             tt: PeerType::Standard,
             addr: peer,
             self_port: 0, // not used
@@ -114,6 +145,7 @@ impl InetEndpoint {
 
         let routes = Arc::clone(&self.routes);
         let sender = self.channel.0.clone();
+        routes.add_peering(id, host.into(), peer, key).await;
         match start_connection(session_data, Arc::clone(&routes), sender.clone()).await {
             Ok(rx) => setup_cleanuptask(rx, sender, &routes).await,
             Err(e) => {
@@ -132,12 +164,13 @@ impl InetEndpoint {
     /// something for it.
     ///
     pub async fn send_one(&self, target: Ident32, envelope: InMemoryEnvelope) -> Result<()> {
-        let valid = self.routes.exists(target).await;
-        if valid {
-            trace!("Target {} exists {}", target, valid);
-            let peer = self.routes.get_peer_by_id(target).await.unwrap();
+        // The peer may disappear between checking and sending, so we
+        // only look it up once
+        if let Some(peer) = self.routes.get_peer_by_id(target).await {
+            trace!("Target {} exists", target);
             match peer.send(&envelope).await {
                 Ok(bytes_written) => {
+                    self.routes.status.sent(bytes_written);
                     let metrics = Arc::clone(&self.routes.metrics);
                     let peer_addr = peer.session.addr;
                     spawn(async move {
@@ -146,11 +179,10 @@ impl InetEndpoint {
                 }
 
                 // In case the connection was dropped, we remove the peer from the routing table
-                Err(_) => {
-                    let peer = self.routes.remove_peer(target).await;
-                    if let Err(e) = peer.send(&envelope).await {
-                        error!("failed to send frame to peer {}: {}", peer.id(), e);
-                    }
+                Err(e) => {
+                    error!("failed to send frame to peer {}: {}", peer.id(), e);
+                    self.routes.status.error(e);
+                    self.routes.remove_peer(target).await;
                 }
             };
         } else {
@@ -174,6 +206,7 @@ impl InetEndpoint {
 
             match peer.send(&envelope).await {
                 Ok(bytes_written) => {
+                    self.routes.status.sent(bytes_written);
                     let metrics = Arc::clone(&self.routes.metrics);
                     let peer_addr = peer.session.addr;
                     spawn(async move {
@@ -182,6 +215,7 @@ impl InetEndpoint {
                 }
                 Err(e) => {
                     error!("failed to send frame to peer {}: {}", peer.id(), e);
+                    self.routes.status.error(e);
                 }
             }
         }
@@ -193,16 +227,79 @@ impl InetEndpoint {
     // TODO: properly map error here
     pub async fn next(&self) -> Option<(Ident32, InMemoryEnvelope)> {
         let mut r = self.channel.1.lock().await;
-        r.recv().await
+        let (target, envelope) = r.recv().await?;
+        self.routes.status.received(envelope.buffer.len());
+        Some((target, envelope))
+    }
+}
+
+/// Split a peer line into the pinned router id (if any) and the host
+fn parse_peer(p: &str) -> Result<(Option<Ident32>, &str)> {
+    let invalid = || RatmanError::Netmod(NetmodError::InvalidPeer(p.into()));
+    match p.split_once('@') {
+        _ if p.is_empty() => Err(invalid()),
+        Some((key, host)) => Ok((Some(Ident32::try_from(key).map_err(|_| invalid())?), host)),
+        None => Ok((None, p)),
     }
 }
 
 #[async_trait::async_trait]
 impl EndpointExt for InetEndpoint {
+    /// Endpoints are identified by the address they listen on
+    fn identifier(&self) -> String {
+        self.bind.to_string()
+    }
+
+    /// Frames are length-prefixed on a TCP stream, so any size goes
+    fn size_hint(&self) -> usize {
+        0
+    }
+
+    /// Peerings which are currently not connected degrade the status
+    async fn status(&self) -> CurrentStatus {
+        let peers = self.routes.inner.read().await.len() as u32;
+        let mut status = self.routes.status.snapshot(peers);
+        if status.state == LinkState::Up && self.routes.missing_peerings().await > 0 {
+            status.state = LinkState::Degraded;
+        }
+        status
+    }
+
     async fn start_peering(&self, addr: &str) -> Result<u16> {
         self.add_peer(addr.to_owned()).await
     }
 
+    async fn stop_peering(&self, id: u16) -> Result<()> {
+        if !self.routes.remove_peering(id).await {
+            return Err(RatmanError::Netmod(NetmodError::InvalidPeer(format!(
+                "unknown peer id {id}"
+            ))));
+        }
+
+        if let Some(peer) = self.routes.remove_target(id).await {
+            info!("Disconnecting from peer {}", peer.session.addr);
+            peer.close().await;
+        }
+
+        Ok(())
+    }
+
+    /// Stop accepting connections and close all existing peers
+    async fn shutdown(&self) -> Result<()> {
+        // Wait for the task to be dropped, which releases the listening socket
+        if let Some(accept) = self.accept.lock().await.take() {
+            accept.abort();
+            let _ = accept.await;
+        }
+
+        for (peer, _) in self.routes.get_all_valid().await {
+            peer.close().await;
+        }
+
+        self.routes.status.set_down();
+        Ok(())
+    }
+
     async fn metrics_for_neighbour(&self, neighbour: Neighbour) -> Result<NeighbourMetrics> {
         match neighbour {
             Neighbour::Single(id) => {
@@ -244,7 +341,9 @@ impl EndpointExt for InetEndpoint {
         match target {
             Neighbour::Single(target) => self.send_one(target, envelope).await?,
             Neighbour::Flood => self.send_all(envelope, exclude).await?,
-            _ => todo!(),
+            Neighbour::FloodExcept(except) => self.send_all(envelope, Some(except)).await?,
+            // Dropped envelopes are filtered by the router already
+            Neighbour::Drop => {}
         }
 
         Ok(())
@@ -263,6 +362,297 @@ impl EndpointExt for InetEndpoint {
     }
 }
 
+#[test]
+fn shutdown_releases_port() {
+    use libratman::rt::AsyncSystem;
+    let system = AsyncSystem::new("shutdown-releases-port".into(), 1);
+    system
+        .exec(async {
+            let inet =
+                InetEndpoint::start("[::1]:0", Ident32::random(), PeerPolicy::open()).await?;
+            let bind = format!("[::1]:{}", inet.port());
+            assert_eq!(inet.identifier(), bind);
+            inet.shutdown().await?;
+            assert_eq!(inet.status().await.state, LinkState::Down);
+
+            InetEndpoint::start(&bind, Ident32::random(), PeerPolicy::open()).await?;
+            Ok::<_, RatmanError>(())
+        })
+        .unwrap()
+}
+
+#[test]
+fn stop_peering() {
+    use libratman::{rt::AsyncSystem, tokio::time::sleep};
+    use std::time::Duration;
+
+    let system = AsyncSystem::new("stop-peering".into(), 2);
+    system
+        .exec(async {
+            let server =
+                InetEndpoint::start("[::1]:0", Ident32::random(), PeerPolicy::open()).await?;
+            let client =
+                InetEndpoint::start("[::1]:0", Ident32::random(), PeerPolicy::open()).await?;
+
+            let id = client
+                .start_peering(&format!("[::1]:{}", server.port()))
+                .await?;
+            sleep(Duration::from_millis(250)).await;
+            assert_eq!(client.routes.get_all_valid().await.len(), 1);
+
+            client.stop_peering(id).await?;
+            assert!(client.routes.get_all_valid().await.is_empty());
+            assert!(client.stop_peering(id).await.is_err());
+            Ok::<_, RatmanError>(())
+        })
+        .unwrap()
+}
+
+#[test]
+fn reject_unknown_peers() {
+    use libratman::{rt::AsyncSystem, tokio::time::sleep};
+    use std::time::Duration;
+
+    let system = AsyncSystem::new("reject-unknown-peers".into(), 2);
+    system
+        .exec(async {
+            let client =
+                InetEndpoint::start("[::1]:0", Ident32::random(), PeerPolicy::open()).await?;
+            let server =
+                InetEndpoint::start("[::1]:0", Ident32::random(), PeerPolicy::default()).await?;
+
+            client
+                .start_peering(&format!("[::1]:{}", server.port()))
+                .await?;
+            sleep(Duration::from_millis(250)).await;
+            assert!(server.routes.get_all_valid().await.is_empty());
+            assert_eq!(server.status().await.rejected, 1);
+            assert!(client.routes.get_all_valid().await.is_empty());
+
+            // Allowed keys may connect, even if they are unknown
+            let allowed = PeerPolicy {
+                allow: [client.public_key()].into(),
+                ..Default::default()
+            };
+            let server = InetEndpoint::start("[::1]:0", Ident32::random(), allowed).await?;
+            client
+                .start_peering(&format!("[::1]:{}", server.port()))
+                .await?;
+            sleep(Duration::from_millis(250)).await;
+            assert_eq!(server.routes.get_all_valid().await.len(), 1);
+            assert_eq!(server.status().await.rejected, 0);
+            Ok::<_, RatmanError>(())
+        })
+        .unwrap()
+}
+
+#[test]
+fn pinned_peer_key() {
+    use crate::routes::SessionState;
+    use libratman::{
+        rt::AsyncSystem,
+        tokio::time::{sleep, Instant},
+    };
+    use std::time::Duration;
+
+    let system = AsyncSystem::new("pinned-peer-key".into(), 2);
+    system
+        .exec(async {
+            let server =
+                InetEndpoint::start("[::1]:0", Ident32::random(), PeerPolicy::open()).await?;
+            let client =
+                InetEndpoint::start("[::1]:0", Ident32::random(), PeerPolicy::open()).await?;
+            let host = format!("[::1]:{}", server.port());
+
+            // A peer presenting a different key is never added
+            client
+                .start_peering(&format!("{}@{}", Ident32::random(), host))
+                .await?;
+            let started = Instant::now();
+            while client.status().await.last_error.is_none() {
+                assert!(started.elapsed() < Duration::from_secs(15));
+                sleep(Duration::from_millis(50)).await;
+            }
+            assert!(client
+                .status()
+                .await
+                .last_error
+                .unwrap()
+                .contains("expected key"));
+            assert!(client.routes.get_all_valid().await.is_empty());
+
+            let id = client
+                .start_peering(&format!("{}@{}", server.public_key(), host))
+                .await?;
+            while client.routes.peering_state(id).await != Some(SessionState::Connected) {
+                assert!(started.elapsed() < Duration::from_secs(15));
+                sleep(Duration::from_millis(50)).await;
+            }
+            assert_eq!(client.routes.get_all_valid().await.len(), 1);
+
+            assert!(client.start_peering("not-a-key@[::1]:5860").await.is_err());
+            Ok::<_, RatmanError>(())
+        })
+        .unwrap()
+}
+
+#[test]
+fn unpinned_peer_key() {
+    use crate::routes::SessionState;
+    use libratman::{
+        rt::AsyncSystem,
+        tokio::time::{sleep, Instant},
+    };
+    use std::time::Duration;
+
+    let system = AsyncSystem::new("unpinned-peer-key".into(), 2);
+    system
+        .exec(async {
+            let server =
+                InetEndpoint::start("[::1]:0", Ident32::random(), PeerPolicy::open()).await?;
+            let client =
+                InetEndpoint::start("[::1]:0", Ident32::random(), PeerPolicy::open()).await?;
+
+            let id = client
+                .start_peering(&format!("[::1]:{}", server.port()))
+                .await?;
+            let started = Instant::now();
+            while client.routes.peering_state(id).await != Some(SessionState::Connected) {
+                assert!(started.elapsed() < Duration::from_secs(15));
+                sleep(Duration::from_millis(50)).await;
+            }
+
+            // Frames are reported with the key the server presented
+            let data = InMemoryEnvelope::test_envelope();
+            server
+                .send(data.clone(), Neighbour::Single(client.public_key()), None)
+                .await?;
+            let (received, from) = EndpointExt::next(&*client).await?;
+            assert_eq!(received, data);
+            assert_eq!(from, Neighbour::Single(server.public_key()));
+            Ok::<_, RatmanError>(())
+        })
+        .unwrap()
+}
+
+#[test]
+fn reconnect_with_backoff() {
+    use crate::routes::SessionState;
+    use libratman::{
+        rt::AsyncSystem,
+        tokio::{
+            net::TcpListener,
+            time::{sleep, Instant},
+        },
+    };
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    let system = AsyncSystem::new("reconnect-with-backoff".into(), 2);
+    system
+        .exec(async {
+            // A listener which drops every connection before the handshake
+            let listener = TcpListener::bind("[::]:0").await?;
+            let port = listener.local_addr()?.port();
+            let dropped = Arc::new(AtomicUsize::new(0));
+            let accept = {
+                let dropped = Arc::clone(&dropped);
+                spawn(async move {
+                    while let Ok((stream, _)) = listener.accept().await {
+                        dropped.fetch_add(1, Ordering::SeqCst);
+                        drop(stream);
+                    }
+                })
+            };
+
+            // Peer lines are resolved again for every attempt
+            let client =
+                InetEndpoint::start("[::1]:0", Ident32::random(), PeerPolicy::open()).await?;
+            let id = client.start_peering(&format!("localhost:{}", port)).await?;
+            sleep(Duration::from_millis(2500)).await;
+
+            // Attempts happen after ~0s, 0.5-1s, and 1.5-3s
+            let attempts = dropped.load(Ordering::SeqCst);
+            assert!((2..=3).contains(&attempts), "{} attempts", attempts);
+            assert!(matches!(
+                client.routes.peering_state(id).await,
+                Some(SessionState::Waiting { .. } | SessionState::Connecting { .. })
+            ));
+            let status = client.status().await;
+            assert_eq!(status.state, LinkState::Degraded);
+            assert!(status.last_error.unwrap().contains("retrying"));
+
+            // Once a router listens on the port the session is established
+            accept.abort();
+            let _ = accept.await;
+            let server = InetEndpoint::start(
+                &format!("[::]:{}", port),
+                Ident32::random(),
+                PeerPolicy::open(),
+            )
+            .await?;
+
+            let started = Instant::now();
+            while client.routes.peering_state(id).await != Some(SessionState::Connected) {
+                assert!(started.elapsed() < Duration::from_secs(15));
+                sleep(Duration::from_millis(100)).await;
+            }
+            assert_eq!(client.routes.get_all_valid().await.len(), 1);
+            assert_eq!(server.routes.get_all_valid().await.len(), 1);
+            Ok::<_, RatmanError>(())
+        })
+        .unwrap()
+}
+
+#[test]
+fn reconnect_after_server_restart() {
+    use crate::routes::SessionState;
+    use libratman::{
+        rt::AsyncSystem,
+        tokio::time::{sleep, Instant},
+    };
+    use std::time::Duration;
+
+    async fn wait_for(client: &InetEndpoint, id: u16, connected: bool) {
+        let started = Instant::now();
+        while (client.routes.peering_state(id).await == Some(SessionState::Connected)) != connected
+        {
+            assert!(started.elapsed() < Duration::from_secs(15));
+            sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    let system = AsyncSystem::new("reconnect-after-server-restart".into(), 2);
+    system
+        .exec(async {
+            let server_key = Ident32::random();
+            let server = InetEndpoint::start("[::1]:0", server_key, PeerPolicy::open()).await?;
+            let bind = format!("[::1]:{}", server.port());
+
+            let client =
+                InetEndpoint::start("[::1]:0", Ident32::random(), PeerPolicy::open()).await?;
+            let id = client.start_peering(&bind).await?;
+            wait_for(&client, id, true).await;
+
+            // Closing the connection on the server makes the client reconnect
+            server.shutdown().await?;
+            wait_for(&client, id, false).await;
+            let server = InetEndpoint::start(&bind, server_key, PeerPolicy::open()).await?;
+            wait_for(&client, id, true).await;
+
+            let data = InMemoryEnvelope::test_envelope();
+            client
+                .send(data.clone(), Neighbour::Single(server.public_key()), None)
+                .await?;
+            let (received, _) = server.next().await.unwrap();
+            assert_eq!(received, data);
+            Ok::<_, RatmanError>(())
+        })
+        .unwrap()
+}
+
 #[test]
 fn test_simple_transmission() {
     use libratman::rt::AsyncSystem;
@@ -295,10 +685,14 @@ async fn simple_transmission() -> Result<()> {
     setup_logging();
 
     let server_kid = Ident32::random();
-    let server = InetEndpoint::start("[::]:12000", server_kid).await.unwrap();
+    let server = InetEndpoint::start("[::]:12000", server_kid, PeerPolicy::open())
+        .await
+        .unwrap();
 
     let client_kid = Ident32::random();
-    let client = InetEndpoint::start("[::]:13000", client_kid).await.unwrap();
+    let client = InetEndpoint::start("[::]:13000", client_kid, PeerPolicy::open())
+        .await
+        .unwrap();
     client.add_peer("[::1]:12000".into()).await.unwrap();
 
     libratman::tokio::time::sleep(std::time::Duration::from_millis(666)).await;
@@ -306,8 +700,9 @@ async fn simple_transmission() -> Result<()> {
 
     let data = InMemoryEnvelope::test_envelope();
     info!("============= SENDING =============");
+    // Peers are identified by their public key
     client
-        .send(data.clone(), Neighbour::Single(server_kid), None)
+        .send(data.clone(), Neighbour::Single(server.public_key()), None)
         .await
         .unwrap();
     info!("Data sent");
@@ -316,5 +711,11 @@ async fn simple_transmission() -> Result<()> {
     info!("Data received!");
 
     assert_eq!(data, received_data);
+
+    let status = client.status().await;
+    assert_eq!(status.state, LinkState::Up);
+    assert_eq!(status.peers, 1);
+    assert!(status.tx_bytes > 0);
+    assert_eq!(server.status().await.rx_bytes, data.buffer.len() as u64);
     Ok(())
 }
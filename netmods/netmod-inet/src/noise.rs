@@ -0,0 +1,376 @@
+// SPDX-FileCopyrightText: 2019-2022 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+//! Encrypted and authenticated peer sessions
+//!
+//! Peers run a Noise `XX` handshake with their router keys as static
+//! keys.  A peer is identified by its public key, which it has to
+//! prove ownership of, so no peer can claim to be another router.
+//!
+//! ```text
+//! -> e
+//! <- e, ee, s, es
+//! -> s, se            (payload: Handshake::Hello)
+//! <- transport        (payload: Handshake::Ack)
+//! ```
+//!
+//! After the handshake every frame is encrypted.  Each Noise message
+//! is sent with a two byte length prefix.  Frames are prefixed with
+//! their length and split across as many messages as needed.
+
+use crate::proto::Handshake;
+use byteorder::{BigEndian, ByteOrder};
+use libratman::{
+    tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    types::Ident32,
+};
+use snow::{
+    params::DHChoice,
+    resolvers::{CryptoResolver, DefaultResolver},
+    Builder, HandshakeState, TransportState,
+};
+use std::{fmt, io, sync::Mutex};
+
+const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_BLAKE2s";
+
+/// The largest message the Noise protocol allows
+const MAX_MESSAGE_LEN: usize = 65535;
+
+/// Every encrypted message carries an authentication tag
+const TAG_LEN: usize = 16;
+
+/// The largest frame accepted from a peer
+const MAX_FRAME_LEN: usize = 1024 * 1024;
+
+/// The private key a router authenticates itself with
+#[derive(Copy, Clone)]
+pub(crate) struct RouterKey {
+    secret: Ident32,
+    public: Ident32,
+}
+
+impl RouterKey {
+    pub(crate) fn new(secret: Ident32) -> Self {
+        let mut dh = DefaultResolver
+            .resolve_dh(&DHChoice::Curve25519)
+            .expect("no x25519 implementation available");
+        dh.set(secret.as_bytes());
+
+        Self {
+            secret,
+            public: Ident32::from_bytes(dh.pubkey()),
+        }
+    }
+
+    /// The public key that peers identify this router by
+    pub(crate) fn public(&self) -> Ident32 {
+        self.public
+    }
+
+    fn builder(&self) -> Builder<'_> {
+        Builder::new(NOISE_PARAMS.parse().unwrap()).local_private_key(self.secret.as_bytes())
+    }
+}
+
+/// Never print the secret key
+impl fmt::Debug for RouterKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RouterKey({})", self.public)
+    }
+}
+
+/// A session after a successful handshake
+pub(crate) struct Established {
+    pub(crate) cipher: Cipher,
+    /// The authenticated public key of the peer
+    pub(crate) remote_key: Ident32,
+    /// The handshake data sent by the peer
+    pub(crate) handshake: Handshake,
+}
+
+/// Connect to a peer, sending our `hello` and returning its answer
+pub(crate) async fn initiate<S>(
+    stream: &mut S,
+    key: &RouterKey,
+    hello: Handshake,
+) -> io::Result<Established>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut noise = key.builder().build_initiator().map_err(noise_error)?;
+    write_handshake(stream, &mut noise, &[]).await?;
+    read_handshake(stream, &mut noise).await?;
+    write_handshake(stream, &mut noise, &hello.encode()).await?;
+
+    let (cipher, remote_key) = finish(noise)?;
+    let handshake = Handshake::decode(&cipher.read(stream).await?)?;
+    Ok(Established {
+        cipher,
+        remote_key,
+        handshake,
+    })
+}
+
+/// Accept a peer's handshake and return its hello
+///
+/// The caller must answer with a `Handshake::Ack` via
+/// [`Cipher::write`](Cipher::write).
+pub(crate) async fn respond<S>(stream: &mut S, key: &RouterKey) -> io::Result<Established>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut noise = key.builder().build_responder().map_err(noise_error)?;
+    read_handshake(stream, &mut noise).await?;
+    write_handshake(stream, &mut noise, &[]).await?;
+    let hello = read_handshake(stream, &mut noise).await?;
+
+    let (cipher, remote_key) = finish(noise)?;
+    Ok(Established {
+        cipher,
+        remote_key,
+        handshake: Handshake::decode(&hello)?,
+    })
+}
+
+fn finish(noise: HandshakeState) -> io::Result<(Cipher, Ident32)> {
+    let remote_key = noise
+        .get_remote_static()
+        .map(Ident32::from_bytes)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "peer sent no static key"))?;
+    let transport = noise.into_transport_mode().map_err(noise_error)?;
+    Ok((Cipher(Mutex::new(transport)), remote_key))
+}
+
+async fn write_handshake<W: AsyncWrite + Unpin>(
+    tx: &mut W,
+    noise: &mut HandshakeState,
+    payload: &[u8],
+) -> io::Result<()> {
+    let mut buf = vec![0; MAX_MESSAGE_LEN];
+    let len = noise
+        .write_message(payload, &mut buf)
+        .map_err(noise_error)?;
+    tx.write_all(&(len as u16).to_be_bytes()).await?;
+    tx.write_all(&buf[..len]).await
+}
+
+async fn read_handshake<R: AsyncRead + Unpin>(
+    rx: &mut R,
+    noise: &mut HandshakeState,
+) -> io::Result<Vec<u8>> {
+    let message = read_message(rx).await?;
+    let mut payload = vec![0; message.len()];
+    let len = noise
+        .read_message(&message, &mut payload)
+        .map_err(noise_error)?;
+    payload.truncate(len);
+    Ok(payload)
+}
+
+async fn read_message<R: AsyncRead + Unpin>(rx: &mut R) -> io::Result<Vec<u8>> {
+    let mut len_buf = [0; 2];
+    rx.read_exact(&mut len_buf).await?;
+    let mut message = vec![0; BigEndian::read_u16(&len_buf) as usize];
+    rx.read_exact(&mut message).await?;
+    Ok(message)
+}
+
+fn noise_error(e: snow::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("noise error: {}", e))
+}
+
+/// Encrypts and decrypts the frames of an established session
+///
+/// Both directions use their own nonces, so the sending and receiving
+/// halves of a stream can share one transport state.  Frames must be
+/// written to the stream in the order they were encrypted in.
+pub(crate) struct Cipher(Mutex<TransportState>);
+
+impl Cipher {
+    /// Encrypt a frame and write it to the stream
+    ///
+    /// Returns the number of bytes written.
+    pub(crate) async fn write<W: AsyncWrite + Unpin>(
+        &self,
+        tx: &mut W,
+        frame: &[u8],
+    ) -> io::Result<usize> {
+        let mut plain = Vec::with_capacity(frame.len() + 4);
+        plain.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+        plain.extend_from_slice(frame);
+
+        let mut buf = vec![];
+        {
+            let mut transport = self.0.lock().unwrap();
+            for chunk in plain.chunks(MAX_MESSAGE_LEN - TAG_LEN) {
+                let start = buf.len();
+                buf.resize(start + 2 + chunk.len() + TAG_LEN, 0);
+                let len = transport
+                    .write_message(chunk, &mut buf[start + 2..])
+                    .map_err(noise_error)?;
+                BigEndian::write_u16(&mut buf[start..start + 2], len as u16);
+            }
+        }
+
+        tx.write_all(&buf).await?;
+        Ok(buf.len())
+    }
+
+    /// Read and decrypt the next frame from the stream
+    pub(crate) async fn read<R: AsyncRead + Unpin>(&self, rx: &mut R) -> io::Result<Vec<u8>> {
+        let first = self.read_chunk(rx).await?;
+        if first.len() < 4 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "encrypted frame is missing its length",
+            ));
+        }
+
+        let len = BigEndian::read_u32(&first[..4]) as usize;
+        if len > MAX_FRAME_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("peer sent a {} byte frame", len),
+            ));
+        }
+
+        let mut frame = first[4..].to_vec();
+        while frame.len() < len {
+            frame.extend_from_slice(&self.read_chunk(rx).await?);
+        }
+
+        if frame.len() != len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "encrypted frame has an invalid length",
+            ));
+        }
+
+        Ok(frame)
+    }
+
+    async fn read_chunk<R: AsyncRead + Unpin>(&self, rx: &mut R) -> io::Result<Vec<u8>> {
+        let message = read_message(rx).await?;
+        let mut payload = vec![0; message.len()];
+        let len = self
+            .0
+            .lock()
+            .unwrap()
+            .read_message(&message, &mut payload)
+            .map_err(noise_error)?;
+        payload.truncate(len);
+        Ok(payload)
+    }
+}
+
+#[cfg(test)]
+async fn connected_pair(
+    client: &RouterKey,
+    server: &RouterKey,
+) -> (
+    (Established, libratman::tokio::io::DuplexStream),
+    (Established, libratman::tokio::io::DuplexStream),
+) {
+    use crate::PeerType;
+    use libratman::tokio::{io::duplex, join};
+
+    let (mut a, mut b) = duplex(MAX_MESSAGE_LEN * 4);
+    let hello = Handshake::Hello {
+        tt: PeerType::Standard,
+        self_port: 0,
+    };
+
+    let (initiated, responded) = join!(initiate(&mut a, client, hello), async {
+        let responded = respond(&mut b, server).await.unwrap();
+        let ack = Handshake::Ack {
+            tt: PeerType::Standard,
+        };
+        responded.cipher.write(&mut b, &ack.encode()).await.unwrap();
+        responded
+    });
+
+    ((initiated.unwrap(), a), (responded, b))
+}
+
+#[test]
+fn handshake_authenticates_both_sides() {
+    use crate::PeerType;
+    use libratman::rt::AsyncSystem;
+
+    AsyncSystem::new("noise-handshake".into(), 1).exec(async {
+        let client = RouterKey::new(Ident32::random());
+        let server = RouterKey::new(Ident32::random());
+        let ((initiated, _), (responded, _)) = connected_pair(&client, &server).await;
+
+        assert_eq!(initiated.remote_key, server.public());
+        assert_eq!(responded.remote_key, client.public());
+        assert_eq!(
+            initiated.handshake,
+            Handshake::Ack {
+                tt: PeerType::Standard
+            }
+        );
+        assert_eq!(
+            responded.handshake,
+            Handshake::Hello {
+                tt: PeerType::Standard,
+                self_port: 0
+            }
+        );
+    });
+}
+
+#[test]
+fn encrypt_large_frames() {
+    use libratman::rt::AsyncSystem;
+
+    AsyncSystem::new("noise-frames".into(), 1).exec(async {
+        let client = RouterKey::new(Ident32::random());
+        let server = RouterKey::new(Ident32::random());
+        let ((client, mut a), (server, mut b)) = connected_pair(&client, &server).await;
+
+        // Larger than a single Noise message
+        let frame: Vec<u8> = (0..150_000).map(|i| i as u8).collect();
+        let written = client.cipher.write(&mut a, &frame).await.unwrap();
+        assert!(written > frame.len());
+        assert_eq!(server.cipher.read(&mut b).await.unwrap(), frame);
+
+        server.cipher.write(&mut b, b"pong").await.unwrap();
+        assert_eq!(client.cipher.read(&mut a).await.unwrap(), b"pong");
+    });
+}
+
+#[test]
+fn reject_tampered_frames() {
+    use libratman::{rt::AsyncSystem, tokio::io::duplex};
+
+    AsyncSystem::new("noise-tamper".into(), 1).exec(async {
+        let client = RouterKey::new(Ident32::random());
+        let server = RouterKey::new(Ident32::random());
+        let ((client, _), (server, _)) = connected_pair(&client, &server).await;
+
+        let mut encrypted = vec![];
+        client
+            .cipher
+            .write(&mut encrypted, b"some frame")
+            .await
+            .unwrap();
+        let last = encrypted.len() - 1;
+        encrypted[last] ^= 1;
+
+        let (mut tx, mut rx) = duplex(1024);
+        tx.write_all(&encrypted).await.unwrap();
+        let err = server.cipher.read(&mut rx).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    });
+}
+
+#[test]
+fn debug_hides_secret() {
+    let secret = Ident32::random();
+    let key = RouterKey::new(secret);
+    let debug = format!("{:?}", key);
+    assert!(debug.contains(&key.public().to_string()));
+    assert!(!debug.contains(&secret.to_string()));
+}
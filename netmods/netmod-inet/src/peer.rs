@@ -6,17 +6,13 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 
 use crate::session::{SessionData, SessionError};
-use crate::{proto, routes::Target};
+use crate::{noise::Cipher, proto, routes::Target};
 use libratman::tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
-use libratman::tokio::{
-    sync::Mutex,
-    task::{spawn, yield_now},
-};
+use libratman::tokio::{sync::Mutex, task::spawn};
 use libratman::types::Ident32;
 use libratman::{
     tokio::sync::mpsc::{Receiver, Sender},
     types::InMemoryEnvelope,
-    EncodingError, RatmanError,
 };
 use useful_netmod_bits::metrics::MetricsTable;
 
@@ -55,6 +51,7 @@ pub(crate) type FrameSender = Sender<(Ident32, InMemoryEnvelope)>;
 /// The two inverse scenarios exist on the "server" side.
 pub struct Peer {
     pub(crate) session: SessionData,
+    cipher: Cipher,
     tx: Mutex<Option<OwnedWriteHalf>>,
     rx: Mutex<Option<OwnedReadHalf>>,
     receiver: FrameSender,
@@ -67,11 +64,13 @@ impl Peer {
         session: SessionData,
         receiver: FrameSender,
         restart: Option<Sender<SessionData>>,
+        cipher: Cipher,
         tx: OwnedWriteHalf,
         rx: OwnedReadHalf,
     ) -> Arc<Self> {
         Arc::new(Self {
             session,
+            cipher,
             tx: Mutex::new(Some(tx)),
             rx: Mutex::new(Some(rx)),
             receiver,
@@ -103,9 +102,12 @@ impl Peer {
         );
         let mut txg = self.tx.lock().await;
 
-        // The TcpStream SHOULD never just disappear
-        let tx = txg.as_mut().unwrap();
-        match proto::write(&mut *tx, env).await {
+        // The TcpStream only disappears when the peer was closed
+        let tx = match txg.as_mut() {
+            Some(tx) => tx,
+            None => return Err(SessionError::Dropped(self.session.addr)),
+        };
+        match proto::write(&mut *tx, &self.cipher, env).await {
             Ok(bytes_written) => Ok(bytes_written),
             Err(e) => {
                 warn!("Failed to send data for peer {}: {e:?}", self.session.id);
@@ -129,10 +131,16 @@ impl Peer {
         }
     }
 
+    /// Close the connection to this peer
+    ///
+    /// Dropping the sending half shuts down the stream, after which the
+    /// remote closes its side as well, and the receive loop exits.
+    pub(crate) async fn close(&self) {
+        self.tx.lock().await.take();
+    }
+
     /// Repeatedly attempt to read from the reading socket
     pub(crate) async fn run(self: Arc<Self>, metrics: Arc<MetricsTable<SocketAddr>>) {
-        let mut no_data_ctr = 0;
-
         loop {
             trace!("Peer::run loop for {:?}", self.session);
             let mut rxg = self.rx.lock().await;
@@ -144,37 +152,25 @@ impl Peer {
                 }
             };
 
-            let envelope = match proto::read(rx).await {
-                Ok(f) => {
-                    no_data_ctr = 0;
-                    f
-                }
-                Err(RatmanError::Encoding(EncodingError::NoData)) => {
-                    no_data_ctr += 1;
-                    drop(rxg);
-
-                    if no_data_ctr > 128 {
-                        break;
-                    } else {
-                        yield_now().await;
-                        continue;
-                    }
-                }
-                Err(RatmanError::Io(io)) => {
+            let envelope = match proto::read(rx, &self.cipher).await {
+                Ok(f) => f,
+                // Closed connections and frames that fail to decrypt
+                // end the session
+                Err(e) => {
                     error!(
                         "Peers {} encountered I/O error during receiving: {}",
                         self.id(),
-                        io
+                        e
                     );
 
-                    // If we were the outgoing peer we signal to re-connect
+                    // If we were the outgoing peer we signal to re-connect.
+                    // A failed send may already have done so.
                     if let Some(ref tx) = self.restart {
-                        tx.send(self.session).await.unwrap();
+                        let _ = tx.send(self.session).await;
                     }
 
                     break;
                 }
-                _ => unreachable!(),
             };
 
             {
@@ -186,10 +182,15 @@ impl Peer {
             }
 
             // If we received a correct frame we forward it to the receiver
-            self.receiver
+            if self
+                .receiver
                 .send((self.session.peer_router_key_id, envelope))
                 .await
-                .unwrap();
+                .is_err()
+            {
+                debug!("Endpoint for peer {} was stopped", self.id());
+                break;
+            }
         }
 
         trace!("Exit receive loop for peer {}", self.id());
@@ -0,0 +1,105 @@
+// SPDX-FileCopyrightText: 2019-2022 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+//! Decide which peers may connect to an endpoint
+
+use crate::routes::Routes;
+use libratman::types::Ident32;
+use std::{collections::BTreeSet, sync::Arc};
+
+/// Rules for accepting incoming peers
+///
+/// Incoming peers are checked after they authenticated themselves
+/// with their router id.  Router ids on the deny list are always
+/// rejected, and ids on the allow list are always accepted.  Peers
+/// that this endpoint is peering with itself are recognised by the
+/// router id pinned for that peering.  Any other peer is only
+/// accepted if `accept_unknown` is set.
+#[derive(Clone, Debug, Default)]
+pub struct PeerPolicy {
+    /// Accept peers that aren't otherwise known
+    pub accept_unknown: bool,
+    /// Router ids that may always connect
+    pub allow: BTreeSet<Ident32>,
+    /// Router ids that may never connect
+    pub deny: BTreeSet<Ident32>,
+}
+
+/// Why an incoming peer was rejected
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub(crate) enum Rejection {
+    #[error("router id is on the deny list")]
+    Denied,
+    #[error("unknown peers are not accepted")]
+    Unknown,
+}
+
+impl PeerPolicy {
+    /// A policy which accepts every peer
+    pub fn open() -> Self {
+        Self {
+            accept_unknown: true,
+            ..Default::default()
+        }
+    }
+
+    /// Check whether a peer which authenticated with `key` may connect
+    pub(crate) async fn check(&self, routes: &Arc<Routes>, key: Ident32) -> Result<(), Rejection> {
+        if self.deny.contains(&key) {
+            return Err(Rejection::Denied);
+        }
+
+        if self.accept_unknown || self.allow.contains(&key) || routes.is_peering(key).await {
+            Ok(())
+        } else {
+            Err(Rejection::Unknown)
+        }
+    }
+}
+
+#[test]
+fn check_peer_policy() {
+    use libratman::rt::AsyncSystem;
+    let system = AsyncSystem::new("check-peer-policy".into(), 1);
+    system.exec(async {
+        let routes = Routes::new();
+        let key = Ident32::random();
+
+        let closed = PeerPolicy::default();
+        assert_eq!(closed.check(&routes, key).await, Err(Rejection::Unknown));
+        assert_eq!(PeerPolicy::open().check(&routes, key).await, Ok(()));
+
+        let allowed = PeerPolicy {
+            allow: [key].into(),
+            ..Default::default()
+        };
+        assert_eq!(allowed.check(&routes, key).await, Ok(()));
+
+        let denied = PeerPolicy {
+            deny: [key].into(),
+            ..PeerPolicy::open()
+        };
+        assert_eq!(denied.check(&routes, key).await, Err(Rejection::Denied));
+
+        // Hosts we peer with are not known by their address, since
+        // other routers behind the same address could connect too
+        routes
+            .add_peering(
+                0,
+                "192.0.2.1:5860".into(),
+                "192.0.2.1:5860".parse().unwrap(),
+                None,
+            )
+            .await;
+        assert_eq!(closed.check(&routes, key).await, Err(Rejection::Unknown));
+
+        // ...only by the router id pinned for the peering
+        routes.pin_peering(0, key).await;
+        assert_eq!(closed.check(&routes, key).await, Ok(()));
+        assert_eq!(
+            closed.check(&routes, Ident32::random()).await,
+            Err(Rejection::Unknown)
+        );
+    })
+}
@@ -4,7 +4,11 @@
 
 // use async_std::net::*;
 
-use std::net::{SocketAddr, ToSocketAddrs};
+use libratman::tokio::net::lookup_host;
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs},
+};
 
 pub struct Resolver;
 
@@ -16,14 +20,33 @@ impl Resolver {
             Some(s) => Some(s),
             // If we have a resolver, try to resolve this payload to
             // an IP address (splitting off the port)
-            None => ToSocketAddrs::to_socket_addrs(peer).ok()?.into_iter().fold(
-                None,
-                |acc, addr| match (acc, addr) {
-                    (None, addr) => Some(addr),
-                    (_, maybe_v6) if maybe_v6.is_ipv6() => Some(maybe_v6),
-                    (addr, _) => addr,
-                },
-            ),
+            None => prefer_ipv6(ToSocketAddrs::to_socket_addrs(peer).ok()?),
         }
     }
+
+    /// Resolve a peer line without blocking the runtime
+    ///
+    /// This is done before every connection attempt, so that peers
+    /// with changing addresses can still be reached.
+    pub(crate) async fn lookup(peer: &str) -> io::Result<SocketAddr> {
+        if let Ok(addr) = peer.parse() {
+            return Ok(addr);
+        }
+
+        prefer_ipv6(lookup_host(peer).await?).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no addresses found for {}", peer),
+            )
+        })
+    }
+}
+
+/// Pick the last IPv6 address, or the first address otherwise
+fn prefer_ipv6(addrs: impl Iterator<Item = SocketAddr>) -> Option<SocketAddr> {
+    addrs.fold(None, |acc, addr| match (acc, addr) {
+        (None, addr) => Some(addr),
+        (_, maybe_v6) if maybe_v6.is_ipv6() => Some(maybe_v6),
+        (addr, _) => addr,
+    })
 }
@@ -12,15 +12,47 @@ use std::{
         Arc,
     },
 };
-use useful_netmod_bits::metrics::MetricsTable;
+use useful_netmod_bits::{metrics::MetricsTable, status::StatusTracker};
 
 pub(crate) type Target = u16;
 
+/// An outgoing peering and the key its peer authenticated with
+#[derive(Clone, Debug)]
+pub(crate) struct Peering {
+    /// The host and port of the peer line this peering was created with
+    pub(crate) host: String,
+    /// The address `host` most recently resolved to
+    pub(crate) addr: SocketAddr,
+    /// The key the peer must authenticate with
+    ///
+    /// This is either pinned in the peer line, or the key presented
+    /// during the first handshake.
+    pub(crate) key: Option<Ident32>,
+    pub(crate) state: SessionState,
+}
+
+/// The connection state of an outgoing peering
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum SessionState {
+    /// Resolving the peer address, connecting, and running the handshake
+    Connecting { attempt: u32 },
+    /// Waiting for the next attempt after a failed one
+    Waiting { attempt: u32 },
+    /// A session is established
+    Connected,
+}
+
 #[derive(Default)]
 pub(crate) struct Routes {
     latest: AtomicU16,
     pub(crate) inner: RwLock<BTreeMap<Ident32, Arc<Peer>>>,
     pub(crate) metrics: Arc<MetricsTable<SocketAddr>>,
+    pub(crate) status: StatusTracker,
+    /// Outgoing peerings which should be kept alive
+    ///
+    /// Sessions for targets that are not in this map are not
+    /// (re-)established.
+    peerings: RwLock<BTreeMap<Target, Peering>>,
 }
 
 impl Routes {
@@ -51,19 +83,111 @@ impl Routes {
     ///
     /// This should only be done by the peer itself, when it closes
     /// its stream
-    pub(crate) async fn remove_peer(self: &Arc<Self>, peer_id: Ident32) -> Arc<Peer> {
+    pub(crate) async fn remove_peer(self: &Arc<Self>, peer_id: Ident32) -> Option<Arc<Peer>> {
         let mut inner = self.inner.write().await;
-        inner.remove(&peer_id).unwrap()
+        inner.remove(&peer_id)
+    }
+
+    /// Remember an outgoing peering so that its session is kept alive
+    pub(crate) async fn add_peering(
+        self: &Arc<Self>,
+        target: Target,
+        host: String,
+        addr: SocketAddr,
+        key: Option<Ident32>,
+    ) {
+        self.peerings.write().await.insert(
+            target,
+            Peering {
+                host,
+                addr,
+                key,
+                state: SessionState::Connecting { attempt: 0 },
+            },
+        );
     }
 
-    /// All peers are valid, but some are more valid than others
+    /// Get the peer line of an outgoing peering, if it wasn't stopped
+    pub(crate) async fn peering_host(self: &Arc<Self>, target: Target) -> Option<String> {
+        self.peerings
+            .read()
+            .await
+            .get(&target)
+            .map(|peering| peering.host.clone())
+    }
+
+    /// Remember the address that an outgoing peering resolved to
+    pub(crate) async fn set_peering_addr(self: &Arc<Self>, target: Target, addr: SocketAddr) {
+        if let Some(peering) = self.peerings.write().await.get_mut(&target) {
+            peering.addr = addr;
+        }
+    }
+
+    /// Update the connection state of an outgoing peering
+    pub(crate) async fn set_peering_state(self: &Arc<Self>, target: Target, state: SessionState) {
+        if let Some(peering) = self.peerings.write().await.get_mut(&target) {
+            peering.state = state;
+        }
+    }
+
+    /// Get the connection state of an outgoing peering
+    #[cfg(test)]
+    pub(crate) async fn peering_state(self: &Arc<Self>, target: Target) -> Option<SessionState> {
+        self.peerings
+            .read()
+            .await
+            .get(&target)
+            .map(|peering| peering.state)
+    }
+
+    /// Remember the key that the peer of an outgoing peering presented
+    pub(crate) async fn pin_peering(self: &Arc<Self>, target: Target, key: Ident32) {
+        if let Some(peering) = self.peerings.write().await.get_mut(&target) {
+            peering.key = Some(key);
+        }
+    }
+
+    /// Forget an outgoing peering, returning `false` if it didn't exist
+    pub(crate) async fn remove_peering(self: &Arc<Self>, target: Target) -> bool {
+        self.peerings.write().await.remove(&target).is_some()
+    }
+
+    /// Remove the connected peer for a particular target, if there is one
+    pub(crate) async fn remove_target(self: &Arc<Self>, target: Target) -> Option<Arc<Peer>> {
+        let mut inner = self.inner.write().await;
+        let peer_id = inner
+            .iter()
+            .find(|(_, peer)| peer.id() == target)
+            .map(|(peer_id, _)| *peer_id)?;
+        inner.remove(&peer_id)
+    }
+
+    /// Check whether the session for an outgoing peering should be kept alive
+    pub(crate) async fn wants_peering(self: &Arc<Self>, target: Target) -> bool {
+        self.peerings.read().await.contains_key(&target)
+    }
+
+    /// Check whether we peer with a router ourselves
     ///
-    /// Check if we can currently send data to this peer (i.e. will
-    /// get_peer_by_id fail?).  There is a race condition in here
-    /// somewhere. Woops
-    pub(crate) async fn exists(self: &Arc<Self>, peer_id: Ident32) -> bool {
-        let inner = self.inner.read().await;
-        inner.get(&peer_id).is_some()
+    /// Peers are only recognised by the router id that is pinned for
+    /// their peering, never by their address, since any router could
+    /// connect from the same address.
+    pub(crate) async fn is_peering(self: &Arc<Self>, key: Ident32) -> bool {
+        self.peerings
+            .read()
+            .await
+            .values()
+            .any(|peering| peering.key == Some(key))
+    }
+
+    /// Count the outgoing peerings which currently have no session
+    pub(crate) async fn missing_peerings(self: &Arc<Self>) -> usize {
+        self.peerings
+            .read()
+            .await
+            .values()
+            .filter(|peering| peering.state != SessionState::Connected)
+            .count()
     }
 
     /// Return the peer associated with a particular target ID
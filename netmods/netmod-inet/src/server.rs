@@ -3,17 +3,19 @@
 // SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
 
 use crate::{
+    noise::{self, RouterKey},
     peer::{FrameSender, Peer},
-    proto::{self, Handshake},
+    policy::PeerPolicy,
+    proto::Handshake,
     routes::Routes,
-    session::SessionData,
+    session::{SessionData, HANDSHAKE_TIMEOUT},
 };
 use libratman::{
     tokio::{
         net::{TcpListener, TcpStream},
         task::spawn,
+        time,
     },
-    types::Ident32,
     NetmodError, RatmanError,
 };
 use std::{
@@ -42,7 +44,8 @@ impl From<io::Error> for ServerError {
 /// Tcp connection listener taking on connections from peers,
 /// configuring links, and spawning async peer handlers
 pub struct Server {
-    self_router_key_id: Ident32,
+    self_router_key: RouterKey,
+    policy: Arc<PeerPolicy>,
     #[allow(unused)]
     ipv4_listen: Option<TcpListener>,
     ipv6_listen: TcpListener,
@@ -52,7 +55,8 @@ impl Server {
     /// Attempt to bind the server socket
     pub(crate) async fn bind(
         bind: &str,
-        self_router_key_id: Ident32,
+        self_router_key: RouterKey,
+        policy: PeerPolicy,
     ) -> Result<Server, RatmanError> {
         let addr: SocketAddr = bind
             .parse()
@@ -65,15 +69,16 @@ impl Server {
         let ipv6_listen = TcpListener::bind(addr).await?;
 
         Ok(Self {
-            self_router_key_id,
+            self_router_key,
+            policy: Arc::new(policy),
             ipv4_listen: None,
             ipv6_listen,
         })
     }
 
-    /// Grab the port this socket is running on for diagnostics
-    pub(crate) fn port(&self) -> u16 {
-        self.ipv6_listen.local_addr().unwrap().port()
+    /// Grab the address this socket is running on for diagnostics
+    pub(crate) fn local_addr(&self) -> SocketAddr {
+        self.ipv6_listen.local_addr().unwrap()
     }
 
     /// Run in a loop to accept incoming connections
@@ -86,11 +91,13 @@ impl Server {
                         stream,
                         sender.clone(),
                         r,
-                        self.self_router_key_id,
+                        self.self_router_key,
+                        Arc::clone(&self.policy),
                     ));
                 }
                 Err(e) => {
                     warn!("Invalid incoming stream: {}", e);
+                    r.status.error(format!("invalid incoming stream: {e}"));
                     continue;
                 }
             }
@@ -102,11 +109,19 @@ impl Server {
 ///
 /// Currently only standard peer connections are supported, meaning
 /// that no reverse channel is created anywhere in this block.
-async fn handle_stream(s: TcpStream, sender: FrameSender, r: Arc<Routes>, self_key_id: Ident32) {
-    let peer = match accept_connection(s, sender, &r, self_key_id).await {
-        Ok(peer) => peer,
+async fn handle_stream(
+    s: TcpStream,
+    sender: FrameSender,
+    r: Arc<Routes>,
+    self_key: RouterKey,
+    policy: Arc<PeerPolicy>,
+) {
+    let peer = match accept_connection(s, sender, &r, self_key, &policy).await {
+        Ok(Some(peer)) => peer,
+        Ok(None) => return,
         Err(e) => {
             error!("Failed to connect to peer: {}", e);
+            r.status.error(format!("failed to accept peer: {e}"));
             return;
         }
     };
@@ -120,23 +135,27 @@ async fn handle_stream(s: TcpStream, sender: FrameSender, r: Arc<Routes>, self_k
     r.add_peer(peer.session.peer_router_key_id, peer).await;
 }
 
+/// Run the encrypted handshake with a connecting peer
+///
+/// Peers that fail to authenticate are rejected before they are
+/// added to the routing table.  Peers that authenticate, but aren't
+/// accepted by the peer policy, are counted and `None` is returned.
 async fn accept_connection(
-    s: TcpStream,
+    mut s: TcpStream,
     sender: FrameSender,
     r: &Arc<Routes>,
-    self_key_id: Ident32,
-) -> Result<Arc<Peer>, io::Error> {
+    self_key: RouterKey,
+    policy: &PeerPolicy,
+) -> Result<Option<Arc<Peer>>, io::Error> {
     let addr = s.peer_addr()?;
-    let (mut read_stream, mut write_stream) = s.into_split();
 
-    // First we read the handshake structure from the socket
-    let frame = proto::read_blocking(&mut read_stream).await.unwrap();
-    let handshake = Handshake::from_carrier(&frame).unwrap();
+    let session = time::timeout(HANDSHAKE_TIMEOUT, noise::respond(&mut s, &self_key))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "handshake timed out"))??;
 
-    let (tt, r_key_id) = match handshake {
-        Handshake::Hello { tt, r_key_id, .. } => (tt, r_key_id),
+    let tt = match session.handshake {
+        Handshake::Hello { tt, .. } => tt,
         Handshake::Ack { .. } => {
-            drop((read_stream, write_stream));
             return Err(io::Error::new(
                 io::ErrorKind::InvalidData,
                 "invalid handshake data",
@@ -144,18 +163,27 @@ async fn accept_connection(
         }
     };
 
+    // Rejected peers don't get an ACK, and their connection is closed
+    if let Err(e) = policy.check(r, session.remote_key).await {
+        warn!(
+            "Rejected peer {:?} with router id {}: {}",
+            addr, session.remote_key, e
+        );
+        r.status.rejected();
+        return Ok(None);
+    }
+
     // Send back an ACK to the client so it can chill out a bit
-    let ack = Handshake::Ack {
-        tt,
-        r_key_id: self_key_id,
-    };
-    let envelope = ack.to_carrier().unwrap();
-    proto::write(&mut write_stream, &envelope).await.unwrap();
+    session
+        .cipher
+        .write(&mut s, &Handshake::Ack { tt }.encode())
+        .await?;
 
+    let r_key_id = session.remote_key;
     let target = r.next_target();
     let data = SessionData {
         self_port: 0,
-        self_router_key_id: self_key_id,
+        self_router_key: self_key,
         peer_router_key_id: r_key_id,
         id: target,
         tt,
@@ -163,14 +191,16 @@ async fn accept_connection(
     };
 
     info!(
-        "Successfully connected with new peer #{} ({:?}) :)",
-        target, addr,
+        "Successfully connected with new peer #{} ({:?}, key {}) :)",
+        target, addr, r_key_id,
     );
-    Ok(Peer::standard(
+    let (read_stream, write_stream) = s.into_split();
+    Ok(Some(Peer::standard(
         data,
         sender,
         None,
+        session.cipher,
         write_stream,
         read_stream,
-    ))
+    )))
 }
@@ -1,9 +1,11 @@
 //! Peer session management
 
 use crate::{
+    noise::{self, RouterKey},
     peer::{FrameSender, Peer},
-    proto::{self, Handshake},
-    routes::{Routes, Target},
+    proto::Handshake,
+    resolve::Resolver,
+    routes::{Routes, SessionState, Target},
     PeerType,
 };
 use libratman::{
@@ -13,33 +15,89 @@ use libratman::{
         task::spawn,
         time,
     },
-    types::{Ident32, InMemoryEnvelope},
-    RatmanError,
+    types::Ident32,
 };
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use rand::Rng;
+use std::{io, net::SocketAddr, sync::Arc, time::Duration};
 
 /// The number of attempts a session maskes to a peer before giving up
-pub const SESSION_TIMEOUT: u16 = 6;
+pub const SESSION_TIMEOUT: u32 = 6;
+
+/// How long a peer may take to complete the handshake
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a single connection attempt may take
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The delay after the first failed connection attempt
+pub const RECONNECT_MIN: Duration = Duration::from_secs(1);
+
+/// The longest delay between two connection attempts
+pub const RECONNECT_MAX: Duration = Duration::from_secs(600);
+
+/// Exponential backoff between connection attempts
+///
+/// Every delay is twice as long as the previous one, up to a maximum.
+/// Delays are randomly shortened by up to half, so that peers which
+/// lost their connection at the same time don't reconnect in
+/// lockstep.
+pub(crate) struct Backoff {
+    attempts: u32,
+    min: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    pub(crate) fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            attempts: 0,
+            min,
+            max,
+        }
+    }
+
+    /// The number of failed attempts so far
+    pub(crate) fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Count a failed attempt and get the delay before the next one
+    pub(crate) fn next_delay(&mut self) -> Duration {
+        let delay = self
+            .min
+            .saturating_mul(1 << self.attempts.min(16))
+            .min(self.max);
+        self.attempts += 1;
+        delay.mul_f64(rand::thread_rng().gen_range(0.5, 1.0))
+    }
+}
 
 #[derive(Debug, thiserror::Error)]
 pub(crate) enum SessionError {
-    #[error("connection to {} refused (after {} tries)", 0, 1)]
-    Refused(SocketAddr, u16),
-    #[error("existing connection to {} was dropped by peer", 0)]
+    #[error("connection to {0} refused (after {1} tries)")]
+    Refused(SocketAddr, u32),
+    #[error("failed to resolve {0}: {1}")]
+    Resolve(String, io::Error),
+    #[error("failed connecting to {0}: {1}")]
+    Connect(SocketAddr, io::Error),
+    #[error("existing connection to {0} was dropped by peer")]
     Dropped(SocketAddr),
-    #[error("mismatched peering expectations with {:?}: {}", 0, 1)]
-    Handshake(SessionData, String),
+    #[error("failed to authenticate peer {0}: {1}")]
+    Authentication(SocketAddr, String),
+    #[error("peering with {0} was stopped")]
+    Stopped(SocketAddr),
 }
 
 /// Create a new session manager for a single peer
 ///
-/// It will re-attempt to establish a connection until one is found.
-/// It then adds the newly created peer to the routing table.
+/// It will re-attempt to establish a connection until one is found,
+/// waiting longer after every failed attempt (see `Backoff`).  It
+/// then adds the newly created peer to the routing table.
 ///
 /// The running task then shuts down.  In case of connection drop,
 /// call `cleanup_connection`
 pub(crate) async fn start_connection(
-    session_data: SessionData,
+    mut session_data: SessionData,
     routes: Arc<Routes>,
     sender: FrameSender,
 ) -> Result<Receiver<SessionData>, SessionError> {
@@ -48,24 +106,73 @@ pub(crate) async fn start_connection(
     let routes2 = Arc::clone(&routes);
     let sender2 = sender.clone();
     spawn(async move {
-        let tcp_stream = match connect(&session_data).await {
-            Ok(tcp) => tcp,
-            Err(e) => {
-                error!("failed to establish session: {}", e);
-                todo!()
-                //            return;
-            }
-        };
+        let target = session_data.id;
+        let mut backoff = Backoff::new(RECONNECT_MIN, RECONNECT_MAX);
+
+        let (peer, id) = loop {
+            let attempt = backoff.attempts() + 1;
+            routes2
+                .set_peering_state(target, SessionState::Connecting { attempt })
+                .await;
+
+            // Peers that fail the handshake are not added either
+            let e = match connect(&mut session_data, &routes2).await {
+                Ok(tcp_stream) => {
+                    match handshake(session_data, sender2.clone(), tx.clone(), tcp_stream).await {
+                        Ok(peer) => break peer,
+                        Err(e) => e,
+                    }
+                }
+                Err(SessionError::Stopped(addr)) => {
+                    debug!("Stopped connecting to {addr}");
+                    return;
+                }
+                Err(e) => e,
+            };
 
-        let (peer, id) = match handshake(session_data, sender2, tx, tcp_stream).await {
-            Ok(peer) => peer,
-            Err(e) => {
-                error!("peering handshake failed: {}", e);
-                todo!()
-                //            return;
+            // For cross-connections we eventually give up
+            if session_data.tt == PeerType::Cross && attempt >= SESSION_TIMEOUT {
+                let e = SessionError::Refused(session_data.addr, attempt);
+                error!("{}", e);
+                routes2.status.error(e);
+                return;
             }
+
+            let delay = backoff.next_delay();
+            error!(
+                "{} [attempt {}], retrying in {}ms",
+                e,
+                attempt,
+                delay.as_millis()
+            );
+            routes2.status.error(format!(
+                "{e} (attempt {attempt}, retrying in {}s)",
+                delay.as_secs()
+            ));
+            routes2
+                .set_peering_state(target, SessionState::Waiting { attempt })
+                .await;
+            time::sleep(delay).await;
         };
 
+        // The peering may have been stopped during the handshake
+        if !routes2.wants_peering(target).await {
+            peer.close().await;
+            return;
+        }
+
+        // Without a pinned key the first key is trusted, and incoming
+        // connections with this key are now known too
+        if session_data.peer_router_key_id == Ident32::uninit() {
+            warn!(
+                "Trusting key {} of unpinned peer {} on first use; pin it with '{}@<host>:<port>'",
+                id, session_data.addr, id
+            );
+            routes2.pin_peering(target, id).await;
+        }
+        routes2
+            .set_peering_state(target, SessionState::Connected)
+            .await;
         spawn(Arc::clone(&peer).run(Arc::clone(&routes.metrics)));
         routes2.add_peer(id, Arc::clone(&peer)).await;
     });
@@ -89,6 +196,10 @@ pub(crate) async fn setup_cleanuptask(
     spawn(async move {
         debug!("setup_cleanuptask spawned");
         match rx.recv().await {
+            // Stopped peerings were already removed from the routing map
+            Some(session_data) if !routes.wants_peering(session_data.id).await => {
+                debug!("Peering {} was stopped, not restarting", session_data.id);
+            }
             Some(session_data) => {
                 debug!("Restart hook notified!");
 
@@ -109,18 +220,23 @@ pub(crate) async fn cleanup_connection(
     routes: &Arc<Routes>,
     sender: FrameSender,
 ) -> Result<(), SessionError> {
-    let peer = routes.remove_peer(session_data.peer_router_key_id).await;
-    debug!("References to PEER left: {}", Arc::strong_count(&peer));
+    if let Some(peer) = routes.remove_peer(session_data.peer_router_key_id).await {
+        debug!("References to PEER left: {}", Arc::strong_count(&peer));
+    }
 
     start_connection(session_data, Arc::clone(&routes), sender).await?;
     Ok(())
 }
 
 /// A convenient data struct to represent a session attempt
+///
+/// For outgoing sessions `peer_router_key_id` is the router id pinned
+/// in the peer line, or `Ident32::uninit()` until the first successful
+/// handshake.  Peers must always present this key.
 #[derive(Copy, Clone, Debug)]
 pub(crate) struct SessionData {
     pub(crate) id: Target,
-    pub(crate) self_router_key_id: Ident32,
+    pub(crate) self_router_key: RouterKey,
     pub(crate) peer_router_key_id: Ident32,
     pub(crate) tt: PeerType,
     pub(crate) addr: SocketAddr,
@@ -128,115 +244,123 @@ pub(crate) struct SessionData {
     pub(crate) self_port: u16,
 }
 
-/// Attempt to start a session with a peer
+/// Make a single attempt to connect to a peer
 ///
-/// When starting a `Standard` peer this session will never time-out
-/// and re-try forever (but with connection back-off).
-///
-/// For a `Cross` peer it will give up after `CROSS_SESSION_TIMEOUT`
+/// The peer line is resolved again for every attempt, so that peers
+/// with changing addresses (for example via dynamic DNS) can still be
+/// reached.  Limited peerings are not supported yet.
 pub(crate) async fn connect(
-    SessionData { tt, addr, .. }: &SessionData,
+    data: &mut SessionData,
+    routes: &Arc<Routes>,
 ) -> Result<TcpStream, SessionError> {
-    let mut holdoff = 2; // in seconds
-    let mut ctr = 0;
-    loop {
-        match TcpStream::connect(addr).await {
-            Ok(c) => {
-                info!("Successfully connected to {}", addr);
-                return Ok(c);
-            }
-            Err(_) => {
-                error!("Failed connecting to {} [attempt {}]", addr, ctr);
-                time::sleep(Duration::from_secs(holdoff)).await;
-                ctr += 1;
-            }
-        }
+    let host = routes
+        .peering_host(data.id)
+        .await
+        .ok_or(SessionError::Stopped(data.addr))?;
 
-        match tt {
-            // For cross-connections we eventually give up
-            PeerType::Cross if ctr >= SESSION_TIMEOUT => {
-                break Err(SessionError::Refused(*addr, ctr));
-            }
-            // For standard connections we just slow down our attempts up to ~69 minutes
-            PeerType::Standard if ctr >= SESSION_TIMEOUT && holdoff < 4096 => holdoff *= 2,
-            // Limited connections are not implemented yet
-            PeerType::Limited(_) => {
-                error!("APOLOGIES this feature is not yet implemented, despite what the documentation tells you >:(");
-                todo!()
-            }
-            // The match block does nothing
-            _ => {}
-        }
+    if let PeerType::Limited(_) = data.tt {
+        return Err(SessionError::Connect(
+            data.addr,
+            io::Error::new(io::ErrorKind::Unsupported, "limited peering"),
+        ));
     }
+
+    data.addr = Resolver::lookup(&host)
+        .await
+        .map_err(|e| SessionError::Resolve(host.clone(), e))?;
+    routes.set_peering_addr(data.id, data.addr).await;
+
+    let stream = time::timeout(CONNECT_TIMEOUT, TcpStream::connect(data.addr))
+        .await
+        .map_err(|_| SessionError::Connect(data.addr, io::ErrorKind::TimedOut.into()))?
+        .map_err(|e| SessionError::Connect(data.addr, e))?;
+
+    info!("Successfully connected to {} ({})", host, data.addr);
+    Ok(stream)
 }
 
 /// Establish the correct type of connection with the peer
 ///
 /// ## Handshake procedure
 ///
-/// To avoid spreading the documentation for this too thin (TODO:
-/// write a manual or something), here is an outline of what needs
-/// to happen.
-///
-/// We have just created a connection to a peer.  Now we need to
-/// send a HANDSHAKE packet, letting the peer know who we are and what
-/// we want.  This includes the PeerType, our own listening port,
-/// and whether we are into dynimac peering or not (not used in
-/// this version yet).
+/// We have just created a connection to a peer.  Now we run the
+/// encrypted handshake (see the `noise` module), which authenticates
+/// both routers by their keys.  As part of it we send a HELLO,
+/// letting the peer know what we want.  This includes the PeerType
+/// and our own listening port.
 ///
-/// If anything goes wrong during the handshake we close the
+/// If anything goes wrong during the handshake, or the peer presents
+/// a different key than during a previous session, we close the
 /// connection again, and re-try to connect from the beginning.
 async fn handshake(
-    data: SessionData,
+    mut data: SessionData,
     sender: FrameSender,
     restart: Sender<SessionData>,
-    stream: TcpStream,
+    mut stream: TcpStream,
 ) -> Result<(Arc<Peer>, Ident32), SessionError> {
     let hello = Handshake::Hello {
         tt: data.tt,
         self_port: 0,
-        r_key_id: data.self_router_key_id,
-    }
-    .to_carrier()
-    .unwrap();
-
-    let peer_addr = stream.peer_addr().expect("todo: fix this error type");
-    let (mut read_stream, mut write_stream) = stream.into_split();
-
-    proto::write(&mut write_stream, &hello)
-        .await
-        .map_err(|e| SessionError::Handshake(data, e.to_string()))
-        .unwrap();
-
-    let ack_env: InMemoryEnvelope = proto::read_blocking(&mut read_stream)
-        .await
-        .map_err(|e| SessionError::Handshake(data, e.to_string()))
-        .unwrap();
-
-    let ack = match Handshake::from_carrier(&ack_env) {
-        Err(RatmanError::Nonfatal(_nf)) => {
-            warn!("Expected to receive a Handshake::Ack but received something different!");
-            unimplemented!()
-        }
-        Ok(ack) => ack,
-        Err(e) => return Err(SessionError::Handshake(data, e.to_string())),
     };
 
-    // ??? what does this match block actually do
-    let r_key_id = match (data.tt, ack) {
-        (outgoing, Handshake::Ack { tt, r_key_id }) if outgoing == tt => {
-            debug!("Handshake with {:?} was successful!", peer_addr);
-            r_key_id
-        }
+    let session = time::timeout(
+        HANDSHAKE_TIMEOUT,
+        noise::initiate(&mut stream, &data.self_router_key, hello),
+    )
+    .await
+    .map_err(|_| SessionError::Authentication(data.addr, "handshake timed out".into()))?
+    .map_err(|e| SessionError::Authentication(data.addr, e.to_string()))?;
+
+    match session.handshake {
+        Handshake::Ack { tt } if tt == data.tt => {}
         _ => {
-            error!("Handshake with {:?} was unsuccessful", peer_addr);
-            drop((write_stream, read_stream));
+            error!("Handshake with {:?} was unsuccessful", data.addr);
             return Err(SessionError::Dropped(data.addr));
         }
-    };
+    }
 
+    let r_key_id = session.remote_key;
+    let known = data.peer_router_key_id;
+    if known != Ident32::uninit() && known != r_key_id {
+        return Err(SessionError::Authentication(
+            data.addr,
+            format!("expected key {} but the peer presented {}", known, r_key_id),
+        ));
+    }
+
+    debug!("Handshake with {:?} was successful!", data.addr);
+
+    // Frames from this peer are reported to the router with its key.
+    // Without a pinned key this would otherwise stay uninitialised.
+    data.peer_router_key_id = r_key_id;
+    let (read_stream, write_stream) = stream.into_split();
     Ok((
-        Peer::standard(data, sender, Some(restart), write_stream, read_stream),
+        Peer::standard(
+            data,
+            sender,
+            Some(restart),
+            session.cipher,
+            write_stream,
+            read_stream,
+        ),
         r_key_id,
     ))
 }
+
+#[test]
+fn backoff_grows_with_jitter() {
+    let min = Duration::from_secs(1);
+    let max = Duration::from_secs(10);
+    let mut backoff = Backoff::new(min, max);
+
+    for base in [1, 2, 4, 8, 10, 10].iter().map(|s| Duration::from_secs(*s)) {
+        let delay = backoff.next_delay();
+        assert!(delay >= base / 2 && delay <= base, "{:?}", delay);
+    }
+    assert_eq!(backoff.attempts(), 6);
+
+    // The delay never overflows
+    for _ in 0..100 {
+        assert!(backoff.next_delay() <= max);
+    }
+}
@@ -18,7 +18,7 @@ pub(crate) use framing::MemoryEnvelopeExt;
 use async_trait::async_trait;
 use libratman::{
     endpoint::{EndpointExt, NeighbourMetrics},
-    types::{Ident32, InMemoryEnvelope, Neighbour},
+    types::{CurrentStatus, Ident32, InMemoryEnvelope, Neighbour},
     NetmodError, NonfatalError, RatmanError, Result,
 };
 use pnet_datalink::interfaces;
@@ -65,6 +65,24 @@ impl Endpoint {
 
 #[async_trait]
 impl EndpointExt for Endpoint {
+    /// Frames are sent as single UDP datagrams, which should fit into an
+    /// ethernet frame to avoid IP fragmentation
+    fn size_hint(&self) -> usize {
+        socket::MAX_DATAGRAM_SIZE
+    }
+
+    /// Every discovered neighbour counts as a peer
+    async fn status(&self) -> CurrentStatus {
+        let peers = self.addrs.all().await.len() as u32;
+        self.socket.status.snapshot(peers)
+    }
+
+    /// Stop receiving frames and release the UDP port
+    async fn shutdown(&self) -> Result<()> {
+        self.socket.shutdown().await;
+        Ok(())
+    }
+
     async fn metrics_for_neighbour(&self, n: Neighbour) -> Result<NeighbourMetrics> {
         match n {
             Neighbour::Single(id) => {
@@ -121,3 +139,24 @@ pub fn default_iface() -> Option<String> {
         .find(|e| e.is_up() && !e.is_loopback() && e.ips.iter().any(|ip| ip.is_ipv6()))
         .map(|iface| iface.name)
 }
+
+#[test]
+fn shutdown_releases_port() {
+    use libratman::{rt::AsyncSystem, types::LinkState};
+    let system = AsyncSystem::new("shutdown-releases-port".into(), 1);
+    system
+        .exec(async {
+            let lan = Endpoint::spawn(Some("lo".into()), 0, Ident32::random())
+                .await
+                .unwrap();
+            let port = lan.socket.port().await.unwrap();
+            lan.shutdown().await?;
+            assert_eq!(lan.status().await.state, LinkState::Down);
+
+            Endpoint::spawn(Some("lo".into()), port, Ident32::random())
+                .await
+                .unwrap();
+            Ok::<_, RatmanError>(())
+        })
+        .unwrap()
+}
@@ -7,7 +7,7 @@
 use crate::{framing::HandshakeV1, AddrTable, MemoryEnvelopeExt};
 use libratman::endpoint::NeighbourMetrics;
 use libratman::futures::future::{self, Future};
-use libratman::tokio::task::spawn;
+use libratman::tokio::task::{spawn, JoinHandle};
 use libratman::tokio::time::{sleep, Instant};
 use libratman::tokio::{net::UdpSocket, sync::RwLock, task};
 use libratman::{
@@ -20,19 +20,26 @@ use std::net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6};
 use std::time::Duration;
 use std::{pin::Pin, sync::Arc, task::Poll};
 use task_notify::Notify;
-use useful_netmod_bits::metrics::MetricsTable;
+use useful_netmod_bits::{metrics::MetricsTable, status::StatusTracker};
 
 const MULTI: Ipv6Addr = Ipv6Addr::new(0xFF02, 0, 0, 0, 0, 0, 0, 0x1312);
 const SELF: Ipv6Addr = Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0);
 
+/// Ethernet MTU minus the IPv6 and UDP headers
+pub(crate) const MAX_DATAGRAM_SIZE: usize = 1500 - 40 - 8;
+
 /// Wraps around a UDP socket an the input queue
 pub(crate) struct Socket {
     port: u16,
     scope: u32,
     self_rk_id: Ident32,
-    sock: Arc<UdpSocket>,
+    /// Taken when the socket is shut down, which releases the port
+    sock: RwLock<Option<Arc<UdpSocket>>>,
+    /// Task receiving incoming datagrams, until the socket is shut down
+    incoming: RwLock<Option<JoinHandle<()>>>,
     inbox: Arc<RwLock<Notify<VecDeque<MemoryEnvelopeExt>>>>,
     pub metrics: Arc<MetricsTable<SocketAddrV6>>,
+    pub status: StatusTracker,
 }
 
 fn if_nametoindex(name: &str) -> std::io::Result<u32> {
@@ -71,16 +78,20 @@ impl Socket {
         sock.set_multicast_loop_v6(false)
             .expect("Failed to set_multicast_loop_v6. Error");
 
+        let sock = Arc::new(sock);
         let arc = Arc::new(Self {
             port,
             scope,
             self_rk_id: r_key_id,
-            sock: Arc::new(sock),
+            sock: RwLock::new(Some(Arc::clone(&sock))),
+            incoming: Default::default(),
             inbox: Default::default(),
             metrics: Arc::new(MetricsTable::default()),
+            status: StatusTracker::default(),
         });
 
-        Self::incoming_handle(Arc::clone(&arc), table);
+        let incoming = Self::incoming_handle(Arc::clone(&arc), sock, table);
+        *arc.incoming.write().await = Some(incoming);
         arc.multicast(&HandshakeV1::Announce(arc.self_rk_id).to_carrier().unwrap())
             .await;
 
@@ -89,28 +100,65 @@ impl Socket {
 
     /// Send a message to one specific client
     pub(crate) async fn send(&self, env: &InMemoryEnvelope, peer: SocketAddrV6) {
-        let bytes_written = self
-            .sock
-            .send_to(&env.buffer.as_slice(), peer)
-            .await
-            .unwrap();
+        let sock = match self.sock.read().await.clone() {
+            Some(sock) => sock,
+            None => return,
+        };
+
+        let bytes_written = match sock.send_to(&env.buffer.as_slice(), peer).await {
+            Ok(bytes_written) => bytes_written,
+            Err(e) => {
+                error!("failed to send frame to {}: {}", peer, e);
+                self.status.error(e);
+                return;
+            }
+        };
+        self.status.sent(bytes_written);
         let metrics = Arc::clone(&self.metrics);
         spawn(async move { metrics.append_write(peer, bytes_written).await });
     }
 
     /// Send a multicast with an InMemoryEnvelope
     pub(crate) async fn multicast(&self, env: &InMemoryEnvelope) {
-        match self
-            .sock
+        let sock = match self.sock.read().await.clone() {
+            Some(sock) => sock,
+            None => return,
+        };
+
+        match sock
             .send_to(
                 &env.buffer.as_slice(),
                 SocketAddrV6::new(MULTI.clone(), self.port, 0, self.scope),
             )
             .await
         {
-            Ok(_) => trace!("Sent multicast announcement"),
-            Err(e) => error!("failed to multicast frame: {}", e),
+            Ok(bytes_written) => {
+                trace!("Sent multicast announcement");
+                self.status.sent(bytes_written);
+            }
+            Err(e) => {
+                error!("failed to multicast frame: {}", e);
+                self.status.error(e);
+            }
+        }
+    }
+
+    /// Stop receiving datagrams and close the socket
+    pub(crate) async fn shutdown(&self) {
+        // Wait for the task to be dropped, since it holds on to the socket
+        if let Some(incoming) = self.incoming.write().await.take() {
+            incoming.abort();
+            let _ = incoming.await;
         }
+
+        self.sock.write().await.take();
+        self.status.set_down();
+    }
+
+    #[cfg(test)]
+    pub(crate) async fn port(&self) -> Option<u16> {
+        let sock = self.sock.read().await.clone()?;
+        sock.local_addr().ok().map(|addr| addr.port())
     }
 
     pub(crate) async fn next(&self) -> MemoryEnvelopeExt {
@@ -131,14 +179,18 @@ impl Socket {
         .await
     }
 
-    #[instrument(skip(arc, table), level = "trace")]
-    fn incoming_handle(arc: Arc<Self>, table: Arc<AddrTable>) {
+    #[instrument(skip(arc, sock, table), level = "trace")]
+    fn incoming_handle(
+        arc: Arc<Self>,
+        sock: Arc<UdpSocket>,
+        table: Arc<AddrTable>,
+    ) -> JoinHandle<()> {
         spawn(async move {
             loop {
                 // fixme: aaaaaaaaaaaaaaaaaaaaaaaaaah
                 let mut buf = vec![0; 1024 * 16];
 
-                match arc.sock.recv_from(&mut buf).await {
+                match sock.recv_from(&mut buf).await {
                     Ok((bytes_read, peer)) => {
                         let peer = match peer {
                             SocketAddr::V6(v6) => v6,
@@ -148,13 +200,14 @@ impl Socket {
                             }
                         };
 
+                        arc.status.received(bytes_read);
                         let metrics = Arc::clone(&arc.metrics);
                         spawn(async move { metrics.append_read(peer, bytes_read).await });
 
                         // Skip this frame if it came from self --
                         // this happens because multicast receives our
                         // own messages too
-                        match arc.sock.local_addr() {
+                        match sock.local_addr() {
                             Ok(SocketAddr::V6(local)) if local == peer => continue,
                             Ok(_) => {}
                             _data => {
@@ -194,14 +247,16 @@ impl Socket {
                             }
                         }
                     }
-                    val => {
+                    Err(e) => {
                         // TODO: handle errors more gracefully
-                        error!("Crashed UDP thread: {:#?}", val);
-                        val.expect("Crashed UDP thread!");
+                        error!("Crashed UDP thread: {:#?}", e);
+                        arc.status.error(&e);
+                        arc.status.set_down();
+                        break;
                     }
                 }
             }
-        });
+        })
     }
 }
 
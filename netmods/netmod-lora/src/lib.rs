@@ -3,25 +3,32 @@
 #[macro_use]
 extern crate tracing;
 
-use irdest_firmware_util::{decode_frame, encode_frame};
 use libratman::{
     endpoint::EndpointExt,
-    types::{Ident32, InMemoryEnvelope, Neighbour},
+    types::{CurrentStatus, Ident32, InMemoryEnvelope, Neighbour},
     NetmodError, RatmanError, Result as RatmanResult,
 };
+use useful_netmod_bits::{
+    fragment::{Fragment, Fragmenter, Reassembler, RtxRequest, FRAGMENT_HEADER_SIZE},
+    status::StatusTracker,
+};
 
 use async_std::{channel, sync::Arc, sync::Mutex, task};
 use async_trait::async_trait;
 
 use serialport::TTYPort;
 use std::io::prelude::*;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 const BUFFER_SIZE: usize = 32; // sets the depth of the netmod's recieve buffer.
 
 const IRDEST_MAGIC: u8 = 0xCA; // sets the unique protocol identifier for irdest traffic, changing will split the network.
 const RADIO_MTU: usize = 255; // sets the size of data block expected by the modem. This is correct for sx127x based modems.
 
+const SENT_HISTORY: usize = 16; // sets how many sent frames are kept to answer retransmission requests.
+const RTX_AFTER: Duration = Duration::from_secs(2); // sets how long an incomplete frame may be idle before requesting lost fragments.
+const MAX_RTX_REQUESTS: u8 = 3; // sets how often lost fragments are requested before a frame is dropped.
+
 #[repr(u8)]
 #[derive(Copy, Clone, Debug)]
 enum CtrlTypeCode {
@@ -68,6 +75,25 @@ impl LoraPacket {
         out
     }
 
+    fn new(packet_type: CtrlTypeCode, data: &[u8]) -> Self {
+        let mut payload = [0; PAYLOAD_SIZE];
+        payload[..data.len()].copy_from_slice(data);
+
+        Self {
+            header: CtrlHeader {
+                magic: IRDEST_MAGIC,
+                packet_type,
+                length: data.len() as u8,
+            },
+            payload,
+        }
+    }
+
+    /// The part of the payload that actually contains data
+    fn data(&self) -> &[u8] {
+        &self.payload[..(self.header.length as usize).min(PAYLOAD_SIZE)]
+    }
+
     fn decode(data: [u8; RADIO_MTU]) -> Result<Self, LoraPacketError> {
         if data[0] != IRDEST_MAGIC {
             return Err(LoraPacketError::InvalidMagicNumber(data[0]));
@@ -93,30 +119,53 @@ impl LoraPacket {
     }
 }
 
+/// A LoRa radio endpoint
+///
+/// Carrier frames are much larger than a single radio packet, so they are
+/// split into fragments.  Incomplete frames are detected after `RTX_AFTER`,
+/// and lost fragments are requested again via `RtxReq` packets.
 #[allow(unused)]
 pub struct LoraEndpoint {
     rx: channel::Receiver<InMemoryEnvelope>,
     router_pk_id: Ident32,
-    serial: Mutex<TTYPort>,
+    /// Taken when the endpoint is shut down, which closes the port
+    serial: Mutex<Option<TTYPort>>,
+    /// Tasks reading from the modem, until the endpoint is shut down
+    tasks: Mutex<Vec<task::JoinHandle<()>>>,
+    fragmenter: Mutex<Fragmenter>,
+    reassembler: Mutex<Reassembler>,
+    status: StatusTracker,
 }
 
 impl LoraEndpoint {
     pub fn spawn(port: &str, baud: u32, router_pk_id: Ident32) -> Arc<Self> {
-        let (tx, rx) = channel::bounded(BUFFER_SIZE);
         let serial = serialport::new(port, baud)
             .timeout(Duration::from_millis(10))
             .open_native()
             .expect("Failed to open port");
 
+        let this = Self::with_serial(serial, router_pk_id);
+        info!("Created Successfully!");
+        this
+    }
+
+    fn with_serial(serial: TTYPort, router_pk_id: Ident32) -> Arc<Self> {
+        let (tx, rx) = channel::bounded(BUFFER_SIZE);
         let this = Arc::new(Self {
             rx,
             router_pk_id,
-            serial: Mutex::new(serial),
+            serial: Mutex::new(Some(serial)),
+            tasks: Mutex::new(vec![]),
+            fragmenter: Mutex::new(Fragmenter::new(PAYLOAD_SIZE, SENT_HISTORY)),
+            reassembler: Mutex::new(Reassembler::new(RTX_AFTER, MAX_RTX_REQUESTS)),
+            status: StatusTracker::default(),
         });
 
-        task::spawn(Self::read_serial(this.clone(), tx));
-
-        info!("Created Successfully!");
+        let tasks = vec![
+            task::spawn(Self::read_serial(this.clone(), tx)),
+            task::spawn(Self::request_lost(this.clone())),
+        ];
+        *this.tasks.try_lock().unwrap() = tasks;
         this
     }
 
@@ -124,11 +173,22 @@ impl LoraEndpoint {
         debug!("Starting serial Read loop");
         let mut buffer: [u8; RADIO_MTU] = [0; RADIO_MTU];
         loop {
-            match self.serial.lock().await.read_exact(&mut buffer) {
+            let read = match self.serial.lock().await.as_mut() {
+                Some(serial) => serial.read_exact(&mut buffer),
+                None => break,
+            };
+
+            match read {
                 Ok(()) => (),
                 Err(e) if e.kind() == std::io::ErrorKind::TimedOut => continue,
-                Err(e) => panic!("{:?}", e),
+                Err(e) => {
+                    error!("Serial read error: {}", e);
+                    self.status.error(e);
+                    self.status.set_down();
+                    break;
+                }
             }
+            self.status.received(RADIO_MTU);
 
             // trace!("rx <= {:?}", buffer);
 
@@ -142,21 +202,109 @@ impl LoraEndpoint {
 
             trace!("recieved packet");
 
-            let frame = match decode_frame(&mut rx_packet.payload.as_slice()) {
-                Ok(f) => f,
-                Err(e) => {
-                    error!("failed to decode recieved packet: {}", e);
-                    continue;
+            let packet_type = rx_packet.header.packet_type;
+            match packet_type {
+                CtrlTypeCode::Data => {
+                    let fragment = match Fragment::decode(rx_packet.data()) {
+                        Ok(f) => f,
+                        Err(e) => {
+                            error!("failed to decode recieved fragment: {}", e);
+                            continue;
+                        }
+                    };
+
+                    match self.reassembler.lock().await.insert(fragment) {
+                        Some(Ok(frame)) => c.send(frame).await.unwrap(),
+                        Some(Err(e)) => error!("failed to decode reassembled frame: {}", e),
+                        None => {}
+                    }
                 }
-            };
+                CtrlTypeCode::RtxReq => {
+                    let req = match RtxRequest::decode(rx_packet.data()) {
+                        Ok(req) => req,
+                        Err(e) => {
+                            error!("failed to decode retransmission request: {}", e);
+                            continue;
+                        }
+                    };
+
+                    // Requests for frames we didn't send yield no fragments
+                    let fragments = self.fragmenter.lock().await.retransmit(&req);
+                    for f in fragments {
+                        self.write_packet(CtrlTypeCode::Data, &f.encode()).await;
+                    }
+                }
+                CtrlTypeCode::_Invalid => {}
+            }
+        }
+    }
+
+    /// Regularly request fragments of incomplete frames
+    async fn request_lost(self: Arc<Self>) {
+        loop {
+            task::sleep(RTX_AFTER / 2).await;
+
+            let requests = self.reassembler.lock().await.lost(Instant::now());
+            for mut req in requests {
+                // Anything beyond a single packet will be requested next time
+                req.missing.truncate(PAYLOAD_SIZE - 5);
+                debug!(
+                    "requesting {} lost fragments of frame {}",
+                    req.missing.len(),
+                    req.msg_id
+                );
+                self.write_packet(CtrlTypeCode::RtxReq, &req.encode()).await;
+            }
+        }
+    }
 
-            c.send(frame).await.unwrap();
+    async fn write_packet(&self, packet_type: CtrlTypeCode, data: &[u8]) {
+        let buffer = LoraPacket::new(packet_type, data).encode();
+
+        // trace!("tx => {:?}", buffer);
+
+        let written = match self.serial.lock().await.as_mut() {
+            Some(serial) => serial.write_all(&buffer),
+            None => return,
+        };
+
+        match written {
+            Ok(()) => {
+                trace!("Sent Packet");
+                self.status.sent(buffer.len());
+            }
+            Err(e) => {
+                error!("Serial Write error: {}", e);
+                self.status.error(e);
+            }
         }
     }
 }
 
 #[async_trait]
 impl EndpointExt for LoraEndpoint {
+    /// Larger frames are fragmented, but frames that fit into a single radio
+    /// packet are much less likely to get lost
+    fn size_hint(&self) -> usize {
+        PAYLOAD_SIZE - FRAGMENT_HEADER_SIZE
+    }
+
+    /// Radio packets are broadcast, so the number of peers is unknown
+    async fn status(&self) -> CurrentStatus {
+        self.status.snapshot(0)
+    }
+
+    /// Stop reading from the modem and close the serial port
+    async fn shutdown(&self) -> RatmanResult<()> {
+        for task in self.tasks.lock().await.drain(..) {
+            task.cancel().await;
+        }
+
+        self.serial.lock().await.take();
+        self.status.set_down();
+        Ok(())
+    }
+
     async fn send(
         &self,
         frame: InMemoryEnvelope,
@@ -168,35 +316,11 @@ impl EndpointExt for LoraEndpoint {
             return Ok(());
         }
 
-        let header = CtrlHeader {
-            magic: IRDEST_MAGIC,
-            packet_type: CtrlTypeCode::Data,
-            length: PAYLOAD_SIZE as u8,
-        };
+        let fragments = self.fragmenter.lock().await.split(&frame)?;
+        trace!("Sending frame in {} fragments", fragments.len());
 
-        let mut payload = vec![];
-        encode_frame(&mut payload, &frame).unwrap();
-
-        loop {
-            if payload.len() == PAYLOAD_SIZE {
-                break;
-            }
-            payload.push(0);
-        }
-
-        assert_eq!(payload.len(), PAYLOAD_SIZE);
-
-        let payload = payload.as_slice().try_into().unwrap();
-
-        let tx_packet = LoraPacket { header, payload };
-
-        let buffer = tx_packet.encode();
-
-        // trace!("tx => {:?}", buffer);
-
-        match self.serial.lock().await.write_all(&buffer) {
-            Ok(()) => trace!("Sent Packet"),
-            Err(e) => error!("Serial Write error: {}", e),
+        for f in fragments {
+            self.write_packet(CtrlTypeCode::Data, &f.encode()).await;
         }
 
         Ok(())
@@ -217,3 +341,20 @@ impl EndpointExt for LoraEndpoint {
         Ok((frame, Neighbour::Single(peer_router_key_id)))
     }
 }
+
+#[test]
+fn shutdown_closes_port() {
+    use libratman::types::LinkState;
+
+    task::block_on(async {
+        let (mut modem, serial) = TTYPort::pair().unwrap();
+        let lora = LoraEndpoint::with_serial(serial, Ident32::random());
+        lora.shutdown().await.unwrap();
+        assert_eq!(lora.status().await.state, LinkState::Down);
+
+        // Reading from the other side of a closed pty fails right away
+        let mut buf = [0; RADIO_MTU];
+        let err = modem.read(&mut buf).unwrap_err();
+        assert_ne!(err.kind(), std::io::ErrorKind::TimedOut);
+    })
+}
@@ -3,18 +3,24 @@
 // SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
 
 use libratman::tokio::sync::mpsc::{channel, Receiver, Sender};
-use libratman::types::InMemoryEnvelope;
+use libratman::types::{Ident32, InMemoryEnvelope};
+
+/// The number of frames that can be in transit on a single channel
+pub(crate) const CHANNEL_SIZE: usize = 64;
+
+/// A frame in transit, tagged with the ID of the sending `MemMod`
+pub(crate) type Tagged = (InMemoryEnvelope, Ident32);
 
 /// A simple I/O wrapper around channels
 pub(crate) struct Io {
-    pub out: Sender<InMemoryEnvelope>,
-    pub inc: Receiver<InMemoryEnvelope>,
+    pub out: Sender<Tagged>,
+    pub inc: Receiver<Tagged>,
 }
 
 impl Io {
     pub(crate) fn make_pair() -> (Io, Io) {
-        let (a_to_b, b_from_a) = channel(1);
-        let (b_to_a, a_from_b) = channel(1);
+        let (a_to_b, b_from_a) = channel(CHANNEL_SIZE);
+        let (b_to_a, a_from_b) = channel(CHANNEL_SIZE);
         let a = Io {
             out: a_to_b,
             inc: a_from_b,
@@ -23,6 +29,6 @@ impl Io {
             out: b_to_a,
             inc: b_from_a,
         };
-        return (a, b);
+        (a, b)
     }
 }
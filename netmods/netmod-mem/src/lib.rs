@@ -5,7 +5,9 @@
 //! `netmod-mem` is an in-memory `netmod` endpoint
 //!
 //! This aims to make testing any structure that binds against
-//! `netmod` easier and reproducible.
+//! `netmod` easier and reproducible.  Two `MemMod`s can be linked
+//! directly to each other, or any number of them can be connected via
+//! a [`BroadcastMedium`](media::BroadcastMedium).
 
 #![doc(html_favicon_url = "https://irde.st/favicon.ico")]
 #![doc(html_logo_url = "https://irde.st/img/logo.png")]
@@ -13,11 +15,14 @@
 use async_trait::async_trait;
 use libratman::{
     endpoint::EndpointExt,
-    tokio::sync::RwLock,
-    types::{Ident32, InMemoryEnvelope, Neighbour},
+    tokio::sync::{mpsc::Receiver, mpsc::Sender, Mutex, RwLock},
+    types::{CurrentStatus, Ident32, InMemoryEnvelope, LinkState, Neighbour},
     NetmodError, RatmanError, Result as RatResult,
 };
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 
 /// An input/output pair of `mpsc::channel`s.
 ///
@@ -25,10 +30,28 @@ use std::sync::Arc;
 /// different places.
 pub(crate) mod io;
 
-/// Represents a one-to-one in-memory netmod for testing purposes
+/// Simulated media that connect more than two `MemMod`s
+pub mod media;
+
+use io::Tagged;
+use media::BroadcastMedium;
+
+/// The outgoing side of a `MemMod`
+pub(crate) enum Link {
+    /// A 1-to-1 link to another `MemMod`
+    Pair { out: Sender<Tagged>, peer: Ident32 },
+    /// A shared medium with any number of other `MemMod`s
+    Medium(Arc<BroadcastMedium>),
+}
+
+/// Represents an in-memory netmod for testing purposes
 pub struct MemMod {
-    /// Internal memory access to send/receive
-    io: Arc<RwLock<Option<io::Io>>>,
+    /// Internal memory access to send
+    link: RwLock<Option<Link>>,
+    /// Internal memory access to receive
+    inc: Mutex<Option<Receiver<Tagged>>>,
+    /// Drop all traffic while the link is partitioned
+    partitioned: AtomicBool,
     self_rk_id: Ident32,
 }
 
@@ -36,7 +59,9 @@ impl MemMod {
     /// Create a new, unpaired `MemMod`.
     pub fn new(self_rk_id: Ident32) -> Arc<Self> {
         Arc::new(Self {
-            io: Default::default(),
+            link: Default::default(),
+            inc: Default::default(),
+            partitioned: AtomicBool::new(false),
             self_rk_id,
         })
     }
@@ -51,10 +76,15 @@ impl MemMod {
         (a, b)
     }
 
+    /// The ID that this `MemMod` is known by to its neighbours
+    pub fn id(&self) -> Ident32 {
+        self.self_rk_id
+    }
+
     /// Return `true` if the MemMod is linked to another one or
     /// `false` otherwise.
     pub async fn linked(&self) -> bool {
-        self.io.read().await.is_some()
+        self.link.read().await.is_some()
     }
 
     /// Establish a 1-to-1 link between two `MemMod`s.
@@ -68,24 +98,82 @@ impl MemMod {
         }
         let (my_io, their_io) = io::Io::make_pair();
 
-        self.set_io_async(my_io).await;
-        pair.set_io_async(their_io).await;
+        self.set_link(
+            Some(Link::Pair {
+                out: my_io.out,
+                peer: pair.id(),
+            }),
+            Some(my_io.inc),
+        )
+        .await;
+        pair.set_link(
+            Some(Link::Pair {
+                out: their_io.out,
+                peer: self.id(),
+            }),
+            Some(their_io.inc),
+        )
+        .await;
     }
 
     /// Remove the connection between MemMods.
     pub async fn split(&self) {
         // The previous value in here will now be dropped,
         // so future messages will fail.
-        self.set_io_async(None).await;
+        if let Some(Link::Medium(medium)) = self.link.write().await.take() {
+            medium.detach(self.self_rk_id).await;
+        }
+    }
+
+    /// Silently drop all traffic on this `MemMod` until it is healed
+    ///
+    /// Unlike [`split`](Self::split) this keeps the link around, so that
+    /// network partitions can be simulated and healed again.
+    pub fn partition(&self) {
+        self.partitioned.store(true, Ordering::Release);
     }
 
-    async fn set_io_async<I: Into<Option<io::Io>>>(&self, val: I) {
-        *self.io.write().await = val.into();
+    /// Resume sending and receiving traffic after a partition
+    pub fn heal(&self) {
+        self.partitioned.store(false, Ordering::Release);
+    }
+
+    /// Return `true` if this `MemMod` is currently partitioned
+    pub fn partitioned(&self) -> bool {
+        self.partitioned.load(Ordering::Acquire)
+    }
+
+    pub(crate) async fn set_link(&self, link: Option<Link>, inc: Option<Receiver<Tagged>>) {
+        *self.link.write().await = link;
+        *self.inc.lock().await = inc;
     }
 }
 
 #[async_trait]
 impl EndpointExt for MemMod {
+    /// Frames are passed around in memory and never need to be split
+    fn size_hint(&self) -> usize {
+        0
+    }
+
+    /// Partitioned links are degraded, and split links are down
+    async fn status(&self) -> CurrentStatus {
+        let (state, peers) = match *self.link.read().await {
+            None => (LinkState::Down, 0),
+            Some(_) if self.partitioned() => (LinkState::Degraded, 0),
+            Some(Link::Pair { .. }) => (LinkState::Up, 1),
+            Some(Link::Medium(ref medium)) => {
+                (LinkState::Up, medium.len().await.saturating_sub(1) as u32)
+            }
+        };
+
+        CurrentStatus {
+            state,
+            peers,
+            ..Default::default()
+        }
+    }
+
     /// Send a message to a specific endpoint (client)
     ///
     /// # Errors
@@ -95,24 +183,46 @@ impl EndpointExt for MemMod {
     async fn send(
         &self,
         frame: InMemoryEnvelope,
-        _: Neighbour,
+        target: Neighbour,
         exclude: Option<Ident32>,
     ) -> RatResult<()> {
-        let io = self.io.read().await;
-        match *io {
+        let link = self.link.read().await;
+        match *link {
             None => Err(RatmanError::Netmod(NetmodError::NotSupported)),
-            Some(ref io) if exclude.is_none() => Ok(io.out.send(frame).await.unwrap()),
-            _ => Ok(()), // when exclude is some we just drop the frame
+            // A partitioned link drops everything
+            Some(_) if self.partitioned() => Ok(()),
+            // Don't send a frame back to the neighbour it came from
+            Some(Link::Pair { peer, .. }) if exclude == Some(peer) => Ok(()),
+            Some(Link::Pair { ref out, .. }) => out
+                .send((frame, self.self_rk_id))
+                .await
+                .map_err(|e| RatmanError::Netmod(NetmodError::ConnectionLost(e.0 .0))),
+            Some(Link::Medium(ref medium)) => {
+                medium
+                    .transmit(self.self_rk_id, frame, target, exclude)
+                    .await;
+                Ok(())
+            }
         }
     }
 
     async fn next(&self) -> RatResult<(InMemoryEnvelope, Neighbour)> {
-        let mut io = self.io.write().await;
-        match *io {
+        let mut inc = self.inc.lock().await;
+        match *inc {
             None => Err(RatmanError::Netmod(NetmodError::NotSupported)),
-            Some(ref mut io) => match io.inc.recv().await {
-                Some(f) => Ok((f, Neighbour::Single(self.self_rk_id))),
-                None => Err(RatmanError::Netmod(NetmodError::RecvSocketClosed)),
+            Some(ref mut inc) => loop {
+                // A split link may still have frames in flight, which are
+                // never received
+                if !self.linked().await {
+                    break Err(RatmanError::Netmod(NetmodError::RecvSocketClosed));
+                }
+
+                match inc.recv().await {
+                    Some(_) if self.partitioned() => continue,
+                    Some(_) if !self.linked().await => continue,
+                    Some((f, from)) => break Ok((f, Neighbour::Single(from))),
+                    None => break Err(RatmanError::Netmod(NetmodError::RecvSocketClosed)),
+                }
             },
         }
     }
@@ -2,8 +2,5 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
 
-mod tagged_frame;
-pub(crate) use tagged_frame::TaggedFrame;
-
 mod broadcast_medium;
 pub use broadcast_medium::BroadcastMedium;
@@ -2,142 +2,85 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
 
-use crate::io::Io;
-use crate::media::TaggedFrame;
-use crate::MemMod;
-use crossbeam_channel::TryRecvError;
-use ratman_netmod::Endpoint;
-use std::collections::{BTreeMap, VecDeque};
+use crate::{
+    io::{Tagged, CHANNEL_SIZE},
+    Link, MemMod,
+};
+use libratman::{
+    tokio::sync::{mpsc::channel, mpsc::Sender, RwLock},
+    types::{Ident32, InMemoryEnvelope, Neighbour},
+};
+use std::{collections::BTreeMap, sync::Arc};
 
-/// A `BroadcastMedium` permits up to 2^32 `MemMod` interfaces to connect and
-/// always sends all messages to all connected interfaces except for the sender.
-// MemMods are assigned a 4-byte tag, and Frames are tagged with the internal ID
-// of their sender, so they can be broadcast to only other connected interfaces,
-// and a TTL value, which is decremented on each tick. Once that value reaches zero,
-// the Frame can be transmitted.
-// This is effective but remains lightweight, adding only 8 bytes to each
-// Frame while in "transit".
+/// A `BroadcastMedium` connects any number of `MemMod` interfaces to each other
+///
+/// Flooded frames are sent to all connected interfaces except for the sender,
+/// while frames for a single neighbour only reach that neighbour.  Each frame
+/// is tagged with the ID of the `MemMod` that sent it, which is reported as the
+/// neighbour by the receiving side.
 #[derive(Default)]
 pub struct BroadcastMedium {
-    /// Records the number of ticks since creation.
-    ticks: u128,
-    /// The number of system ticks a frame spends in transmission; that is, the time
-    /// between a frame being sent and the frame being available to other modules.
-    latency: u32,
-    /// The last tag used for a MemMod.
-    last_tag: u32,
-    /// The frames currently in transmission.
-    buffer: VecDeque<TaggedFrame>,
-    /// The raw `mpsc` transmission interfaces, with their associated tags.
-    interfaces: BTreeMap<u32, Io>,
+    /// The receiving channels of all connected interfaces
+    interfaces: RwLock<BTreeMap<Ident32, Sender<Tagged>>>,
 }
 
 impl BroadcastMedium {
-    /// Create a new `BroadcastMedium` with the given latency.
-    ///
-    /// No `MemMod`s will be connected and no packets will be in transit.
-    pub fn with_latency(latency: u32) -> Self {
-        assert_ne!(
-            latency, 0,
-            "Cannot create a BroadcastMedium with latency == 0."
-        );
-        Self {
-            latency,
-            ..Default::default()
-        }
+    /// Create a new `BroadcastMedium` without any connected `MemMod`s
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
     }
 
-    /// Create and return an `Endpoint` (a `MemMod`) connected to this `BroadcastMedium`.
-    ///
-    /// This `Endpoint` will be assigned a unique ID and can immediately be used to
-    /// send and recieve messages from the `BroadcastMedium`.
-    ///
-    /// # Examples
+    /// Create a `MemMod` that is connected to this `BroadcastMedium`
     ///
-    /// ```
-    /// # use netmod_mem::media::BroadcastMedium;
-    /// # use ratman_netmod::{Frame, Endpoint, Target};
-    /// # #[async_std::main]
-    /// # async fn main() {
-    /// let mut medium = BroadcastMedium::with_latency(1);
-    /// let mut a = medium.make_netmod();
-    /// a.send(Frame::dummy(), Target::default()).await.expect("Couldn't send frame from a.");
-    ///
-    /// let mut b = medium.make_netmod();
-    /// medium.tick();
-    /// b.next().await.expect("Failed to get message at b. Error");
-    /// # }
-    /// ```
-    pub fn make_netmod(&mut self) -> impl Endpoint {
-        let mut mm = MemMod::new();
-        let (mm_io, my_io) = Io::make_pair();
-        mm.link_raw(mm_io);
-        self.interfaces.insert(self.last_tag, my_io);
-        self.last_tag += 1;
+    /// The `MemMod` is assigned a random ID and can immediately be used to send
+    /// and receive frames from all other interfaces on this medium.
+    pub async fn make_netmod(self: &Arc<Self>) -> Arc<MemMod> {
+        let (tx, rx) = channel(CHANNEL_SIZE);
+        let mm = MemMod::new(Ident32::random());
+        self.interfaces.write().await.insert(mm.id(), tx);
+        mm.set_link(Some(Link::Medium(Arc::clone(self))), Some(rx))
+            .await;
         mm
     }
 
-    /// Return the number of ticks run on this `BroadcastMedium` since creation.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// # use netmod_mem::media::BroadcastMedium;
-    /// let mut medium = BroadcastMedium::with_latency(1);
-    /// for _ in 1..=128 {
-    ///     medium.tick();
-    /// }
-    /// assert_eq!(medium.ticks(), 128);
-    /// ```
-    pub fn ticks(&self) -> u128 {
-        self.ticks
+    /// Return the number of interfaces connected to this medium
+    pub async fn len(&self) -> usize {
+        self.interfaces.read().await.len()
     }
 
-    /// Propagate frames in this `BroadcastMedium`.
-    pub fn tick(&mut self) {
-        let mut disconnected: Vec<u32> = Vec::new();
-        for (tag, io) in &mut self.interfaces {
-            match io.inc.try_recv() {
-                Ok(frame) => {
-                    self.buffer
-                        .push_back(TaggedFrame::new(*tag, self.latency, frame));
-                }
-                Err(e) => match e {
-                    TryRecvError::Empty => (),
-                    TryRecvError::Disconnected => disconnected.push(*tag),
-                },
-            }
-        }
+    /// Return `true` if no interfaces are connected to this medium
+    pub async fn is_empty(&self) -> bool {
+        self.interfaces.read().await.is_empty()
+    }
 
-        let mut to_send = 0;
-        self.buffer
-            .iter_mut()
-            .enumerate()
-            .for_each(|(_index, mut frame)| {
-                frame.ttl -= 1;
-                if frame.ttl == 0 {
-                    to_send += 1;
-                }
-            });
+    /// Disconnect an interface from this medium
+    pub(crate) async fn detach(&self, id: Ident32) {
+        self.interfaces.write().await.remove(&id);
+    }
 
-        while to_send > 0 {
-            to_send -= 1;
-            let frame = self.buffer.pop_front().expect(
-                "No frames in buffer despite having recorded a frame as requiring send this tick.",
-            );
-            self.interfaces
-                .iter()
-                .for_each(|(tag, io)| match io.out.send(frame.frame.clone()) {
-                    Ok(_) => (),
-                    Err(_) => disconnected.push(*tag),
-                });
-        }
+    /// Propagate a frame to all interfaces that should receive it
+    pub(crate) async fn transmit(
+        &self,
+        from: Ident32,
+        frame: InMemoryEnvelope,
+        target: Neighbour,
+        exclude: Option<Ident32>,
+    ) {
+        let interfaces = self.interfaces.read().await;
+        for (id, tx) in interfaces.iter() {
+            let receives = match target {
+                Neighbour::Single(target) => target == *id,
+                Neighbour::FloodExcept(except) => except != *id,
+                Neighbour::Flood => true,
+                Neighbour::Drop => false,
+            };
 
-        disconnected.sort_unstable();
-        disconnected.dedup();
-        disconnected.iter().for_each(|i| {
-            self.interfaces.remove(i);
-        });
-        self.ticks += 1;
+            if *id == from || !receives || exclude == Some(*id) {
+                continue;
+            }
+
+            // Interfaces that went away simply don't receive anything anymore
+            let _ = tx.send((frame.clone(), from)).await;
+        }
     }
 }
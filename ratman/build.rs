@@ -0,0 +1,40 @@
+// SPDX-FileCopyrightText: 2024 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+//! The dashboard assets are embedded into the router binary, but
+//! they have to be built with `yarn` first.  If that hasn't happened
+//! we embed a placeholder page instead, so that the router (and the
+//! dashboard API) can still be built.  The placeholder is generated
+//! into `OUT_DIR`, to keep the source tree clean.
+
+use std::{env, fs, path::Path};
+
+const PLACEHOLDER: &str = "<!DOCTYPE html>
+<html>
+  <head><title>ratmand</title></head>
+  <body>
+    <p>This router was built without the dashboard.  The API is available under
+      <a href=\"/api/v1/openapi.json\">/api/v1</a>.</p>
+  </body>
+</html>
+";
+
+fn main() {
+    println!("cargo:rustc-check-cfg=cfg(dashboard_placeholder)");
+    if env::var_os("CARGO_FEATURE_DASHBOARD").is_none() {
+        return;
+    }
+
+    let dist = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("dashboard/dist");
+    if dist.exists() {
+        println!("cargo:rerun-if-changed=dashboard/dist");
+    } else {
+        // A missing path would re-run this script on every build
+        println!("cargo:rerun-if-changed=dashboard");
+        println!("cargo:warning=dashboard assets have not been built, embedding a placeholder");
+        let out_dir = env::var("OUT_DIR").unwrap();
+        fs::write(Path::new(&out_dir).join("placeholder.html"), PLACEHOLDER).unwrap();
+        println!("cargo:rustc-cfg=dashboard_placeholder");
+    }
+}
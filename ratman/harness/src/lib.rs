@@ -0,0 +1,36 @@
+// SPDX-FileCopyrightText: 2024 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+//! A multi-router test harness for Ratman
+//!
+//! This crate starts any number of ephemeral `ratmand` instances in the
+//! current process and connects them with in-memory drivers from
+//! [`netmod_mem`].  Each router gets its own client API socket, which the
+//! harness uses to create addresses, send message streams, and wait for them
+//! to be delivered.
+//!
+//! ```no_run
+//! # use ratman_harness::{Network, Topology};
+//! # use std::time::Duration;
+//! # async fn test() -> libratman::Result<()> {
+//! let net = Network::start(Topology::Line(3)).await?;
+//! let alice = net.create_address(0).await?;
+//! let bob = net.create_address(2).await?;
+//! net.wait_for_peer(0, bob.addr, Duration::from_secs(10)).await?;
+//!
+//! let delivery = net.expect_delivery(&bob).await?;
+//! net.send(&alice, bob.addr, b"hello bob!").await?;
+//! delivery.assert_payload(b"hello bob!", Duration::from_secs(10)).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Tests using the harness need a multi-threaded runtime, for example via
+//! `#[libratman::tokio::test(flavor = "multi_thread")]`.
+
+mod network;
+mod router;
+
+pub use network::{Delivery, Network, TestAddress, Topology};
+pub use router::Router;
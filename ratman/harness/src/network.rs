@@ -0,0 +1,271 @@
+// SPDX-FileCopyrightText: 2024 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+use crate::Router;
+use libratman::{
+    api::types::DeliveryReceipt,
+    api::{RatmanIpcExtV1, RatmanStreamExtV1},
+    tokio::{
+        io::AsyncReadExt,
+        task::JoinHandle,
+        time::{sleep, timeout, Instant},
+    },
+    types::{AddrAuth, Address, LetterheadV1, Recipient},
+    RatmanError, Result, ScheduleError,
+};
+use netmod_mem::{media::BroadcastMedium, MemMod};
+use ratmand::{config::ConfigTree, routes::ScorerRegistry};
+use std::{collections::BTreeSet, sync::Arc, time::Duration};
+
+/// The shape of a test network
+#[derive(Clone, Debug)]
+pub enum Topology {
+    /// `n` routers, each linked to the one before and after it
+    Line(usize),
+    /// `n` routers, with router `0` linked to all others
+    Star(usize),
+    /// `n` routers, each linked to every other router
+    Mesh(usize),
+    /// `n` routers, sharing a single broadcast medium
+    Broadcast(usize),
+    /// `routers` routers, with an explicit set of 1-to-1 links
+    Custom {
+        routers: usize,
+        links: Vec<(usize, usize)>,
+    },
+}
+
+impl Topology {
+    fn routers(&self) -> usize {
+        match *self {
+            Self::Line(n) | Self::Star(n) | Self::Mesh(n) | Self::Broadcast(n) => n,
+            Self::Custom { routers, .. } => routers,
+        }
+    }
+
+    /// All 1-to-1 links in this topology
+    fn links(&self) -> Vec<(usize, usize)> {
+        match *self {
+            Self::Line(n) => (1..n).map(|i| (i - 1, i)).collect(),
+            Self::Star(n) => (1..n).map(|i| (0, i)).collect(),
+            Self::Mesh(n) => (0..n)
+                .flat_map(|a| (a + 1..n).map(move |b| (a, b)))
+                .collect(),
+            Self::Broadcast(_) => vec![],
+            Self::Custom { ref links, .. } => links.clone(),
+        }
+    }
+}
+
+/// A 1-to-1 link between two routers
+struct Link {
+    a: (usize, Arc<MemMod>),
+    b: (usize, Arc<MemMod>),
+}
+
+/// An address created by the harness on one of its routers
+#[derive(Copy, Clone, Debug)]
+pub struct TestAddress {
+    pub router: usize,
+    pub addr: Address,
+    pub auth: AddrAuth,
+}
+
+/// A set of ephemeral routers, connected via in-memory links
+pub struct Network {
+    routers: Vec<Router>,
+    links: Vec<Link>,
+}
+
+impl Network {
+    /// Start a network with the default router configuration
+    pub async fn start(topology: Topology) -> Result<Self> {
+        Self::start_with(topology, |_, cfg| cfg).await
+    }
+
+    /// Start a network, customising the configuration of each router
+    ///
+    /// The `configure` function is called with the index of each router and
+    /// its default configuration.  The harness always overrides the settings
+    /// it needs to run the routers side by side in one process.
+    pub async fn start_with<F>(topology: Topology, configure: F) -> Result<Self>
+    where
+        F: Fn(usize, ConfigTree) -> ConfigTree,
+    {
+        let num = topology.routers();
+        let mut endpoints: Vec<Vec<Arc<MemMod>>> = vec![vec![]; num];
+        let mut links = vec![];
+
+        for (a, b) in topology.links() {
+            assert!(a < num && b < num, "link ({a}, {b}) is out of bounds");
+            let (mm_a, mm_b) = MemMod::make_pair().await;
+            endpoints[a].push(Arc::clone(&mm_a));
+            endpoints[b].push(Arc::clone(&mm_b));
+            links.push(Link {
+                a: (a, mm_a),
+                b: (b, mm_b),
+            });
+        }
+
+        if let Topology::Broadcast(_) = topology {
+            let medium = BroadcastMedium::new();
+            for eps in endpoints.iter_mut() {
+                eps.push(medium.make_netmod().await);
+            }
+        }
+
+        let mut routers = Vec::with_capacity(num);
+        for (idx, eps) in endpoints.into_iter().enumerate() {
+            let cfg = configure(
+                idx,
+                ConfigTree::default_in_memory().patch("ratmand/verbosity", "warn"),
+            );
+            routers.push(Router::start(cfg, ScorerRegistry::default(), eps).await?);
+        }
+
+        Ok(Self { routers, links })
+    }
+
+    /// Get a router by its index in the topology
+    pub fn router(&self, idx: usize) -> &Router {
+        &self.routers[idx]
+    }
+
+    /// Create a new address on one of the routers
+    pub async fn create_address(&self, router: usize) -> Result<TestAddress> {
+        let (addr, auth) = self.router(router).create_address().await?;
+        Ok(TestAddress { router, addr, auth })
+    }
+
+    /// Wait until a router has learned about a remote address
+    pub async fn wait_for_peer(&self, router: usize, addr: Address, limit: Duration) -> Result<()> {
+        let started = Instant::now();
+        loop {
+            if self.router(router).known_peers().await?.contains(&addr) {
+                break Ok(());
+            }
+
+            if started.elapsed() > limit {
+                break Err(timed_out().await);
+            }
+
+            sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    /// Cut all links between the given routers and the rest of the network
+    ///
+    /// On a broadcast medium, the given routers stop sending and receiving
+    /// anything at all.
+    pub fn partition(&self, side: &[usize]) {
+        let side: BTreeSet<_> = side.iter().collect();
+
+        for link in &self.links {
+            if side.contains(&link.a.0) != side.contains(&link.b.0) {
+                link.a.1.partition();
+                link.b.1.partition();
+            }
+        }
+
+        for idx in side {
+            for mm in self.router(*idx).endpoints() {
+                mm.partition();
+            }
+        }
+    }
+
+    /// Restore all links that were cut by a partition
+    pub fn heal(&self) {
+        for router in &self.routers {
+            router.endpoints().iter().for_each(|mm| mm.heal());
+        }
+    }
+
+    /// Send a message stream from a local address to a remote one
+    pub async fn send(&self, from: &TestAddress, to: Address, payload: &[u8]) -> Result<()> {
+        let ipc = self.router(from.router).connect().await?;
+        ipc.addr_up(from.auth, from.addr).await?;
+
+        let mut letterhead = LetterheadV1::send(from.addr, Recipient::Address(to));
+        letterhead.stream_size = payload.len() as u64;
+        ipc.send_to(from.auth, letterhead, payload).await
+    }
+
+    /// Send a message stream and wait for its delivery receipt
+    pub async fn send_confirmed(
+        &self,
+        from: &TestAddress,
+        to: Address,
+        payload: &[u8],
+        limit: Duration,
+    ) -> Result<DeliveryReceipt> {
+        let ipc = self.router(from.router).connect().await?;
+        ipc.addr_up(from.auth, from.addr).await?;
+
+        let mut letterhead = LetterheadV1::send(from.addr, Recipient::Address(to));
+        letterhead.stream_size = payload.len() as u64;
+        ipc.send_to_confirmed(from.auth, letterhead, payload, limit)
+            .await
+    }
+
+    /// Start waiting for a message stream on the given address
+    ///
+    /// Call this before sending the message that is expected.
+    pub async fn expect_delivery(&self, to: &TestAddress) -> Result<Delivery> {
+        let ipc = self.router(to.router).connect().await?;
+        ipc.addr_up(to.auth, to.addr).await?;
+
+        let TestAddress { addr, auth, .. } = *to;
+        let task = libratman::tokio::spawn(async move {
+            let (letterhead, mut stream) =
+                ipc.recv_one(auth, addr, Recipient::Address(addr)).await?;
+
+            let mut payload = vec![0; letterhead.stream_size as usize];
+            stream.as_reader().read_exact(&mut payload).await?;
+            stream.drop().await?;
+            Ok((letterhead, payload))
+        });
+
+        Ok(Delivery { task })
+    }
+}
+
+/// A pending message stream delivery
+pub struct Delivery {
+    task: JoinHandle<Result<(LetterheadV1, Vec<u8>)>>,
+}
+
+impl Delivery {
+    /// Check whether the message stream has arrived yet, without waiting
+    pub fn delivered(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    /// Wait for the message stream to arrive
+    pub async fn wait(self, limit: Duration) -> Result<(LetterheadV1, Vec<u8>)> {
+        match timeout(limit, self.task).await {
+            Ok(res) => res?,
+            Err(elapsed) => Err(RatmanError::Schedule(ScheduleError::Timeout(elapsed))),
+        }
+    }
+
+    /// Wait for the message stream and check that it has the expected contents
+    ///
+    /// # Panics
+    ///
+    /// Panics if the received payload differs from `expected`.
+    pub async fn assert_payload(self, expected: &[u8], limit: Duration) -> Result<LetterheadV1> {
+        let (letterhead, payload) = self.wait(limit).await?;
+        assert_eq!(payload, expected, "delivered payload does not match");
+        Ok(letterhead)
+    }
+}
+
+/// Create a timeout error for polling loops
+async fn timed_out() -> RatmanError {
+    let elapsed = timeout(Duration::ZERO, std::future::pending::<()>())
+        .await
+        .unwrap_err();
+    RatmanError::Schedule(ScheduleError::Timeout(elapsed))
+}
@@ -0,0 +1,122 @@
+// SPDX-FileCopyrightText: 2024 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+use libratman::{
+    api::{RatmanIpc, RatmanIpcExtV1},
+    endpoint::EndpointExt,
+    tokio::time::{sleep, Instant},
+    types::{AddrAuth, Address},
+    Result,
+};
+use netmod_mem::MemMod;
+use ratmand::{config::ConfigTree, context::RatmanContext, routes::ScorerRegistry};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+/// How long to wait for a router's client API to come up
+const API_STARTUP: Duration = Duration::from_secs(10);
+
+/// A single ephemeral router, managed by the test harness
+pub struct Router {
+    ctx: Arc<RatmanContext>,
+    api_bind: SocketAddr,
+    ipc: Arc<RatmanIpc>,
+    endpoints: Vec<Arc<MemMod>>,
+}
+
+impl Router {
+    /// Start a router with a set of in-memory endpoints
+    ///
+    /// The configuration is patched to run the router ephemerally, bind its
+    /// client API to a free local port, and disable all hardware drivers.
+    /// The port is assigned when the router binds it, so that routers of
+    /// tests running in parallel can't end up with the same one.
+    pub async fn start(
+        cfg: ConfigTree,
+        scorers: ScorerRegistry,
+        endpoints: Vec<Arc<MemMod>>,
+    ) -> Result<Self> {
+        let cfg = cfg
+            .patch("ratmand/ephemeral", true)
+            .patch("ratmand/enable_dashboard", false)
+            .patch("ratmand/api_bind", "127.0.0.1:0")
+            .patch("inet/enable", false)
+            .patch("lan/enable", false)
+            .patch("lora/enable", false)
+            .patch("datalink/enable", false);
+
+        let links = endpoints
+            .iter()
+            .map(|mm| {
+                let ep: Arc<dyn EndpointExt + Send + Sync> = Arc::clone(mm) as _;
+                ("mem".to_owned(), ep)
+            })
+            .collect();
+        let ctx = RatmanContext::start_embedded(cfg, std::env::temp_dir(), scorers, links).await;
+        let api_bind = ctx.api_bind().expect("router started without a client API");
+
+        let ipc = connect(api_bind).await?;
+        Ok(Self {
+            ctx,
+            api_bind,
+            ipc,
+            endpoints,
+        })
+    }
+
+    /// The client API socket of this router
+    pub fn api_bind(&self) -> SocketAddr {
+        self.api_bind
+    }
+
+    /// A client API connection to this router
+    pub fn ipc(&self) -> &Arc<RatmanIpc> {
+        &self.ipc
+    }
+
+    /// Open an additional client API connection to this router
+    pub async fn connect(&self) -> Result<Arc<RatmanIpc>> {
+        connect(self.api_bind).await
+    }
+
+    /// The in-memory endpoints attached to this router
+    pub fn endpoints(&self) -> &[Arc<MemMod>] {
+        &self.endpoints
+    }
+
+    /// Create a new address on this router and mark it as up
+    pub async fn create_address(&self) -> Result<(Address, AddrAuth)> {
+        let (addr, auth) = self.ipc.addr_create(None).await?;
+        self.ipc.addr_up(auth, addr).await?;
+        Ok((addr, auth))
+    }
+
+    /// List all remote addresses this router currently knows about
+    pub async fn known_peers(&self) -> Result<Vec<Address>> {
+        Ok(self
+            .ipc
+            .peers_list()
+            .await?
+            .into_iter()
+            .map(|peer| peer.addr)
+            .collect())
+    }
+}
+
+impl Drop for Router {
+    fn drop(&mut self) {
+        self.ctx.wipe_ephemeral_state();
+    }
+}
+
+/// Connect to a client API socket, waiting for the router to start
+async fn connect(api_bind: SocketAddr) -> Result<Arc<RatmanIpc>> {
+    let started = Instant::now();
+    loop {
+        match RatmanIpc::start(api_bind).await {
+            Ok(ipc) => break Ok(ipc),
+            Err(e) if started.elapsed() > API_STARTUP => break Err(e),
+            Err(_) => sleep(Duration::from_millis(50)).await,
+        }
+    }
+}
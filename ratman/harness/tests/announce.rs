@@ -0,0 +1,67 @@
+// SPDX-FileCopyrightText: 2024 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+//! Address announcements across different network topologies
+//!
+//! In every topology the routers at the far edges of the network must
+//! learn about each other's addresses, which means that announcements
+//! have to be re-flooded by the routers in between.
+
+use libratman::{
+    api::{types::PeerEvent, RatmanIpcExtV1},
+    tokio::{self, time::timeout},
+    Result,
+};
+use ratman_harness::{Network, Topology};
+use std::time::Duration;
+
+const DISCOVERY: Duration = Duration::from_secs(20);
+
+async fn discover_edges(topology: Topology, first: usize, last: usize) -> Result<()> {
+    let net = Network::start(topology).await?;
+    let a = net.create_address(first).await?;
+    let b = net.create_address(last).await?;
+
+    net.wait_for_peer(first, b.addr, DISCOVERY).await?;
+    net.wait_for_peer(last, a.addr, DISCOVERY).await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn announce_line() -> Result<()> {
+    discover_edges(Topology::Line(3), 0, 2).await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn announce_star() -> Result<()> {
+    discover_edges(Topology::Star(4), 1, 3).await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn announce_mesh() -> Result<()> {
+    discover_edges(Topology::Mesh(3), 0, 2).await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn announce_broadcast() -> Result<()> {
+    discover_edges(Topology::Broadcast(3), 0, 2).await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn announce_peer_events() -> Result<()> {
+    let net = Network::start(Topology::Line(2)).await?;
+    let a = net.create_address(0).await?;
+    let mut events = net.router(0).ipc().peers_subscribe(a.auth).await?;
+
+    // Addresses that are discovered after subscribing are reported
+    let b = net.create_address(1).await?;
+    let wait_for_b = async {
+        while events.next_event().await? != PeerEvent::Discovered(b.addr) {}
+        Result::<()>::Ok(())
+    };
+    timeout(DISCOVERY, wait_for_b)
+        .await
+        .expect("no event for the discovered address")?;
+    Ok(())
+}
@@ -0,0 +1,155 @@
+// SPDX-FileCopyrightText: 2024 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+//! Message stream delivery between routers
+
+use libratman::{
+    api::{RatmanIpcExtV1, RatmanStreamExtV1, SubscriptionEvent},
+    tokio,
+    types::{LetterheadV1, Recipient},
+    Result,
+};
+use ratman_harness::{Network, Topology};
+use std::time::Duration;
+
+const DISCOVERY: Duration = Duration::from_secs(20);
+const DELIVERY: Duration = Duration::from_secs(30);
+
+#[tokio::test(flavor = "multi_thread")]
+async fn deliver_across_line() -> Result<()> {
+    let net = Network::start(Topology::Line(3)).await?;
+    let alice = net.create_address(0).await?;
+    let bob = net.create_address(2).await?;
+    net.wait_for_peer(0, bob.addr, DISCOVERY).await?;
+
+    let delivery = net.expect_delivery(&bob).await?;
+    net.send(&alice, bob.addr, b"Hello Bob, how are you?")
+        .await?;
+
+    let letterhead = delivery
+        .assert_payload(b"Hello Bob, how are you?", DELIVERY)
+        .await?;
+    assert_eq!(letterhead.from, alice.addr);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn partition_and_heal() -> Result<()> {
+    let net = Network::start(Topology::Line(3)).await?;
+    let alice = net.create_address(0).await?;
+    let bob = net.create_address(2).await?;
+    net.wait_for_peer(0, bob.addr, DISCOVERY).await?;
+
+    // Cut bob's router off from the rest of the network.  Nothing gets
+    // through while the partition holds
+    net.partition(&[2]);
+    let delivery = net.expect_delivery(&bob).await?;
+    net.send(&alice, bob.addr, b"Are you still there?").await?;
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    assert!(!delivery.delivered());
+
+    // Once the network heals messages get through again, though the one
+    // from during the partition may still overtake the new one
+    net.heal();
+    net.send(&alice, bob.addr, b"There you are!").await?;
+    let (_, payload) = delivery.wait(DELIVERY).await?;
+    assert!(
+        payload == b"There you are!" || payload == b"Are you still there?",
+        "delivered payload does not match"
+    );
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn store_and_forward_offline_recipient() -> Result<()> {
+    let net = Network::start(Topology::Line(3)).await?;
+    let alice = net.create_address(0).await?;
+    let bob = net.create_address(2).await?;
+    net.wait_for_peer(0, bob.addr, DISCOVERY).await?;
+
+    // Bob goes offline before alice sends anything.  His router keeps the
+    // message until he comes back
+    net.router(2).ipc().addr_down(bob.auth, bob.addr).await?;
+    net.send(&alice, bob.addr, b"Call me when you're back")
+        .await?;
+    tokio::time::sleep(Duration::from_secs(2)).await;
+
+    let delivery = net.expect_delivery(&bob).await?;
+    delivery
+        .assert_payload(b"Call me when you're back", DELIVERY)
+        .await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn confirm_delivery_across_line() -> Result<()> {
+    let net = Network::start(Topology::Line(3)).await?;
+    let alice = net.create_address(0).await?;
+    let bob = net.create_address(2).await?;
+    net.wait_for_peer(0, bob.addr, DISCOVERY).await?;
+    net.wait_for_peer(2, alice.addr, DISCOVERY).await?;
+
+    let delivery = net.expect_delivery(&bob).await?;
+    let receipt = net
+        .send_confirmed(&alice, bob.addr, b"Did you get this?", DELIVERY)
+        .await?;
+    assert_eq!(receipt.from, bob.addr);
+    assert_eq!(receipt.to, alice.addr);
+
+    delivery
+        .assert_payload(b"Did you get this?", DELIVERY)
+        .await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn receipt_subscription_event() -> Result<()> {
+    let net = Network::start(Topology::Line(2)).await?;
+    let alice = net.create_address(0).await?;
+    let bob = net.create_address(1).await?;
+    net.wait_for_peer(0, bob.addr, DISCOVERY).await?;
+    net.wait_for_peer(1, alice.addr, DISCOVERY).await?;
+
+    let ipc = net.router(0).connect().await?;
+    ipc.addr_up(alice.auth, alice.addr).await?;
+    let mut sub = ipc
+        .subs_create(alice.auth, alice.addr, Recipient::Address(alice.addr))
+        .await?;
+
+    let payload = b"Ping me back when you've read this";
+    let mut letterhead = LetterheadV1::send(alice.addr, Recipient::Address(bob.addr));
+    letterhead.stream_size = payload.len() as u64;
+    ipc.send_to(alice.auth, letterhead.request_receipt(), &payload[..])
+        .await?;
+
+    match tokio::time::timeout(DELIVERY, sub.next_event()).await {
+        Ok(Ok(SubscriptionEvent::Delivered(receipt))) => {
+            assert_eq!(receipt.from, bob.addr);
+            assert_eq!(receipt.to, alice.addr);
+        }
+        other => panic!("expected a delivery receipt, got {other:?}"),
+    }
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fetch_unknown_stream() -> Result<()> {
+    use libratman::{ClientError, RatmanError, ReadCapability};
+
+    let net = Network::start(Topology::Line(2)).await?;
+    let alice = net.create_address(0).await?;
+
+    // None of the routers have any blocks of this stream
+    let read_cap = ReadCapability::from_binary(&[[10, 0].as_slice(), &[1; 64]].concat()).unwrap();
+    let res = net
+        .router(0)
+        .ipc()
+        .fetch_stream(alice.auth, alice.addr, read_cap, Duration::from_secs(2))
+        .await;
+    assert!(matches!(
+        res,
+        Err(RatmanError::ClientApi(ClientError::StreamTimeout(_)))
+    ));
+    Ok(())
+}
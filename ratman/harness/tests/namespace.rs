@@ -0,0 +1,42 @@
+// SPDX-FileCopyrightText: 2024 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+//! Namespace key management via the client API
+
+use libratman::{api::NamespaceAnycastExtV1, tokio, ClientError, RatmanError, Result};
+use ratman_harness::{Network, Topology};
+
+fn is_refused<T>(res: Result<T>) -> bool {
+    matches!(res, Err(RatmanError::ClientApi(ClientError::InvalidAuth)))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn only_owner_manages_namespace() -> Result<()> {
+    let net = Network::start(Topology::Line(1)).await?;
+    let owner = net.create_address(0).await?;
+    let other = net.create_address(0).await?;
+    let ipc = net.router(0).ipc();
+
+    let namespace = ipc.namespace_create(owner.auth, None).await?;
+
+    // Other addresses on the same router can't touch the namespace key
+    assert!(is_refused(
+        ipc.namespace_export(other.auth, namespace).await
+    ));
+    assert!(is_refused(
+        ipc.namespace_rotate(other.auth, namespace).await
+    ));
+    assert!(is_refused(
+        ipc.namespace_destroy(other.auth, namespace).await
+    ));
+
+    let (pubkey, _) = ipc.namespace_export(owner.auth, namespace).await?;
+    assert_eq!(pubkey, namespace);
+
+    // Rotating the key keeps its owner
+    let rotated = ipc.namespace_rotate(owner.auth, namespace).await?;
+    assert!(is_refused(ipc.namespace_destroy(other.auth, rotated).await));
+    ipc.namespace_destroy(owner.auth, rotated).await?;
+    Ok(())
+}
@@ -1,13 +1,19 @@
 use crate::{
-    api::{socket_v2::RawSocketHandle, SubscriptionHandle},
-    types::{error::UserError, AddrAuth, Address, Ident32, LetterheadV1, Namespace, Recipient},
+    api::{socket_v2::RawSocketHandle, PeerSubscription, SubscriptionHandle},
+    types::{
+        error::UserError, AddrAuth, Address, Ident32, LetterheadV1, Modify, Namespace, Recipient,
+    },
     ClientError, Result,
 };
+use async_eris::ReadCapability;
 use async_trait::async_trait;
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use std::{collections::BTreeMap, net::SocketAddr, sync::Arc, time::Duration};
 use tokio::{io::AsyncRead, sync::MutexGuard};
 
-use super::types::{PeerEntry, RouterStatus, ServerPing};
+use super::types::{
+    ConfigChange, ContactEntry, ContactFilter, DeliveryReceipt, LinkEntry, PeerEntry, PrunedPeer,
+    RouterStatus, ServerPing,
+};
 
 #[async_trait]
 pub trait RatmanIpcExtV1 {
@@ -27,13 +33,8 @@ pub trait RatmanIpcExtV1 {
     /// shared with any other network participant or client and purely
     /// serves as a human identifier.
     ///
-    /// A WEIRD QUIRK in this API: to limit scope I didn't implement a namespace
-    /// management API yet, which means listening to messages addressed to a
-    /// namespace becomes very hard.  To deal with this and to make namespaces
-    /// (for subscriptions!) persistent between restarts you can optionally
-    /// supply the namespace private key material.  It's suggested to include a
-    /// copy of this key in your application's source code rather than
-    /// configuration.
+    /// Namespaces are managed separately via [`NamespaceAnycastExtV1`], which
+    /// can create router-held namespace keys, or import existing ones.
     async fn addr_create<'n>(
         self: &Arc<Self>,
         name: Option<&'n String>,
@@ -59,6 +60,62 @@ pub trait RatmanIpcExtV1 {
 
     async fn peers_list(self: &Arc<Self>) -> Result<Vec<PeerEntry>>;
 
+    /// Start peering with a neighbour router via one of the router's netmods
+    ///
+    /// The peer uses the `<netmod>:<address>` syntax of the ratmand
+    /// configuration.  Peers added this way are remembered by the router
+    /// and re-connected after a restart, until they are removed again.
+    async fn peers_add(self: &Arc<Self>, auth: AddrAuth, peer: String) -> Result<()>;
+
+    /// Stop peering with a neighbour router which was added before
+    async fn peers_remove(self: &Arc<Self>, auth: AddrAuth, peer: String) -> Result<()>;
+
+    /// Remove stale links and peers from the routing table
+    ///
+    /// Any expiry limit that isn't provided uses the router's configured
+    /// policy.  Returns the set of peers that were affected.
+    async fn peers_prune(
+        self: &Arc<Self>,
+        auth: AddrAuth,
+        lost_link_hours: Option<u32>,
+        forget_peer_days: Option<u32>,
+    ) -> Result<Vec<PrunedPeer>>;
+
+    /// Follow changes to the set of known peers
+    ///
+    /// An event is sent for every newly discovered address, and for every
+    /// peer that was pruned from the routing table, either via
+    /// [`peers_prune`](Self::peers_prune) or by the router's periodic
+    /// expiry task.
+    async fn peers_subscribe(self: &Arc<Self>, auth: AddrAuth) -> Result<PeerSubscription>;
+
+    //
+    // (@^_^@) Link commands
+    //
+
+    /// List the netmod instances currently attached to the router
+    async fn link_list(self: &Arc<Self>) -> Result<Vec<LinkEntry>>;
+
+    /// Start new netmod instances from a KDL configuration snippet
+    ///
+    /// The snippet uses the same `settings "<netmod>" { ... }` syntax as
+    /// the ratmand configuration file.  Returns the links that were
+    /// started.
+    async fn link_up(self: &Arc<Self>, auth: AddrAuth, config: String) -> Result<Vec<LinkEntry>>;
+
+    /// Stop a netmod instance and remove it from the router
+    async fn link_down(self: &Arc<Self>, auth: AddrAuth, id: u32) -> Result<()>;
+
+    //
+    // (@^_^@) Configuration commands
+    //
+
+    /// Make the router read its configuration file again
+    ///
+    /// Returns every setting that changed, and whether the change was
+    /// applied or needs a router restart.
+    async fn config_reload(self: &Arc<Self>, auth: AddrAuth) -> Result<Vec<ConfigChange>>;
+
     //
     // (@^_^@) Status commands
     //
@@ -69,36 +126,41 @@ pub trait RatmanIpcExtV1 {
     // (@^_^@) Contact commands
     //
 
-    // /// Create a new contact entry for an address
-    // ///
-    // /// Each client has its own contact book.  Currently there's no
-    // /// way to share contacts between clients.
-    // async fn contact_add(
-    //     self: &Arc<Self>,
-    //     auth: AddrAuth,
-    //     addr: Address,
-    //     note: Option<String>,
-    //     tags: BTreeMap<String, String>,
-    //     trust: u8,
-    // ) -> Result<Ident32>;
-
-    // /// Apply a simple change across one or multiple contact entries
-    // async fn contact_modify(
-    //     self: &Arc<Self>,
-    //     auth: AddrAuth,
-
-    //     // Selection filter section
-    //     addr_filter: Vec<Address>,
-    //     note_filter: Option<String>,
-    //     tags_filter: BTreeMap<String, String>,
-
-    //     // Modification section
-    //     note_modify: Modify<String>,
-    //     tags_modify: Modify<(String, String)>,
-    // ) -> Result<Vec<Ident32>>;
-
-    // /// Delete existing contact entries via filters
-    // async fn contact_delete(self: &Arc<Self>, auth: AddrAuth, addr: Address) -> Result<()>;
+    /// Create a new contact entry for an address
+    ///
+    /// Each local address has its own contact book, selected by the given
+    /// authentication.  Currently there's no way to share contacts between
+    /// addresses.  Trust levels range from 1 (lowest) to 7 (highest).
+    async fn contact_add(
+        self: &Arc<Self>,
+        auth: AddrAuth,
+        addr: Address,
+        note: Option<String>,
+        tags: BTreeMap<String, String>,
+        trust: u8,
+    ) -> Result<Ident32>;
+
+    /// List contact book entries, optionally filtered
+    async fn contact_list(
+        self: &Arc<Self>,
+        auth: AddrAuth,
+        filter: ContactFilter,
+    ) -> Result<Vec<ContactEntry>>;
+
+    /// Apply a simple change across one or multiple contact entries
+    ///
+    /// Returns the IDs of all entries that were selected by the filter.
+    async fn contact_modify(
+        self: &Arc<Self>,
+        auth: AddrAuth,
+        filter: ContactFilter,
+        note_modify: Modify<String>,
+        tags_modify: Modify<(String, String)>,
+        new_trust: Option<u8>,
+    ) -> Result<Vec<Ident32>>;
+
+    /// Delete all contact entries for an address
+    async fn contact_delete(self: &Arc<Self>, auth: AddrAuth, addr: Address) -> Result<()>;
 
     //
     // (@^_^@) Subscription commands
@@ -173,6 +235,36 @@ pub trait RatmanStreamExtV1: RatmanIpcExtV1 {
         data_reader: I,
     ) -> Result<()>;
 
+    /// Send a message stream and wait for the recipient to confirm delivery
+    ///
+    /// This requests a delivery receipt via the letterhead, sends the stream
+    /// like [`send_to`](Self::send_to), and then waits for the signed receipt
+    /// from the recipient router.  If no receipt arrives within `timeout` a
+    /// `NonfatalError::NoReceipt` is returned.  The stream may still have been
+    /// delivered in this case!
+    ///
+    /// The API socket is blocked while waiting for the receipt.  Use a
+    /// subscription to receive receipts in the background instead.
+    async fn send_to_confirmed<I: AsyncRead + Unpin + Send>(
+        self: &Arc<Self>,
+        auth: AddrAuth,
+        letterhead: LetterheadV1,
+        data_reader: I,
+        timeout: Duration,
+    ) -> Result<DeliveryReceipt>;
+
+    /// Wait for the delivery receipt of a previously sent stream
+    ///
+    /// The stream ID is the root reference of the stream manifest.  Receipts
+    /// that arrived before this function was called are still returned, as
+    /// long as the router hasn't evicted them yet.
+    async fn await_receipt(
+        self: &Arc<Self>,
+        auth: AddrAuth,
+        stream_id: Ident32,
+        timeout: Duration,
+    ) -> Result<DeliveryReceipt>;
+
     /// Send the same message stream to multiple recipients
     ///
     /// Most of the Letterhead
@@ -219,6 +311,48 @@ pub trait RatmanStreamExtV1: RatmanIpcExtV1 {
         to: Recipient,
         num: Option<u32>,
     ) -> Result<StreamGenerator<'s>>;
+
+    /// Fetch a message stream by its read capability
+    ///
+    /// Blocks of the stream which aren't in the router's journal yet are
+    /// requested from all neighbouring routers, on behalf of the given
+    /// address.  If the stream isn't complete after `timeout` a
+    /// `ClientError::StreamTimeout` is returned.
+    ///
+    /// Otherwise this returns the length of the stream and a reader for its
+    /// contents.  As with `recv_one` you **must** drop the `ReadStream` after
+    /// reading exactly this many bytes.
+    async fn fetch_stream<'s>(
+        self: &'s Arc<Self>,
+        auth: AddrAuth,
+        addr: Address,
+        read_cap: ReadCapability,
+        timeout: Duration,
+    ) -> Result<(u64, ReadStream<'s>)>;
+
+    /// Attach tags and associated streams to a message stream
+    ///
+    /// Linked streams are kept in the router's journal, even when its storage
+    /// quota is exhausted.  Linking a stream again replaces its previous tags
+    /// and associations.
+    async fn stream_link(
+        self: &Arc<Self>,
+        auth: AddrAuth,
+        addr: Address,
+        stream_id: Ident32,
+        streams: Vec<Ident32>,
+        tags: Vec<String>,
+    ) -> Result<()>;
+
+    /// Remove the link data of a message stream
+    ///
+    /// The stream can be evicted from the router's journal again afterwards.
+    async fn stream_unlink(
+        self: &Arc<Self>,
+        auth: AddrAuth,
+        addr: Address,
+        stream_id: Ident32,
+    ) -> Result<()>;
 }
 
 pub struct ReadStream<'a>(pub(crate) MutexGuard<'a, RawSocketHandle>);
@@ -272,6 +406,12 @@ pub trait NamespaceAnycastExtV1: RatmanIpcExtV1 {
     ///
     /// The private key must be included in every instance of your application
     /// to allow for transport layer space signatures and encryption.
+    ///
+    /// This can also be used to import a key that was previously exported
+    /// from another router via `namespace_export`.
+    ///
+    /// Only the address that registered a namespace can export, rotate, or
+    /// destroy it.
     async fn namespace_register(
         self: &Arc<Self>,
         auth: AddrAuth,
@@ -279,6 +419,50 @@ pub trait NamespaceAnycastExtV1: RatmanIpcExtV1 {
         space_privkey: Ident32,
     ) -> Result<()>;
 
+    /// Create a new namespace with a key generated and held by the router
+    ///
+    /// Optionally you may give this namespace a name.  It won't be shared with
+    /// any other network participant and purely serves as a human identifier.
+    /// Returns the new namespace address, which is owned by the address that
+    /// created it.
+    async fn namespace_create<'n>(
+        self: &Arc<Self>,
+        auth: AddrAuth,
+        name: Option<&'n String>,
+    ) -> Result<Address>;
+
+    /// List all namespaces known to the router
+    async fn namespace_list(self: &Arc<Self>) -> Result<Vec<Address>>;
+
+    /// Export the public and private key of a namespace
+    ///
+    /// The returned key pair can be imported on another router with
+    /// `namespace_register`.
+    async fn namespace_export(
+        self: &Arc<Self>,
+        auth: AddrAuth,
+        space_pubkey: Address,
+    ) -> Result<(Address, Ident32)>;
+
+    /// Replace a namespace key with a newly generated one
+    ///
+    /// Because a namespace address is its public key, this returns the new
+    /// namespace address.  Applications that marked the old namespace as "up"
+    /// are moved to the new one.  Other routers which imported the old key
+    /// need to import the new one as well.
+    async fn namespace_rotate(
+        self: &Arc<Self>,
+        auth: AddrAuth,
+        space_pubkey: Address,
+    ) -> Result<Address>;
+
+    /// Delete a namespace key from the router
+    async fn namespace_destroy(
+        self: &Arc<Self>,
+        auth: AddrAuth,
+        space_pubkey: Address,
+    ) -> Result<()>;
+
     /// Mark a given namespace as "up" for a given application
     ///
     /// This is different from a stream subscription, which listens to messages
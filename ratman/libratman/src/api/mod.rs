@@ -28,15 +28,20 @@
 //! behaviour can be disabled via the `RatmanIpc` API.
 
 mod _trait;
-use _trait::StreamGenerator;
-pub use _trait::{NamespaceAnycastExtV1, RatmanIpcExtV1, RatmanStreamExtV1, ReadStream};
+pub use _trait::{
+    NamespaceAnycastExtV1, RatmanIpcExtV1, RatmanStreamExtV1, ReadStream, StreamGenerator,
+};
 
 mod subscriber;
-pub use subscriber::SubscriptionHandle;
+pub use subscriber::{PeerSubscription, SubscriptionEvent, SubscriptionHandle};
 use types::{
-    AnycastProbe, NamespaceDown, NamespaceRegister, NamespaceUp, PeerEntry, RecvMany, RouterStatus,
-    SendMany,
+    AnycastProbe, ConfigChange, ContactAdd, ContactDelete, ContactEntry, ContactFilter,
+    ContactModify, FetchStream, LinkDown, LinkEntry, LinkUp, NamespaceCreate, NamespaceDestroy,
+    NamespaceDown, NamespaceExport, NamespaceRegister, NamespaceRotate, NamespaceUp, PeerAdd,
+    PeerDelete, PeerEntry, PeerPrune, PrunedPeer, RecvMany, RouterStatus, SendMany, StreamLink,
+    StreamUnlink,
 };
+use types::{AwaitReceipt, DeliveryReceipt};
 
 pub mod socket_v2;
 pub mod types;
@@ -51,11 +56,13 @@ use crate::{
         types::{Handshake, RecvOne, SendOne, ServerPing, SubsCreate, SubsDelete, SubsRestore},
     },
     frame::micro::{client_modes as cm, MicroframeHeader},
-    types::{AddrAuth, Address, Ident32, LetterheadV1, Recipient},
-    ClientError, EncodingError, Result,
+    types::{to_cstring, AddrAuth, Address, Ident32, LetterheadV1, Modify, Recipient},
+    ClientError, EncodingError, NonfatalError, Result,
 };
+use async_eris::ReadCapability;
 use async_trait::async_trait;
 use std::{
+    collections::BTreeMap,
     ffi::CString,
     net::{Ipv4Addr, SocketAddr, SocketAddrV4},
     sync::Arc,
@@ -285,6 +292,187 @@ impl RatmanIpcExtV1 for RatmanIpc {
         }
     }
 
+    async fn peers_add(self: &Arc<Self>, auth: AddrAuth, peer: String) -> Result<()> {
+        let mut socket = self.socket().lock().await;
+        socket
+            .write_microframe(
+                MicroframeHeader {
+                    modes: cm::make(cm::PEER, cm::ADD),
+                    auth: Some(auth),
+                    ..Default::default()
+                },
+                PeerAdd {
+                    peer: to_cstring(&peer),
+                },
+            )
+            .await?;
+
+        match socket.read_microframe::<ServerPing>().await?.1? {
+            ServerPing::Ok => Ok(()),
+            ServerPing::Error(e) => Err(e.into()),
+            other => Err(ClientError::Internal(format!("{other:?}")).into()),
+        }
+    }
+
+    async fn peers_remove(self: &Arc<Self>, auth: AddrAuth, peer: String) -> Result<()> {
+        let mut socket = self.socket().lock().await;
+        socket
+            .write_microframe(
+                MicroframeHeader {
+                    modes: cm::make(cm::PEER, cm::DELETE),
+                    auth: Some(auth),
+                    ..Default::default()
+                },
+                PeerDelete {
+                    peer: to_cstring(&peer),
+                },
+            )
+            .await?;
+
+        match socket.read_microframe::<ServerPing>().await?.1? {
+            ServerPing::Ok => Ok(()),
+            ServerPing::Error(e) => Err(e.into()),
+            other => Err(ClientError::Internal(format!("{other:?}")).into()),
+        }
+    }
+
+    async fn peers_prune(
+        self: &Arc<Self>,
+        auth: AddrAuth,
+        lost_link_hours: Option<u32>,
+        forget_peer_days: Option<u32>,
+    ) -> Result<Vec<PrunedPeer>> {
+        let mut socket = self.socket().lock().await;
+        socket
+            .write_microframe(
+                MicroframeHeader {
+                    modes: cm::make(cm::PEER, cm::DESTROY),
+                    auth: Some(auth),
+                    ..Default::default()
+                },
+                PeerPrune {
+                    lost_link_hours: lost_link_hours.unwrap_or(0),
+                    forget_peer_days: forget_peer_days.unwrap_or(0),
+                },
+            )
+            .await?;
+
+        match socket.read_microframe::<ServerPing>().await?.1? {
+            ServerPing::Pruned(list) => Ok(list),
+            ServerPing::Error(e) => Err(e.into()),
+            other => Err(ClientError::Internal(format!("{other:?}")).into()),
+        }
+    }
+
+    async fn peers_subscribe(self: &Arc<Self>, auth: AddrAuth) -> Result<PeerSubscription> {
+        let mut socket = self.socket().lock().await;
+        socket
+            .write_microframe(
+                MicroframeHeader {
+                    modes: cm::make(cm::PEER, cm::SUB),
+                    auth: Some(auth),
+                    ..Default::default()
+                },
+                (),
+            )
+            .await?;
+
+        match socket.read_microframe::<ServerPing>().await?.1? {
+            ServerPing::Subscription { sub_bind, .. } => {
+                let bind_str: String = sub_bind
+                    .into_string()
+                    .map_err(|e| EncodingError::Internal(e.to_string()))?;
+
+                Ok(PeerSubscription {
+                    socket: RawSocketHandle::new(TcpStream::connect(&bind_str).await?),
+                })
+            }
+            ServerPing::Error(e) => Err(e.into()),
+            other => Err(ClientError::Internal(format!("{other:?}")).into()),
+        }
+    }
+
+    async fn link_list(self: &Arc<Self>) -> Result<Vec<LinkEntry>> {
+        let mut socket = self.socket().lock().await;
+        socket
+            .write_microframe(
+                MicroframeHeader {
+                    modes: cm::make(cm::LINK, cm::LIST),
+                    ..Default::default()
+                },
+                (),
+            )
+            .await?;
+
+        match socket.read_microframe::<ServerPing>().await?.1? {
+            ServerPing::LinkList(list) => Ok(list),
+            ServerPing::Error(e) => Err(e.into()),
+            other => Err(ClientError::Internal(format!("{other:?}")).into()),
+        }
+    }
+
+    async fn link_up(self: &Arc<Self>, auth: AddrAuth, config: String) -> Result<Vec<LinkEntry>> {
+        let mut socket = self.socket().lock().await;
+        socket
+            .write_microframe(
+                MicroframeHeader {
+                    modes: cm::make(cm::LINK, cm::UP),
+                    auth: Some(auth),
+                    ..Default::default()
+                },
+                LinkUp {
+                    config: to_cstring(&config),
+                },
+            )
+            .await?;
+
+        match socket.read_microframe::<ServerPing>().await?.1? {
+            ServerPing::LinkList(list) => Ok(list),
+            ServerPing::Error(e) => Err(e.into()),
+            other => Err(ClientError::Internal(format!("{other:?}")).into()),
+        }
+    }
+
+    async fn link_down(self: &Arc<Self>, auth: AddrAuth, id: u32) -> Result<()> {
+        let mut socket = self.socket().lock().await;
+        socket
+            .write_microframe(
+                MicroframeHeader {
+                    modes: cm::make(cm::LINK, cm::DOWN),
+                    auth: Some(auth),
+                    ..Default::default()
+                },
+                LinkDown { id },
+            )
+            .await?;
+
+        match socket.read_microframe::<ServerPing>().await?.1? {
+            ServerPing::Ok => Ok(()),
+            ServerPing::Error(e) => Err(e.into()),
+            other => Err(ClientError::Internal(format!("{other:?}")).into()),
+        }
+    }
+
+    async fn config_reload(self: &Arc<Self>, auth: AddrAuth) -> Result<Vec<ConfigChange>> {
+        let mut socket = self.socket().lock().await;
+        socket
+            .write_microframe(
+                MicroframeHeader {
+                    modes: cm::make(cm::CONFIG, cm::RELOAD),
+                    auth: Some(auth),
+                    ..Default::default()
+                },
+                (),
+            )
+            .await?;
+
+        match socket.read_microframe::<ServerPing>().await?.1? {
+            ServerPing::ConfigChanges(changes) => Ok(changes),
+            ServerPing::Error(e) => Err(e.into()),
+            other => Err(ClientError::Internal(format!("{other:?}")).into()),
+        }
+    }
+
     async fn router_status(self: &Arc<Self>) -> Result<RouterStatus> {
         let mut socket = self.socket().lock().await;
         socket
@@ -300,65 +488,114 @@ impl RatmanIpcExtV1 for RatmanIpc {
         let (_, ping) = socket.read_microframe::<ServerPing>().await?;
 
         match ping? {
-            ServerPing::Status {
-                num_peers,
-                num_local,
-                num_auth,
-                num_collector_workers,
-            } => Ok(RouterStatus {
-                num_peers,
-                num_local,
-                num_auth,
-                num_collector_workers,
-            }),
+            ServerPing::Status(status) => Ok(status),
             ServerPing::Error(e) => Err(e.into()),
             _ => Err(ClientError::ConnectionLost.into()),
         }
     }
 
-    // async fn contact_add(
-    //     self: &Arc<Self>,
-    //     _auth: AddrAuth,
-    //     _addr: Address,
-    //     _note: Option<String>,
-    //     _tags: BTreeMap<String, String>,
-    //     _trust: u8,
-    // ) -> crate::Result<Ident32> {
-    //     todo!(
-    //         "This API endpoint is unimplemented in {}",
-    //         version_str(&crate::api::VERSION)
-    //     );
-    // }
-
-    // async fn contact_modify(
-    //     self: &Arc<Self>,
-    //     _auth: AddrAuth,
-
-    //     // Selection filter section
-    //     _addr_filter: Vec<Address>,
-    //     _note_filter: Option<String>,
-    //     _tags_filter: BTreeMap<String, String>,
-
-    //     // Modification section
-    //     _note_modify: Modify<String>,
-    //     _tags_modify: Modify<(String, String)>,
-    // ) -> crate::Result<Vec<Ident32>> {
-    //     todo!(
-    //         "This API endpoint is unimplemented in {}",
-    //         version_str(&crate::api::VERSION)
-    //     );
-    // }
-
-    // async fn contact_delete(
-    //     self: &Arc<Self>,
-    //     _auth: AddrAuth,
-    //     _addr: Address,
-    // ) -> crate::Result<()> {
-    //     todo!(
-    //         "This API endpoint is unimplemented in {}",
-    //         version_str(&crate::api::VERSION)
-    //     );
-    // }
+    async fn contact_add(
+        self: &Arc<Self>,
+        auth: AddrAuth,
+        addr: Address,
+        note: Option<String>,
+        tags: BTreeMap<String, String>,
+        trust: u8,
+    ) -> crate::Result<Ident32> {
+        let mut socket = self.socket().lock().await;
+        socket
+            .write_microframe(
+                MicroframeHeader {
+                    modes: cm::make(cm::CONTACT, cm::ADD),
+                    auth: Some(auth),
+                    ..Default::default()
+                },
+                ContactAdd::new(addr, note, tags.into_iter(), trust),
+            )
+            .await?;
+
+        match socket.read_microframe::<ServerPing>().await?.1? {
+            ServerPing::ContactList(mut list) if list.len() == 1 => Ok(list.remove(0).id),
+            ServerPing::Error(e) => Err(e.into()),
+            other => Err(ClientError::Internal(format!("{other:?}")).into()),
+        }
+    }
+
+    async fn contact_list(
+        self: &Arc<Self>,
+        auth: AddrAuth,
+        filter: ContactFilter,
+    ) -> crate::Result<Vec<ContactEntry>> {
+        let mut socket = self.socket().lock().await;
+        socket
+            .write_microframe(
+                MicroframeHeader {
+                    modes: cm::make(cm::CONTACT, cm::LIST),
+                    auth: Some(auth),
+                    ..Default::default()
+                },
+                filter,
+            )
+            .await?;
+
+        match socket.read_microframe::<ServerPing>().await?.1? {
+            ServerPing::ContactList(list) => Ok(list),
+            ServerPing::Error(e) => Err(e.into()),
+            other => Err(ClientError::Internal(format!("{other:?}")).into()),
+        }
+    }
+
+    async fn contact_modify(
+        self: &Arc<Self>,
+        auth: AddrAuth,
+        filter: ContactFilter,
+        note_modify: Modify<String>,
+        tags_modify: Modify<(String, String)>,
+        new_trust: Option<u8>,
+    ) -> crate::Result<Vec<Ident32>> {
+        let mut socket = self.socket().lock().await;
+        socket
+            .write_microframe(
+                MicroframeHeader {
+                    modes: cm::make(cm::CONTACT, cm::MODIFY),
+                    auth: Some(auth),
+                    ..Default::default()
+                },
+                ContactModify {
+                    filter,
+                    note_modify: note_modify.map(|note| to_cstring(&note)),
+                    tags_modify: tags_modify.map(|(k, v)| (to_cstring(&k), to_cstring(&v))),
+                    new_trust,
+                },
+            )
+            .await?;
+
+        match socket.read_microframe::<ServerPing>().await?.1? {
+            ServerPing::ContactList(list) => Ok(list.into_iter().map(|entry| entry.id).collect()),
+            ServerPing::Error(e) => Err(e.into()),
+            other => Err(ClientError::Internal(format!("{other:?}")).into()),
+        }
+    }
+
+    async fn contact_delete(self: &Arc<Self>, auth: AddrAuth, addr: Address) -> crate::Result<()> {
+        let mut socket = self.socket().lock().await;
+        socket
+            .write_microframe(
+                MicroframeHeader {
+                    modes: cm::make(cm::CONTACT, cm::DELETE),
+                    auth: Some(auth),
+                    ..Default::default()
+                },
+                ContactDelete { addr },
+            )
+            .await?;
+
+        match socket.read_microframe::<ServerPing>().await?.1? {
+            ServerPing::Ok => Ok(()),
+            ServerPing::Error(e) => Err(e.into()),
+            other => Err(ClientError::Internal(format!("{other:?}")).into()),
+        }
+    }
 
     async fn subs_available(
         self: &Arc<Self>,
@@ -504,10 +741,12 @@ impl RatmanIpc {
         self.socket.as_ref().unwrap()
     }
 
-    async fn shutdown(&self) {
-        self.socket()
-            .lock()
-            .await
+    /// Tell the router that this client is going away
+    ///
+    /// Errors are ignored, since the router may have shut down already.
+    async fn shutdown(socket: Mutex<RawSocketHandle>) {
+        let mut socket = socket.lock().await;
+        let _ = socket
             .write_microframe(
                 MicroframeHeader {
                     modes: cm::make(cm::INTRINSIC, cm::DOWN),
@@ -516,19 +755,69 @@ impl RatmanIpc {
                 },
                 (),
             )
-            .await
-            .unwrap();
-        self.socket().lock().await.shutdown().await.unwrap();
+            .await;
+        let _ = socket.shutdown().await;
+    }
+
+    /// Send a single message stream and return the stream IDs from the router
+    async fn send_one<I: AsyncRead + Unpin + Send>(
+        &self,
+        auth: AddrAuth,
+        letterhead: LetterheadV1,
+        data_reader: I,
+    ) -> Result<Vec<Ident32>> {
+        let plen = letterhead.stream_size;
+
+        let mut socket = self.socket().lock().await;
+        socket
+            .write_microframe(
+                MicroframeHeader {
+                    modes: cm::make(cm::SEND, cm::ONE),
+                    auth: Some(auth),
+                    ..Default::default()
+                },
+                SendOne { letterhead },
+            )
+            .await?;
+
+        let (_, ping) = socket.read_microframe::<ServerPing>().await?;
+        let bind = match ping? {
+            ServerPing::SendSocket { socket_bind } => socket_bind,
+            ServerPing::Error(e) => return Err(e.into()),
+            _ => return Err(EncodingError::Parsing("Invalid payload response!".into()).into()),
+        };
+
+        let mut send_s =
+            TcpStream::connect(bind.to_str().unwrap().parse::<SocketAddr>().unwrap()).await?;
+
+        // Only forward as many bytes as the letterhead announced
+        let mut reader = Box::pin(data_reader).take(plen);
+        tokio::io::copy(&mut reader, &mut send_s).await?;
+        drop(send_s);
+
+        read_send_response(&mut socket).await
+    }
+}
+
+/// Read the router response after a message stream was sent
+///
+/// If any of the sent letterheads requested a delivery receipt the router
+/// replies with the IDs of all sent streams, otherwise the list is empty.
+async fn read_send_response(socket: &mut RawSocketHandle) -> Result<Vec<Ident32>> {
+    let (_, ping) = socket.read_microframe::<ServerPing>().await?;
+    match ping? {
+        ServerPing::Ok => Ok(vec![]),
+        ServerPing::Sent(stream_ids) => Ok(stream_ids),
+        ServerPing::Error(e) => Err(e.into()),
+        i => Err(ClientError::Internal(format!("Invalid router response: {i:?}")).into()),
     }
 }
 
 impl Drop for RatmanIpc {
     fn drop(&mut self) {
-        let socket = core::mem::replace(&mut self.socket, None);
-        tokio::task::spawn(async move {
-            let this = Self { socket };
-            this.shutdown().await;
-        });
+        if let Some(socket) = self.socket.take() {
+            tokio::task::spawn(Self::shutdown(socket));
+        }
     }
 }
 
@@ -557,44 +846,58 @@ impl RatmanStreamExtV1 for RatmanIpc {
         letterhead: LetterheadV1,
         data_reader: I,
     ) -> crate::Result<()> {
-        let plen = letterhead.stream_size;
-        let chunk_size = if plen < 1024 {
-            plen
-        } else if plen > 1024 && plen < (1024 * 32) {
-            4 * 1024
-        } else {
-            16 * 1025
-        };
+        self.send_one(auth, letterhead, data_reader).await?;
+        Ok(())
+    }
 
+    /// Send a message stream and wait for the recipient to confirm delivery
+    async fn send_to_confirmed<I: AsyncRead + Unpin + Send>(
+        self: &Arc<Self>,
+        auth: AddrAuth,
+        letterhead: LetterheadV1,
+        data_reader: I,
+        timeout: Duration,
+    ) -> crate::Result<DeliveryReceipt> {
+        let stream_ids = self
+            .send_one(auth, letterhead.request_receipt(), data_reader)
+            .await?;
+        let stream_id = stream_ids
+            .into_iter()
+            .next()
+            .ok_or_else(|| ClientError::Internal("Router did not return a stream ID".into()))?;
+
+        self.await_receipt(auth, stream_id, timeout).await
+    }
+
+    /// Wait for the delivery receipt of a previously sent stream
+    async fn await_receipt(
+        self: &Arc<Self>,
+        auth: AddrAuth,
+        stream_id: Ident32,
+        timeout: Duration,
+    ) -> crate::Result<DeliveryReceipt> {
         let mut socket = self.socket().lock().await;
         socket
             .write_microframe(
                 MicroframeHeader {
-                    modes: cm::make(cm::SEND, cm::ONE),
+                    modes: cm::make(cm::SEND, cm::RECEIPT),
                     auth: Some(auth),
                     ..Default::default()
                 },
-                SendOne { letterhead },
+                AwaitReceipt {
+                    stream_id,
+                    timeout_ms: timeout.as_millis() as u64,
+                },
             )
             .await?;
 
-        let mut reader = Box::pin(data_reader);
-
-        let mut remaining = plen;
-        loop {
-            let mut buf = vec![0_u8; chunk_size.min(remaining) as usize];
-            reader.read_exact(&mut buf).await?;
-            remaining -= buf.len() as u64;
-
-            println!("Writing chunk to router socket {buf:?}");
-            socket.write_buffer(buf).await?;
-
-            if remaining == 0 {
-                break;
-            }
+        let (_, ping) = socket.read_microframe::<ServerPing>().await?;
+        match ping? {
+            ServerPing::Delivered(receipt) => Ok(receipt),
+            ServerPing::Timeout => Err(NonfatalError::NoReceipt(stream_id).into()),
+            ServerPing::Error(e) => Err(e.into()),
+            i => Err(ClientError::Internal(format!("Invalid router response: {i:?}")).into()),
         }
-
-        Ok(())
     }
 
     /// Send the same message stream to multiple recipients
@@ -631,12 +934,8 @@ impl RatmanStreamExtV1 for RatmanIpc {
         tokio::io::copy(&mut data_reader, &mut send_s).await?;
         drop(send_s);
 
-        let (_, ping) = socket.read_microframe::<ServerPing>().await?;
-        match ping? {
-            ServerPing::Ok => Ok(()),
-            ServerPing::Error(e) => Err(e.into()),
-            i => Err(ClientError::Internal(format!("Invalid router response: {i:?}")).into()),
-        }
+        read_send_response(&mut socket).await?;
+        Ok(())
     }
 
     /// Block this task/ socket to wait for a single incoming message stream
@@ -700,6 +999,93 @@ impl RatmanStreamExtV1 for RatmanIpc {
             inner: ReadStream(socket),
         })
     }
+
+    async fn fetch_stream<'s>(
+        self: &'s Arc<Self>,
+        auth: AddrAuth,
+        addr: Address,
+        read_cap: ReadCapability,
+        timeout: Duration,
+    ) -> crate::Result<(u64, ReadStream<'s>)> {
+        let mut socket = self.socket().lock().await;
+        socket
+            .write_microframe(
+                MicroframeHeader {
+                    modes: cm::make(cm::RECV, cm::QUERY),
+                    auth: Some(auth),
+                    ..Default::default()
+                },
+                FetchStream {
+                    addr,
+                    read_cap,
+                    timeout_ms: timeout.as_millis() as u64,
+                },
+            )
+            .await?;
+
+        match socket.read_microframe::<ServerPing>().await?.1? {
+            ServerPing::Fetched { length } => Ok((length, ReadStream(socket))),
+            ServerPing::Error(e) => Err(e.into()),
+            other => Err(ClientError::Internal(format!("{other:?}")).into()),
+        }
+    }
+
+    async fn stream_link(
+        self: &Arc<Self>,
+        auth: AddrAuth,
+        addr: Address,
+        stream_id: Ident32,
+        streams: Vec<Ident32>,
+        tags: Vec<String>,
+    ) -> crate::Result<()> {
+        let mut socket = self.socket().lock().await;
+        socket
+            .write_microframe(
+                MicroframeHeader {
+                    modes: cm::make(cm::STREAM, cm::MODIFY),
+                    auth: Some(auth),
+                    ..Default::default()
+                },
+                StreamLink {
+                    addr,
+                    stream_id,
+                    streams,
+                    tags: tags.iter().map(to_cstring).collect(),
+                },
+            )
+            .await?;
+
+        match socket.read_microframe::<ServerPing>().await?.1? {
+            ServerPing::Ok => Ok(()),
+            ServerPing::Error(e) => Err(e.into()),
+            other => Err(ClientError::Internal(format!("{other:?}")).into()),
+        }
+    }
+
+    async fn stream_unlink(
+        self: &Arc<Self>,
+        auth: AddrAuth,
+        addr: Address,
+        stream_id: Ident32,
+    ) -> crate::Result<()> {
+        let mut socket = self.socket().lock().await;
+        socket
+            .write_microframe(
+                MicroframeHeader {
+                    modes: cm::make(cm::STREAM, cm::DELETE),
+                    auth: Some(auth),
+                    ..Default::default()
+                },
+                StreamUnlink { addr, stream_id },
+            )
+            .await?;
+
+        match socket.read_microframe::<ServerPing>().await?.1? {
+            ServerPing::Ok => Ok(()),
+            ServerPing::Error(e) => Err(e.into()),
+            other => Err(ClientError::Internal(format!("{other:?}")).into()),
+        }
+    }
 }
 
 #[async_trait]
@@ -732,6 +1118,132 @@ impl NamespaceAnycastExtV1 for RatmanIpc {
         }
     }
 
+    async fn namespace_create<'n>(
+        self: &Arc<Self>,
+        auth: AddrAuth,
+        name: Option<&'n String>,
+    ) -> Result<Address> {
+        let mut socket = self.socket().lock().await;
+        socket
+            .write_microframe(
+                MicroframeHeader {
+                    modes: cm::make(cm::SPACE, cm::ADD),
+                    auth: Some(auth),
+                    ..Default::default()
+                },
+                NamespaceCreate {
+                    name: name.map(|n| {
+                        CString::new(n.as_bytes()).expect("failed to encode String to CString")
+                    }),
+                },
+            )
+            .await?;
+
+        match socket.read_microframe::<ServerPing>().await?.1? {
+            ServerPing::AddrList(mut list) if list.len() == 1 => Ok(list.remove(0)),
+            ServerPing::Error(e) => Err(e.into()),
+            other => Err(ClientError::Internal(format!("{other:?}")).into()),
+        }
+    }
+
+    async fn namespace_list(self: &Arc<Self>) -> Result<Vec<Address>> {
+        let mut socket = self.socket().lock().await;
+        socket
+            .write_microframe(
+                MicroframeHeader {
+                    modes: cm::make(cm::SPACE, cm::LIST),
+                    auth: None,
+                    ..Default::default()
+                },
+                (),
+            )
+            .await?;
+
+        match socket.read_microframe::<ServerPing>().await?.1? {
+            ServerPing::AddrList(list) => Ok(list),
+            ServerPing::Error(e) => Err(e.into()),
+            other => Err(ClientError::Internal(format!("{other:?}")).into()),
+        }
+    }
+
+    async fn namespace_export(
+        self: &Arc<Self>,
+        auth: AddrAuth,
+        space_pubkey: Address,
+    ) -> Result<(Address, Ident32)> {
+        let mut socket = self.socket().lock().await;
+        socket
+            .write_microframe(
+                MicroframeHeader {
+                    modes: cm::make(cm::SPACE, cm::QUERY),
+                    auth: Some(auth),
+                    ..Default::default()
+                },
+                NamespaceExport {
+                    namespace_addr: space_pubkey,
+                },
+            )
+            .await?;
+
+        match socket.read_microframe::<ServerPing>().await?.1? {
+            ServerPing::NamespaceKey { pubkey, privkey } => Ok((pubkey, privkey)),
+            ServerPing::Error(e) => Err(e.into()),
+            other => Err(ClientError::Internal(format!("{other:?}")).into()),
+        }
+    }
+
+    async fn namespace_rotate(
+        self: &Arc<Self>,
+        auth: AddrAuth,
+        space_pubkey: Address,
+    ) -> Result<Address> {
+        let mut socket = self.socket().lock().await;
+        socket
+            .write_microframe(
+                MicroframeHeader {
+                    modes: cm::make(cm::SPACE, cm::MODIFY),
+                    auth: Some(auth),
+                    ..Default::default()
+                },
+                NamespaceRotate {
+                    namespace_addr: space_pubkey,
+                },
+            )
+            .await?;
+
+        match socket.read_microframe::<ServerPing>().await?.1? {
+            ServerPing::AddrList(mut list) if list.len() == 1 => Ok(list.remove(0)),
+            ServerPing::Error(e) => Err(e.into()),
+            other => Err(ClientError::Internal(format!("{other:?}")).into()),
+        }
+    }
+
+    async fn namespace_destroy(
+        self: &Arc<Self>,
+        auth: AddrAuth,
+        space_pubkey: Address,
+    ) -> Result<()> {
+        let mut socket = self.socket().lock().await;
+        socket
+            .write_microframe(
+                MicroframeHeader {
+                    modes: cm::make(cm::SPACE, cm::DESTROY),
+                    auth: Some(auth),
+                    ..Default::default()
+                },
+                NamespaceDestroy {
+                    namespace_addr: space_pubkey,
+                },
+            )
+            .await?;
+
+        match socket.read_microframe::<ServerPing>().await?.1? {
+            ServerPing::Ok => Ok(()),
+            ServerPing::Error(e) => Err(e.into()),
+            other => Err(ClientError::Internal(format!("{other:?}")).into()),
+        }
+    }
+
     async fn namespace_up(
         self: &Arc<Self>,
         client_addr: Address,
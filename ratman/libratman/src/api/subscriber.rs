@@ -1,10 +1,47 @@
 use crate::{
-    api::RawSocketHandle,
+    api::{
+        types::{DeliveryReceipt, PeerEvent, ServerPing},
+        RawSocketHandle,
+    },
+    frame::micro::client_modes as cm,
     types::{Ident32, LetterheadV1},
-    NonfatalError, RatmanError, Result,
+    ClientError, EncodingError, NonfatalError, RatmanError, Result,
 };
 use tokio::io::AsyncReadExt;
 
+/// An event received on a subscription socket
+#[derive(Clone, Debug)]
+pub enum SubscriptionEvent {
+    /// A new message stream is available
+    ///
+    /// The stream contents must be read via `read_to_buf` before waiting for
+    /// the next event.
+    Stream(LetterheadV1),
+    /// A stream sent by the subscribed address was delivered
+    Delivered(DeliveryReceipt),
+}
+
+/// A subscription to changes in the set of peers known to the router
+///
+/// Created via `RatmanIpcExtV1::peers_subscribe`.
+pub struct PeerSubscription {
+    pub(crate) socket: RawSocketHandle,
+}
+
+impl PeerSubscription {
+    /// Wait for the next peer to be discovered or pruned
+    pub async fn next_event(&mut self) -> Result<PeerEvent> {
+        let header = self.socket.read_header().await?;
+        match header.modes {
+            m if m == cm::make(cm::PEER, cm::SUB) => Ok(self
+                .socket
+                .read_payload::<PeerEvent>(header.payload_size)
+                .await??),
+            m => Err(EncodingError::Parsing(format!("unexpected peer event mode {m}")).into()),
+        }
+    }
+}
+
 pub struct SubscriptionHandle {
     pub id: Ident32,
     pub(crate) curr_stream: Option<LetterheadV1>,
@@ -36,19 +73,64 @@ impl SubscriptionHandle {
     /// When calling this function before a previous stream has completed it
     /// will return a `NonfatalError::OngoingStream`, which indicates that the
     /// previous stream must be completed before starting a new one
+    ///
+    /// Delivery receipts that arrive in the meantime are skipped.  Use
+    /// `next_event()` if your application wants to handle them.
     pub async fn wait_for_stream(&mut self) -> Result<LetterheadV1> {
+        loop {
+            match self.next_event().await? {
+                SubscriptionEvent::Stream(lh) => break Ok(lh),
+                SubscriptionEvent::Delivered(_) => continue,
+            }
+        }
+    }
+
+    /// Wait for the next incoming stream or delivery receipt
+    ///
+    /// The same restrictions as for `wait_for_stream()` apply: a previous
+    /// stream must be read to completion before calling this function.
+    ///
+    /// If the router gives up on an incoming stream because blocks are
+    /// missing, a `ClientError::StreamTimeout` is returned.  Incoming blocks
+    /// that fail authentication are reported as `ClientError::Unauthenticated`
+    /// (or `Unsealed`, `NoChunkKey`).  The subscription stays usable
+    /// afterwards.
+    pub async fn next_event(&mut self) -> Result<SubscriptionEvent> {
         if self.curr_stream.is_some() {
             return Err(NonfatalError::OngoingStream.into());
         }
 
-        let (_, letterhead) = self.socket.read_microframe::<LetterheadV1>().await?;
-        match letterhead {
-            Ok(lh) => {
+        let header = self.socket.read_header().await?;
+        match header.modes {
+            m if m == cm::make(cm::SUB, cm::ONE) => {
+                let lh = self
+                    .socket
+                    .read_payload::<LetterheadV1>(header.payload_size)
+                    .await??;
                 self.curr_stream = Some(lh.clone());
                 self.read_from_stream = 0;
-                Ok(lh)
+                Ok(SubscriptionEvent::Stream(lh))
+            }
+            m if m == cm::make(cm::SUB, cm::RECEIPT) => {
+                let receipt = self
+                    .socket
+                    .read_payload::<DeliveryReceipt>(header.payload_size)
+                    .await??;
+                Ok(SubscriptionEvent::Delivered(receipt))
+            }
+            m if m == cm::make(cm::INTRINSIC, cm::INTRINSIC) => {
+                match self
+                    .socket
+                    .read_payload::<ServerPing>(header.payload_size)
+                    .await??
+                {
+                    ServerPing::Error(e) => Err(e.into()),
+                    _ => Err(ClientError::ConnectionLost.into()),
+                }
             }
-            err => err,
+            m => Err(
+                EncodingError::Parsing(format!("unexpected subscription event mode {m}")).into(),
+            ),
         }
     }
 
@@ -10,6 +10,27 @@ use crate::{
 use nom::IResult;
 use std::ffi::CString;
 
+/// Write an address, prefixed with a tag byte
+///
+/// Addresses are derived from public keys and can start with a zero
+/// byte, so `maybe` can't tell a missing address from a present one.
+fn generate_address(addr: Address, buf: &mut Vec<u8>) -> Result<()> {
+    buf.push(1);
+    addr.generate(buf)
+}
+
+/// Read an address that was written by [`generate_address`]
+fn take_tagged_address(input: &[u8]) -> IResult<&[u8], Option<Address>> {
+    let (input, exists) = parse::take_byte(input)?;
+
+    if exists == 1 {
+        let (input, addr) = parse::take_address(input)?;
+        Ok((input, Some(addr)))
+    } else {
+        Ok((input, None))
+    }
+}
+
 pub struct AddrCreate {
     pub name: Option<CString>,
 }
@@ -45,7 +66,7 @@ pub struct AddrDestroy {
 
 impl FrameGenerator for AddrDestroy {
     fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
-        self.addr.generate(buf)?;
+        generate_address(self.addr, buf)?;
         match self.force {
             true => buf.push(1),
             false => buf.push(0),
@@ -57,7 +78,7 @@ impl FrameGenerator for AddrDestroy {
 impl FrameParser for AddrDestroy {
     type Output = Result<Self>;
     fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
-        let (input, addr) = maybe(parse::take_address, input)?;
+        let (input, addr) = take_tagged_address(input)?;
         let (input, _) = maybe(parse::take(1 as u8), input)?;
 
         let res = match addr {
@@ -76,15 +97,14 @@ pub struct AddrUp {
 
 impl FrameGenerator for AddrUp {
     fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
-        self.addr.generate(buf)?;
-        Ok(())
+        generate_address(self.addr, buf)
     }
 }
 
 impl FrameParser for AddrUp {
     type Output = Result<Self>;
     fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
-        let (input, addr) = maybe(parse::take_address, input)?;
+        let (input, addr) = take_tagged_address(input)?;
 
         let res = match addr {
             Some(addr) => Ok(Self { addr }),
@@ -102,15 +122,14 @@ pub struct AddrDown {
 
 impl FrameGenerator for AddrDown {
     fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
-        self.addr.generate(buf)?;
-        Ok(())
+        generate_address(self.addr, buf)
     }
 }
 
 impl FrameParser for AddrDown {
     type Output = Result<Self>;
     fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
-        let (input, addr) = maybe(parse::take_address, input)?;
+        let (input, addr) = take_tagged_address(input)?;
 
         let res = match addr {
             Some(addr) => Ok(Self { addr }),
@@ -140,3 +159,29 @@ impl FrameParser for AddrList {
         Ok((input, Ok(Self { list })))
     }
 }
+
+#[test]
+fn addr_zero_byte_roundtrip() {
+    let mut bytes = [7; 32];
+    bytes[0] = 0;
+    let addr = Address::from_bytes(&bytes);
+
+    let mut buf = vec![];
+    AddrUp { addr }.generate(&mut buf).unwrap();
+    let (rest, parsed) = AddrUp::parse(&buf).unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(parsed.unwrap().addr, addr);
+
+    let mut buf = vec![];
+    AddrDown { addr }.generate(&mut buf).unwrap();
+    let (rest, parsed) = AddrDown::parse(&buf).unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(parsed.unwrap().addr, addr);
+
+    let mut buf = vec![];
+    AddrDestroy { addr, force: true }
+        .generate(&mut buf)
+        .unwrap();
+    let (_, parsed) = AddrDestroy::parse(&buf).unwrap();
+    assert_eq!(parsed.unwrap().addr, addr);
+}
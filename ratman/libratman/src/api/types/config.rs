@@ -0,0 +1,117 @@
+use crate::{
+    frame::{
+        generate::{generate_cstring, generate_option_cstring},
+        parse::{maybe_cstring, take_byte, take_cstring},
+        FrameGenerator, FrameParser,
+    },
+    types::to_cstring,
+    EncodingError, Result,
+};
+use nom::IResult;
+use serde::{Deserialize, Serialize};
+use std::{ffi::CString, fmt::Display};
+
+/// What happened to a changed setting when the configuration was reloaded
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChangeOutcome {
+    /// The new value is in use
+    Applied,
+    /// The new value is only used after the router is restarted
+    NeedsRestart,
+    /// The new value could not be applied
+    Failed(String),
+}
+
+/// A setting that changed when the router configuration was reloaded
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigChange {
+    /// The path of the setting, for example `ratmand/verbosity`
+    pub key: String,
+    pub outcome: ChangeOutcome,
+}
+
+impl Display for ConfigChange {
+    fn fmt(&self, w: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.outcome {
+            ChangeOutcome::Applied => write!(w, "{}\tapplied", self.key),
+            ChangeOutcome::NeedsRestart => write!(w, "{}\trestart required", self.key),
+            ChangeOutcome::Failed(ref e) => write!(w, "{}\tfailed\t{}", self.key, e),
+        }
+    }
+}
+
+impl FrameGenerator for ConfigChange {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        generate_cstring(to_cstring(&self.key), buf)?;
+        match self.outcome {
+            ChangeOutcome::Applied => buf.push(0),
+            ChangeOutcome::NeedsRestart => buf.push(1),
+            ChangeOutcome::Failed(e) => {
+                buf.push(2);
+                generate_option_cstring(Some(to_cstring(&e)), buf)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FrameParser for ConfigChange {
+    type Output = Result<Self>;
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, key) = take_cstring(input)?;
+        let (input, tt) = take_byte(input)?;
+
+        let to_string = |c: CString| -> Result<String> {
+            c.into_string()
+                .map_err(|e| EncodingError::Parsing(e.to_string()).into())
+        };
+
+        let (input, outcome) = match tt {
+            0 => (input, Ok(ChangeOutcome::Applied)),
+            1 => (input, Ok(ChangeOutcome::NeedsRestart)),
+            2 => {
+                let (input, error) = maybe_cstring(input)?;
+                let error = error.and_then(|e| e.map(to_string).transpose());
+                (
+                    input,
+                    error.map(|e| ChangeOutcome::Failed(e.unwrap_or_default())),
+                )
+            }
+            _ => (
+                input,
+                Err(EncodingError::Parsing(format!("Invalid ChangeOutcome type={}", tt)).into()),
+            ),
+        };
+
+        Ok((
+            input,
+            key.and_then(to_string).and_then(|key| {
+                Ok(Self {
+                    key,
+                    outcome: outcome?,
+                })
+            }),
+        ))
+    }
+}
+
+#[test]
+fn config_change_roundtrip() {
+    for outcome in [
+        ChangeOutcome::Applied,
+        ChangeOutcome::NeedsRestart,
+        ChangeOutcome::Failed("netmod 'lora' failed to start".into()),
+        ChangeOutcome::Failed("".into()),
+    ] {
+        let change = ConfigChange {
+            key: "ratmand/verbosity".into(),
+            outcome,
+        };
+
+        let mut buf = vec![];
+        change.clone().generate(&mut buf).unwrap();
+        let (rest, parsed) = ConfigChange::parse(&buf).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(parsed.unwrap(), change);
+    }
+}
@@ -2,15 +2,22 @@ use nom::IResult;
 
 use crate::{
     frame::{
-        carrier::take_address,
-        generate::{generate_cstring_tuple_vec, generate_option_cstring},
-        parse::maybe_cstring,
+        generate::{generate_cstring, generate_cstring_tuple_vec, generate_option_cstring},
+        parse::{
+            maybe_cstring, take_address, take_byte, take_cstring, take_cstring_tuple_vec, take_id,
+        },
         FrameGenerator, FrameParser,
     },
-    types::{to_cstring, Address},
-    Result,
+    types::{to_cstring, Address, Ident32, Modify},
+    EncodingError, Result,
 };
-use std::ffi::CString;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, ffi::CString, fmt::Display};
+
+/// The lowest trust level a contact can be given
+pub const TRUST_MIN: u8 = 1;
+/// The highest trust level a contact can be given
+pub const TRUST_MAX: u8 = 7;
 
 pub struct ContactAdd {
     /// The address to add as a contact
@@ -54,15 +61,22 @@ impl FrameGenerator for ContactAdd {
 impl FrameParser for ContactAdd {
     type Output = Result<Self>;
     fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
-        let (input, _addr) = take_address(input)?;
-
-        // Read a CString note
+        let (input, addr) = take_address(input)?;
         let (input, note) = maybe_cstring(input)?;
-        if let Err(e) = note {
-            return Ok((input, Err(e)));
-        }
+        let (input, tags) = take_cstring_tuple_vec(input)?;
+        let (input, trust) = take_byte(input)?;
 
-        todo!()
+        Ok((
+            input,
+            note.and_then(|note| {
+                tags.map(|tags| Self {
+                    addr,
+                    note,
+                    tags,
+                    trust,
+                })
+            }),
+        ))
     }
 }
 
@@ -71,9 +85,274 @@ pub struct ContactDelete {
     pub addr: Address,
 }
 
+impl FrameGenerator for ContactDelete {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        self.addr.generate(buf)
+    }
+}
+
+impl FrameParser for ContactDelete {
+    type Output = Self;
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, addr) = take_address(input)?;
+        Ok((input, Self { addr }))
+    }
+}
+
+/// Select a set of contact book entries
+///
+/// An empty filter selects all entries.  Otherwise an entry must match
+/// every provided filter.
+#[derive(Default)]
+pub struct ContactFilter {
+    /// Only select entries for any of these addresses
+    pub addrs: Vec<Address>,
+    /// Only select entries whose note contains this string
+    pub note: Option<CString>,
+    /// Only select entries which have all of these tags
+    pub tags: Vec<(CString, CString)>,
+}
+
+impl FrameGenerator for ContactFilter {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        self.addrs.generate(buf)?;
+        generate_option_cstring(self.note, buf)?;
+        generate_cstring_tuple_vec(self.tags, buf)?;
+        Ok(())
+    }
+}
+
+impl FrameParser for ContactFilter {
+    type Output = Result<Self>;
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, addrs) = Vec::<Address>::parse(input)?;
+        let (input, note) = maybe_cstring(input)?;
+        let (input, tags) = take_cstring_tuple_vec(input)?;
+
+        Ok((
+            input,
+            note.and_then(|note| tags.map(|tags| Self { addrs, note, tags })),
+        ))
+    }
+}
+
+/// Apply a change to all contact book entries selected by a filter
 pub struct ContactModify {
-    pub add_tags: Vec<(CString, CString)>,
-    pub remove_tags_by_key: Vec<CString>,
-    pub addr: Option<Address>,
+    pub filter: ContactFilter,
+    pub note_modify: Modify<CString>,
+    /// `DeleteOne` only considers the tag key
+    pub tags_modify: Modify<(CString, CString)>,
     pub new_trust: Option<u8>,
 }
+
+impl FrameGenerator for ContactModify {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        self.filter.generate(buf)?;
+        generate_modify(self.note_modify, buf, generate_cstring)?;
+        generate_modify(self.tags_modify, buf, |(k, v), buf| {
+            generate_cstring(k, buf)?;
+            generate_cstring(v, buf)
+        })?;
+        match self.new_trust {
+            Some(trust) => trust.generate(buf)?,
+            None => buf.push(0),
+        }
+        Ok(())
+    }
+}
+
+impl FrameParser for ContactModify {
+    type Output = Result<Self>;
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, filter) = ContactFilter::parse(input)?;
+        let (input, note_modify) = take_modify(input, take_cstring)?;
+        let (input, tags_modify) = take_modify(input, |input| {
+            let (input, k) = take_cstring(input)?;
+            let (input, v) = take_cstring(input)?;
+            Ok((input, k.and_then(|k| v.map(|v| (k, v)))))
+        })?;
+        let (input, new_trust) = take_byte(input)?;
+
+        Ok((
+            input,
+            filter.and_then(|filter| {
+                Ok(Self {
+                    filter,
+                    note_modify: note_modify?,
+                    tags_modify: tags_modify?,
+                    new_trust: if new_trust == 0 {
+                        None
+                    } else {
+                        Some(new_trust)
+                    },
+                })
+            }),
+        ))
+    }
+}
+
+fn generate_modify<T>(
+    m: Modify<T>,
+    buf: &mut Vec<u8>,
+    inner: impl Fn(T, &mut Vec<u8>) -> Result<()>,
+) -> Result<()> {
+    match m {
+        Modify::Keep => buf.push(0),
+        Modify::Change(t) => {
+            buf.push(1);
+            inner(t, buf)?;
+        }
+        Modify::DeleteOne(t) => {
+            buf.push(2);
+            inner(t, buf)?;
+        }
+        Modify::DeleteAll => buf.push(3),
+    }
+    Ok(())
+}
+
+fn take_modify<'a, T>(
+    input: &'a [u8],
+    inner: impl Fn(&'a [u8]) -> IResult<&'a [u8], Result<T>>,
+) -> IResult<&'a [u8], Result<Modify<T>>> {
+    let (input, tt) = take_byte(input)?;
+    match tt {
+        0 => Ok((input, Ok(Modify::Keep))),
+        1 => inner(input).map(|(input, t)| (input, t.map(Modify::Change))),
+        2 => inner(input).map(|(input, t)| (input, t.map(Modify::DeleteOne))),
+        3 => Ok((input, Ok(Modify::DeleteAll))),
+        _ => Ok((
+            input,
+            Err(EncodingError::Parsing(format!("Invalid Modify type={}", tt)).into()),
+        )),
+    }
+}
+
+/// A single contact book entry
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContactEntry {
+    pub id: Ident32,
+    pub addr: Address,
+    pub note: Option<String>,
+    pub tags: BTreeMap<String, String>,
+    pub trust: u8,
+}
+
+impl Display for ContactEntry {
+    fn fmt(&self, w: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            w,
+            "{}\t{}\ttrust={}\t{}\t{}",
+            self.id,
+            self.addr,
+            self.trust,
+            self.tags
+                .iter()
+                .map(|(k, v)| format!("{k}={v}"))
+                .collect::<Vec<_>>()
+                .join(","),
+            self.note.as_deref().unwrap_or(""),
+        )
+    }
+}
+
+impl FrameGenerator for ContactEntry {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        self.id.generate(buf)?;
+        self.addr.generate(buf)?;
+        generate_option_cstring(self.note.as_ref().map(to_cstring), buf)?;
+        generate_cstring_tuple_vec(
+            self.tags
+                .iter()
+                .map(|(k, v)| (to_cstring(k), to_cstring(v)))
+                .collect(),
+            buf,
+        )?;
+        self.trust.generate(buf)?;
+        Ok(())
+    }
+}
+
+impl FrameParser for ContactEntry {
+    type Output = Result<Self>;
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, id) = take_id(input)?;
+        let (input, addr) = take_address(input)?;
+        let (input, note) = maybe_cstring(input)?;
+        let (input, tags) = take_cstring_tuple_vec(input)?;
+        let (input, trust) = take_byte(input)?;
+
+        let to_string = |c: CString| -> Result<String> {
+            c.into_string()
+                .map_err(|e| EncodingError::Parsing(e.to_string()).into())
+        };
+
+        Ok((
+            input,
+            note.and_then(|note| {
+                let tags = tags?
+                    .into_iter()
+                    .map(|(k, v)| Ok((to_string(k)?, to_string(v)?)))
+                    .collect::<Result<_>>()?;
+
+                Ok(Self {
+                    id,
+                    addr,
+                    note: note.map(to_string).transpose()?,
+                    tags,
+                    trust,
+                })
+            }),
+        ))
+    }
+}
+
+#[test]
+fn contact_add_roundtrip() {
+    let addr = Address::random();
+    let mut buf = vec![];
+    ContactAdd::new(
+        addr,
+        Some("met at the hackspace".into()),
+        vec![("group".to_string(), "friends".to_string())].into_iter(),
+        5,
+    )
+    .generate(&mut buf)
+    .unwrap();
+
+    let (rest, add) = ContactAdd::parse(&buf).unwrap();
+    let add = add.unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(add.addr, addr);
+    assert_eq!(add.note, Some(to_cstring(&"met at the hackspace".into())));
+    assert_eq!(
+        add.tags,
+        vec![(to_cstring(&"group".into()), to_cstring(&"friends".into()))]
+    );
+    assert_eq!(add.trust, 5);
+}
+
+#[test]
+fn contact_modify_roundtrip() {
+    let addr = Address::random();
+    let mut buf = vec![];
+    ContactModify {
+        filter: ContactFilter {
+            addrs: vec![addr],
+            ..Default::default()
+        },
+        note_modify: Modify::DeleteAll,
+        tags_modify: Modify::Change((to_cstring(&"k".into()), to_cstring(&"v".into()))),
+        new_trust: Some(2),
+    }
+    .generate(&mut buf)
+    .unwrap();
+
+    let (rest, modify) = ContactModify::parse(&buf).unwrap();
+    let modify = modify.unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(modify.filter.addrs, vec![addr]);
+    assert!(matches!(modify.note_modify, Modify::DeleteAll));
+    assert!(matches!(modify.tags_modify, Modify::Change(_)));
+    assert_eq!(modify.new_trust, Some(2));
+}
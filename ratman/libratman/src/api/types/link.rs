@@ -1,7 +1,165 @@
-pub struct LinkUp {}
+use crate::{
+    frame::{
+        generate::{generate_cstring, generate_option_cstring},
+        parse::{maybe_cstring, take_cstring, take_u32},
+        FrameGenerator, FrameParser,
+    },
+    types::{to_cstring, CurrentStatus},
+    EncodingError, Result,
+};
+use nom::IResult;
+use serde::{Deserialize, Serialize};
+use std::{ffi::CString, fmt::Display};
 
+/// Start one or more new netmod instances
 ///
-pub struct LinkDown {}
+/// The configuration uses the same KDL syntax as the netmod sections of
+/// the ratmand configuration file, for example `settings "inet" { bind
+/// "[::]:9001"; }`.  The `enable` setting is implied.
+pub struct LinkUp {
+    pub config: CString,
+}
 
-/// List available links
-pub struct LinkList {}
+impl FrameGenerator for LinkUp {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        generate_cstring(self.config, buf)
+    }
+}
+
+impl FrameParser for LinkUp {
+    type Output = Result<Self>;
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, config) = take_cstring(input)?;
+        Ok((input, config.map(|config| Self { config })))
+    }
+}
+
+/// Stop a netmod instance and remove it from the router
+pub struct LinkDown {
+    /// The link ID, as shown by the link list
+    pub id: u32,
+}
+
+impl FrameGenerator for LinkDown {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        self.id.generate(buf)
+    }
+}
+
+impl FrameParser for LinkDown {
+    type Output = Self;
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, id) = take_u32(input)?;
+        Ok((input, Self { id }))
+    }
+}
+
+/// A netmod instance which is attached to the router
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkEntry {
+    pub id: u32,
+    /// The name of the netmod driver (for example `inet`)
+    pub name: String,
+    /// The instance identifier reported by the driver
+    pub identifier: String,
+    /// Largest frame this link can carry, 0 if there's no limit
+    pub mtu: u32,
+    pub status: CurrentStatus,
+}
+
+impl Display for LinkEntry {
+    fn fmt(&self, w: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            w,
+            "{}\t{}\t{}\tpeers={}\ttx={}\trx={}\trejected={}\tmtu={}\t{}",
+            self.id,
+            self.name,
+            self.status.state,
+            self.status.peers,
+            self.status.tx_bytes,
+            self.status.rx_bytes,
+            self.status.rejected,
+            match self.mtu {
+                0 => "-".to_owned(),
+                mtu => mtu.to_string(),
+            },
+            self.identifier,
+        )?;
+
+        // Errors may contain whitespace, so they go last
+        match self.status.last_error {
+            Some(ref e) => write!(w, "\terror={e}"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl FrameGenerator for LinkEntry {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        self.id.generate(buf)?;
+        generate_option_cstring(Some(to_cstring(&self.name)), buf)?;
+        generate_option_cstring(Some(to_cstring(&self.identifier)), buf)?;
+        self.mtu.generate(buf)?;
+        self.status.generate(buf)?;
+        Ok(())
+    }
+}
+
+impl FrameParser for LinkEntry {
+    type Output = Result<Self>;
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, id) = take_u32(input)?;
+        let (input, name) = maybe_cstring(input)?;
+        let (input, identifier) = maybe_cstring(input)?;
+        let (input, mtu) = take_u32(input)?;
+        let (input, status) = CurrentStatus::parse(input)?;
+
+        // Empty strings are encoded as a single null byte
+        let to_string = |c: Result<Option<CString>>| -> Result<String> {
+            c?.map(|c| c.into_string())
+                .transpose()
+                .map(Option::unwrap_or_default)
+                .map_err(|e| EncodingError::Parsing(e.to_string()).into())
+        };
+
+        Ok((
+            input,
+            to_string(name).and_then(|name| {
+                Ok(Self {
+                    id,
+                    name,
+                    identifier: to_string(identifier)?,
+                    mtu,
+                    status: status?,
+                })
+            }),
+        ))
+    }
+}
+
+#[cfg(test)]
+use crate::types::LinkState;
+
+#[test]
+fn link_entry_roundtrip() {
+    let entry = LinkEntry {
+        id: 3,
+        name: "inet".into(),
+        identifier: "".into(),
+        mtu: 1452,
+        status: CurrentStatus {
+            state: LinkState::Up,
+            peers: 1,
+            tx_bytes: 128,
+            rx_bytes: 0,
+            rejected: 0,
+            last_error: None,
+        },
+    };
+
+    let mut buf = vec![];
+    entry.clone().generate(&mut buf).unwrap();
+    let (rest, parsed) = LinkEntry::parse(&buf).unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(parsed.unwrap(), entry);
+}
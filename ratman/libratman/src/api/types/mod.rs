@@ -3,36 +3,38 @@
 //!
 
 mod addr;
+mod config;
 mod contact;
 mod link;
 mod namespace;
 mod peer;
 mod recv;
 mod send;
+mod status;
 
 pub use addr::*;
 use byteorder::{BigEndian, ByteOrder};
+pub use config::*;
 pub use contact::*;
 pub use link::*;
 pub use namespace::*;
 pub use peer::*;
 pub use recv::*;
 pub use send::*;
-use serde::{Deserialize, Serialize};
+pub use status::*;
 
 use crate::{
     frame::{
         generate::generate_cstring,
         micro::parse::vec_of,
-        parse::{self, take_cstring, take_id, take_u32, take_u64},
+        parse::{self, take_cstring, take_id, take_u32},
         FrameGenerator, FrameParser,
     },
     types::{Address, Ident32},
     ClientError, EncodingError, Result,
 };
-use core::fmt;
 use nom::{bytes::complete::take, IResult};
-use std::{ffi::CString, fmt::Display};
+use std::ffi::CString;
 
 /// Sent from the router to the client when a client connects
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
@@ -116,31 +118,32 @@ pub enum ServerPing {
     SendSocket {
         socket_bind: CString,
     },
-    Status {
-        num_peers: u64,
-        num_local: u64,
-        num_auth: u64,
-        num_collector_workers: u64,
-    },
+    /// Diagnostics about the current router state
+    Status(RouterStatus),
     Anycast(Vec<(Address, u64)>),
-}
-
-#[derive(Serialize, Deserialize)]
-pub struct RouterStatus {
-    pub num_peers: u64,
-    pub num_local: u64,
-    pub num_auth: u64,
-    pub num_collector_workers: u64,
-}
-
-impl Display for RouterStatus {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "known peers: {}, local addrs: {}, active auths: {}, collector workers: {}",
-            self.num_peers, self.num_local, self.num_auth, self.num_collector_workers
-        )
-    }
+    /// Exported namespace key material
+    NamespaceKey {
+        pubkey: Address,
+        privkey: Ident32,
+    },
+    /// A set of contact book entries
+    ContactList(Vec<ContactEntry>),
+    /// Peers affected by pruning the routing table
+    Pruned(Vec<PrunedPeer>),
+    /// Streams were sent, and at least one of them requested a receipt
+    ///
+    /// Contains the stream IDs in the order of the provided letterheads.
+    Sent(Vec<Ident32>),
+    /// A stream was confirmed to be delivered
+    Delivered(DeliveryReceipt),
+    /// A set of netmod instances attached to the router
+    LinkList(Vec<LinkEntry>),
+    /// Settings that changed when the router configuration was reloaded
+    ConfigChanges(Vec<ConfigChange>),
+    /// A fetched stream of this many bytes follows the response
+    Fetched {
+        length: u64,
+    },
 }
 
 impl FrameGenerator for ServerPing {
@@ -190,22 +193,47 @@ impl FrameGenerator for ServerPing {
                 buf.push(9);
                 generate_cstring(socket_bind, buf)?;
             }
-            Self::Status {
-                num_peers,
-                num_local,
-                num_auth,
-                num_collector_workers,
-            } => {
+            Self::Status(status) => {
                 buf.push(10);
-                num_peers.generate(buf)?;
-                num_local.generate(buf)?;
-                num_auth.generate(buf)?;
-                num_collector_workers.generate(buf)?;
+                status.generate(buf)?;
             }
             Self::Anycast(list) => {
                 buf.push(11);
                 list.generate(buf)?;
             }
+            Self::NamespaceKey { pubkey, privkey } => {
+                buf.push(12);
+                pubkey.generate(buf)?;
+                privkey.generate(buf)?;
+            }
+            Self::ContactList(list) => {
+                buf.push(13);
+                list.generate(buf)?;
+            }
+            Self::Pruned(list) => {
+                buf.push(14);
+                list.generate(buf)?;
+            }
+            Self::Sent(stream_ids) => {
+                buf.push(15);
+                stream_ids.generate(buf)?;
+            }
+            Self::Delivered(receipt) => {
+                buf.push(16);
+                receipt.generate(buf)?;
+            }
+            Self::LinkList(list) => {
+                buf.push(17);
+                list.generate(buf)?;
+            }
+            Self::ConfigChanges(list) => {
+                buf.push(18);
+                list.generate(buf)?;
+            }
+            Self::Fetched { length } => {
+                buf.push(19);
+                length.generate(buf)?;
+            }
         }
 
         Ok(())
@@ -269,23 +297,62 @@ impl FrameParser for ServerPing {
                 send_bind.map(|socket_bind| Self::SendSocket { socket_bind })
             }
             10 => {
-                let (input_, num_peers) = take_u64(input)?;
-                let (input_, num_local) = take_u64(input_)?;
-                let (input_, num_auth) = take_u64(input_)?;
-                let (input_, num_collector_workers) = take_u64(input_)?;
+                let (input_, status) = RouterStatus::parse(input)?;
                 input = input_;
-                Ok(Self::Status {
-                    num_peers,
-                    num_local,
-                    num_auth,
-                    num_collector_workers,
-                })
+                status.map(Self::Status)
             }
             11 => {
                 let (input_, list) = Vec::<(Address, u64)>::parse(input)?;
                 input = input_;
                 Ok(Self::Anycast(list))
             }
+            12 => {
+                let (input_, pubkey) = parse::take_address(input)?;
+                let (input_, privkey) = take_id(input_)?;
+                input = input_;
+                Ok(Self::NamespaceKey { pubkey, privkey })
+            }
+            13 => {
+                let (input_, list) = vec_of(ContactEntry::parse, input)?;
+                input = input_;
+                list.into_iter()
+                    .collect::<Result<Vec<_>>>()
+                    .map(Self::ContactList)
+            }
+            14 => {
+                let (input_, list) = vec_of(PrunedPeer::parse, input)?;
+                input = input_;
+                Ok(Self::Pruned(list))
+            }
+            15 => {
+                let (input_, stream_ids) = vec_of(take_id, input)?;
+                input = input_;
+                Ok(Self::Sent(stream_ids))
+            }
+            16 => {
+                let (input_, receipt) = DeliveryReceipt::parse(input)?;
+                input = input_;
+                receipt.map(Self::Delivered)
+            }
+            17 => {
+                let (input_, list) = vec_of(LinkEntry::parse, input)?;
+                input = input_;
+                list.into_iter()
+                    .collect::<Result<Vec<_>>>()
+                    .map(Self::LinkList)
+            }
+            18 => {
+                let (input_, list) = vec_of(ConfigChange::parse, input)?;
+                input = input_;
+                list.into_iter()
+                    .collect::<Result<Vec<_>>>()
+                    .map(Self::ConfigChanges)
+            }
+            19 => {
+                let (input_, length) = parse::take_u64(input)?;
+                input = input_;
+                Ok(Self::Fetched { length })
+            }
             _ => Err(EncodingError::Parsing(format!("Invalid ServerPing type={}", tt)).into()),
         };
 
@@ -1,12 +1,14 @@
 use crate::{
     frame::{
-        parse::{take_address, take_id, take_u128},
+        generate::generate_option_cstring,
+        parse::{maybe_cstring, take_address, take_id, take_u128},
         FrameGenerator, FrameParser,
     },
     types::{Address, Ident32, Namespace},
     Result,
 };
 use nom::IResult;
+use std::ffi::CString;
 
 /// Register a new namespace key and subscribe to it on the server side (for a
 /// given application/ address pair)
@@ -34,6 +36,96 @@ impl FrameParser for NamespaceRegister {
     }
 }
 
+/// Create a new namespace with a key that is generated and held by the router
+pub struct NamespaceCreate {
+    pub name: Option<CString>,
+}
+
+impl FrameGenerator for NamespaceCreate {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        generate_option_cstring(self.name, buf)?;
+        Ok(())
+    }
+}
+
+impl FrameParser for NamespaceCreate {
+    type Output = Result<Self>;
+
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, name) = maybe_cstring(input)?;
+        Ok((input, name.map(|name| NamespaceCreate { name })))
+    }
+}
+
+/// Export the key material of a namespace held by the router
+///
+/// The router replies with a `ServerPing::NamespaceKey`, which can be
+/// imported on another router via `NamespaceRegister`.
+pub struct NamespaceExport {
+    pub namespace_addr: Namespace,
+}
+
+impl FrameGenerator for NamespaceExport {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        self.namespace_addr.generate(buf)?;
+        Ok(())
+    }
+}
+
+impl FrameParser for NamespaceExport {
+    type Output = NamespaceExport;
+
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, namespace_addr) = take_address(input)?;
+        Ok((input, NamespaceExport { namespace_addr }))
+    }
+}
+
+/// Replace a namespace key with a newly generated one
+///
+/// Since a namespace address is its public key, the router replies with the
+/// new namespace address.
+pub struct NamespaceRotate {
+    pub namespace_addr: Namespace,
+}
+
+impl FrameGenerator for NamespaceRotate {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        self.namespace_addr.generate(buf)?;
+        Ok(())
+    }
+}
+
+impl FrameParser for NamespaceRotate {
+    type Output = NamespaceRotate;
+
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, namespace_addr) = take_address(input)?;
+        Ok((input, NamespaceRotate { namespace_addr }))
+    }
+}
+
+/// Delete a namespace key from the router
+pub struct NamespaceDestroy {
+    pub namespace_addr: Namespace,
+}
+
+impl FrameGenerator for NamespaceDestroy {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        self.namespace_addr.generate(buf)?;
+        Ok(())
+    }
+}
+
+impl FrameParser for NamespaceDestroy {
+    type Output = NamespaceDestroy;
+
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, namespace_addr) = take_address(input)?;
+        Ok((input, NamespaceDestroy { namespace_addr }))
+    }
+}
+
 pub struct NamespaceUp {
     pub client_addr: Address,
     pub namespace_addr: Namespace,
@@ -126,3 +218,23 @@ impl FrameParser for AnycastProbe {
         ))
     }
 }
+
+#[test]
+fn namespace_create_roundtrip() {
+    let mut buf = vec![];
+    NamespaceCreate {
+        name: Some(CString::new("my-app").unwrap()),
+    }
+    .generate(&mut buf)
+    .unwrap();
+
+    let (rest, create) = NamespaceCreate::parse(&buf).unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(create.unwrap().name, Some(CString::new("my-app").unwrap()));
+
+    let mut buf = vec![];
+    NamespaceCreate { name: None }.generate(&mut buf).unwrap();
+    let (rest, create) = NamespaceCreate::parse(&buf).unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(create.unwrap().name, None);
+}
@@ -1,11 +1,12 @@
 use crate::{
     frame::{
+        generate::generate_cstring,
         micro::parse::*,
-        parse::{take_address, take_byte, take_datetime},
+        parse::{take_address, take_byte, take_cstring, take_datetime, take_u32},
         FrameGenerator, FrameParser,
     },
     types::{Address, TrustFilter},
-    Result,
+    EncodingError, Result,
 };
 use chrono::{DateTime, Utc};
 use core::fmt;
@@ -124,3 +125,215 @@ impl FrameParser for PeerList {
         ))
     }
 }
+
+/// Prune stale entries from the routing table
+///
+/// A value of `0` uses the expiry policy configured on the router.
+#[derive(Debug, Default)]
+pub struct PeerPrune {
+    /// Drop links that haven't been seen for this many hours
+    pub lost_link_hours: u32,
+    /// Forget peers that haven't been seen for this many days
+    pub forget_peer_days: u32,
+}
+
+impl FrameGenerator for PeerPrune {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        self.lost_link_hours.generate(buf)?;
+        self.forget_peer_days.generate(buf)?;
+        Ok(())
+    }
+}
+
+impl FrameParser for PeerPrune {
+    type Output = Self;
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, lost_link_hours) = take_u32(input)?;
+        let (input, forget_peer_days) = take_u32(input)?;
+        Ok((
+            input,
+            Self {
+                lost_link_hours,
+                forget_peer_days,
+            },
+        ))
+    }
+}
+
+/// Start peering with a new neighbour router
+///
+/// The peer uses the same `<netmod>:<address>` syntax as the ratmand
+/// configuration, for example `inet:[fe80::1]:9000`.
+pub struct PeerAdd {
+    pub peer: CString,
+}
+
+impl FrameGenerator for PeerAdd {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        generate_cstring(self.peer, buf)
+    }
+}
+
+impl FrameParser for PeerAdd {
+    type Output = Result<Self>;
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, peer) = take_cstring(input)?;
+        Ok((input, peer.map(|peer| Self { peer })))
+    }
+}
+
+/// Stop peering with a neighbour router that was added before
+pub struct PeerDelete {
+    pub peer: CString,
+}
+
+impl FrameGenerator for PeerDelete {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        generate_cstring(self.peer, buf)
+    }
+}
+
+impl FrameParser for PeerDelete {
+    type Output = Result<Self>;
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, peer) = take_cstring(input)?;
+        Ok((input, peer.map(|peer| Self { peer })))
+    }
+}
+
+/// A peer that was affected by pruning the routing table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrunedPeer {
+    pub addr: Address,
+    /// Number of links to this peer that were dropped
+    pub dropped_links: u32,
+    /// Whether the peer was removed from the routing table entirely
+    pub forgotten: bool,
+}
+
+impl Display for PrunedPeer {
+    fn fmt(&self, w: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            w,
+            "{}\tdropped links: {}\t{}",
+            self.addr,
+            self.dropped_links,
+            if self.forgotten { "FORGOTTEN" } else { "KEPT" }
+        )
+    }
+}
+
+impl FrameGenerator for PrunedPeer {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        self.addr.generate(buf)?;
+        self.dropped_links.generate(buf)?;
+        buf.push(self.forgotten as u8);
+        Ok(())
+    }
+}
+
+impl FrameParser for PrunedPeer {
+    type Output = Self;
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, addr) = take_address(input)?;
+        let (input, dropped_links) = take_u32(input)?;
+        let (input, forgotten) = take_byte(input)?;
+        Ok((
+            input,
+            Self {
+                addr,
+                dropped_links,
+                forgotten: forgotten == 1,
+            },
+        ))
+    }
+}
+
+/// A change to the set of peers known to the router
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PeerEvent {
+    /// A previously unknown address was announced
+    Discovered(Address),
+    /// Links to a peer were dropped, or the peer was forgotten
+    Pruned(PrunedPeer),
+}
+
+impl Display for PeerEvent {
+    fn fmt(&self, w: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Discovered(addr) => write!(w, "{}	DISCOVERED", addr),
+            Self::Pruned(pruned) => pruned.fmt(w),
+        }
+    }
+}
+
+impl FrameGenerator for PeerEvent {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        match self {
+            Self::Discovered(addr) => {
+                buf.push(0);
+                addr.generate(buf)
+            }
+            Self::Pruned(pruned) => {
+                buf.push(1);
+                pruned.generate(buf)
+            }
+        }
+    }
+}
+
+impl FrameParser for PeerEvent {
+    type Output = Result<Self>;
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, tt) = take_byte(input)?;
+        match tt {
+            0 => {
+                let (input, addr) = take_address(input)?;
+                Ok((input, Ok(Self::Discovered(addr))))
+            }
+            1 => {
+                let (input, pruned) = PrunedPeer::parse(input)?;
+                Ok((input, Ok(Self::Pruned(pruned))))
+            }
+            _ => Ok((
+                input,
+                Err(EncodingError::Parsing(format!("Invalid PeerEvent type={}", tt)).into()),
+            )),
+        }
+    }
+}
+
+#[test]
+fn peer_event_roundtrip() {
+    let events = vec![
+        PeerEvent::Discovered(Address::random()),
+        PeerEvent::Pruned(PrunedPeer {
+            addr: Address::random(),
+            dropped_links: 1,
+            forgotten: false,
+        }),
+    ];
+
+    for event in events {
+        let mut buf = vec![];
+        event.clone().generate(&mut buf).unwrap();
+        let (rest, parsed) = PeerEvent::parse(&buf).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(parsed.unwrap(), event);
+    }
+}
+
+#[test]
+fn pruned_peer_roundtrip() {
+    let pruned = PrunedPeer {
+        addr: Address::random(),
+        dropped_links: 3,
+        forgotten: true,
+    };
+
+    let mut buf = vec![];
+    pruned.clone().generate(&mut buf).unwrap();
+    let (rest, parsed) = PrunedPeer::parse(&buf).unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(parsed, pruned);
+}
@@ -1,12 +1,16 @@
 use crate::{
     frame::{
-        parse::{take_address, take_id},
+        generate::generate_cstring,
+        micro::parse::vec_of,
+        parse::{take_address, take_cstring_vec, take_id, take_u64},
         FrameGenerator, FrameParser,
     },
     types::{Address, Ident32, Recipient},
-    Result,
+    EncodingError, Result,
 };
-use nom::IResult;
+use async_eris::ReadCapability;
+use nom::{bytes::complete::take, combinator::map_opt, IResult};
+use std::ffi::CString;
 
 pub struct SubsCreate {
     pub addr: Address,
@@ -139,3 +143,175 @@ impl FrameParser for RecvMany {
         ))
     }
 }
+
+/// Length of a binary encoded read capability
+const READ_CAP_LEN: usize = 66;
+
+pub struct FetchStream {
+    pub addr: Address,
+    pub read_cap: ReadCapability,
+    /// How long the router should wait for missing blocks
+    pub timeout_ms: u64,
+}
+
+impl FrameGenerator for FetchStream {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        self.addr.generate(buf)?;
+        buf.extend_from_slice(&self.read_cap.binary());
+        self.timeout_ms.generate(buf)?;
+        Ok(())
+    }
+}
+
+impl FrameParser for FetchStream {
+    type Output = Self;
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, addr) = take_address(input)?;
+        let (input, read_cap) = map_opt(take(READ_CAP_LEN), ReadCapability::from_binary)(input)?;
+        let (input, timeout_ms) = take_u64(input)?;
+        Ok((
+            input,
+            Self {
+                addr,
+                read_cap,
+                timeout_ms,
+            },
+        ))
+    }
+}
+
+/// Attach link data to a message stream
+///
+/// Linked streams are never evicted from the router's journal, even when its
+/// storage quota is exhausted.  Linking a stream again replaces its previous
+/// link data.
+pub struct StreamLink {
+    pub addr: Address,
+    /// The stream (manifest) ID to link
+    pub stream_id: Ident32,
+    /// Other message streams that are associated with this one
+    pub streams: Vec<Ident32>,
+    /// Free-form tags, strings are \0 terminated
+    pub tags: Vec<CString>,
+}
+
+impl FrameGenerator for StreamLink {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        self.addr.generate(buf)?;
+        Some(self.stream_id).generate(buf)?;
+        self.streams.generate(buf)?;
+
+        let len: u16 = self
+            .tags
+            .len()
+            .try_into()
+            .map_err(|_| EncodingError::FrameTooLarge(self.tags.len()))?;
+        len.generate(buf)?;
+        for tag in self.tags {
+            generate_cstring(tag, buf)?;
+        }
+        Ok(())
+    }
+}
+
+impl FrameParser for StreamLink {
+    type Output = Result<Self>;
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, addr) = take_address(input)?;
+        let (input, stream_id) = take_id(input)?;
+        let (input, streams) = vec_of(take_id, input)?;
+        let (input, tags) = take_cstring_vec(input)?;
+        Ok((
+            input,
+            tags.map(|tags| Self {
+                addr,
+                stream_id,
+                streams,
+                tags,
+            }),
+        ))
+    }
+}
+
+/// Remove the link data of a message stream
+pub struct StreamUnlink {
+    pub addr: Address,
+    pub stream_id: Ident32,
+}
+
+impl FrameGenerator for StreamUnlink {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        self.addr.generate(buf)?;
+        Some(self.stream_id).generate(buf)?;
+        Ok(())
+    }
+}
+
+impl FrameParser for StreamUnlink {
+    type Output = Self;
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, addr) = take_address(input)?;
+        let (input, stream_id) = take_id(input)?;
+        Ok((input, Self { addr, stream_id }))
+    }
+}
+
+#[test]
+fn stream_link_roundtrip() {
+    let (addr, stream_id, other) = (Address::random(), Ident32::random(), Ident32::random());
+
+    let mut buf = vec![];
+    StreamLink {
+        addr,
+        stream_id,
+        streams: vec![other],
+        tags: vec![
+            CString::new("keep").unwrap(),
+            CString::new("photos").unwrap(),
+        ],
+    }
+    .generate(&mut buf)
+    .unwrap();
+
+    let (rest, link) = StreamLink::parse(&buf).unwrap();
+    let link = link.unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(link.addr, addr);
+    assert_eq!(link.stream_id, stream_id);
+    assert_eq!(link.streams, vec![other]);
+    assert_eq!(
+        link.tags,
+        vec![
+            CString::new("keep").unwrap(),
+            CString::new("photos").unwrap()
+        ]
+    );
+}
+
+#[test]
+fn fetch_stream_roundtrip() {
+    use async_eris::{BlockKey, BlockReference};
+
+    let addr = Address::random();
+    let read_cap = ReadCapability {
+        root_reference: BlockReference::from([1; 32]),
+        root_key: BlockKey::from([2; 32]),
+        level: 3,
+        block_size: 32768,
+    };
+
+    let mut buf = vec![];
+    FetchStream {
+        addr,
+        read_cap,
+        timeout_ms: 5000,
+    }
+    .generate(&mut buf)
+    .unwrap();
+
+    let (rest, fetch) = FetchStream::parse(&buf).unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(fetch.addr, addr);
+    assert_eq!(fetch.read_cap, read_cap);
+    assert_eq!(fetch.timeout_ms, 5000);
+}
@@ -1,9 +1,15 @@
 use crate::{
-    frame::{micro::parse::vec_of, FrameGenerator, FrameParser},
-    types::LetterheadV1,
+    frame::{
+        micro::parse::vec_of,
+        parse::{take_address, take_datetime, take_id, take_u64},
+        FrameGenerator, FrameParser,
+    },
+    types::{Address, Ident32, LetterheadV1},
     Result,
 };
+use chrono::{DateTime, Utc};
 use nom::IResult;
+use std::fmt::{self, Display};
 
 pub struct SendOne {
     pub letterhead: LetterheadV1,
@@ -48,3 +54,107 @@ impl FrameParser for SendMany {
         ))
     }
 }
+
+/// Wait for the delivery receipt of a previously sent stream
+pub struct AwaitReceipt {
+    pub stream_id: Ident32,
+    /// How long the router should wait before giving up
+    pub timeout_ms: u64,
+}
+
+impl FrameGenerator for AwaitReceipt {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        self.stream_id.generate(buf)?;
+        self.timeout_ms.generate(buf)?;
+        Ok(())
+    }
+}
+
+impl FrameParser for AwaitReceipt {
+    type Output = Self;
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, stream_id) = take_id(input)?;
+        let (input, timeout_ms) = take_u64(input)?;
+        Ok((
+            input,
+            Self {
+                stream_id,
+                timeout_ms,
+            },
+        ))
+    }
+}
+
+/// Confirmation that a message stream was delivered to its recipient
+///
+/// Receipts are only sent for streams whose letterhead requested one via
+/// [`LetterheadV1::request_receipt`].  The signature of the recipient is
+/// verified by the sending router before the receipt is handed to clients.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeliveryReceipt {
+    /// The stream ID, as returned when sending the stream
+    pub stream_id: Ident32,
+    /// The address that received the stream
+    pub from: Address,
+    /// The address that sent the stream
+    pub to: Address,
+    /// The time at which the recipient router completed the stream
+    pub delivered_at: DateTime<Utc>,
+}
+
+impl Display for DeliveryReceipt {
+    fn fmt(&self, w: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            w,
+            "{}\tdelivered to {} at {}",
+            self.stream_id.pretty_string(),
+            self.from,
+            self.delivered_at
+        )
+    }
+}
+
+impl FrameGenerator for DeliveryReceipt {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        self.stream_id.generate(buf)?;
+        self.from.generate(buf)?;
+        self.to.generate(buf)?;
+        self.delivered_at.generate(buf)?;
+        Ok(())
+    }
+}
+
+impl FrameParser for DeliveryReceipt {
+    type Output = Result<Self>;
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, stream_id) = take_id(input)?;
+        let (input, from) = take_address(input)?;
+        let (input, to) = take_address(input)?;
+        let (input, delivered_at) = take_datetime(input)?;
+        Ok((
+            input,
+            delivered_at.map(|delivered_at| Self {
+                stream_id,
+                from,
+                to,
+                delivered_at,
+            }),
+        ))
+    }
+}
+
+#[test]
+fn delivery_receipt_roundtrip() {
+    let receipt = DeliveryReceipt {
+        stream_id: Ident32::random(),
+        from: Address::random(),
+        to: Address::random(),
+        delivered_at: Utc::now(),
+    };
+
+    let mut buf = vec![];
+    receipt.clone().generate(&mut buf).unwrap();
+    let (rest, parsed) = DeliveryReceipt::parse(&buf).unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(parsed.unwrap(), receipt);
+}
@@ -0,0 +1,265 @@
+use crate::{
+    api::types::LinkEntry,
+    frame::{
+        micro::parse::vec_of,
+        parse::{take_id, take_u64},
+        FrameGenerator, FrameParser,
+    },
+    types::Ident32,
+    Result,
+};
+use nom::IResult;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+
+/// Storage use of the router journal
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalStatus {
+    /// Frames which haven't been collected into blocks yet
+    pub frames: u64,
+    pub blocks: u64,
+    pub manifests: u64,
+    /// Storage used, in bytes, as of the last quota check
+    pub usage: u64,
+    /// Storage budget in bytes
+    pub quota: u64,
+}
+
+impl FrameGenerator for JournalStatus {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        self.frames.generate(buf)?;
+        self.blocks.generate(buf)?;
+        self.manifests.generate(buf)?;
+        self.usage.generate(buf)?;
+        self.quota.generate(buf)?;
+        Ok(())
+    }
+}
+
+impl FrameParser for JournalStatus {
+    type Output = Self;
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, frames) = take_u64(input)?;
+        let (input, blocks) = take_u64(input)?;
+        let (input, manifests) = take_u64(input)?;
+        let (input, usage) = take_u64(input)?;
+        let (input, quota) = take_u64(input)?;
+        Ok((
+            input,
+            Self {
+                frames,
+                blocks,
+                manifests,
+                usage,
+                quota,
+            },
+        ))
+    }
+}
+
+/// Number of items waiting in the router's internal queues
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueueStatus {
+    /// Completed blocks waiting to be assembled into streams
+    pub ingress: u64,
+    /// Frames waiting to be collected into blocks
+    pub collector: u64,
+    /// Requests for missing blocks waiting to be answered
+    pub retransmit: u64,
+    /// Outgoing streams waiting to be sent in 1K blocks
+    pub send_1k: u64,
+    /// Outgoing streams waiting to be sent in 32K blocks
+    pub send_32k: u64,
+}
+
+impl FrameGenerator for QueueStatus {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        self.ingress.generate(buf)?;
+        self.collector.generate(buf)?;
+        self.retransmit.generate(buf)?;
+        self.send_1k.generate(buf)?;
+        self.send_32k.generate(buf)?;
+        Ok(())
+    }
+}
+
+impl FrameParser for QueueStatus {
+    type Output = Self;
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, ingress) = take_u64(input)?;
+        let (input, collector) = take_u64(input)?;
+        let (input, retransmit) = take_u64(input)?;
+        let (input, send_1k) = take_u64(input)?;
+        let (input, send_32k) = take_u64(input)?;
+        Ok((
+            input,
+            Self {
+                ingress,
+                collector,
+                retransmit,
+                send_1k,
+                send_32k,
+            },
+        ))
+    }
+}
+
+/// A snapshot of the router state, for monitoring purposes
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouterStatus {
+    /// Seconds since the router was started
+    pub uptime: u64,
+    /// The public key this router authenticates itself to peers with
+    pub router_id: Ident32,
+    pub num_peers: u64,
+    pub num_local: u64,
+    pub num_auth: u64,
+    pub num_collector_workers: u64,
+    /// Subscriptions that are registered on the router
+    pub num_subscriptions: u64,
+    pub journal: JournalStatus,
+    pub queues: QueueStatus,
+    /// All netmod instances attached to the router
+    pub links: Vec<LinkEntry>,
+}
+
+impl Display for RouterStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "uptime: {}h {}m {}s",
+            self.uptime / 3600,
+            self.uptime % 3600 / 60,
+            self.uptime % 60
+        )?;
+        writeln!(f, "router id: {}", self.router_id)?;
+        writeln!(
+            f,
+            "known peers: {}, local addrs: {}, active auths: {}, subscriptions: {}, collector workers: {}",
+            self.num_peers,
+            self.num_local,
+            self.num_auth,
+            self.num_subscriptions,
+            self.num_collector_workers
+        )?;
+        writeln!(
+            f,
+            "journal: {} frames, {} blocks, {} manifests, {} of {} bytes used",
+            self.journal.frames,
+            self.journal.blocks,
+            self.journal.manifests,
+            self.journal.usage,
+            self.journal.quota
+        )?;
+        writeln!(
+            f,
+            "queues: ingress {}, collector {}, retransmit {}, send {} (1K) {} (32K)",
+            self.queues.ingress,
+            self.queues.collector,
+            self.queues.retransmit,
+            self.queues.send_1k,
+            self.queues.send_32k
+        )?;
+
+        write!(f, "links:")?;
+        if self.links.is_empty() {
+            write!(f, " none")?;
+        }
+        for link in &self.links {
+            write!(f, "\n{link}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FrameGenerator for RouterStatus {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        self.uptime.generate(buf)?;
+        self.router_id.generate(buf)?;
+        self.num_peers.generate(buf)?;
+        self.num_local.generate(buf)?;
+        self.num_auth.generate(buf)?;
+        self.num_collector_workers.generate(buf)?;
+        self.num_subscriptions.generate(buf)?;
+        self.journal.generate(buf)?;
+        self.queues.generate(buf)?;
+        self.links.generate(buf)?;
+        Ok(())
+    }
+}
+
+impl FrameParser for RouterStatus {
+    type Output = Result<Self>;
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, uptime) = take_u64(input)?;
+        let (input, router_id) = take_id(input)?;
+        let (input, num_peers) = take_u64(input)?;
+        let (input, num_local) = take_u64(input)?;
+        let (input, num_auth) = take_u64(input)?;
+        let (input, num_collector_workers) = take_u64(input)?;
+        let (input, num_subscriptions) = take_u64(input)?;
+        let (input, journal) = JournalStatus::parse(input)?;
+        let (input, queues) = QueueStatus::parse(input)?;
+        let (input, links) = vec_of(LinkEntry::parse, input)?;
+
+        Ok((
+            input,
+            links
+                .into_iter()
+                .collect::<Result<Vec<_>>>()
+                .map(|links| Self {
+                    uptime,
+                    router_id,
+                    num_peers,
+                    num_local,
+                    num_auth,
+                    num_collector_workers,
+                    num_subscriptions,
+                    journal,
+                    queues,
+                    links,
+                }),
+        ))
+    }
+}
+
+#[test]
+fn router_status_roundtrip() {
+    use crate::types::CurrentStatus;
+
+    let status = RouterStatus {
+        uptime: 3723,
+        router_id: Ident32::random(),
+        num_peers: 4,
+        num_local: 1,
+        num_subscriptions: 2,
+        journal: JournalStatus {
+            frames: 12,
+            blocks: 3,
+            manifests: 1,
+            usage: 1024,
+            quota: 4096,
+        },
+        queues: QueueStatus {
+            ingress: 1,
+            send_32k: 2,
+            ..Default::default()
+        },
+        links: vec![LinkEntry {
+            id: 0,
+            name: "inet".into(),
+            identifier: "".into(),
+            mtu: 0,
+            status: CurrentStatus::default(),
+        }],
+        ..Default::default()
+    };
+
+    let mut buf = vec![];
+    status.clone().generate(&mut buf).unwrap();
+    let (rest, parsed) = RouterStatus::parse(&buf).unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(parsed.unwrap(), status);
+    assert!(status.to_string().starts_with("uptime: 1h 2m 3s\n"));
+}
@@ -31,8 +31,22 @@ pub trait EndpointExt {
     }
 
     /// Return a status digest for the current instance
-    fn status(&self) -> CurrentStatus {
-        CurrentStatus::Unknown
+    ///
+    /// Netmods should at least report whether they are able to send and
+    /// receive frames, and how many peers they are connected to.  The
+    /// default implementation reports an `Unknown` state.
+    async fn status(&self) -> CurrentStatus {
+        CurrentStatus::default()
+    }
+
+    /// Return the largest frame (in bytes) that this link can carry
+    ///
+    /// This value is used to fill in the path MTU of announcements received
+    /// over this link, which in turn limits the size of frames sent towards
+    /// the announced address.  Links without a frame size limit should return
+    /// `0`.
+    fn size_hint(&self) -> usize {
+        0
     }
 
     /// Query collected connection metrics to a given neighbour.  Currently this
@@ -50,16 +64,34 @@ pub trait EndpointExt {
     /// on cryptographic IDs of nearby gateways.
     ///
     /// The identifier returned must be a unique peer identifier, similar to the
-    /// `Neighbour` abstraction that is used by `send` and `next`.  Currently
-    /// this API doesn't consider stopping a peering intent (i.e. even if a
+    /// `Neighbour` abstraction that is used by `send` and `next`.  Even if a
     /// connection drops, the netmod should always attempt to re-establish the
-    /// connection).  The returned peer identifier can be used in the future to
-    /// disconnect two routers from each other without having to restart all
-    /// other connections.
+    /// connection, until the peering is stopped via `stop_peering`.
     async fn start_peering(&self, _addr: &str) -> Result<u16> {
         Err(crate::RatmanError::Netmod(crate::NetmodError::NotSupported))
     }
 
+    /// Stop a peering session that was started with `start_peering`
+    ///
+    /// Any existing connection to the peer is closed, and the netmod must
+    /// not attempt to re-establish it.  Connections to other peers are not
+    /// affected.  Unknown peer identifiers should return
+    /// `NetmodError::InvalidPeer`.
+    async fn stop_peering(&self, _id: u16) -> Result<()> {
+        Err(crate::RatmanError::Netmod(crate::NetmodError::NotSupported))
+    }
+
+    /// Stop this netmod instance
+    ///
+    /// This is called when a link is removed from a running router.  The
+    /// implementation should stop accepting new connections and close any
+    /// existing ones, so that its resources (sockets, serial ports, etc)
+    /// can be used by a new instance.  Sending and receiving after this
+    /// call is not required to work.
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+
     /// Send a frame envelope to a target over this link
     ///
     /// Sending characteristics are entirely up to the implementation.
@@ -98,6 +130,18 @@ pub trait EndpointExt {
 
 #[async_trait]
 impl<T: EndpointExt + Send + Sync> EndpointExt for Arc<T> {
+    fn identifier(&self) -> String {
+        T::identifier(self)
+    }
+
+    async fn status(&self) -> CurrentStatus {
+        T::status(self).await
+    }
+
+    fn size_hint(&self) -> usize {
+        T::size_hint(self)
+    }
+
     async fn send(
         &self,
         envelope: InMemoryEnvelope,
@@ -107,6 +151,18 @@ impl<T: EndpointExt + Send + Sync> EndpointExt for Arc<T> {
         T::send(self, envelope, target, exclude).await
     }
 
+    async fn start_peering(&self, addr: &str) -> Result<u16> {
+        T::start_peering(self, addr).await
+    }
+
+    async fn stop_peering(&self, id: u16) -> Result<()> {
+        T::stop_peering(self, id).await
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        T::shutdown(self).await
+    }
+
     async fn next(&self) -> Result<(InMemoryEnvelope, Neighbour)> {
         T::next(self).await
     }
@@ -103,6 +103,16 @@ impl OriginDataV1 {
         }
     }
 
+    /// Create an OriginDataV1 for a specific point in time
+    pub fn from_timestamp(timestamp: DateTime<Utc>) -> Self {
+        Self { timestamp }
+    }
+
+    /// The time at which this origin data was created (and signed)
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        self.timestamp
+    }
+
     pub fn elapsed(&self) -> Duration {
         (Utc::now() - self.timestamp)
             .to_std()
@@ -139,6 +149,18 @@ pub struct RouteDataV1 {
     pub available_mtu: u32,
 }
 
+impl RouteDataV1 {
+    /// Account for the MTU of the link this announcement was received on
+    ///
+    /// An MTU of `0` means that no limit is known, either for the link or for
+    /// the path so far.
+    pub fn limit_mtu(&mut self, link_mtu: u32) {
+        if link_mtu != 0 && (self.available_mtu == 0 || link_mtu < self.available_mtu) {
+            self.available_mtu = link_mtu;
+        }
+    }
+}
+
 impl FrameParser for RouteDataV1 {
     type Output = Self;
 
@@ -177,3 +199,20 @@ fn generate_parse_announce() {
     assert_eq!(rem.len(), 0);
     assert_eq!(a, a_dec.unwrap());
 }
+
+#[test]
+fn limit_route_mtu() {
+    let mut route = RouteDataV1 { available_mtu: 0 };
+
+    // Unlimited links don't change anything
+    route.limit_mtu(0);
+    assert_eq!(route.available_mtu, 0);
+
+    route.limit_mtu(1452);
+    assert_eq!(route.available_mtu, 1452);
+
+    route.limit_mtu(246);
+    route.limit_mtu(1500);
+    route.limit_mtu(0);
+    assert_eq!(route.available_mtu, 246);
+}
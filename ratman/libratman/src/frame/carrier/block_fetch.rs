@@ -0,0 +1,102 @@
+use crate::{
+    frame::{micro::parse::vec_of, parse, FrameGenerator, FrameParser},
+    types::Ident32,
+    EncodingError, Result,
+};
+use nom::IResult;
+
+use super::MAX_RETRANSMIT_BLOCKS;
+
+/// The largest number of blocks that can be fetched with one frame
+pub const MAX_FETCH_BLOCKS: usize = MAX_RETRANSMIT_BLOCKS;
+
+/// Ask neighbouring routers for blocks by their reference
+///
+/// Unlike a retransmission request this frame isn't addressed to the sender
+/// of a stream.  It is sent to all direct neighbours, and any router that has
+/// one of the blocks in its journal answers with data frames for it.  Since
+/// blocks are content-addressed the requester doesn't need to trust whoever
+/// answers.
+#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum BlockFetchFrame {
+    V1(BlockFetchFrameV1),
+}
+
+impl BlockFetchFrame {
+    pub fn as_v1(&self) -> &BlockFetchFrameV1 {
+        match self {
+            Self::V1(frame_v1) => frame_v1,
+        }
+    }
+}
+
+impl FrameParser for BlockFetchFrame {
+    type Output = Result<Self>;
+
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, version) = parse::take_byte(input)?;
+        match version {
+            1 => {
+                let (input, inner) = BlockFetchFrameV1::parse(input)?;
+                Ok((input, Ok(BlockFetchFrame::V1(inner))))
+            }
+            unknown_version => Ok((
+                input,
+                Err(EncodingError::InvalidVersion(unknown_version).into()),
+            )),
+        }
+    }
+}
+
+impl FrameGenerator for BlockFetchFrame {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        match self {
+            Self::V1(v1) => {
+                buf.push(1);
+                v1.generate(buf)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct BlockFetchFrameV1 {
+    /// Block size of the requested blocks, encoded like in the manifest
+    pub block_size: u8,
+    /// References of the requested blocks
+    pub blocks: Vec<Ident32>,
+}
+
+impl FrameParser for BlockFetchFrameV1 {
+    type Output = Self;
+
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, block_size) = parse::take_byte(input)?;
+        let (input, blocks) = vec_of(parse::take_id, input)?;
+
+        Ok((input, Self { block_size, blocks }))
+    }
+}
+
+impl FrameGenerator for BlockFetchFrameV1 {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        buf.push(self.block_size);
+        self.blocks.generate(buf)?;
+        Ok(())
+    }
+}
+
+#[test]
+fn generate_parse_block_fetch() {
+    let request = BlockFetchFrame::V1(BlockFetchFrameV1 {
+        block_size: 32,
+        blocks: vec![Ident32::random()],
+    });
+
+    let mut buf = vec![];
+    request.clone().generate(&mut buf).unwrap();
+
+    let (rem, parsed) = BlockFetchFrame::parse(buf.as_slice()).unwrap();
+    assert_eq!(rem.len(), 0);
+    assert_eq!(parsed.unwrap(), request);
+}
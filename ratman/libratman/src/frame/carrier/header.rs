@@ -1,5 +1,9 @@
 use crate::{
-    frame::{carrier::modes, generate::pad_aux_data, parse, FrameGenerator, FrameParser},
+    frame::{
+        carrier::{modes, seal::schemes, ChunkSeal},
+        generate::pad_aux_data,
+        parse, FrameGenerator, FrameParser,
+    },
     types::{Address, Ident32, Recipient, SequenceIdV1},
     EncodingError, Result,
 };
@@ -83,7 +87,29 @@ impl CarrierFrameHeader {
         })
     }
 
-    /// Allocate a new header for an ERIS-block data frame
+    /// Allocate a new header for a sealed ERIS-block data frame
+    ///
+    /// The chunk seal is carried in the auxiliary data section so that
+    /// routers which don't understand it can still forward the frame.
+    pub fn new_sealed_blockdata_frame(
+        sender: Address,
+        recipient: Recipient,
+        seq_id: SequenceIdV1,
+        seal: ChunkSeal,
+        payload_length: u16,
+    ) -> Result<Self> {
+        Ok(Self::V1(CarrierFrameHeaderV1 {
+            modes: modes::DATA,
+            sender,
+            recipient: Some(recipient),
+            seq_id: Some(seq_id),
+            auxiliary_data: Some(seal.to_aux_data()?),
+            signature_data: None,
+            payload_length,
+        }))
+    }
+
+    /// Allocate a new header for an ERIS-block manifest frame
     pub fn new_blockmanifest_frame(
         sender: Address,
         recipient: Recipient,
@@ -101,7 +127,78 @@ impl CarrierFrameHeader {
         })
     }
 
+    /// Allocate a new header for a stream delivery receipt
+    ///
+    /// The sequence ID hash is the ID of the stream being acknowledged,
+    /// which allows the receipt to be tracked by the journal like any
+    /// other frame.
+    pub fn new_receipt_frame(
+        sender: Address,
+        recipient: Recipient,
+        stream_id: Ident32,
+        payload_length: u16,
+    ) -> Self {
+        Self::V1(CarrierFrameHeaderV1 {
+            modes: modes::RECEIPT,
+            sender,
+            recipient: Some(recipient),
+            seq_id: Some(SequenceIdV1 {
+                hash: stream_id,
+                num: 0,
+                max: 0,
+            }),
+            auxiliary_data: None,
+            signature_data: None,
+            payload_length,
+        })
+    }
+
+    /// Allocate a new header for a block fetch request
+    ///
+    /// Block fetch requests are only sent to direct neighbours, so they
+    /// don't have a recipient.  Answers are addressed to the `sender`.
+    pub fn new_block_fetch_frame(sender: Address, payload_length: u16) -> Self {
+        Self::V1(CarrierFrameHeaderV1 {
+            modes: modes::BLOCK_FETCH,
+            sender,
+            recipient: None,
+            seq_id: None,
+            auxiliary_data: None,
+            signature_data: None,
+            payload_length,
+        })
+    }
+
+    /// Allocate a new header for a missing block retransmission request
+    ///
+    /// `attempt` counts the requests sent for the same stream, and is used
+    /// as the sequence number.
+    pub fn new_retransmit_frame(
+        sender: Address,
+        recipient: Recipient,
+        stream_id: Ident32,
+        attempt: u8,
+        payload_length: u16,
+    ) -> Self {
+        Self::V1(CarrierFrameHeaderV1 {
+            modes: modes::RETRANSMIT,
+            sender,
+            recipient: Some(recipient),
+            seq_id: Some(SequenceIdV1 {
+                hash: stream_id,
+                num: attempt,
+                max: 0,
+            }),
+            auxiliary_data: None,
+            signature_data: None,
+            payload_length,
+        })
+    }
+
     /// Allocate a new header for an address announcement frame
+    ///
+    /// The auxiliary data section advertises the newest chunk sealing
+    /// scheme this router supports.
     pub fn new_announce_frame(sender: Address, payload_length: u16) -> Self {
         Self::V1(CarrierFrameHeaderV1 {
             modes: modes::ANNOUNCE,
@@ -112,7 +209,7 @@ impl CarrierFrameHeader {
                 num: 0,
                 max: 0,
             }),
-            auxiliary_data: None,
+            auxiliary_data: Some(pad_aux_data(vec![schemes::LATEST])),
             signature_data: None,
             payload_length,
         })
@@ -132,6 +229,25 @@ impl CarrierFrameHeader {
         .get_size()
     }
 
+    pub fn get_sealed_blockdata_size(sender: Address, recipient: Recipient) -> usize {
+        CarrierFrameHeader::new_sealed_blockdata_frame(
+            sender,
+            recipient,
+            SequenceIdV1 {
+                hash: Ident32::random(),
+                num: 0,
+                max: 0,
+            },
+            ChunkSeal::XChaCha20Poly1305 {
+                nonce: [0; 24],
+                tag: [0; 16],
+            },
+            0,
+        )
+        .expect("failed to encode chunk seal")
+        .get_size()
+    }
+
     /// Calculate the size of this metadata header
     pub fn get_size(&self) -> usize {
         match self {
@@ -154,7 +270,7 @@ impl CarrierFrameHeader {
                     Some(_) => 64,
                     None => 1,
                 };
-                let sign_data_size = match header.auxiliary_data {
+                let sign_data_size = match header.signature_data {
                     Some(_) => 64,
                     None => 1,
                 };
@@ -205,6 +321,31 @@ impl CarrierFrameHeader {
             Self::V1(inner) => inner.auxiliary_data,
         }
     }
+
+    /// Get the chunk seal of a sealed data frame, if it has one
+    pub fn get_chunk_seal(&self) -> Option<Result<ChunkSeal>> {
+        match self {
+            Self::V1(inner) if inner.modes == modes::DATA => inner
+                .auxiliary_data
+                .as_ref()
+                .map(|aux| ChunkSeal::from_aux_data(aux)),
+            _ => None,
+        }
+    }
+
+    /// Get the newest chunk sealing scheme advertised by an announcement
+    ///
+    /// Announcements from routers that predate chunk sealing don't carry
+    /// any auxiliary data, which means they only support unsealed data.
+    pub fn get_announced_seal_scheme(&self) -> u8 {
+        match self {
+            Self::V1(inner) if inner.modes == modes::ANNOUNCE => inner
+                .auxiliary_data
+                .map(|aux| aux[0])
+                .unwrap_or(schemes::NONE),
+            _ => schemes::NONE,
+        }
+    }
 }
 
 impl FrameParser for CarrierFrameHeader {
@@ -1,13 +1,21 @@
 //! Carrier frame format types
 
 mod announce;
+mod block_fetch;
 mod header;
 mod manifest;
+mod receipt;
+mod retransmit;
+mod seal;
 
 ////// Frame type exports
 pub use announce::*;
+pub use block_fetch::*;
 pub use header::*;
 pub use manifest::*;
+pub use receipt::*;
+pub use retransmit::*;
+pub use seal::*;
 
 ////// Expose the generator and parser APIs for other types
 pub use crate::frame::parse::{take_address, IResult as ParserResult};
@@ -28,6 +36,9 @@ pub mod modes {
             ANNOUNCE => "announce frame",
             DATA => "ERIS block data frame",
             MANIFEST => "ERIS root manifest frame",
+            RECEIPT => "stream delivery receipt",
+            RETRANSMIT => "missing block retransmission request",
+            BLOCK_FETCH => "neighbour block fetch request",
             ROUTER_PEERING => "Router-to-Router introduction",
             _ => "[UNKNOWN]",
         }
@@ -39,6 +50,9 @@ pub mod modes {
     // 8 - are main data payloads
     pub const DATA: u16 = 8;
     pub const MANIFEST: u16 = 9;
+    pub const RECEIPT: u16 = 10;
+    pub const RETRANSMIT: u16 = 11;
+    pub const BLOCK_FETCH: u16 = 12;
 
     // The set of router-router peering protocols are 64-127
     pub const ROUTER_PEERING: u16 = 64;
@@ -0,0 +1,129 @@
+use crate::{
+    frame::{parse, FrameGenerator, FrameParser},
+    types::{Address, Ident32},
+    EncodingError, Result,
+};
+use chrono::{DateTime, Utc};
+use nom::IResult;
+
+/// A signed acknowledgement that a message stream was delivered
+///
+/// Receipts are sent by the recipient router back to the sender of a
+/// stream after `reassemble_message_stream` has verified the manifest.
+/// They are only generated if the stream letterhead requested one.
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum ReceiptFrame {
+    V1(ReceiptFrameV1),
+}
+
+impl ReceiptFrame {
+    pub fn as_v1(&self) -> &ReceiptFrameV1 {
+        match self {
+            Self::V1(frame_v1) => frame_v1,
+        }
+    }
+}
+
+impl FrameParser for ReceiptFrame {
+    type Output = Result<Self>;
+
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, version) = parse::take_byte(input)?;
+        match version {
+            1 => {
+                let (input, inner) = ReceiptFrameV1::parse(input)?;
+                Ok((input, inner.map(ReceiptFrame::V1)))
+            }
+            unknown_version => Ok((
+                input,
+                Err(EncodingError::InvalidVersion(unknown_version).into()),
+            )),
+        }
+    }
+}
+
+impl FrameGenerator for ReceiptFrame {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        match self {
+            Self::V1(v1) => {
+                buf.push(1);
+                v1.generate(buf)
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct ReceiptFrameV1 {
+    /// The manifest root reference of the delivered stream
+    pub stream_id: Ident32,
+    /// The time at which the recipient router completed the stream
+    pub delivered_at: DateTime<Utc>,
+    /// Signature of the recipient address over the receipt data
+    ///
+    /// See [`ReceiptFrameV1::signed_data`] for what is covered.
+    pub signature: [u8; 64],
+}
+
+impl ReceiptFrameV1 {
+    /// The data covered by the receipt signature
+    ///
+    /// Besides the stream ID and delivery time this includes the
+    /// address of the original stream sender, so that a receipt can't
+    /// be replayed towards another address.
+    pub fn signed_data(
+        stream_id: Ident32,
+        stream_sender: Address,
+        delivered_at: DateTime<Utc>,
+    ) -> Result<Vec<u8>> {
+        let mut buf = vec![];
+        buf.extend_from_slice(stream_id.as_bytes());
+        buf.extend_from_slice(stream_sender.as_bytes());
+        delivered_at.generate(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+impl FrameParser for ReceiptFrameV1 {
+    type Output = Result<Self>;
+
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, stream_id) = parse::take_id(input)?;
+        let (input, delivered_at) = parse::take_datetime(input)?;
+        let (input, signature) = parse::take_signature(input)?;
+
+        Ok((
+            input,
+            delivered_at.map(|delivered_at| Self {
+                stream_id,
+                delivered_at,
+                signature,
+            }),
+        ))
+    }
+}
+
+impl FrameGenerator for ReceiptFrameV1 {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        buf.extend_from_slice(self.stream_id.as_bytes());
+        self.delivered_at.generate(buf)?;
+        self.signature.generate(buf)?;
+        Ok(())
+    }
+}
+
+#[test]
+fn generate_parse_receipt() {
+    let receipt = ReceiptFrame::V1(ReceiptFrameV1 {
+        stream_id: Ident32::random(),
+        delivered_at: Utc::now(),
+        signature: [7; 64],
+    });
+
+    let mut buf = vec![];
+    receipt.generate(&mut buf).unwrap();
+
+    let (rem, parsed) = ReceiptFrame::parse(buf.as_slice()).unwrap();
+    assert_eq!(rem.len(), 0);
+    assert_eq!(parsed.unwrap(), receipt);
+}
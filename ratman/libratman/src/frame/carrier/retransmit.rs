@@ -0,0 +1,113 @@
+use crate::{
+    frame::{micro::parse::vec_of, parse, FrameGenerator, FrameParser},
+    types::Ident32,
+    EncodingError, Result,
+};
+use nom::IResult;
+
+/// The largest number of blocks that can be requested in one frame
+///
+/// This keeps retransmission requests small enough to fit into a single
+/// carrier frame on most links.
+pub const MAX_RETRANSMIT_BLOCKS: usize = 32;
+
+/// Ask the sender of a stream to send specific blocks again
+///
+/// A receiving router sends this frame when it has been waiting for blocks of
+/// a stream for too long.  The blocks are identified by their ERIS block
+/// reference, which is also the sequence ID hash of their data frames.
+#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum RetransmitFrame {
+    V1(RetransmitFrameV1),
+}
+
+impl RetransmitFrame {
+    pub fn as_v1(&self) -> &RetransmitFrameV1 {
+        match self {
+            Self::V1(frame_v1) => frame_v1,
+        }
+    }
+}
+
+impl FrameParser for RetransmitFrame {
+    type Output = Result<Self>;
+
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, version) = parse::take_byte(input)?;
+        match version {
+            1 => {
+                let (input, inner) = RetransmitFrameV1::parse(input)?;
+                Ok((input, Ok(RetransmitFrame::V1(inner))))
+            }
+            unknown_version => Ok((
+                input,
+                Err(EncodingError::InvalidVersion(unknown_version).into()),
+            )),
+        }
+    }
+}
+
+impl FrameGenerator for RetransmitFrame {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        match self {
+            Self::V1(v1) => {
+                buf.push(1);
+                v1.generate(buf)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct RetransmitFrameV1 {
+    /// The manifest root reference of the incomplete stream
+    pub stream_id: Ident32,
+    /// Block size of the stream, encoded like in the manifest
+    pub block_size: u8,
+    /// References of the missing blocks
+    pub blocks: Vec<Ident32>,
+}
+
+impl FrameParser for RetransmitFrameV1 {
+    type Output = Self;
+
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, stream_id) = parse::take_id(input)?;
+        let (input, block_size) = parse::take_byte(input)?;
+        let (input, blocks) = vec_of(parse::take_id, input)?;
+
+        Ok((
+            input,
+            Self {
+                stream_id,
+                block_size,
+                blocks,
+            },
+        ))
+    }
+}
+
+impl FrameGenerator for RetransmitFrameV1 {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        self.stream_id.generate(buf)?;
+        buf.push(self.block_size);
+        self.blocks.generate(buf)?;
+        Ok(())
+    }
+}
+
+#[test]
+fn generate_parse_retransmit() {
+    let request = RetransmitFrame::V1(RetransmitFrameV1 {
+        stream_id: Ident32::random(),
+        block_size: 1,
+        blocks: vec![Ident32::random(), Ident32::random()],
+    });
+
+    let mut buf = vec![];
+    request.clone().generate(&mut buf).unwrap();
+
+    let (rem, parsed) = RetransmitFrame::parse(buf.as_slice()).unwrap();
+    assert_eq!(rem.len(), 0);
+    assert_eq!(parsed.unwrap(), request);
+}
@@ -0,0 +1,126 @@
+// SPDX-FileCopyrightText: 2024 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+use crate::{
+    frame::{generate::pad_aux_data, parse, FrameGenerator, FrameParser},
+    EncodingError, Result,
+};
+use nom::IResult;
+
+/// Chunk sealing schemes
+///
+/// The scheme byte is the first byte of a sealed data frame's
+/// auxiliary data section.  Routers advertise the newest scheme they
+/// understand in the auxiliary data section of their address
+/// announcements, which lets senders fall back to unsealed chunks for
+/// peers that haven't been upgraded yet.
+pub mod schemes {
+    /// Chunks are sent without any authentication
+    ///
+    /// This can never appear in a sealed frame, since an auxiliary
+    /// data section starting with a zero byte is considered empty.
+    pub const NONE: u8 = 0;
+    /// Chunks are sealed with XChaCha20-Poly1305
+    pub const XCHACHA20_POLY1305: u8 = 1;
+
+    /// The newest sealing scheme supported by this version
+    pub const LATEST: u8 = XCHACHA20_POLY1305;
+}
+
+/// Authentication metadata for a single sealed data chunk
+///
+/// The encrypted chunk itself is carried as the frame payload, while
+/// the nonce and authentication tag are carried in the header's
+/// auxiliary data section.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ChunkSeal {
+    XChaCha20Poly1305 { nonce: [u8; 24], tag: [u8; 16] },
+}
+
+impl ChunkSeal {
+    pub fn scheme(&self) -> u8 {
+        match self {
+            Self::XChaCha20Poly1305 { .. } => schemes::XCHACHA20_POLY1305,
+        }
+    }
+
+    /// Encode this seal into a header auxiliary data section
+    pub fn to_aux_data(self) -> Result<[u8; 64]> {
+        let mut buf = vec![];
+        self.generate(&mut buf)?;
+        Ok(pad_aux_data(buf))
+    }
+
+    /// Decode a seal from a header auxiliary data section
+    pub fn from_aux_data(aux: &[u8; 64]) -> Result<Self> {
+        match Self::parse(aux) {
+            Ok((_, seal)) => seal,
+            Err(e) => Err(EncodingError::from(e).into()),
+        }
+    }
+}
+
+impl FrameGenerator for ChunkSeal {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        buf.push(self.scheme());
+        match self {
+            Self::XChaCha20Poly1305 { nonce, tag } => {
+                buf.extend_from_slice(&nonce);
+                buf.extend_from_slice(&tag);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl FrameParser for ChunkSeal {
+    type Output = Result<Self>;
+
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, scheme) = parse::take_byte(input)?;
+
+        match scheme {
+            schemes::XCHACHA20_POLY1305 => {
+                let (input, nonce) = parse::take(24usize)(input)?;
+                let (input, tag) = parse::take(16usize)(input)?;
+
+                let mut seal_nonce = [0; 24];
+                seal_nonce.copy_from_slice(nonce);
+                let mut seal_tag = [0; 16];
+                seal_tag.copy_from_slice(tag);
+
+                Ok((
+                    input,
+                    Ok(Self::XChaCha20Poly1305 {
+                        nonce: seal_nonce,
+                        tag: seal_tag,
+                    }),
+                ))
+            }
+            unknown_scheme => Ok((
+                input,
+                Err(EncodingError::InvalidVersion(unknown_scheme).into()),
+            )),
+        }
+    }
+}
+
+#[test]
+fn seal_aux_data_roundtrip() {
+    let seal = ChunkSeal::XChaCha20Poly1305 {
+        nonce: [7; 24],
+        tag: [13; 16],
+    };
+
+    let aux = seal.to_aux_data().unwrap();
+    assert_eq!(aux[0], schemes::XCHACHA20_POLY1305);
+    assert_eq!(ChunkSeal::from_aux_data(&aux).unwrap(), seal);
+}
+
+#[test]
+fn seal_unknown_scheme() {
+    let aux = pad_aux_data(vec![0xFF]);
+    assert!(ChunkSeal::from_aux_data(&aux).is_err());
+}
@@ -41,6 +41,8 @@ pub mod client_modes {
     pub const STREAM: u8    = 0x7;
     /// Namespace addresses
     pub const SPACE: u8     = 0x8;
+    /// Router configuration
+    pub const CONFIG: u8    = 0x9;
     
 
     //// Creating new data or destroying it permanently
@@ -70,6 +72,10 @@ pub mod client_modes {
 
     /// TODO: replace this with a more generic protocol extension.  This sucks
     pub const ANYCAST: u8   = 0x35;
+    /// Delivery receipts for sent message streams
+    pub const RECEIPT: u8   = 0x36;
+    /// Re-read a resource from its source
+    pub const RELOAD: u8    = 0x37;
     
 
     /// Assemble a full mode byte from a command namespace and a
@@ -21,7 +21,7 @@ pub fn maybe<const LEN: usize>(input: &[u8]) -> IResult<&[u8], Option<[u8; LEN]>
     let (input, first) = peek(take(1 as usize))(input)?;
     if first == &[0] {
         // Take the byte we just peeked into to burn it
-        let (input, _) = take(1 as usize)(input)?;
+        let (input, _) = take(1usize)(input)?;
         Ok((input, None))
     } else {
         let (input, maybe_slice) = take(LEN)(input).map(|(i, s)| {
@@ -56,6 +56,8 @@ pub fn take_address(input: &[u8]) -> IResult<&[u8], Address> {
 
 pub fn take_cstring(input: &[u8]) -> IResult<&[u8], Result<CString>> {
     let (input, bytes) = take_while1(|c| c as char != '\0')(input)?;
+    // Burn the null terminator so that the next field can be read
+    let (input, _) = take(1usize)(input)?;
     Ok((
         input,
         CString::new(bytes).map_err(|c| EncodingError::Parsing(format!("{:?}", c)).into()),
@@ -68,7 +70,7 @@ pub fn maybe_cstring(input: &[u8]) -> IResult<&[u8], Result<Option<CString>>> {
         let (input, cstr) = take_cstring(input)?;
         Ok((input, cstr.map(|c| Some(c))))
     } else {
-        let (input, _) = take(1 as usize)(input)?;
+        let (input, _) = take(1usize)(input)?;
         Ok((input, Ok(None)))
     }
 }
@@ -133,7 +135,7 @@ pub fn maybe_address(input: &[u8]) -> IResult<&[u8], Option<Address>> {
     let (input, first) = peek(take(1 as usize))(input)?;
     if first == &[0] {
         // Take the byte we just peeked into to burn it
-        let (input, _) = take(1 as usize)(input)?;
+        let (input, _) = take(1usize)(input)?;
         Ok((input, None))
     } else {
         let (input, addr) = take_address(input)?;
@@ -201,7 +203,7 @@ pub fn maybe_signature(input: &[u8]) -> IResult<&[u8], Option<[u8; 64]>> {
         let (input, sig) = take_signature(input)?;
         Ok((input, Some(sig)))
     } else {
-        let (input, _) = take(1 as usize)(input)?;
+        let (input, _) = take(1usize)(input)?;
         Ok((input, None))
     }
 }
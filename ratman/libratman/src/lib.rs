@@ -44,6 +44,7 @@ pub use tokio_stream;
 pub use tokio_util;
 
 // Re-export some other utilities too
+pub use async_eris::ReadCapability;
 pub use hex;
 use types::{Address, Ident32};
 
@@ -100,6 +100,8 @@ pub enum NonfatalError {
     NoMetrics,
     #[error("requested address could not be routed to at this moment")]
     NoAvailableRoute,
+    #[error("no delivery receipt for stream {0} arrived in time")]
+    NoReceipt(Ident32),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -159,6 +161,12 @@ pub enum BlockError {
     },
     #[error("ERIS block decoding failed because {0}")]
     Eris(#[from] async_eris::Error),
+    #[error("data chunk {num} of block {hash} failed authentication")]
+    Unauthenticated { hash: Ident32, num: u8 },
+    #[error("data chunk {num} of block {hash} is not sealed, but its sender seals chunks")]
+    Unsealed { hash: Ident32, num: u8 },
+    #[error("no unlocked key for address {0} is available to open sealed chunks")]
+    NoChunkKey(Address),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -177,7 +185,7 @@ pub enum ScheduleError {
 ///
 /// Importantly, more base-type errors (such as I/O and encoding) are
 /// handled by [RatmanError](crate::RatmanError) instead!
-#[derive(Debug, thiserror::Error, Serialize, Deserialize)]
+#[derive(Clone, Debug, thiserror::Error, Serialize, Deserialize)]
 pub enum ClientError {
     #[error("ratman-client ({0}) and router ({1}) have incompatible versions")]
     IncompatibleVersion(String, String),
@@ -195,8 +203,20 @@ pub enum ClientError {
     Internal(String),
     #[error("requested subscrition ({0}) does not exist")]
     NoSuchSubscription(Ident32),
+    #[error("incoming stream {0} timed out while waiting for missing blocks")]
+    StreamTimeout(Ident32),
     #[error("bad user input data: {0}")]
     User(#[from] UserError),
+    #[error("requested link ({0}) does not exist")]
+    NoSuchLink(u32),
+    #[error("data chunk {num} of incoming block {hash} failed authentication")]
+    Unauthenticated { hash: Ident32, num: u8 },
+    #[error(
+        "data chunk {num} of incoming block {hash} is not sealed, but its sender seals chunks"
+    )]
+    Unsealed { hash: Ident32, num: u8 },
+    #[error("no unlocked key for address {0} is available to open incoming blocks")]
+    NoChunkKey(Address),
 }
 
 /// Any error that can occur when interacting with a netmod driver
@@ -239,7 +259,7 @@ pub enum MicroframeError {
     MissingFields(&'static [&'static str]),
 }
 
-#[derive(Debug, thiserror::Error, Serialize, Deserialize)]
+#[derive(Clone, Debug, thiserror::Error, Serialize, Deserialize)]
 pub enum UserError {
     InvalidInput(String, Option<String>),
     MissingInput(String),
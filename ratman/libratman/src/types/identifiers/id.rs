@@ -230,6 +230,13 @@ impl Ident32 {
     }
 }
 
+/// The default identifier is un-initialised (see [`uninit`](Ident32::uninit))
+impl Default for Ident32 {
+    fn default() -> Self {
+        Self::uninit()
+    }
+}
+
 /// Implement RAW `From` binary array
 impl From<[u8; ID_LEN]> for Ident32 {
     fn from(i: [u8; ID_LEN]) -> Self {
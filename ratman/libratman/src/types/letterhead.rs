@@ -14,6 +14,9 @@ use std::ffi::CString;
 
 use super::Ident32;
 
+/// Auxiliary data key used to request a delivery receipt
+const RECEIPT_KEY: &str = "delivery-receipt";
+
 /// Message stream letterhead
 ///
 /// This type is used by the sending and receiving routers to negotiate sending/
@@ -90,6 +93,23 @@ impl LetterheadV1 {
         self
     }
 
+    /// Ask the recipient router to confirm delivery of this stream
+    ///
+    /// Once the stream was reassembled, the recipient router sends a signed
+    /// receipt back to the sender.  Receipts are only sent for streams
+    /// addressed to a single address, not to namespaces.
+    pub fn request_receipt(self) -> Self {
+        self.add_aux_data(RECEIPT_KEY, "requested")
+    }
+
+    /// Check whether this stream asked for a delivery receipt
+    pub fn wants_receipt(&self) -> bool {
+        self.auxiliary_data
+            .iter()
+            .any(|(key, _)| key.as_bytes() == RECEIPT_KEY.as_bytes())
+            && matches!(self.to, Recipient::Address(_))
+    }
+
     /// Add your own metadata to the stream
     ///
     /// This data is only attached to the stream *Manifest* message and will be
@@ -30,7 +30,7 @@ pub use platform::{Os, StateDirectoryLock};
 pub use recipient::Recipient;
 pub use router::RouterMeta;
 pub use sequence_id::SequenceIdV1;
-pub use status::CurrentStatus;
+pub use status::{CurrentStatus, LinkState};
 
 use std::ffi::CString;
 
@@ -1,3 +1,137 @@
-pub enum CurrentStatus {
+use crate::{
+    frame::{
+        generate::generate_option_cstring,
+        parse::{maybe_cstring, take_byte, take_u32, take_u64},
+        FrameGenerator, FrameParser,
+    },
+    types::to_cstring,
+    EncodingError, Result,
+};
+use nom::IResult;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+
+/// The overall state of a netmod instance
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LinkState {
+    /// The netmod doesn't report its state
+    #[default]
     Unknown,
+    /// Working as expected
+    Up,
+    /// Working, but some peers can't be reached or errors occured recently
+    Degraded,
+    /// Not able to send or receive anything
+    Down,
+}
+
+impl Display for LinkState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unknown => write!(f, "unknown"),
+            Self::Up => write!(f, "up"),
+            Self::Degraded => write!(f, "degraded"),
+            Self::Down => write!(f, "down"),
+        }
+    }
+}
+
+/// A status digest for a single netmod instance
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CurrentStatus {
+    pub state: LinkState,
+    /// Number of currently connected peers
+    ///
+    /// Netmods which can't tell who is listening (for example
+    /// broadcast radios) report `0`.
+    pub peers: u32,
+    /// Bytes sent since the netmod was started
+    pub tx_bytes: u64,
+    /// Bytes received since the netmod was started
+    pub rx_bytes: u64,
+    /// Incoming peers that were rejected since the netmod was started
+    pub rejected: u64,
+    /// The most recent error this netmod encountered
+    pub last_error: Option<String>,
+}
+
+impl Display for CurrentStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\tpeers={}\ttx={}\trx={}\trejected={}",
+            self.state, self.peers, self.tx_bytes, self.rx_bytes, self.rejected
+        )?;
+
+        match self.last_error {
+            Some(ref e) => write!(f, "\terror={e}"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl FrameGenerator for CurrentStatus {
+    fn generate(self, buf: &mut Vec<u8>) -> Result<()> {
+        (self.state as u8).generate(buf)?;
+        self.peers.generate(buf)?;
+        self.tx_bytes.generate(buf)?;
+        self.rx_bytes.generate(buf)?;
+        self.rejected.generate(buf)?;
+        generate_option_cstring(self.last_error.map(|e| to_cstring(&e)), buf)?;
+        Ok(())
+    }
+}
+
+impl FrameParser for CurrentStatus {
+    type Output = Result<Self>;
+    fn parse(input: &[u8]) -> IResult<&[u8], Self::Output> {
+        let (input, state) = take_byte(input)?;
+        let (input, peers) = take_u32(input)?;
+        let (input, tx_bytes) = take_u64(input)?;
+        let (input, rx_bytes) = take_u64(input)?;
+        let (input, rejected) = take_u64(input)?;
+        let (input, last_error) = maybe_cstring(input)?;
+
+        let state = match state {
+            1 => LinkState::Up,
+            2 => LinkState::Degraded,
+            3 => LinkState::Down,
+            _ => LinkState::Unknown,
+        };
+
+        Ok((
+            input,
+            last_error.and_then(|e| {
+                Ok(Self {
+                    state,
+                    peers,
+                    tx_bytes,
+                    rx_bytes,
+                    rejected,
+                    last_error: e
+                        .map(|e| e.into_string())
+                        .transpose()
+                        .map_err(|e| EncodingError::Parsing(e.to_string()))?,
+                })
+            }),
+        ))
+    }
+}
+
+#[test]
+fn current_status_roundtrip() {
+    let status = CurrentStatus {
+        state: LinkState::Degraded,
+        peers: 2,
+        tx_bytes: 1312,
+        rx_bytes: 4096,
+        rejected: 3,
+        last_error: Some("connection refused".into()),
+    };
+
+    let mut buf = vec![];
+    status.clone().generate(&mut buf).unwrap();
+    let (rest, parsed) = CurrentStatus::parse(&buf).unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(parsed.unwrap(), status);
 }
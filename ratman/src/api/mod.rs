@@ -28,15 +28,23 @@ use libratman::{
 };
 use std::{future::IntoFuture, net::SocketAddr, sync::Arc};
 
-/// Start a new thread to run the client API socket
+/// Bind the client API socket and start a new thread to run it
+///
+/// Returns the address that the socket was bound to.
 pub async fn start_api_thread(
     context: Arc<RatmanContext>,
     addr: SocketAddr,
     senders: Arc<SenderSystem>,
-) -> Result<()> {
-    new_async_thread("ratmand-api-acceptor", 1024, async move {
+) -> Result<SocketAddr> {
+    // Bind the socket here so that errors reach the caller, and only
+    // hand it to the acceptor runtime once that is running
+    let listener = std::net::TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+
+    new_async_thread("ratmand-api-acceptor", 8, async move {
         info!("Listening to API socket on {addr}");
-        let l = TcpListener::bind(addr).await?;
+        let l = TcpListener::from_std(listener)?;
 
         while let Ok((stream, client_addr)) = l.accept().await {
             let client_id = Ident32::random();
@@ -53,7 +61,7 @@ pub async fn start_api_thread(
             let ctx = Arc::clone(&context);
             new_async_thread(
                 format!("ratmand-api-{}", client_id.to_string().to_ascii_lowercase()),
-                1024 * 16,
+                16,
                 async move {
                     debug!("Oiiii");
                     let res = jh
@@ -81,7 +89,7 @@ pub async fn start_api_thread(
 
         Ok(())
     });
-    Ok(())
+    Ok(addr)
 }
 
 pub async fn run_client_handler(
@@ -2,7 +2,11 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
 
-use crate::{context::RatmanContext, crypto, procedures::SenderSystem};
+use crate::{
+    context::RatmanContext,
+    crypto,
+    procedures::{self, SenderSystem},
+};
 use async_eris::BlockSize;
 use chrono::Utc;
 use libratman::{
@@ -26,7 +30,7 @@ pub async fn exec_send_many_socket(
     auth: AddrAuth,
     letterheads: Vec<LetterheadV1>,
     senders: &Arc<SenderSystem>,
-) -> Result<()> {
+) -> Result<Vec<Ident32>> {
     let this_key = crypto::get_addr_key(&ctx.meta_db, this_addr, auth)
         .await
         // we can unwrap here because the session gets checked before
@@ -67,6 +71,7 @@ pub async fn exec_send_many_socket(
 
     let buf_r = BufReader::new(buf_f);
     let mut stream = buf_r.compat();
+    let mut stream_ids = Vec::with_capacity(letterheads.len());
 
     for mut lh in letterheads {
         lh.stream_size = stream_size;
@@ -83,10 +88,8 @@ pub async fn exec_send_many_socket(
             lh.from.pretty_string()
         );
 
-        let chosen_block_size = match lh.stream_size {
-            m if m < (8 * 1024) => async_eris::BlockSize::_1K,
-            _ => async_eris::BlockSize::_32K,
-        };
+        let path_mtu = ctx.routes.path_mtu(lh.to.inner_address()).await;
+        let chosen_block_size = procedures::select_block_size(lh.stream_size, path_mtu);
         trace!("{client_id} Start encoding for block size {chosen_block_size}");
 
         let read_cap = async_eris::encode(
@@ -99,12 +102,13 @@ pub async fn exec_send_many_socket(
         .unwrap();
 
         trace!("Block encoding complete");
+        stream_ids.push(Ident32::from_bytes(read_cap.root_reference.as_slice()));
         trace!("Dispatch block on {chosen_block_size} queue");
         match chosen_block_size {
             BlockSize::_1K => {
                 senders
                     .tx_1k
-                    .send((read_cap, lh))
+                    .send((read_cap, lh, shared_key))
                     .await
                     .map_err(|e| {
                         RatmanError::Schedule(libratman::ScheduleError::Contention(e.to_string()))
@@ -114,7 +118,7 @@ pub async fn exec_send_many_socket(
             BlockSize::_32K => {
                 senders
                     .tx_32k
-                    .send((read_cap, lh))
+                    .send((read_cap, lh, shared_key))
                     .await
                     .map_err(|e| {
                         RatmanError::Schedule(libratman::ScheduleError::Contention(e.to_string()))
@@ -128,5 +132,5 @@ pub async fn exec_send_many_socket(
 
     drop(stream);
     info!("Sender stream {} has completed", this_addr.pretty_string());
-    Ok(())
+    Ok(stream_ids)
 }
@@ -6,23 +6,31 @@ use crate::{
     api::send_util::exec_send_many_socket,
     context::RatmanContext,
     crypto,
-    procedures::{handle_subscription_socket, SenderSystem},
+    procedures::{self, handle_subscription_socket, SenderSystem},
+    storage::{
+        contact::{self, ContactData},
+        link::LinkData,
+    },
 };
 use libratman::{
     api::{
         socket_v2::RawSocketHandle,
         types::{
-            AddrCreate, AddrDestroy, AddrDown, AddrList, AddrUp, AnycastProbe, Handshake,
-            NamespaceDown, NamespaceRegister, NamespaceUp, PeerList, RecvMany, RecvOne, SendMany,
-            SendOne, ServerPing, SubsCreate, SubsDelete, SubsRestore,
+            AddrCreate, AddrDestroy, AddrDown, AddrList, AddrUp, AnycastProbe, AwaitReceipt,
+            ContactAdd, ContactDelete, ContactFilter, ContactModify, FetchStream, Handshake,
+            LinkDown, LinkUp, NamespaceCreate, NamespaceDestroy, NamespaceDown, NamespaceExport,
+            NamespaceRegister, NamespaceRotate, NamespaceUp, PeerAdd, PeerDelete, PeerList,
+            PeerPrune, RecvMany, RecvOne, SendMany, SendOne, ServerPing, StreamLink, StreamUnlink,
+            SubsCreate, SubsDelete, SubsRestore,
         },
         version_str, versions_compatible,
     },
     frame::micro::{client_modes as cm, MicroframeHeader},
     tokio::{
-        io::ErrorKind,
+        io::{AsyncWriteExt, ErrorKind},
         net::{TcpListener, TcpStream},
-        sync::broadcast::channel as bcast_channel,
+        select,
+        sync::broadcast::{channel as bcast_channel, error::RecvError},
         task::spawn,
         time::timeout,
     },
@@ -92,6 +100,37 @@ async fn check_auth<'a>(
         })
 }
 
+/// Check that the given auth belongs to any address that is currently up
+async fn check_any_auth(header: &MicroframeHeader, expected_auth: &AuthGuard) -> Result<AddrAuth> {
+    check_auth_owner(header, expected_auth)
+        .await
+        .map(|(auth, _)| auth)
+}
+
+/// Check that the given auth belongs to the address that owns a namespace
+async fn check_space_owner(
+    ctx: &Arc<RatmanContext>,
+    header: &MicroframeHeader,
+    namespace: Address,
+    expected_auth: &AuthGuard,
+) -> Result<(AddrAuth, Address)> {
+    let (auth, owner) = check_auth_owner(header, expected_auth).await?;
+    crypto::check_namespace_owner(&ctx.meta_db, namespace, owner).await?;
+    Ok((auth, owner))
+}
+
+/// Check that the given auth is active and return the address it belongs to
+async fn check_auth_owner(
+    header: &MicroframeHeader,
+    expected_auth: &AuthGuard,
+) -> Result<(AddrAuth, Address)> {
+    let auth = expected_auth.lock().await;
+    header
+        .auth
+        .and_then(|given_auth| auth.get(&given_auth).map(|addr| (given_auth, *addr)))
+        .ok_or_else(|| RatmanError::ClientApi(ClientError::InvalidAuth))
+}
+
 async fn reply_ok(raw_socket: &mut RawSocketHandle, auth: AddrAuth) -> Result<()> {
     raw_socket
         .write_microframe(MicroframeHeader::intrinsic_auth(auth), ServerPing::Ok)
@@ -298,6 +337,123 @@ pub(super) async fn single_session_exchange<'a>(
                 .await?;
         }
 
+        //
+        //
+        // CONTACT COMMANDS
+        //
+        //
+
+        //
+        //
+        // ^-^ Add a new entry to the contact book of an address
+        m if m == cm::make(cm::CONTACT, cm::ADD) => {
+            let ContactAdd {
+                addr,
+                note,
+                tags,
+                trust,
+            } = raw_socket
+                .read_payload::<ContactAdd>(header.payload_size)
+                .await??;
+
+            let (auth, owner) = check_auth_owner(&header, auth_guard).await?;
+
+            let entry = contact::add_contact(
+                &ctx.meta_db,
+                owner,
+                ContactData {
+                    addr,
+                    note: note.map(|n| n.to_string_lossy().into_owned()),
+                    tags: tags
+                        .into_iter()
+                        .map(|(k, v)| {
+                            (
+                                k.to_string_lossy().into_owned(),
+                                v.to_string_lossy().into_owned(),
+                            )
+                        })
+                        .collect(),
+                    trust,
+                },
+            )
+            .await?;
+
+            raw_socket
+                .write_microframe(
+                    MicroframeHeader::intrinsic_auth(auth),
+                    ServerPing::ContactList(vec![entry]),
+                )
+                .await?;
+        }
+        //
+        //
+        // ^-^ List contact book entries, optionally filtered
+        m if m == cm::make(cm::CONTACT, cm::LIST) => {
+            let filter = raw_socket
+                .read_payload::<ContactFilter>(header.payload_size)
+                .await??;
+
+            let (auth, owner) = check_auth_owner(&header, auth_guard).await?;
+            let entries = contact::list_contacts(&ctx.meta_db, owner, &filter);
+
+            raw_socket
+                .write_microframe(
+                    MicroframeHeader::intrinsic_auth(auth),
+                    ServerPing::ContactList(entries),
+                )
+                .await?;
+        }
+        //
+        //
+        // ^-^ Change all contact book entries selected by a filter
+        m if m == cm::make(cm::CONTACT, cm::MODIFY) => {
+            let ContactModify {
+                filter,
+                note_modify,
+                tags_modify,
+                new_trust,
+            } = raw_socket
+                .read_payload::<ContactModify>(header.payload_size)
+                .await??;
+
+            let (auth, owner) = check_auth_owner(&header, auth_guard).await?;
+
+            let entries = contact::modify_contacts(
+                &ctx.meta_db,
+                owner,
+                &filter,
+                note_modify.map(|n| n.to_string_lossy().into_owned()),
+                tags_modify.map(|(k, v)| {
+                    (
+                        k.to_string_lossy().into_owned(),
+                        v.to_string_lossy().into_owned(),
+                    )
+                }),
+                new_trust,
+            )
+            .await?;
+
+            raw_socket
+                .write_microframe(
+                    MicroframeHeader::intrinsic_auth(auth),
+                    ServerPing::ContactList(entries),
+                )
+                .await?;
+        }
+        //
+        //
+        // ^-^ Delete all contact book entries for an address
+        m if m == cm::make(cm::CONTACT, cm::DELETE) => {
+            let ContactDelete { addr } = raw_socket
+                .read_payload::<ContactDelete>(header.payload_size)
+                .await?;
+
+            let (auth, owner) = check_auth_owner(&header, auth_guard).await?;
+            contact::delete_contacts(&ctx.meta_db, owner, addr).await?;
+
+            reply_ok(raw_socket, auth).await?;
+        }
+
         //
         //
         // NAMESPACE COMMANDS
@@ -308,13 +464,105 @@ pub(super) async fn single_session_exchange<'a>(
                 .read_payload::<NamespaceRegister>(header.payload_size)
                 .await?;
 
-            crypto::create_namespace(&ctx.meta_db, None, pubkey, privkey).await?;
+            let (auth, owner) = check_auth_owner(&header, auth_guard).await?;
+
+            crypto::create_namespace(&ctx.meta_db, owner, None, pubkey, privkey).await?;
             ctx.routes.register_local_route(pubkey).await?;
 
+            reply_ok(raw_socket, auth).await?;
+        }
+        //
+        //
+        // ^-^ Create a new namespace with a router-generated key
+        m if m == cm::make(cm::SPACE, cm::ADD) => {
+            let NamespaceCreate { name } = raw_socket
+                .read_payload::<NamespaceCreate>(header.payload_size)
+                .await??;
+
+            let (auth, owner) = check_auth_owner(&header, auth_guard).await?;
+
+            let namespace = crypto::generate_namespace(&ctx.meta_db, owner, name).await?;
+            ctx.routes.register_local_route(namespace).await?;
+
             raw_socket
-                .write_microframe(MicroframeHeader::intrinsic_noauth(), ServerPing::Ok)
+                .write_microframe(
+                    MicroframeHeader::intrinsic_auth(auth),
+                    ServerPing::AddrList(vec![namespace]),
+                )
+                .await?;
+        }
+        //
+        //
+        // ^-^ List all namespaces this router holds keys for
+        m if m == cm::make(cm::SPACE, cm::LIST) => {
+            let namespaces = crypto::list_namespace_keys(&ctx.meta_db);
+            raw_socket
+                .write_microframe(
+                    MicroframeHeader::intrinsic_noauth(),
+                    ServerPing::AddrList(namespaces),
+                )
                 .await?;
         }
+        //
+        //
+        // ^-^ Export a namespace key so it can be imported on another router
+        m if m == cm::make(cm::SPACE, cm::QUERY) => {
+            let NamespaceExport { namespace_addr } = raw_socket
+                .read_payload::<NamespaceExport>(header.payload_size)
+                .await?;
+
+            let (auth, _) = check_space_owner(ctx, &header, namespace_addr, auth_guard).await?;
+
+            let (pubkey, privkey) = crypto::export_namespace(&ctx.meta_db, namespace_addr).await?;
+
+            raw_socket
+                .write_microframe(
+                    MicroframeHeader::intrinsic_auth(auth),
+                    ServerPing::NamespaceKey { pubkey, privkey },
+                )
+                .await?;
+        }
+        //
+        //
+        // ^-^ Replace a namespace key, moving all listeners to the new one
+        m if m == cm::make(cm::SPACE, cm::MODIFY) => {
+            let NamespaceRotate { namespace_addr } = raw_socket
+                .read_payload::<NamespaceRotate>(header.payload_size)
+                .await?;
+
+            let (auth, owner) = check_space_owner(ctx, &header, namespace_addr, auth_guard).await?;
+
+            let new_namespace =
+                crypto::rotate_namespace(&ctx.meta_db, owner, namespace_addr).await?;
+            ctx.routes.scrub_local(namespace_addr).await?;
+            ctx.routes.register_local_route(new_namespace).await?;
+            ctx.protocol
+                .move_namespace(namespace_addr, new_namespace)
+                .await;
+
+            raw_socket
+                .write_microframe(
+                    MicroframeHeader::intrinsic_auth(auth),
+                    ServerPing::AddrList(vec![new_namespace]),
+                )
+                .await?;
+        }
+        //
+        //
+        // ^-^ Delete a namespace key and drop all of its listeners
+        m if m == cm::make(cm::SPACE, cm::DESTROY) => {
+            let NamespaceDestroy { namespace_addr } = raw_socket
+                .read_payload::<NamespaceDestroy>(header.payload_size)
+                .await?;
+
+            let (auth, _) = check_space_owner(ctx, &header, namespace_addr, auth_guard).await?;
+
+            crypto::destroy_namespace(&ctx.meta_db, namespace_addr).await?;
+            ctx.routes.scrub_local(namespace_addr).await?;
+            ctx.protocol.remove_namespace(namespace_addr).await;
+
+            reply_ok(raw_socket, auth).await?;
+        }
         m if m == cm::make(cm::SPACE, cm::UP) => {
             let NamespaceUp {
                 client_addr,
@@ -414,7 +662,15 @@ pub(super) async fn single_session_exchange<'a>(
                 debug!("Starting subscription one-shot socket");
                 if let Ok((stream, _)) = sub_listen.accept().await {
                     let raw_socket = RawSocketHandle::new(stream);
-                    handle_subscription_socket(stream_ctx, rx, raw_socket, auth, sub_id).await;
+                    handle_subscription_socket(
+                        stream_ctx,
+                        rx,
+                        raw_socket,
+                        subs_create.addr,
+                        auth,
+                        sub_id,
+                    )
+                    .await;
                 }
                 debug!("Subscription one-shot has completed");
             });
@@ -447,6 +703,50 @@ pub(super) async fn single_session_exchange<'a>(
         }
         //
         //
+        // ^-^ Attach link data to a stream, which protects it from eviction
+        m if m == cm::make(cm::STREAM, cm::MODIFY) => {
+            let StreamLink {
+                addr,
+                stream_id,
+                streams,
+                tags,
+            } = raw_socket
+                .read_payload::<StreamLink>(header.payload_size)
+                .await??;
+
+            let auth = check_auth(&header, addr, auth_guard).await?;
+
+            ctx.meta_db
+                .links
+                .insert(
+                    stream_id.to_string(),
+                    &LinkData {
+                        streams,
+                        tags: tags
+                            .into_iter()
+                            .map(|tag| tag.to_string_lossy().into_owned())
+                            .collect(),
+                    },
+                )
+                .await?;
+
+            reply_ok(raw_socket, auth).await?;
+        }
+        //
+        //
+        // ^-^ Remove the link data of a stream
+        m if m == cm::make(cm::STREAM, cm::DELETE) => {
+            let StreamUnlink { addr, stream_id } = raw_socket
+                .read_payload::<StreamUnlink>(header.payload_size)
+                .await?;
+
+            let auth = check_auth(&header, addr, auth_guard).await?;
+            ctx.meta_db.links.remove(stream_id.to_string()).await?;
+
+            reply_ok(raw_socket, auth).await?;
+        }
+        //
+        //
         // ^-^ List all available subscriptions
         m if m == cm::make(cm::STREAM, cm::LIST) => {
             let addr = raw_socket
@@ -500,22 +800,186 @@ pub(super) async fn single_session_exchange<'a>(
         }
         //
         //
+        // ^-^ Connect to a new peer via one of the attached links
+        m if m == cm::make(cm::PEER, cm::ADD) => {
+            let PeerAdd { peer } = raw_socket
+                .read_payload::<PeerAdd>(header.payload_size)
+                .await??;
+            let auth = check_any_auth(&header, auth_guard).await?;
+
+            ctx.peers.add(&peer.to_string_lossy()).await?;
+            raw_socket
+                .write_microframe(MicroframeHeader::intrinsic_auth(auth), ServerPing::Ok)
+                .await?;
+        }
+        //
+        //
+        // ^-^ Stop peering with a previously added peer
+        m if m == cm::make(cm::PEER, cm::DELETE) => {
+            let PeerDelete { peer } = raw_socket
+                .read_payload::<PeerDelete>(header.payload_size)
+                .await??;
+            let auth = check_any_auth(&header, auth_guard).await?;
+
+            ctx.peers.remove(&peer.to_string_lossy()).await?;
+            raw_socket
+                .write_microframe(MicroframeHeader::intrinsic_auth(auth), ServerPing::Ok)
+                .await?;
+        }
+        //
+        //
+        // ^-^ Drop stale links and peers from the routing table
+        m if m == cm::make(cm::PEER, cm::DESTROY) => {
+            let PeerPrune {
+                lost_link_hours,
+                forget_peer_days,
+            } = raw_socket
+                .read_payload::<PeerPrune>(header.payload_size)
+                .await?;
+            let auth = check_any_auth(&header, auth_guard).await?;
+
+            let mut policy = ctx.routes.expiry;
+            if lost_link_hours > 0 {
+                policy.lost_link_after = chrono::Duration::hours(lost_link_hours as i64);
+            }
+            if forget_peer_days > 0 {
+                policy.forget_peer_after = chrono::Duration::days(forget_peer_days as i64);
+            }
+
+            let pruned = ctx.routes.prune(&policy).await?;
+            raw_socket
+                .write_microframe(
+                    MicroframeHeader::intrinsic_auth(auth),
+                    ServerPing::Pruned(pruned),
+                )
+                .await?;
+        }
+        //
+        //
+        // ^-^ Follow discovered and pruned peers
+        m if m == cm::make(cm::PEER, cm::SUB) => {
+            let auth = check_any_auth(&header, auth_guard).await?;
+
+            // Subscribe before replying, so that no event sent after this
+            // request is missed
+            let mut events = ctx.routes.subscribe();
+            let sub_listen = TcpListener::bind("127.0.0.1:0").await?;
+            let bind = sub_listen.local_addr()?.to_string();
+            debug!("Starting peer event subscription on socket {}", bind);
+
+            let tripwire = ctx.tripwire.clone();
+            spawn(async move {
+                if let Ok((stream, _)) = sub_listen.accept().await {
+                    let mut event_socket = RawSocketHandle::new(stream);
+                    loop {
+                        let event = select! {
+                            biased;
+                            _ = tripwire.clone() => break,
+                            event = events.recv() => event,
+                        };
+
+                        match event {
+                            Ok(event) => {
+                                if let Err(e) = event_socket
+                                    .write_microframe(
+                                        MicroframeHeader {
+                                            modes: cm::make(cm::PEER, cm::SUB),
+                                            auth: Some(auth),
+                                            payload_size: 0,
+                                        },
+                                        event,
+                                    )
+                                    .await
+                                {
+                                    debug!("Peer event subscription closed: {e}");
+                                    break;
+                                }
+                            }
+                            Err(RecvError::Lagged(n)) => {
+                                warn!("Peer event subscription missed {n} events")
+                            }
+                            Err(RecvError::Closed) => break,
+                        }
+                    }
+                }
+            });
+
+            raw_socket
+                .write_microframe(
+                    MicroframeHeader::intrinsic_auth(auth),
+                    ServerPing::Subscription {
+                        sub_id: Ident32::random(),
+                        sub_bind: CString::new(bind).unwrap(),
+                    },
+                )
+                .await?;
+        }
+        //
+        //
+        // ^-^ List all links attached to the router
+        m if m == cm::make(cm::LINK, cm::LIST) => {
+            let links = ctx.link_list().await;
+            raw_socket
+                .write_microframe(
+                    MicroframeHeader::intrinsic_noauth(),
+                    ServerPing::LinkList(links),
+                )
+                .await?;
+        }
+        //
+        //
+        // ^-^ Start new links from a configuration snippet
+        m if m == cm::make(cm::LINK, cm::UP) => {
+            let LinkUp { config } = raw_socket
+                .read_payload::<LinkUp>(header.payload_size)
+                .await??;
+            let auth = check_any_auth(&header, auth_guard).await?;
+
+            let started = ctx.link_up(&config.to_string_lossy()).await?;
+            raw_socket
+                .write_microframe(
+                    MicroframeHeader::intrinsic_auth(auth),
+                    ServerPing::LinkList(started),
+                )
+                .await?;
+        }
+        //
+        //
+        // ^-^ Stop a link and remove it from the router
+        m if m == cm::make(cm::LINK, cm::DOWN) => {
+            let LinkDown { id } = raw_socket
+                .read_payload::<LinkDown>(header.payload_size)
+                .await?;
+            let auth = check_any_auth(&header, auth_guard).await?;
+
+            ctx.link_down(id as usize).await?;
+            raw_socket
+                .write_microframe(MicroframeHeader::intrinsic_auth(auth), ServerPing::Ok)
+                .await?;
+        }
+        //
+        //
+        // ^-^ Reload the configuration file and apply changes
+        m if m == cm::make(cm::CONFIG, cm::RELOAD) => {
+            let auth = check_any_auth(&header, auth_guard).await?;
+
+            let changes = ctx.reload_config().await?;
+            raw_socket
+                .write_microframe(
+                    MicroframeHeader::intrinsic_auth(auth),
+                    ServerPing::ConfigChanges(changes),
+                )
+                .await?;
+        }
+        //
+        //
         // ^-^ Get some diagnostics about the current status of the router
         m if m == cm::make(cm::INTRINSIC, cm::STATUS) => {
-            let num_peers = ctx.routes.list_remote().await?.len() as u64;
-            let num_local = crypto::list_addr_keys(&ctx.meta_db).len() as u64;
-            let num_auth = ctx.clients.active_auth().lock().await.len() as u64;
-            let num_collector_workers = ctx.collector.num_workers().await;
-
+            let status = ctx.router_status(senders).await?;
             raw_socket
                 .write_microframe(
                     MicroframeHeader::intrinsic_noauth(),
-                    ServerPing::Status {
-                        num_peers,
-                        num_local,
-                        num_auth,
-                        num_collector_workers,
-                    },
+                    ServerPing::Status(status),
                 )
                 .await?;
         }
@@ -548,6 +1012,7 @@ pub(super) async fn single_session_exchange<'a>(
                         stream_ctx,
                         rx,
                         raw_socket,
+                        subs_restore.addr,
                         auth,
                         subs_restore.sub_id,
                     )
@@ -581,7 +1046,12 @@ pub(super) async fn single_session_exchange<'a>(
                 .await?;
 
             let (tx, mut rx) = bcast_channel(1);
-            ctx.clients.insert_sync_listener(recv_one.to, tx).await;
+            ctx.clients
+                .insert_sync_listener(recv_one.to, tx.clone())
+                .await;
+            if let Err(e) = procedures::replay_pending_stream(ctx, recv_one.to, &tx).await {
+                warn!("Failed to hand over a pending stream: {e}");
+            }
             debug!("Blocking task on synchronous stream receiver");
 
             match rx.recv().await {
@@ -648,9 +1118,15 @@ pub(super) async fn single_session_exchange<'a>(
                 .await?;
 
             let (tx, mut rx) = bcast_channel(8);
-            ctx.clients.insert_sync_listener(to, tx).await;
+            ctx.clients.insert_sync_listener(to, tx.clone()).await;
 
             loop {
+                // Streams that arrived while nobody was listening are handed
+                // over one by one, before waiting for new ones
+                if let Err(e) = procedures::replay_pending_stream(ctx, to, &tx).await {
+                    warn!("Failed to hand over a pending stream: {e}");
+                }
+
                 match rx.recv().await {
                     Ok((letterhead, read_cap)) => {
                         raw_socket
@@ -707,6 +1183,72 @@ pub(super) async fn single_session_exchange<'a>(
         }
         //
         //
+        // ^-^ Client wants to fetch a stream by its read capability
+        m if m == cm::make(cm::RECV, cm::QUERY) => {
+            let FetchStream {
+                addr,
+                read_cap,
+                timeout_ms,
+            } = raw_socket
+                .read_payload::<FetchStream>(header.payload_size)
+                .await?;
+            let auth = check_auth(&header, addr, auth_guard).await?;
+
+            // Fetched blocks are sealed for the address, which can only be
+            // opened while it is up
+            if ctx.protocol.get_online_auth(addr).await.is_none() {
+                raw_socket
+                    .write_microframe(
+                        MicroframeHeader::intrinsic_auth(auth),
+                        ServerPing::Error(ClientError::User(UserError::InvalidInput(
+                            format!("address {} is down", addr.pretty_string()),
+                            Some("an address that is up".into()),
+                        ))),
+                    )
+                    .await?;
+                return Ok(SessionResult::Next);
+            }
+
+            let stream_id = Ident32::from_bytes(read_cap.root_reference.as_slice());
+            debug!("Fetch stream {}", stream_id.pretty_string());
+
+            let complete =
+                procedures::fetch_stream(ctx, addr, &read_cap, Duration::from_millis(timeout_ms))
+                    .await?;
+
+            let mut content = vec![];
+            let res = match complete {
+                true => async_eris::decode(&mut content, &read_cap, &ctx.journal.blocks)
+                    .await
+                    .map_err(|e| ClientError::Internal(e.to_string())),
+                false => Err(ClientError::StreamTimeout(stream_id)),
+            };
+
+            match res {
+                Ok(()) => {
+                    raw_socket
+                        .write_microframe(
+                            MicroframeHeader::intrinsic_auth(auth),
+                            ServerPing::Fetched {
+                                length: content.len() as u64,
+                            },
+                        )
+                        .await?;
+                    raw_socket.stream().write_all(&content).await?;
+                    reply_ok(raw_socket, auth).await?;
+                }
+                Err(e) => {
+                    raw_socket
+                        .write_microframe(
+                            MicroframeHeader::intrinsic_auth(auth),
+                            ServerPing::Error(e),
+                        )
+                        .await?
+                }
+            }
+        }
+        //
+        //
         // ^-^ Client wants to send a message to one recipient
         m if m == cm::make(cm::SEND, cm::ONE) => {
             debug!("Handle send::one request payload: {}", header.payload_size);
@@ -719,6 +1261,7 @@ pub(super) async fn single_session_exchange<'a>(
 
             let auth = check_auth(&header, letterhead.from, auth_guard).await?;
             debug!("{client_id} Passed authentication on [send : one]");
+            let wants_receipt = letterhead.wants_receipt();
 
             let ctx = Arc::clone(&ctx);
             let senders = Arc::clone(senders);
@@ -752,6 +1295,15 @@ pub(super) async fn single_session_exchange<'a>(
             // return status
             let send_sys_res = join.await?;
             match send_sys_res {
+                // Streams that requested a receipt need their IDs to wait for it
+                Ok(stream_ids) if wants_receipt => {
+                    raw_socket
+                        .write_microframe(
+                            MicroframeHeader::intrinsic_auth(auth),
+                            ServerPing::Sent(stream_ids),
+                        )
+                        .await?
+                }
                 Ok(_) => reply_ok(raw_socket, auth).await?,
                 Err(e) => {
                     raw_socket
@@ -786,6 +1338,7 @@ pub(super) async fn single_session_exchange<'a>(
 
             let auth = check_auth(&header, this_addr, auth_guard).await?;
             debug!("{client_id} Passed authentication on [send : many]");
+            let wants_receipt = letterheads.iter().any(|lh| lh.wants_receipt());
 
             let ctx = Arc::clone(&ctx);
             let senders = Arc::clone(senders);
@@ -819,6 +1372,15 @@ pub(super) async fn single_session_exchange<'a>(
             // return status
             let send_sys_res = join.await?;
             match send_sys_res {
+                // Streams that requested a receipt need their IDs to wait for it
+                Ok(stream_ids) if wants_receipt => {
+                    raw_socket
+                        .write_microframe(
+                            MicroframeHeader::intrinsic_auth(auth),
+                            ServerPing::Sent(stream_ids),
+                        )
+                        .await?
+                }
                 Ok(_) => reply_ok(raw_socket, auth).await?,
                 Err(e) => {
                     raw_socket
@@ -832,6 +1394,31 @@ pub(super) async fn single_session_exchange<'a>(
         }
         //
         //
+        // ^-^ Client wants to wait for the delivery receipt of a stream
+        m if m == cm::make(cm::SEND, cm::RECEIPT) => {
+            let AwaitReceipt {
+                stream_id,
+                timeout_ms,
+            } = raw_socket
+                .read_payload::<AwaitReceipt>(header.payload_size)
+                .await?;
+            let (auth, addr) = check_auth_owner(&header, auth_guard).await?;
+
+            let reply = match ctx
+                .receipts
+                .wait_for(stream_id, addr, Duration::from_millis(timeout_ms))
+                .await
+            {
+                Some(receipt) => ServerPing::Delivered(receipt),
+                None => ServerPing::Timeout,
+            };
+
+            raw_socket
+                .write_microframe(MicroframeHeader::intrinsic_auth(auth), reply)
+                .await?;
+        }
+        //
+        //
         // u-u Don't know what to do with this
         mode => {
             raw_socket
@@ -78,6 +78,11 @@ fn main() {
 
     let mut config = match sys_startup.exec(ConfigTree::load_path(&cfg_path)) {
         Ok(cfg) => cfg,
+        // Never replace a configuration that exists but is invalid
+        Err(e) if cfg_path.exists() => {
+            eprintln!("failed to load configuration: {}", e);
+            std::process::exit(codes::INVALID_CONFIG as i32);
+        }
         Err(_) => {
             // If the configuration couldn't be loaded we assume that
             // it just doesn't exist yet and we try to create it.
@@ -91,16 +96,16 @@ fn main() {
                         .unwrap_or("<unprintable path>")
                 );
             }
-            cfg
+            cfg.with_path(&cfg_path)
         }
     };
 
     // config.pretty_print();
 
     // Override the ephemeral value
-    // if arg_matches.is_present("EPHEMERAL") {
-    //     config = config.patch("ratmand/ephemeral", true);
-    // }
+    if arg_matches.is_present("EPHEMERAL") {
+        config = config.patch("ratmand/ephemeral", true);
+    }
 
     // Override the config verbosity value with the CLI value if desired
     if let Some(verbosity) = arg_matches.value_of("VERBOSE") {
@@ -23,14 +23,17 @@ use libratman::{
         fs::{File, OpenOptions},
         io::{AsyncReadExt, AsyncWriteExt},
     },
-    Result,
+    types::error::UserError,
+    ClientError, Result,
 };
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 mod default;
 pub mod helpers;
 pub mod netmods;
 pub mod peers;
+pub(crate) mod reload;
+pub(crate) mod scorers;
 
 /// Represent the well-known `ratmand` configuration tree
 pub(crate) const CFG_RATMAND: &'static str = "ratmand";
@@ -44,9 +47,14 @@ pub(crate) const CFG_RATMAND: &'static str = "ratmand";
 /// `ratmand` and `irdest.intrinsics`).  Other keys MUST be handled
 /// optionally, depending on the desired runtime configuration of the
 /// router instance.
+#[derive(Default)]
 pub struct ConfigTree {
     #[doc(hidden)]
     pub inner: KdlDocument,
+    /// The file this configuration was loaded from
+    path: Option<PathBuf>,
+    /// Overrides from `patch`, which are kept when reloading the file
+    patches: Vec<(String, KdlValue)>,
 }
 
 impl ConfigTree {
@@ -63,29 +71,42 @@ impl ConfigTree {
     pub fn default_in_memory() -> Self {
         Self {
             inner: default::create_new_default(),
+            ..Default::default()
         }
     }
 
     /// Quickly override any parts of the default config for tests
     ///
     /// A key path is segments of the configuration, split by `/`, so
-    /// for example `ratmand/verbosity`, or `inet/enable`.
+    /// for example `ratmand/verbosity`, or `inet/enable`.  Overrides
+    /// are applied again when the configuration is reloaded.
     pub fn patch(mut self, key_path: &str, value: impl Into<KdlValue>) -> Self {
-        let (tree, setting) = key_path.split_once('/').expect("invalid key_path syntax");
+        let value = value.into();
+        if let Err(e) = self.apply_patch(key_path, value.clone()) {
+            panic!("{}", e);
+        }
+
+        self.patches.push((key_path.into(), value));
+        self
+    }
+
+    fn apply_patch(&mut self, key_path: &str, value: KdlValue) -> std::result::Result<(), String> {
+        let (tree, setting) = key_path
+            .split_once('/')
+            .ok_or_else(|| "invalid key_path syntax".to_string())?;
 
         let subtree = helpers::select_mut_settings_tree(&mut self.inner, tree)
-            .expect(&format!("invalid subtree {}", tree));
+            .ok_or_else(|| format!("invalid subtree {}", tree))?;
 
         let node = subtree
             .children_mut()
             .as_mut()
-            .unwrap()
-            .get_mut(setting)
-            .expect(&format!("setting {} doesn't exist", setting));
+            .and_then(|children| children.get_mut(setting))
+            .ok_or_else(|| format!("setting {} doesn't exist", setting))?;
         node.clear_entries();
         node.push(value);
 
-        self
+        Ok(())
     }
 
     /// Use this function to patch a list block (for example `ratmand/peers`)
@@ -105,24 +126,73 @@ impl ConfigTree {
     }
 
     pub async fn load_path(path: impl Into<PathBuf>) -> Result<Self> {
-        let mut f = File::open(path.into()).await?;
+        let path = path.into();
+        let mut f = File::open(&path).await?;
         let mut buf = String::new();
         f.read_to_string(&mut buf).await?;
 
+        let inner = buf.parse().map_err(|e| {
+            ClientError::User(UserError::InvalidInput(
+                format!("invalid configuration {}: {}", path.display(), e),
+                None,
+            ))
+        })?;
+
+        // Daemonised routers change their working directory, so relative
+        // paths would no longer point at the same file
+        let path = std::fs::canonicalize(&path).unwrap_or(path);
         Ok(Self {
-            inner: buf.parse().unwrap(),
+            inner,
+            path: Some(path),
+            patches: vec![],
         })
     }
 
+    /// Remember the file that this configuration is stored in
+    ///
+    /// This is needed to reload configurations that weren't created
+    /// with `load_path`.
+    pub fn with_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// The file this configuration was loaded from, if any
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Load the configuration file again
+    ///
+    /// Overrides that were applied with `patch` (for example from
+    /// command-line flags) are applied to the new configuration too.
+    pub async fn reload(&self) -> Result<Self> {
+        let path = self.path.clone().ok_or_else(|| {
+            ClientError::User(UserError::MissingInput("a configuration file".into()))
+        })?;
+
+        let mut new = Self::load_path(path).await?;
+        for (key_path, value) in &self.patches {
+            match new.apply_patch(key_path, value.clone()) {
+                Ok(()) => new.patches.push((key_path.clone(), value.clone())),
+                Err(e) => warn!("override for {key_path} no longer applies: {e}"),
+            }
+        }
+
+        Ok(new)
+    }
+
     /// Take the current in-memory configuration and write it to disk
     ///
     /// This will mostly occur when running ratmand for the first
-    /// time, but may also be done after applying changes to the
-    /// router configuration from the web management dashboard.
+    /// time, or when generating a configuration with `ratmand
+    /// generate`.  Edits made to the file afterwards can be applied
+    /// to a running router with `reload`.
     pub async fn write_changes(&self, path: impl Into<PathBuf>) -> Result<()> {
         let mut f = OpenOptions::new()
             .create(true)
             .write(true)
+            .truncate(true)
             .open(path.into())
             .await?;
         f.write_all(self.inner.to_string().as_bytes()).await?;
@@ -135,6 +205,22 @@ impl ConfigTree {
     pub fn get_subtree(&self, id: &str) -> Option<SubConfig<'_>> {
         helpers::select_settings_tree(&self.inner, id).map(|inner| SubConfig { inner })
     }
+
+    /// Iterate over all named subtrees, for example `settings "inet" { ... }`
+    pub fn subtrees(&self) -> impl Iterator<Item = (&str, SubConfig<'_>)> {
+        self.inner.nodes().iter().filter_map(|inner| {
+            helpers::get_node_name_attribute(inner).map(|name| (name, SubConfig { inner }))
+        })
+    }
+}
+
+impl From<KdlDocument> for ConfigTree {
+    fn from(inner: KdlDocument) -> Self {
+        Self {
+            inner,
+            ..Default::default()
+        }
+    }
 }
 
 #[derive(Debug)]
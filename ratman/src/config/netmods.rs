@@ -6,14 +6,22 @@
 //!
 //!
 
-use crate::{config::ConfigTree, links::LinksMap, storage::MetadataDb};
+use crate::{
+    config::{ConfigTree, SubConfig, CFG_RATMAND},
+    links::{GenericEndpoint, LinksMap},
+    storage::MetadataDb,
+};
+use libratman::{types::error::UserError, ClientError, NetmodError, Result};
 use std::sync::Arc;
 
+#[cfg(feature = "inet")]
+use {libratman::types::Ident32, std::convert::TryFrom};
+
 #[cfg(feature = "datalink")]
 use netmod_datalink::Endpoint as DatalinkEndpoint;
 
 #[cfg(feature = "inet")]
-use netmod_inet::InetEndpoint;
+use netmod_inet::{InetEndpoint, PeerPolicy};
 
 #[cfg(feature = "lan")]
 use netmod_lan::Endpoint as LanEndpoint;
@@ -21,6 +29,12 @@ use netmod_lan::Endpoint as LanEndpoint;
 #[cfg(feature = "lora")]
 use netmod_lora::LoraEndpoint;
 
+/// Names of the netmod configuration trees that ratmand knows about
+///
+/// Whether a netmod can actually be started depends on the features
+/// ratmand was built with.
+pub(crate) const NETMODS: &[&str] = &["inet", "lan", "lora", "datalink"];
+
 /// This function does not fail or abort router initialisation.  In
 /// the future maybe we want to log errors, but then also pass them
 /// upwards ?  For now, errors and warnings are logged to the user, so
@@ -31,117 +45,192 @@ pub(crate) async fn initialise_netmods(
     links: &Arc<LinksMap>,
     meta_db: &Arc<MetadataDb>,
 ) {
+    for name in NETMODS {
+        // If the config tree for this netmod exists and is enabled...
+        let tree = match cfg.get_subtree(name) {
+            Some(tree) if tree.get_bool_value("enable") == Some(true) => tree,
+            _ => continue,
+        };
+
+        match start_netmod(name, &tree, cfg, meta_db).await {
+            Ok(ep) => {
+                let id = links.add(name.to_string(), ep).await;
+                info!("Initialised {name} driver as id:{id}");
+            }
+            Err(e) => {
+                error!("Netmod '{name}' failed to initialise: {e}. skipping...");
+            }
+        }
+    }
+}
+
+fn missing_field(name: &str, field: &str) -> libratman::RatmanError {
+    ClientError::User(UserError::MissingInput(format!("{name}/{field}"))).into()
+}
+
+/// Start a single netmod instance from its configuration tree
+///
+/// The `enable` field of the tree is not considered here, so this can
+/// also be used to start netmods at runtime.  Router-wide settings
+/// (such as `accept_unknown_peers`) are taken from `cfg`.
+#[allow(unused_variables)]
+pub(crate) async fn start_netmod(
+    name: &str,
+    tree: &SubConfig<'_>,
+    cfg: &ConfigTree,
+    meta_db: &MetadataDb,
+) -> Result<Arc<GenericEndpoint>> {
     let router_pk_id = meta_db.router_id();
 
-    //// If the 'inet' config tree exists...
-    #[cfg(feature = "inet")]
-    if let Some(tree) = cfg.get_subtree("inet") {
-        let enable = tree.get_bool_value("enable");
-        let bind = tree.get_string_value("bind");
+    match name {
+        #[cfg(feature = "inet")]
+        "inet" => {
+            // Print a helpful warning about a missing feature
+            if let Some(true) = tree.get_bool_value("use_upnp") {
+                warn!("UPNP setup is currently broken; the configuration option 'use_upnp' will be ignored");
+            }
 
-        // Print a helpful warning about a missing feature
-        if let Some(true) = tree.get_bool_value("use_upnp") {
-            warn!("UPNP setup is currently broken; the configuration option 'use_upnp' will be ignored");
+            let bind = tree
+                .get_string_value("bind")
+                .ok_or_else(|| missing_field(name, "bind"))?;
+            // Inet sessions are authenticated with the router key
+            let policy = inet_policy(cfg, tree)?;
+            Ok(InetEndpoint::start(bind.as_str(), meta_db.router_key(), policy).await?)
         }
 
-        match (enable, bind) {
-            // If enable is true and a bind address was provided
-            (Some(true), Some(bind)) => {
-                match InetEndpoint::start(bind.as_str(), router_pk_id).await {
-                    Ok(inet) => {
-                        let id = links.add("inet".into(), inet).await;
-                        info!("Initialised inet driver as id:{id}");
-                    }
-                    Err(e) => {
-                        error!(
-                            "Netmod 'inet' failed to initialise, because of {}. skipping...",
-                            e
-                        );
-                    }
-                }
-            }
-            // If enable is true, but no (valid utf-8) bind address was provided
-            (Some(true), None) => {
-                error!("Netmod 'inet' requires configuration field 'bind' to start!");
-                // TODO: should initialisation just fail here then ??
-            }
-            // If enable is false, we do nothing
-            _ => {}
+        #[cfg(feature = "lan")]
+        "lan" => {
+            let iface = tree.get_string_value("interface");
+            let port = tree
+                .get_number_value("port")
+                .ok_or_else(|| missing_field(name, "port"))?;
+            LanEndpoint::spawn(iface, port as u16, router_pk_id)
+                .await
+                .map(|lan| lan as Arc<GenericEndpoint>)
+                .map_err(|e| NetmodError::InvalidBind(e.into()).into())
         }
-    }
 
-    //// If the 'lan' subtree exists...
-    #[cfg(feature = "lan")]
-    if let Some(tree) = cfg.get_subtree("lan") {
-        let iface = tree.get_string_value("interface");
-        let enable = tree.get_bool_value("enable");
-        let port = tree.get_number_value("port");
-
-        match (enable, port) {
-            // If enable is true and a port was provided (in principle)
-            (Some(true), Some(port)) => {
-                match LanEndpoint::spawn(iface, port as u16, router_pk_id).await {
-                    Ok(lan) => {
-                        let id = links.add("lan".into(), lan).await;
-                        info!("Initialised lan driver as id:{id}");
-                    }
-                    Err(e) => {
-                        error!("Netmod 'lan' failed to initialise: {}. skipping...", e);
-                    }
-                }
-            }
-            // If enable is true, but no port was provided
-            (Some(true), None) => {
-                warn!("Netmod 'lan' requires configuration field 'port' to start!");
-            }
-            // If enable is false we do nothing
-            _ => {}
+        #[cfg(feature = "lora")]
+        "lora" => {
+            let port = tree
+                .get_string_value("port")
+                .ok_or_else(|| missing_field(name, "port"))?;
+            let baud = tree
+                .get_number_value("baud")
+                .ok_or_else(|| missing_field(name, "baud"))?;
+            Ok(LoraEndpoint::spawn(&port, baud as u32, router_pk_id))
         }
-    }
 
-    //// If the 'lora' subtree exists
-    #[cfg(feature = "lora")]
-    if let Some(tree) = cfg.get_subtree("lora") {
-        let enable = tree.get_bool_value("enable");
-        let port = tree.get_string_value("port");
-        let baud = tree.get_number_value("baud");
-
-        match (enable, port, baud) {
-            (Some(true), Some(port), Some(baud)) => {
-                let lora = LoraEndpoint::spawn(&port, baud as u32, router_pk_id);
-                let id = links.add("lora".into(), lora).await;
-                info!("Initialised lora driver as id:{id}");
-            }
-            (Some(true), None, _) => {
-                warn!("Netmod 'lora' requires configuration field 'port' to start!");
-            }
-            (Some(true), _, None) => {
-                warn!("Netmod 'lora' requires configuration field 'baud' to start!");
-            }
-            _ => {}
+        // We don't care about whether interface or ssid are missing
+        #[cfg(feature = "datalink")]
+        "datalink" => {
+            let iface = tree.get_string_value("interface");
+            let ssid = tree.get_string_value("ssid");
+            Ok(DatalinkEndpoint::spawn(iface.as_deref(), ssid.as_deref(), router_pk_id).await)
         }
+
+        _ => Err(ClientError::User(UserError::InvalidInput(
+            format!("Unknown netmod '{name}'"),
+            Some(format!(
+                "This ratmand supports: {}",
+                NETMODS
+                    .iter()
+                    .filter(|name| supported(name))
+                    .copied()
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        ))
+        .into()),
     }
+}
 
-    #[cfg(feature = "datalink")]
-    if let Some(tree) = cfg.get_subtree("datalink") {
-        let enable = tree.get_bool_value("enable");
-        let interface = tree.get_string_value("interface");
-        let ssid = tree.get_string_value("ssid");
-
-        match (enable, interface, ssid) {
-            // If enable is true, we don't care about whether interface or ssid are missing
-            (Some(true), iface, ssid) => {
-                let id = links
-                    .add(
-                        "datalink".into(),
-                        DatalinkEndpoint::spawn(iface.as_deref(), ssid.as_deref(), router_pk_id)
-                            .await,
-                    )
-                    .await;
-                info!("Initialised datalink driver as id:{id}");
-            }
-            // If enable is false, we do nothing
-            _ => {}
-        }
+/// Read which peers may connect to an inet endpoint
+///
+/// Unlike other settings, invalid router keys are an error, so that
+/// a typo can't silently let a denied peer connect.
+#[cfg(feature = "inet")]
+fn inet_policy(cfg: &ConfigTree, tree: &SubConfig<'_>) -> Result<PeerPolicy> {
+    let keys = |field: &str| {
+        tree.get_string_list_block(field)
+            .unwrap_or_default()
+            .iter()
+            .map(|key| {
+                Ident32::try_from(key.as_str()).map_err(|_| {
+                    ClientError::User(UserError::InvalidInput(
+                        format!("inet/{field}: invalid router id '{key}'"),
+                        Some("a router id as shown by 'ratctl status system'".into()),
+                    ))
+                    .into()
+                })
+            })
+            .collect::<Result<_>>()
+    };
+
+    Ok(PeerPolicy {
+        accept_unknown: cfg
+            .get_subtree(CFG_RATMAND)
+            .and_then(|ratmand| ratmand.get_bool_value("accept_unknown_peers"))
+            .unwrap_or(false),
+        allow: keys("allow_peers")?,
+        deny: keys("deny_peers")?,
+    })
+}
+
+/// Check whether this ratmand was built with support for a netmod
+fn supported(name: &str) -> bool {
+    match name {
+        "inet" => cfg!(feature = "inet"),
+        "lan" => cfg!(feature = "lan"),
+        "lora" => cfg!(feature = "lora"),
+        "datalink" => cfg!(feature = "datalink"),
+        _ => false,
     }
 }
+
+#[cfg(feature = "inet")]
+#[test]
+fn inet_policy_config() {
+    let key = Ident32::random();
+    let parse = |cfg: String| ConfigTree {
+        inner: cfg.parse().unwrap(),
+        ..Default::default()
+    };
+
+    let cfg = parse(format!(
+        r#"settings "ratmand" {{
+            accept_unknown_peers true
+        }}
+        settings "inet" {{
+            deny_peers {{
+                - "{key}"
+            }}
+        }}"#
+    ));
+    let policy = inet_policy(&cfg, &cfg.get_subtree("inet").unwrap()).unwrap();
+    assert!(policy.accept_unknown);
+    assert!(policy.allow.is_empty());
+    assert!(policy.deny.contains(&key));
+
+    // Unknown peers are rejected by default
+    let cfg = parse(format!(
+        r#"settings "inet" {{
+            allow_peers {{
+                - "{key}"
+            }}
+        }}"#
+    ));
+    let policy = inet_policy(&cfg, &cfg.get_subtree("inet").unwrap()).unwrap();
+    assert!(!policy.accept_unknown);
+    assert!(policy.allow.contains(&key));
+
+    let cfg = parse(
+        r#"settings "inet" {
+            deny_peers {
+                - "not-a-key"
+            }
+        }"#
+        .into(),
+    );
+    assert!(inet_policy(&cfg, &cfg.get_subtree("inet").unwrap()).is_err());
+}
@@ -1,11 +1,35 @@
 //! Utility module to handle verifying peers and initialising drivers
 //!
 //! Ratman can either be launched with a known set of peers, or it
-//! must be configured to `accept_unknown_peers`.
+//! must be configured to `accept_unknown_peers`.  Additional peers
+//! can be added and removed at runtime via the client API, and are
+//! remembered in the metadata database.
 
-use crate::{links::LinksMap, storage::MetadataDb};
-use libratman::{types::RouterMeta, NetmodError, RatmanError};
-use std::sync::Arc;
+use crate::{
+    config::{helpers, ConfigTree, CFG_RATMAND},
+    links::{GenericEndpoint, LinksMap},
+    storage::{peer::PeerData, MetadataDb},
+};
+use chrono::Utc;
+use libratman::{
+    tokio::sync::Mutex, types::error::UserError, ClientError, NetmodError, RatmanError, Result,
+};
+use std::{collections::BTreeMap, sync::Arc};
+
+/// Get the peers that are listed in the configuration
+///
+/// Either this is done via the `peer_file` field, which is then read
+/// and parsed, or via the `peers` list block.  Returns `None` if
+/// neither is set.
+///
+/// FIXME: At this point the peer syntax also hasn't been validated yet!
+pub(crate) fn configured_peers(cfg: &ConfigTree) -> Option<Vec<String>> {
+    let ratmand = cfg.get_subtree(CFG_RATMAND)?;
+    ratmand
+        .get_string_value("peer_file")
+        .and_then(|path| helpers::load_peers_file(path).ok())
+        .or(ratmand.get_string_list_block("peers"))
+}
 
 /// A helper that parses, validates, and attaches peer data to drivers
 ///
@@ -14,6 +38,8 @@ use std::sync::Arc;
 pub struct PeeringBuilder {
     links: Arc<LinksMap>,
     meta_db: Arc<MetadataDb>,
+    /// Active peerings and the ID that their driver gave them
+    active: Mutex<BTreeMap<String, (Arc<GenericEndpoint>, u16)>>,
 }
 
 impl PeeringBuilder {
@@ -22,14 +48,18 @@ impl PeeringBuilder {
     /// The strings used for identification are used as prefixes in
     /// the peer syntax.
     pub(crate) fn new(links: Arc<LinksMap>, meta_db: Arc<MetadataDb>) -> Self {
-        Self { links, meta_db }
+        Self {
+            links,
+            meta_db,
+            active: Mutex::new(BTreeMap::new()),
+        }
     }
 
     /// Attach a peer to one of the existing drivers
     ///
     /// This function will log errors that are encountered, but not
     /// fail.
-    pub async fn attach(&mut self, peer: &str) -> Result<(), RatmanError> {
+    pub async fn attach(&self, peer: &str) -> Result<()> {
         let (driver_id, address_str) = match peer.split_once(':') {
             Some(split) => split,
             None => {
@@ -38,6 +68,15 @@ impl PeeringBuilder {
             }
         };
 
+        let mut active = self.active.lock().await;
+        if active.contains_key(peer) {
+            return Err(ClientError::User(UserError::InvalidInput(
+                peer.into(),
+                Some("a peer that isn't connected yet".into()),
+            ))
+            .into());
+        }
+
         match self.links.get_by_name(driver_id).await {
             Some(endpoint) => {
                 // let router_meta = RouterMeta {
@@ -46,9 +85,9 @@ impl PeeringBuilder {
                 //     available_buffer: 0,
                 // };
 
-                // Ignore the peer_id for now
                 debug!("Start peering request with {address_str}");
-                let _peer_id = endpoint.start_peering(address_str).await;
+                let peer_id = endpoint.start_peering(address_str).await?;
+                active.insert(peer.into(), (endpoint, peer_id));
                 Ok(())
             }
             None => {
@@ -60,4 +99,124 @@ impl PeeringBuilder {
             }
         }
     }
+
+    /// Stop peering with a previously attached peer
+    pub async fn detach(&self, peer: &str) -> Result<()> {
+        let (endpoint, peer_id) = self.active.lock().await.remove(peer).ok_or_else(|| {
+            ClientError::User(UserError::InvalidInput(
+                peer.into(),
+                Some("a currently connected peer".into()),
+            ))
+        })?;
+
+        debug!("Stop peering with {peer}");
+        endpoint.stop_peering(peer_id).await
+    }
+
+    /// Forget all peerings of a link that was stopped
+    ///
+    /// Peers of other links of the same driver stay attached.
+    pub(crate) async fn forget_link(&self, endpoint: &Arc<GenericEndpoint>) {
+        self.active
+            .lock()
+            .await
+            .retain(|_, (ep, _)| !Arc::ptr_eq(ep, endpoint));
+    }
+
+    /// Check whether a peer is currently attached
+    pub(crate) async fn is_attached(&self, peer: &str) -> bool {
+        self.active.lock().await.contains_key(peer)
+    }
+
+    /// Check whether a peer was added via the client API
+    pub(crate) async fn is_remembered(&self, peer: &str) -> bool {
+        self.meta_db.peers.contains(peer).await.unwrap_or(false)
+    }
+
+    /// Attach all peers that were added via the client API before
+    pub(crate) async fn restore(&self) {
+        for (peer, _) in self.meta_db.peers.iter() {
+            if let Err(e) = self.attach(&peer).await {
+                error!("failed to restore peer {peer}: {e}");
+            }
+        }
+    }
+
+    /// Attach the remembered peers of a driver that was started later
+    pub(crate) async fn restore_driver(&self, driver_id: &str) {
+        let prefix = format!("{driver_id}:");
+        for (peer, _) in self.meta_db.peers.iter() {
+            if !peer.starts_with(&prefix) {
+                continue;
+            }
+
+            if let Err(e) = self.attach(&peer).await {
+                error!("failed to restore peer {peer}: {e}");
+            }
+        }
+    }
+
+    /// Attach a new peer and remember it for future router starts
+    pub(crate) async fn add(&self, peer: &str) -> Result<()> {
+        self.attach(peer).await.map_err(client_error)?;
+        self.meta_db
+            .peers
+            .insert(peer.into(), &PeerData { added: Utc::now() })
+            .await
+    }
+
+    /// Detach a peer and forget it
+    ///
+    /// Peers from the configuration file are detached too, but will be
+    /// attached again when the router restarts.
+    pub(crate) async fn remove(&self, peer: &str) -> Result<()> {
+        self.detach(peer).await.map_err(client_error)?;
+        self.meta_db.peers.remove(peer.into()).await
+    }
+}
+
+/// Make peering errors presentable to API clients
+fn client_error(e: RatmanError) -> RatmanError {
+    match e {
+        RatmanError::ClientApi(e) => e,
+        RatmanError::Netmod(NetmodError::InvalidPeer(peer)) => ClientError::User(
+            UserError::InvalidInput(peer, Some("<netmod>:<address>".into())),
+        ),
+        RatmanError::Netmod(NetmodError::NotSupported) => ClientError::NotSupported,
+        e => ClientError::Internal(e.to_string()),
+    }
+    .into()
+}
+
+#[cfg(test)]
+use libratman::tokio;
+
+#[libratman::tokio::test]
+async fn forget_stopped_link() {
+    use libratman::types::Ident32;
+    use netmod_inet::{InetEndpoint, PeerPolicy};
+
+    let db = fjall::Keyspace::open(fjall::Config::new(
+        tempdir::TempDir::new("peers").unwrap().into_path(),
+    ))
+    .unwrap();
+    let links = LinksMap::new();
+    let peers = PeeringBuilder::new(Arc::clone(&links), Arc::new(MetadataDb::new(db).unwrap()));
+
+    let server = InetEndpoint::start("[::1]:0", Ident32::random(), PeerPolicy::open())
+        .await
+        .unwrap();
+    let peer = format!("inet:[::1]:{}", server.port());
+    let client: Arc<GenericEndpoint> =
+        InetEndpoint::start("[::1]:0", Ident32::random(), PeerPolicy::open())
+            .await
+            .unwrap();
+    links.add("inet".into(), Arc::clone(&client)).await;
+
+    peers.attach(&peer).await.unwrap();
+    assert!(peers.attach(&peer).await.is_err());
+
+    peers.forget_link(&client).await;
+    assert!(peers.active.lock().await.is_empty());
+    assert!(peers.detach(&peer).await.is_err());
 }
@@ -0,0 +1,250 @@
+// SPDX-FileCopyrightText: 2024 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+//! Compare configurations to apply changes to a running router
+//!
+//! Settings are identified by the same key paths that
+//! `ConfigTree::patch` uses, for example `ratmand/verbosity` or
+//! `inet/enable`.  Only some of them can be changed without
+//! restarting the router; everything else is reported back to the
+//! user.
+
+use crate::config::{helpers, netmods::NETMODS, ConfigTree};
+use kdl::{KdlDocument, KdlNode};
+use std::collections::BTreeMap;
+
+/// How a changed setting can be applied to a running router
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Action {
+    /// Replace the log filter
+    Logging,
+    /// The new value is used the next time the setting is read
+    Live,
+    /// Attach or detach configured peers
+    Peers,
+    /// Start or stop the netmod with this name
+    Netmod(String),
+    /// The setting is only read when the router starts
+    Restart,
+}
+
+/// Collect every setting of a configuration by its key path
+///
+/// Settings are compared in their formatted representation, without
+/// any comments or whitespace.
+fn settings(doc: &KdlDocument) -> BTreeMap<String, String> {
+    let mut map = BTreeMap::new();
+    for tree in doc.nodes() {
+        let name = match helpers::get_node_name_attribute(tree) {
+            Some(name) => name,
+            None => continue,
+        };
+
+        for setting in tree.children().iter().flat_map(|doc| doc.nodes()) {
+            let mut setting = setting.clone();
+            setting.clear_fmt_recursive();
+            map.insert(
+                format!("{}/{}", name, setting.name().value()),
+                setting.to_string().trim().to_string(),
+            );
+        }
+    }
+
+    map
+}
+
+/// List the key paths of all settings that differ between two configurations
+///
+/// Settings that were added or removed are included too.
+pub(crate) fn changed_settings(old: &ConfigTree, new: &ConfigTree) -> Vec<String> {
+    let old = settings(&old.inner);
+    let mut new = settings(&new.inner);
+
+    let mut changed = vec![];
+    for (key, value) in old {
+        match new.remove(&key) {
+            Some(ref new_value) if *new_value == value => {}
+            _ => changed.push(key),
+        }
+    }
+    changed.extend(new.into_keys());
+    changed.sort();
+    changed
+}
+
+/// Put the old value of a setting back into a new configuration
+///
+/// This is used for settings that failed to apply, so that they are
+/// still considered changed the next time the configuration is
+/// reloaded.  Settings that didn't exist before are removed.
+pub(crate) fn keep_setting(old: &ConfigTree, new: &mut ConfigTree, key: &str) {
+    let (tree, setting) = match key.split_once('/') {
+        Some(split) => split,
+        None => return,
+    };
+    let old_node = helpers::select_settings_tree(&old.inner, tree)
+        .and_then(|node| node.children())
+        .and_then(|children| children.get(setting))
+        .cloned();
+
+    if helpers::select_settings_tree(&new.inner, tree).is_none() {
+        match old_node {
+            // Add an empty copy of the old tree to hold the setting
+            Some(_) => {
+                let mut node = helpers::select_settings_tree(&old.inner, tree)
+                    .unwrap()
+                    .clone();
+                node.set_children(KdlDocument::new());
+                new.inner.nodes_mut().push(node);
+            }
+            None => return,
+        }
+    }
+
+    let new_tree = helpers::select_mut_settings_tree(&mut new.inner, tree).unwrap();
+    let children = new_tree.ensure_children().nodes_mut();
+    let idx = children
+        .iter()
+        .position(|node: &KdlNode| node.name().value() == setting);
+    match (idx, old_node) {
+        (Some(idx), Some(node)) => children[idx] = node,
+        (Some(idx), None) => {
+            children.remove(idx);
+        }
+        (None, Some(node)) => children.push(node),
+        (None, None) => {}
+    }
+}
+
+/// Check whether a netmod is enabled in a configuration
+pub(crate) fn netmod_enabled(cfg: &ConfigTree, name: &str) -> bool {
+    cfg.get_subtree(name)
+        .and_then(|tree| tree.get_bool_value("enable"))
+        .unwrap_or(false)
+}
+
+/// Decide how a changed setting can be applied
+pub(crate) fn action(key: &str, old: &ConfigTree, new: &ConfigTree) -> Action {
+    let (tree, setting) = match key.split_once('/') {
+        Some(split) => split,
+        None => return Action::Restart,
+    };
+
+    match (tree, setting) {
+        ("ratmand", "verbosity") => Action::Logging,
+        ("ratmand", "announce_delay") => Action::Live,
+        ("ratmand", "peers") | ("ratmand", "peer_file") => Action::Peers,
+        (netmod, "enable") if NETMODS.contains(&netmod) => Action::Netmod(netmod.into()),
+        // Netmods only read their settings when they are started, so
+        // changes only need a restart if the netmod keeps running
+        (netmod, _)
+            if NETMODS.contains(&netmod)
+                && !(netmod_enabled(old, netmod) && netmod_enabled(new, netmod)) =>
+        {
+            Action::Live
+        }
+        _ => Action::Restart,
+    }
+}
+
+#[test]
+fn changed_settings_ignore_formatting() {
+    let parse = |cfg: &str| ConfigTree {
+        inner: cfg.parse().unwrap(),
+        ..Default::default()
+    };
+
+    let old = parse(
+        r#"settings "ratmand" {
+            // How much to log
+            verbosity "debug"
+            announce_delay 2
+            peers {
+                - "inet:hub.irde.st:5860"
+            }
+        }
+        settings "inet" {
+            enable true
+            bind "[::]:5860"
+        }"#,
+    );
+    let same = parse(
+        r#"settings "ratmand" {
+            verbosity   "debug"
+            announce_delay 2 // seconds
+            peers { - "inet:hub.irde.st:5860"; }
+        }
+        settings "inet" { enable true; bind "[::]:5860"; }"#,
+    );
+    assert!(changed_settings(&old, &same).is_empty());
+
+    let new = parse(
+        r#"settings "ratmand" {
+            verbosity "info"
+            peers {
+                - "inet:hub.irde.st:5860"
+                - "inet:hyperion.kookie.space:5860"
+            }
+            api_bind "localhost:5852"
+        }
+        settings "inet" {
+            enable true
+            bind "[::]:5870"
+        }"#,
+    );
+    assert_eq!(
+        changed_settings(&old, &new),
+        vec![
+            "inet/bind",
+            "ratmand/announce_delay",
+            "ratmand/api_bind",
+            "ratmand/peers",
+            "ratmand/verbosity",
+        ]
+    );
+
+    assert_eq!(action("ratmand/verbosity", &old, &new), Action::Logging);
+    assert_eq!(action("ratmand/announce_delay", &old, &new), Action::Live);
+    assert_eq!(action("ratmand/peers", &old, &new), Action::Peers);
+    assert_eq!(action("ratmand/api_bind", &old, &new), Action::Restart);
+    assert_eq!(action("inet/bind", &old, &new), Action::Restart);
+
+    // Settings of a netmod that is being started are used right away
+    let stopped = parse(r#"settings "inet" { enable false; bind "[::]:5860"; }"#);
+    assert_eq!(action("inet/bind", &stopped, &new), Action::Live);
+    assert_eq!(
+        action("inet/enable", &stopped, &new),
+        Action::Netmod("inet".into())
+    );
+}
+
+#[test]
+fn keep_failed_settings() {
+    let parse = |cfg: &str| ConfigTree {
+        inner: cfg.parse().unwrap(),
+        ..Default::default()
+    };
+
+    let old = parse(
+        r#"settings "ratmand" { verbosity "debug"; }
+        settings "inet" { enable false; bind "[::]:5860"; }"#,
+    );
+    let mut new = parse(
+        r#"settings "ratmand" { verbosity "info"; api_bind "localhost:5852"; }
+        settings "inet" { enable true; bind "[::]:5870"; }"#,
+    );
+
+    keep_setting(&old, &mut new, "inet/enable");
+    keep_setting(&old, &mut new, "ratmand/api_bind");
+    assert_eq!(
+        changed_settings(&old, &new),
+        vec!["inet/bind", "ratmand/verbosity"]
+    );
+
+    // Trees that were removed come back with only the kept setting
+    let mut new = parse(r#"settings "ratmand" { verbosity "debug"; }"#);
+    keep_setting(&old, &mut new, "inet/enable");
+    assert!(!netmod_enabled(&new, "inet"));
+    assert_eq!(changed_settings(&old, &new), vec!["inet/bind"]);
+}
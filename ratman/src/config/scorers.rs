@@ -0,0 +1,120 @@
+// SPDX-FileCopyrightText: 2024 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+//! Route scorer selection and trust weights
+//!
+//! Both are read from the `ratmand` settings tree:
+//!
+//! ```kdl
+//! scorers {
+//!     - "default"
+//!     - "store-forward"
+//! }
+//!
+//! trust {
+//!     "<address>" 150
+//! }
+//! ```
+
+use crate::{
+    config::{ConfigTree, CFG_RATMAND},
+    routes::{BoxedScorer, ScorerConfiguration, ScorerRegistry},
+};
+use libratman::types::Address;
+
+/// Select the configured route scorers and initial scorer state
+///
+/// Invalid trust entries are logged and skipped, and an empty (or
+/// missing) scorer list selects the built-in defaults.
+pub(crate) fn initialise_scorers(
+    cfg: &ConfigTree,
+    registry: ScorerRegistry,
+) -> (Vec<BoxedScorer>, ScorerConfiguration) {
+    let ratmand_config = cfg.get_subtree(CFG_RATMAND);
+
+    let names = ratmand_config
+        .as_ref()
+        .and_then(|tree| tree.get_string_list_block("scorers"))
+        .unwrap_or_default();
+    let trust = ratmand_config
+        .as_ref()
+        .and_then(|tree| tree.get_subtree("trust"))
+        .and_then(|tree| tree.inner.children())
+        .map(|doc| {
+            doc.nodes()
+                .iter()
+                .filter_map(|node| {
+                    let addr = node.name().value();
+                    let weight = node.entries().first().and_then(|e| e.value().as_i64());
+                    match (parse_address(addr), weight) {
+                        (Some(addr), Some(weight)) if weight >= 0 => Some((addr, weight as u32)),
+                        _ => {
+                            warn!("Invalid trust entry for '{addr}': expected an address and a positive number; skipping");
+                            None
+                        }
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    (
+        registry.select(&names),
+        ScorerConfiguration {
+            trust,
+            ..Default::default()
+        },
+    )
+}
+
+/// Parse an address without panicking on bad user input
+fn parse_address(s: &str) -> Option<Address> {
+    let valid = s
+        .split('-')
+        .all(|seg| seg.len() % 2 == 0 && seg.chars().all(|c| c.is_ascii_hexdigit()));
+    let len = s.chars().filter(|c| *c != '-').count();
+
+    if valid && len == 64 {
+        Some(Address::from_string(&s.to_owned()))
+    } else {
+        None
+    }
+}
+
+#[test]
+fn scorer_config() {
+    let addr = Address::random();
+    let cfg = ConfigTree {
+        inner: format!(
+            r#"settings "ratmand" {{
+                scorers {{
+                    - "store-forward"
+                    - "no-such-scorer"
+                    - "store-forward"
+                }}
+                trust {{
+                    "{addr}" 0
+                    "not-an-address" 200
+                }}
+            }}"#
+        )
+        .parse()
+        .unwrap(),
+        ..Default::default()
+    };
+
+    let (scorers, state) = initialise_scorers(&cfg, ScorerRegistry::default());
+    assert_eq!(scorers.len(), 1);
+    assert_eq!(state.trust.len(), 1);
+    assert_eq!(state.trust_weight(&addr), 0);
+    assert_eq!(
+        state.trust_weight(&Address::random()),
+        crate::routes::TRUST_NEUTRAL
+    );
+
+    // The default configuration selects both built-in scorers
+    let (scorers, _) =
+        initialise_scorers(&ConfigTree::default_in_memory(), ScorerRegistry::default());
+    assert_eq!(scorers.len(), 2);
+}
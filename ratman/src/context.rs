@@ -6,48 +6,94 @@
 use crate::{
     api::{self, ConnectionManager},
     config::{
-        helpers, netmods::initialise_netmods, peers::PeeringBuilder, ConfigTree, CFG_RATMAND,
+        netmods::{initialise_netmods, start_netmod},
+        peers::{configured_peers, PeeringBuilder},
+        reload::{self, Action},
+        scorers::initialise_scorers,
+        ConfigTree, CFG_RATMAND,
+    },
+    crypto,
+    journal::{quota, Journal},
+    links::{GenericEndpoint, LinksMap},
+    procedures::{
+        self, BlockCollector, BlockNotifier, DeliveryReceipts, MessageNotifier, RetransmitPolicy,
+        SenderSystem, SubsManager,
     },
-    journal::Journal,
-    links::LinksMap,
-    procedures::{self, BlockCollector, BlockNotifier, SenderSystem, SubsManager},
     protocol::{Protocol, RouterAnnouncement},
-    routes::RouteTable,
+    routes::{AnnounceWindow, ExpiryPolicy, RouteTable, ScorerRegistry},
     storage::MetadataDb,
-    util::{self, codes, setup_logging},
+    util::{self, codes, reload_logging, setup_logging},
 };
 use async_eris::ReadCapability;
 use libratman::{
+    api::types::{ChangeOutcome, ConfigChange, LinkEntry, QueueStatus, RouterStatus},
+    endpoint::EndpointExt,
     rt::new_async_thread,
     tokio::{
+        select,
+        signal::unix::{signal, SignalKind},
         sync::{
             broadcast::{channel as bcast_channel, Sender as BcastSender},
-            mpsc::channel,
+            mpsc::{channel, Sender},
+            Mutex as AsyncMutex,
         },
         task::spawn,
     },
-    types::{Ident32, LetterheadV1, Os, Recipient, StateDirectoryLock},
-    RatmanError, Result,
+    types::{
+        error::UserError, Ident32, InMemoryEnvelope, LetterheadV1, Os, Recipient,
+        StateDirectoryLock,
+    },
+    ClientError, RatmanError, Result,
 };
 
 // External imports
 use atomptr::AtomPtr;
 use fjall::Config;
-use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+use kdl::KdlDocument;
+use std::{
+    collections::BTreeSet,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+use tempdir::TempDir;
 use tripwire::Tripwire;
 
+async fn link_entry(id: usize, name: String, ep: &Arc<GenericEndpoint>) -> LinkEntry {
+    LinkEntry {
+        id: id as u32,
+        name,
+        identifier: ep.identifier(),
+        mtu: ep.size_hint() as u32,
+        status: ep.status().await,
+    }
+}
+
+/// Number of items waiting in a channel
+fn queue_depth<T>(tx: &Sender<T>) -> u64 {
+    (tx.max_capacity() - tx.capacity()) as u64
+}
+
 /// Top-level Ratman router state handle
 ///
 /// This type is responsible for starting and owning various types
 /// that control client and driver connections, and internal coherency
 /// tasks.
 pub struct RatmanContext {
-    /// Keep a version of the launch configuration around
-    pub(crate) config: ConfigTree,
+    /// The configuration the router is currently running with
+    ///
+    /// It is replaced when the configuration is reloaded; use
+    /// `config()` to access it.
+    config: AtomPtr<ConfigTree>,
+    /// Make sure only one configuration reload runs at a time
+    reload_lock: AsyncMutex<()>,
     /// Responsible for collecting individual frames back into blocks
     pub(crate) collector: Arc<BlockCollector>,
     /// Runtime management of connected network drivers
     pub(crate) links: Arc<LinksMap>,
+    /// Peers that were attached to one of the drivers
+    pub(crate) peers: PeeringBuilder,
     /// Keep track of blocks, frames, incomplete messages and seen IDs
     pub(crate) journal: Arc<Journal>,
     /// Keep track of network and router metadata
@@ -60,14 +106,40 @@ pub struct RatmanContext {
     pub(crate) clients: Arc<ConnectionManager>,
     /// Keep track of local subscriptions
     pub(crate) subs: Arc<SubsManager>,
+    /// Delivery receipts for streams sent from local addresses
+    pub(crate) receipts: Arc<DeliveryReceipts>,
+    /// When to request missing blocks of incoming streams
+    pub(crate) retransmit: RetransmitPolicy,
     /// React to shutdown signals and gracefully quit
     pub(crate) tripwire: Tripwire,
+    /// When the router was started, to report its uptime
+    started: Instant,
     /// Atomic state directory lock
     ///
     /// If None, ratman is running in ephemeral mode and no data will
     /// be saved this session.  This is usually the case in test
     /// scenarious, but may also be the case on low-power devices.
     _statedir_lock: Arc<AtomPtr<Option<StateDirectoryLock>>>,
+    /// Temporary storage directory used in ephemeral mode
+    ///
+    /// All databases are kept in this directory instead of the state
+    /// directory, and it is removed when the router shuts down.
+    ephemeral_dir: Mutex<Option<TempDir>>,
+    /// Connections from switches to the rest of the router
+    ///
+    /// These are only available once the router has been started.
+    switch_channels: Mutex<Option<SwitchChannels>>,
+    /// The address that the client API socket was bound to
+    api_bind: Mutex<Option<SocketAddr>>,
+}
+
+/// Channels that a switch uses to hand frames to other router systems
+#[derive(Clone)]
+struct SwitchChannels {
+    ingress_tx: Sender<MessageNotifier>,
+    collector_tx: Sender<InMemoryEnvelope>,
+    retransmit_tx: Sender<InMemoryEnvelope>,
+    block_notify_tx: BcastSender<BlockNotifier>,
 }
 
 impl RatmanContext {
@@ -77,48 +149,126 @@ impl RatmanContext {
         config: ConfigTree,
         state_path: PathBuf,
         block_notify_tx: BcastSender<BlockNotifier>,
+        scorers: ScorerRegistry,
     ) -> Result<Arc<Self>> {
         let (tripwire, tw_worker) = Tripwire::new_signals();
         let protocol = Protocol::new();
 
         spawn(tw_worker);
 
-        // Initialise storage systems
-        let journal_fjall = Config::new(state_path.join("journal.fjall"))
-            .fsync_ms(Some(25))
+        // Initialise storage systems.  In ephemeral mode they live in a
+        // temporary directory and are never synced to disk
+        let ephemeral_dir = match config
+            .get_subtree(CFG_RATMAND)
+            .and_then(|ratmand| ratmand.get_bool_value("ephemeral"))
+        {
+            Some(true) => Some(TempDir::new("ratmand-ephemeral")?),
+            _ => None,
+        };
+        let (storage_path, fsync_ms) = match ephemeral_dir {
+            Some(ref dir) => (dir.path().to_path_buf(), None),
+            None => (state_path, Some(25)),
+        };
+
+        let journal_fjall = Config::new(storage_path.join("journal.fjall"))
+            .fsync_ms(fsync_ms)
             .open()?;
-        let meta_fjall = Config::new(state_path.join("metadata.fjall"))
-            .fsync_ms(Some(25))
+        let meta_fjall = Config::new(storage_path.join("metadata.fjall"))
+            .fsync_ms(fsync_ms)
             .open()?;
-        let journal = Arc::new(Journal::new(journal_fjall)?);
+        let quota = config
+            .get_subtree(CFG_RATMAND)
+            .map(|ratmand| quota::quota_from_config(&ratmand))
+            .unwrap_or(quota::DEFAULT_QUOTA);
+        let journal = Arc::new(Journal::new(journal_fjall, quota)?);
         let meta_db = Arc::new(MetadataDb::new(meta_fjall)?);
 
         let links = LinksMap::new();
-        let routes = RouteTable::new(Arc::clone(&meta_db));
+        let (solvers, solver_state) = initialise_scorers(&config, scorers);
+        let expiry = config
+            .get_subtree(CFG_RATMAND)
+            .map(|ratmand| ExpiryPolicy::from_config(&ratmand))
+            .unwrap_or_default();
+        let announce_window = config
+            .get_subtree(CFG_RATMAND)
+            .map(|ratmand| AnnounceWindow::from_config(&ratmand))
+            .unwrap_or_default();
+        let routes = RouteTable::new(
+            Arc::clone(&meta_db),
+            solvers,
+            solver_state,
+            expiry,
+            announce_window,
+        );
+        let retransmit = config
+            .get_subtree(CFG_RATMAND)
+            .map(|ratmand| RetransmitPolicy::from_config(&ratmand))
+            .unwrap_or_default();
 
-        let collector =
-            BlockCollector::restore(Arc::clone(&journal), Arc::clone(&meta_db), block_notify_tx)
-                .await?;
-        let clients = Arc::new(ConnectionManager::new());
         let subs = SubsManager::new(&meta_db);
+        let collector = BlockCollector::restore(
+            Arc::clone(&journal),
+            Arc::clone(&meta_db),
+            Arc::clone(&protocol),
+            Arc::clone(&routes),
+            Arc::clone(&subs),
+            block_notify_tx,
+        )
+        .await?;
+        let clients = Arc::new(ConnectionManager::new());
+        let peers = PeeringBuilder::new(Arc::clone(&links), Arc::clone(&meta_db));
 
         Ok(Arc::new(Self {
-            config,
+            config: AtomPtr::new(config),
+            reload_lock: AsyncMutex::new(()),
             collector,
             links,
+            peers,
             journal,
             meta_db,
             routes,
             protocol,
             clients,
             subs,
+            receipts: DeliveryReceipts::new(),
+            retransmit,
             tripwire,
+            started: Instant::now(),
             _statedir_lock: Arc::new(AtomPtr::new(None)),
+            ephemeral_dir: Mutex::new(ephemeral_dir),
+            switch_channels: Mutex::new(None),
+            api_bind: Mutex::new(None),
         }))
     }
 
     /// Create and start a new Ratman router context with a config
     pub async fn start(cfg: ConfigTree, state_path: PathBuf) {
+        Self::start_with_scorers(cfg, state_path, ScorerRegistry::default()).await
+    }
+
+    /// Create and start a new Ratman router context with additional route scorers
+    pub async fn start_with_scorers(cfg: ConfigTree, state_path: PathBuf, scorers: ScorerRegistry) {
+        let this = Self::start_embedded(cfg, state_path, scorers, vec![]).await;
+
+        // Reload the configuration file when asked to by the system
+        spawn(Arc::clone(&this).reload_on_hangup());
+
+        this.tripwire.clone().await;
+        info!("Ratmand core shutting down...");
+        this.wipe_ephemeral_state();
+    }
+
+    /// Create and start a new Ratman router context without blocking
+    ///
+    /// The provided endpoints are attached to the router in addition to the
+    /// drivers enabled in the configuration.  This is mostly useful to run
+    /// several routers in the same process, connected via in-memory drivers.
+    pub async fn start_embedded(
+        cfg: ConfigTree,
+        state_path: PathBuf,
+        scorers: ScorerRegistry,
+        endpoints: Vec<(String, Arc<dyn EndpointExt + Send + Sync>)>,
+    ) -> Arc<Self> {
         // Before we do anything else, make sure we see logs
         setup_logging(&cfg.get_subtree(CFG_RATMAND).expect("no 'ratmand' tree"));
 
@@ -128,7 +278,8 @@ impl RatmanContext {
         let (block_notify_tx, _) = bcast_channel(8);
 
         // Initialise in-memory state and restore any existing state from disk
-        let this = match Self::new(cfg, state_path.clone(), block_notify_tx.clone()).await {
+        let this = match Self::new(cfg, state_path.clone(), block_notify_tx.clone(), scorers).await
+        {
             Ok(t) => t,
             Err(e) => libratman::elog(
                 format!("failed to initialise/ restore journal state: {e:?}"),
@@ -136,18 +287,19 @@ impl RatmanContext {
             ),
         };
 
-        let ratmand_config = this
-            .config
-            .get_subtree(&CFG_RATMAND)
-            .expect("No 'ratmand' tree");
+        // Give route scorers a chance to set up their state before any
+        // routes need to be resolved
+        this.routes.configure_scorers(&this).await;
+
+        let config = this.config();
+        let ratmand_config = config.get_subtree(CFG_RATMAND).expect("No 'ratmand' tree");
 
         // If ratmand isn't set up to run ephemerally (for tests) try
         // to lock the state directory here and crash if we can't.
-        if ratmand_config.get_bool_value("ephemeral").unwrap_or(false) {
-            // warn!("ratmand is running in ephemeral mode: no data will be persisted to disk");
-            // warn!("State directory locking is unimplemented");
-            // warn!("Take care that peering hardware is not used from multiple drivers!");
-            warn!("ephemeral mode is currently unimplemented");
+        if let Some(dir) = this.ephemeral_dir() {
+            warn!("ratmand is running in ephemeral mode: no data will be persisted to disk");
+            debug!("Ephemeral state is kept in {dir:?}");
+            warn!("Take care that peering hardware is not used from multiple drivers!");
         } else {
             match Os::lock_state_directory(Some(state_path)).await {
                 Ok(Some(lock)) => {
@@ -164,25 +316,18 @@ impl RatmanContext {
         }
 
         // This never fails, we will have a map of netmods here, even if it is empty
-        initialise_netmods(&this.config, &this.links, &this.meta_db).await;
-
-        // Get the initial set of peers from the configuration.  Either this is
-        // done via the `peer_file` field, which is then read and parsed, or via
-        // the `peers` list block.  In either way we have to check for encoding
-        // problems.
-        //
-        // FIXME: At this point the peer syntax also hasn't been validated yet!
-        match ratmand_config
-            .get_string_value("peer_file")
-            .and_then(|path| helpers::load_peers_file(path).ok())
-            .or(ratmand_config.get_string_list_block("peers"))
-        {
+        initialise_netmods(&config, &this.links, &this.meta_db).await;
+        for (name, ep) in endpoints {
+            let id = this.links.add(name.clone(), ep).await;
+            info!("Attached {name} endpoint as id:{id}");
+        }
+
+        // Get the initial set of peers from the configuration
+        match configured_peers(&config) {
             // If peers exist, add them to the drivers
             Some(peers) => {
-                let mut peer_builder =
-                    PeeringBuilder::new(Arc::clone(&this.links), Arc::clone(&this.meta_db));
                 for peer in peers {
-                    if let Err(e) = peer_builder.attach(peer.as_str()).await {
+                    if let Err(e) = this.peers.attach(peer.as_str()).await {
                         error!("failed to add peer: {}", e);
                     }
                 }
@@ -202,8 +347,7 @@ impl RatmanContext {
             None if !ratmand_config
                 .get_bool_value("accept_unknown_peers")
                 .unwrap_or(false)
-                & /* and */ this
-                    .config
+                & /* and */ config
                     .get_subtree("lan")
                     .and_then(|tree| tree.get_bool_value("enable"))
                     .unwrap_or(false) =>
@@ -215,20 +359,23 @@ impl RatmanContext {
             _ => {}
         };
 
+        // Re-connect to peers that were added via the client API
+        this.peers.restore().await;
+
         // If the dashboard feature and configuration is enabled
         #[cfg(feature = "dashboard")]
         if let Some(true) = ratmand_config.get_bool_value("enable_dashboard") {
-            let _dashboard_bind = ratmand_config
+            let dashboard_bind = ratmand_config
                 .get_string_value("dashboard_bind")
                 .unwrap_or_else(|| "localhost:8090".to_owned());
 
             let mut registry = prometheus_client::registry::Registry::default();
-            // this.core.register_metrics(&mut registry);
             this.protocol.register_metrics(&mut registry);
+            this.routes.register_metrics(&mut registry);
 
-            // if let Err(e) = crate::web::start(this.clone(), registry, dashboard_bind) {
-            //     error!("failed to start web dashboard server: {}", e);
-            // }
+            if let Err(e) = crate::web::start(this.clone(), registry, dashboard_bind).await {
+                error!("failed to start web dashboard server: {}", e);
+            }
         }
 
         // Finally, we start the machinery that accepts new client
@@ -257,7 +404,7 @@ impl RatmanContext {
             let block_notify_tx = block_notify_tx.clone();
 
             let this_ = Arc::clone(&this);
-            new_async_thread("ratmand-ingress", 1024 * 8, async move {
+            new_async_thread("ratmand-ingress", 8, async move {
                 procedures::exec_ingress_system(this_, ingress_rx, block_notify_tx).await;
                 Ok(())
             });
@@ -272,11 +419,23 @@ impl RatmanContext {
         {
             let ctx = Arc::clone(&this);
             let block_notify_tx = block_notify_tx.clone();
-            new_async_thread("ratmand-collector", 1024 * 32, async move {
+            new_async_thread("ratmand-collector", 32, async move {
                 procedures::exec_block_collector_system(ctx, collector_rx, block_notify_tx).await
             });
         }
 
+        // Setup the retransmission system, responsible for answering requests
+        // for blocks that never arrived at their recipient
+        let (retransmit_tx, retransmit_rx) = channel(8);
+        {
+            let ctx = Arc::clone(&this);
+            let block_notify_tx = block_notify_tx.clone();
+            new_async_thread("ratmand-retransmit", 8, async move {
+                procedures::exec_retransmit_system(ctx, retransmit_rx, block_notify_tx).await;
+                Ok(())
+            });
+        }
+
         let sender1k_tx = procedures::exec_sender_system::<1024>(
             &this.journal,
             &this.routes,
@@ -299,42 +458,23 @@ impl RatmanContext {
         .await;
 
         // Start the switches and off we go
-        {
-            let links = Arc::clone(&this.links);
-
-            // todo: use the configurable netmod runtime here instead
-            for (name, ep, id) in links.get_with_ids().await {
-                let this_ = Arc::clone(&this);
-                let ingress_tx = ingress_tx.clone();
-                let collector_tx = collector_tx.clone();
-                let block_notify_tx = block_notify_tx.clone();
-
-                new_async_thread::<String, _, ()>(
-                    format!("ratmand-switch-{name}"),
-                    1024 * 4,
-                    async move {
-                        procedures::exec_switching_batch(
-                            id,
-                            &this_.routes,
-                            &this_.links,
-                            &this_.journal,
-                            &this_.collector,
-                            &this_.protocol,
-                            this_.tripwire.clone(),
-                            (&name, &ep),
-                            ingress_tx,
-                            collector_tx,
-                            block_notify_tx.clone(),
-                            // #[cfg(feature = "dashboard")]
-                            // todo!()
-                        )
-                        .await;
-                        Ok(())
-                    },
-                );
-            }
+        *this.switch_channels.lock().unwrap() = Some(SwitchChannels {
+            ingress_tx: ingress_tx.clone(),
+            collector_tx,
+            retransmit_tx,
+            block_notify_tx: block_notify_tx.clone(),
+        });
+        for (name, ep, id) in this.links.get_with_ids().await {
+            this.start_switch(id, name, ep);
         }
 
+        // Regularly drop stale links and peers from the routing table
+        this.routes.start_expiry_task(this.tripwire.clone());
+
+        // Keep the journal within its storage quota
+        this.journal
+            .start_quota_task(Arc::clone(&this.meta_db), this.tripwire.clone());
+
         // Start the router announcement protocol
         Arc::new(RouterAnnouncement {
             key_id: this.meta_db.router_id(),
@@ -342,7 +482,7 @@ impl RatmanContext {
         .run(Arc::clone(&this));
 
         // todo: setup management machinery to handle result events
-        if let Err(e) = api::start_api_thread(
+        match api::start_api_thread(
             Arc::clone(&this),
             api_bind_addr,
             Arc::new(SenderSystem {
@@ -352,15 +492,371 @@ impl RatmanContext {
         )
         .await
         {
+            Ok(bound) => *this.api_bind.lock().unwrap() = Some(bound),
             // todo: setup tripwire here
-            libratman::elog(
+            Err(e) => libratman::elog(
                 format!("failed to start client handler: {e}"),
                 util::codes::FATAL,
-            );
+            ),
         }
 
-        this.tripwire.clone().await;
-        info!("Ratmand core shutting down...");
+        this
+    }
+
+    /// Start the switch for a single link
+    ///
+    /// The switch runs until the router shuts down, or the link is
+    /// removed from the links map.
+    fn start_switch(self: &Arc<Self>, id: usize, name: String, ep: Arc<GenericEndpoint>) {
+        let SwitchChannels {
+            ingress_tx,
+            collector_tx,
+            retransmit_tx,
+            block_notify_tx,
+        } = match self.switch_channels.lock().unwrap().clone() {
+            Some(channels) => channels,
+            // Switches for all links are started with the router
+            None => return,
+        };
+        let this = Arc::clone(self);
+
+        // todo: use the configurable netmod runtime here instead
+        new_async_thread::<String, _, ()>(format!("ratmand-switch-{name}"), 4, async move {
+            select! {
+                _ = procedures::exec_switching_batch(
+                    id,
+                    &this.routes,
+                    &this.links,
+                    &this.journal,
+                    &this.collector,
+                    &this.protocol,
+                    &this.receipts,
+                    this.tripwire.clone(),
+                    (&name, &ep),
+                    ingress_tx,
+                    collector_tx,
+                    retransmit_tx,
+                    block_notify_tx,
+                    // #[cfg(feature = "dashboard")]
+                    // todo!()
+                ) => {}
+                _ = this.links.removed(id) => {
+                    debug!("Stopped switch for removed link {name} (id:{id})");
+                }
+            }
+            Ok(())
+        });
+    }
+
+    /// List all links that are currently attached to the router
+    pub(crate) async fn link_list(&self) -> Vec<LinkEntry> {
+        let mut links = vec![];
+        for (name, ep, id) in self.links.get_with_ids().await {
+            links.push(link_entry(id, name, &ep).await);
+        }
+        links
+    }
+
+    /// Collect a snapshot of the router state for monitoring clients
+    pub(crate) async fn router_status(
+        self: &Arc<Self>,
+        senders: &SenderSystem,
+    ) -> Result<RouterStatus> {
+        let mut queues = QueueStatus {
+            send_1k: queue_depth(&senders.tx_1k),
+            send_32k: queue_depth(&senders.tx_32k),
+            ..Default::default()
+        };
+        if let Some(ref channels) = *self.switch_channels.lock().unwrap() {
+            queues.ingress = queue_depth(&channels.ingress_tx);
+            queues.collector = queue_depth(&channels.collector_tx);
+            queues.retransmit = queue_depth(&channels.retransmit_tx);
+        }
+
+        Ok(RouterStatus {
+            uptime: self.started.elapsed().as_secs(),
+            router_id: self.meta_db.router_id(),
+            num_peers: self.routes.list_remote().await?.len() as u64,
+            num_local: crypto::list_addr_keys(&self.meta_db).len() as u64,
+            num_auth: self.clients.active_auth().lock().await.len() as u64,
+            num_collector_workers: self.collector.num_workers().await,
+            num_subscriptions: self.subs.recipients.lock().await.len() as u64,
+            journal: self.journal.status().await?,
+            queues,
+            links: self.link_list().await,
+        })
+    }
+
+    /// Start new links from a KDL configuration snippet
+    ///
+    /// Every `settings "<netmod>" { ... }` node in the snippet starts a
+    /// new instance of that netmod, which is attached to the router and
+    /// gets its own switch.  Returns the links that were started.
+    pub(crate) async fn link_up(self: &Arc<Self>, config: &str) -> Result<Vec<LinkEntry>> {
+        let inner: KdlDocument = config.parse().map_err(|e| {
+            ClientError::User(UserError::InvalidInput(
+                format!("invalid link configuration: {e}"),
+                None,
+            ))
+        })?;
+        let config = ConfigTree::from(inner);
+
+        let mut started = vec![];
+        for (name, tree) in config.subtrees() {
+            // Errors from the netmod are reported to the client, instead of
+            // ending its session
+            let ep = start_netmod(name, &tree, &self.config(), &self.meta_db)
+                .await
+                .map_err(|e| match e {
+                    RatmanError::ClientApi(e) => e,
+                    e => ClientError::Internal(format!("netmod '{name}' failed to start: {e}")),
+                })?;
+
+            let id = self.links.add(name.to_string(), Arc::clone(&ep)).await;
+            info!("Attached {name} driver as id:{id}");
+
+            self.start_switch(id, name.to_string(), Arc::clone(&ep));
+            started.push(link_entry(id, name.to_string(), &ep).await);
+        }
+
+        if started.is_empty() {
+            return Err(ClientError::User(UserError::MissingInput(
+                r#"settings "<netmod>" { ... }"#.into(),
+            ))
+            .into());
+        }
+
+        Ok(started)
+    }
+
+    /// Stop a link and remove it from the router
+    ///
+    /// Routes via this link are ignored from now on, and expire like
+    /// any other lost link.
+    pub(crate) async fn link_down(&self, id: usize) -> Result<()> {
+        let (name, ep) = self
+            .links
+            .remove(id)
+            .await
+            .ok_or(ClientError::NoSuchLink(id as u32))?;
+
+        if let Err(e) = ep.shutdown().await {
+            warn!("{name} driver (id:{id}) failed to shut down cleanly: {e}");
+        }
+        self.peers.forget_link(&ep).await;
+
+        info!("Removed {name} driver (id:{id})");
+        Ok(())
+    }
+
+    /// The configuration the router is currently running with
+    pub(crate) fn config(&self) -> Arc<ConfigTree> {
+        self.config.get_ref().consume()
+    }
+
+    /// Load the configuration file again and apply what has changed
+    ///
+    /// The log level, `announce_delay`, peers, and enabling or
+    /// disabling netmods take effect immediately.  All other changed
+    /// settings are reported as needing a restart.  The new
+    /// configuration replaces the old one, except for settings that
+    /// failed to apply.  These keep their old value, so that the next
+    /// reload tries to apply them again.
+    pub(crate) async fn reload_config(self: &Arc<Self>) -> Result<Vec<ConfigChange>> {
+        let _guard = self.reload_lock.lock().await;
+        let old = self.config();
+        let mut new = old.reload().await?;
+        let ratmand = new.get_subtree(CFG_RATMAND).ok_or_else(|| {
+            ClientError::User(UserError::MissingInput(
+                r#"settings "ratmand" { ... }"#.into(),
+            ))
+        })?;
+        info!("Reloading configuration from {:?}", new.path());
+
+        let mut changes = vec![];
+        let mut peer_keys = vec![];
+        let mut started = vec![];
+        for key in reload::changed_settings(&old, &new) {
+            let outcome = match reload::action(&key, &old, &new) {
+                Action::Logging => match reload_logging(&ratmand) {
+                    Ok(()) => ChangeOutcome::Applied,
+                    Err(e) => ChangeOutcome::Failed(e),
+                },
+                Action::Live => ChangeOutcome::Applied,
+                Action::Peers => {
+                    peer_keys.push(key);
+                    continue;
+                }
+                Action::Netmod(name) => {
+                    let outcome = self.reload_netmod(&name, &old, &new).await;
+                    if outcome == ChangeOutcome::Applied && reload::netmod_enabled(&new, &name) {
+                        started.push(name);
+                    }
+                    outcome
+                }
+                Action::Restart => ChangeOutcome::NeedsRestart,
+            };
+
+            changes.push(ConfigChange { key, outcome });
+        }
+
+        // The peer file can change without the configuration changing
+        let old_peers: BTreeSet<_> = configured_peers(&old).into_iter().flatten().collect();
+        let new_peers: BTreeSet<_> = configured_peers(&new).into_iter().flatten().collect();
+        if peer_keys.is_empty() && old_peers != new_peers {
+            peer_keys.push("ratmand/peer_file".into());
+        }
+
+        let mut errors = vec![];
+        for peer in old_peers.difference(&new_peers) {
+            // Peers that were added via the client API stay connected
+            if self.peers.is_remembered(peer).await {
+                continue;
+            }
+            if let Err(e) = self.peers.detach(peer).await {
+                debug!("peer {peer} was not connected: {e}");
+            }
+        }
+        for peer in new_peers.iter().filter(|peer| {
+            let driver = peer.split_once(':').map(|(driver, _)| driver);
+            !old_peers.contains(*peer) || started.iter().any(|name| Some(name.as_str()) == driver)
+        }) {
+            // Peers that were attached before a failed reload are kept
+            if self.peers.is_attached(peer).await {
+                continue;
+            }
+            if let Err(e) = self.peers.attach(peer).await {
+                error!("failed to add peer: {e}");
+                errors.push(format!("{peer}: {e}"));
+            }
+        }
+        for name in &started {
+            self.peers.restore_driver(name).await;
+        }
+
+        changes.extend(peer_keys.into_iter().map(|key| ConfigChange {
+            key,
+            outcome: match errors.is_empty() {
+                true => ChangeOutcome::Applied,
+                false => ChangeOutcome::Failed(errors.join(", ")),
+            },
+        }));
+
+        for change in &changes {
+            match change.outcome {
+                ChangeOutcome::Applied => info!("Applied new setting {}", change.key),
+                ChangeOutcome::NeedsRestart => {
+                    warn!("Setting {} only changes after a restart", change.key)
+                }
+                ChangeOutcome::Failed(ref e) => {
+                    error!("failed to apply new setting {}: {e}", change.key);
+                    reload::keep_setting(&old, &mut new, &change.key);
+                }
+            }
+        }
+
+        self.config.swap(new);
+        Ok(changes)
+    }
+
+    /// Start or stop a netmod after its `enable` setting changed
+    ///
+    /// Stopping a netmod removes every link of that netmod, including
+    /// links that were added via the client API.
+    async fn reload_netmod(
+        self: &Arc<Self>,
+        name: &str,
+        old: &ConfigTree,
+        new: &ConfigTree,
+    ) -> ChangeOutcome {
+        match (
+            reload::netmod_enabled(old, name),
+            reload::netmod_enabled(new, name),
+        ) {
+            (false, true) => {
+                let tree = new.get_subtree(name).expect("enabled netmod has no tree");
+                match start_netmod(name, &tree, new, &self.meta_db).await {
+                    Ok(ep) => {
+                        let id = self.links.add(name.to_string(), Arc::clone(&ep)).await;
+                        info!("Attached {name} driver as id:{id}");
+                        self.start_switch(id, name.to_string(), ep);
+                        ChangeOutcome::Applied
+                    }
+                    Err(e) => {
+                        ChangeOutcome::Failed(format!("netmod '{name}' failed to start: {e}"))
+                    }
+                }
+            }
+            (true, false) => {
+                for (link_name, _, id) in self.links.get_with_ids().await {
+                    if link_name == name {
+                        if let Err(e) = self.link_down(id).await {
+                            warn!("failed to stop {name} driver (id:{id}): {e}");
+                        }
+                    }
+                }
+                ChangeOutcome::Applied
+            }
+            _ => ChangeOutcome::Applied,
+        }
+    }
+
+    /// Reload the configuration whenever ratmand receives SIGHUP
+    async fn reload_on_hangup(self: Arc<Self>) {
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(hangups) => hangups,
+            Err(e) => {
+                warn!("failed to listen for SIGHUP, configuration reloads are unavailable: {e}");
+                return;
+            }
+        };
+
+        loop {
+            select! {
+                _ = self.tripwire.clone() => break,
+                Some(()) = hangups.recv() => {
+                    info!("Received SIGHUP");
+                    if let Err(e) = self.reload_config().await {
+                        error!("failed to reload configuration: {e}");
+                    }
+                }
+            }
+        }
+    }
+
+    /// The address that the client API socket was bound to
+    ///
+    /// This resolves a port of `0` in the `api_bind` setting to the port
+    /// that was assigned, and is `None` until the router has started.
+    pub fn api_bind(&self) -> Option<SocketAddr> {
+        *self.api_bind.lock().unwrap()
+    }
+
+    /// The temporary storage directory of an ephemeral router
+    pub(crate) fn ephemeral_dir(&self) -> Option<PathBuf> {
+        self.ephemeral_dir
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|dir| dir.path().to_path_buf())
+    }
+
+    /// Remove all storage of an ephemeral router
+    ///
+    /// The journal and metadata database are closed first, so that their
+    /// background threads don't write into the removed directory.  This does
+    /// nothing for routers that persist their state.
+    pub fn wipe_ephemeral_state(&self) {
+        if let Some(dir) = self.ephemeral_dir.lock().unwrap().take() {
+            self.journal.close();
+            self.meta_db.close();
+
+            let path = dir.path().to_path_buf();
+            match dir.close() {
+                Ok(()) => debug!("Removed ephemeral state directory {path:?}"),
+                Err(e) => warn!("failed to remove ephemeral state directory {path:?}: {e}"),
+            }
+        }
     }
 
     /// Test whether Ratman is capable of writing anything to disk
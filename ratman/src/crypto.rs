@@ -6,6 +6,11 @@
 //!
 //! An address corresponds to the public key of a key pair, where the
 //! private key is not shared outside the router.
+//!
+//! Data chunks sent between two addresses are sealed with
+//! XChaCha20-Poly1305, using a key derived from the same shared
+//! secret.  Address keys at rest are still wrapped with bare
+//! ChaCha20 via `encrypt_raw`/`decrypt_raw`.
 
 // Utility imports
 use crate::storage::{
@@ -13,15 +18,20 @@ use crate::storage::{
     MetadataDb,
 };
 use libratman::{
-    types::{AddrAuth, Address, Ident32},
-    ClientError, RatmanError, Result,
+    frame::{carrier::ChunkSeal, FrameGenerator},
+    types::{AddrAuth, Address, Ident32, Recipient, SequenceIdV1},
+    BlockError, ClientError, EncodingError, RatmanError, Result,
 };
 use rand::{rngs::OsRng, thread_rng, RngCore};
 use std::{convert::TryInto, ffi::CString, sync::Arc};
 
 // Cryptography imports
-use chacha20::cipher::{KeyIvInit, StreamCipher};
-use chacha20::ChaCha20;
+use chacha20::cipher::{consts::U10, KeyIvInit, StreamCipher};
+use chacha20::{hchacha, ChaCha20};
+use chacha20poly1305::{
+    aead::{AeadInPlace, KeyInit},
+    Tag, XChaCha20Poly1305, XNonce,
+};
 use curve25519_dalek::edwards::CompressedEdwardsY;
 use ed25519_dalek::{ExpandedSecretKey, PublicKey, SecretKey, Signature, Verifier};
 use x25519_dalek::{PublicKey as X25519Pubkey, SharedSecret, StaticSecret as X25519Secret};
@@ -46,12 +56,92 @@ impl Keypair {
     }
 }
 
-/// Encrypt a data chunk with a shared secret and random nonce
+/// Domain separator used to derive chunk keys from a shared secret
+const CHUNK_KEY_CONTEXT: &[u8; 16] = b"ratman/chunk/key";
+
+/// Derive the chunk sealing key from a diffie-hellman shared secret
+///
+/// The raw shared secret is also used as the ERIS convergence secret,
+/// so we never use it as a cipher key directly.
+fn chunk_key(shared_key: &SharedSecret) -> [u8; 32] {
+    hchacha::<U10>(shared_key.as_bytes().into(), CHUNK_KEY_CONTEXT.into()).into()
+}
+
+/// Compute the associated data that binds a chunk to its frame
+///
+/// This prevents a relay from moving a valid chunk to a different
+/// position in a block, or re-addressing it.
+fn chunk_aad(sender: Address, recipient: Recipient, seq_id: SequenceIdV1) -> Result<Vec<u8>> {
+    let mut aad = vec![];
+    sender.generate(&mut aad)?;
+    Some(recipient).generate(&mut aad)?;
+    Some(seq_id).generate(&mut aad)?;
+    Ok(aad)
+}
+
+/// Seal a data chunk with a shared secret and random nonce
 ///
-/// The provided chunk is encrypted in place and the selected nonce (which must
-/// be provided to decrypt) is returned
-pub fn encrypt_chunk<const L: usize>(shared_key: &SharedSecret, chunk: &mut [u8; L]) -> [u8; 12] {
-    encrypt_raw(shared_key.as_bytes(), chunk)
+/// The provided chunk is encrypted in place and the returned seal (which must
+/// be provided to open it again) contains the nonce and authentication tag.
+pub fn seal_chunk(
+    shared_key: &SharedSecret,
+    sender: Address,
+    recipient: Recipient,
+    seq_id: SequenceIdV1,
+    chunk: &mut [u8],
+) -> Result<ChunkSeal> {
+    let mut nonce = [0; 24];
+    thread_rng().fill_bytes(&mut nonce);
+
+    let aad = chunk_aad(sender, recipient, seq_id)?;
+    let cipher = XChaCha20Poly1305::new(&chunk_key(shared_key).into());
+    let tag = cipher
+        .encrypt_in_place_detached(XNonce::from_slice(&nonce), &aad, chunk)
+        .map_err(|_| {
+            RatmanError::Encoding(EncodingError::Encryption(
+                "failed to seal data chunk".into(),
+            ))
+        })?;
+
+    Ok(ChunkSeal::XChaCha20Poly1305 {
+        nonce,
+        tag: tag.into(),
+    })
+}
+
+/// Open a sealed data chunk with a shared secret
+///
+/// The chunk is decrypted in place.  If the chunk or any of its header fields
+/// were modified in transit this returns `BlockError::Unauthenticated` and the
+/// chunk contents must be discarded.
+pub fn open_chunk(
+    shared_key: &SharedSecret,
+    sender: Address,
+    recipient: Recipient,
+    seq_id: SequenceIdV1,
+    seal: ChunkSeal,
+    chunk: &mut [u8],
+) -> Result<()> {
+    let aad = chunk_aad(sender, recipient, seq_id)?;
+
+    match seal {
+        ChunkSeal::XChaCha20Poly1305 { nonce, tag } => {
+            let cipher = XChaCha20Poly1305::new(&chunk_key(shared_key).into());
+            cipher
+                .decrypt_in_place_detached(
+                    XNonce::from_slice(&nonce),
+                    &aad,
+                    chunk,
+                    Tag::from_slice(&tag),
+                )
+                .map_err(|_| {
+                    RatmanError::Block(BlockError::Unauthenticated {
+                        hash: seq_id.hash,
+                        num: seq_id.num,
+                    })
+                })
+        }
+    }
 }
 
 pub fn encrypt_raw(secret: &[u8; 32], data: &mut [u8]) -> [u8; 12] {
@@ -63,17 +153,6 @@ pub fn encrypt_raw(secret: &[u8; 32], data: &mut [u8]) -> [u8; 12] {
     nonce
 }
 
-/// Decrypt a data chunk with a shared secret and nonce
-pub fn decrypt_chunk(
-    shared_key: &SharedSecret,
-    nonce: [u8; 12],
-    mut encrypted_chunk: Vec<u8>,
-) -> Vec<u8> {
-    let mut cipher = ChaCha20::new(&(*shared_key.as_bytes()).into(), &nonce.into());
-    cipher.apply_keystream(&mut encrypted_chunk);
-    encrypted_chunk
-}
-
 pub fn decrypt_raw(secret: &[u8; 32], nonce: [u8; 12], encrypted_data: &mut Vec<u8>) {
     let mut cipher = ChaCha20::new(&(*secret).into(), &nonce.into());
     cipher.apply_keystream(encrypted_data.as_mut_slice());
@@ -111,6 +190,7 @@ pub fn list_addr_keys(meta_db: &Arc<MetadataDb>) -> Vec<Address> {
         .addrs
         .iter()
         .into_iter()
+        .filter(|(_, data)| matches!(data, AddressData::Local(_, _)))
         .map(|(addr, _)| Address::from_string(&addr))
         .collect()
 }
@@ -184,10 +264,18 @@ pub async fn get_addr_key(
     Ok(Keypair::new(secret_key))
 }
 
+/// Get the address key this router answers block fetch requests with
+pub fn fetch_keypair(meta_db: &MetadataDb) -> Keypair {
+    // Any 32 bytes are a valid ed25519 secret key
+    Keypair::new(SecretKey::from_bytes(meta_db.fetch_key().as_bytes()).unwrap())
+}
+
 //////// Namespace key commands
 
+/// Store a namespace key, which can only be managed by its `owner` address
 pub async fn create_namespace(
     meta_db: &Arc<MetadataDb>,
+    owner: Address,
     name: Option<CString>,
     pubkey: Address,
     privkey: Ident32,
@@ -199,7 +287,7 @@ pub async fn create_namespace(
 
     let mut encrypted_secret = *secret.as_bytes();
     let nonce = encrypt_raw(
-        meta_db.router_id().as_bytes().try_into().unwrap(),
+        meta_db.storage_key().as_bytes().try_into().unwrap(),
         &mut encrypted_secret,
     );
 
@@ -216,10 +304,112 @@ pub async fn create_namespace(
             ),
         )
         .await?;
+    meta_db
+        .space_owners
+        .insert(addr.to_string(), &owner)
+        .await?;
 
     Ok(())
 }
 
+/// Create a new namespace with a randomly generated key
+pub async fn generate_namespace(
+    meta_db: &Arc<MetadataDb>,
+    owner: Address,
+    name: Option<CString>,
+) -> Result<Address> {
+    let (pubkey, privkey) = libratman::generate_space_key();
+    create_namespace(meta_db, owner, name, pubkey, privkey).await?;
+    Ok(pubkey)
+}
+
+/// Make sure that an address is allowed to manage a namespace
+///
+/// Namespaces that were created before their owner was recorded are
+/// claimed by the first address that manages them.
+pub async fn check_namespace_owner(
+    meta_db: &Arc<MetadataDb>,
+    namespace: Address,
+    addr: Address,
+) -> Result<()> {
+    get_namespace_name(meta_db, namespace).await?;
+    match meta_db.space_owners.get(&namespace.to_string()).await? {
+        Some(owner) if owner == addr => Ok(()),
+        Some(_) => Err(ClientError::InvalidAuth.into()),
+        None => {
+            meta_db
+                .space_owners
+                .insert(namespace.to_string(), &addr)
+                .await
+        }
+    }
+}
+
+pub fn list_namespace_keys(meta_db: &Arc<MetadataDb>) -> Vec<Address> {
+    list_namespaces(meta_db)
+        .into_iter()
+        .map(|(addr, _)| addr)
+        .collect()
+}
+
+/// List all namespaces this router holds keys for, with their names
+pub fn list_namespaces(meta_db: &Arc<MetadataDb>) -> Vec<(Address, Option<CString>)> {
+    meta_db
+        .addrs
+        .iter()
+        .into_iter()
+        .filter_map(|(addr, data)| match data {
+            AddressData::Space(_, name) => Some((Address::from_string(&addr), name)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Get the name of a namespace, making sure that it actually is one
+async fn get_namespace_name(
+    meta_db: &Arc<MetadataDb>,
+    namespace: Address,
+) -> Result<Option<CString>> {
+    match meta_db.addrs.get(&namespace.to_string()).await? {
+        Some(AddressData::Space(_, name)) => Ok(name),
+        _ => Err(ClientError::NoAddress.into()),
+    }
+}
+
+/// Get the public and private key of a namespace for exporting it
+pub async fn export_namespace(
+    meta_db: &Arc<MetadataDb>,
+    namespace: Address,
+) -> Result<(Address, Ident32)> {
+    get_namespace_name(meta_db, namespace).await?;
+    let key = get_namespace_key(meta_db, namespace).await?;
+    Ok((
+        Address::from_bytes(key.inner.public.as_bytes()),
+        Ident32::from_bytes(key.inner.secret.as_bytes()),
+    ))
+}
+
+/// Replace a namespace key with a new one, keeping its name
+///
+/// Returns the new namespace address.  The old key is deleted.
+pub async fn rotate_namespace(
+    meta_db: &Arc<MetadataDb>,
+    owner: Address,
+    namespace: Address,
+) -> Result<Address> {
+    let name = get_namespace_name(meta_db, namespace).await?;
+    let new_namespace = generate_namespace(meta_db, owner, name).await?;
+    destroy_namespace(meta_db, namespace).await?;
+    Ok(new_namespace)
+}
+
+/// Delete a namespace key
+pub async fn destroy_namespace(meta_db: &Arc<MetadataDb>, namespace: Address) -> Result<()> {
+    get_namespace_name(meta_db, namespace).await?;
+    meta_db.space_owners.remove(namespace.to_string()).await?;
+    destroy_addr_key(meta_db, namespace).await
+}
+
 pub async fn get_namespace_key(meta_db: &Arc<MetadataDb>, namespace: Address) -> Result<Keypair> {
     let key_data =
         match meta_db
@@ -236,7 +426,7 @@ pub async fn get_namespace_key(meta_db: &Arc<MetadataDb>, namespace: Address) ->
 
     let mut decrypted_key = key_data.encrypted.clone();
     decrypt_raw(
-        meta_db.router_id().as_bytes().try_into().unwrap(),
+        meta_db.storage_key().as_bytes().try_into().unwrap(),
         key_data.nonce,
         &mut decrypted_key,
     );
@@ -265,3 +455,116 @@ pub fn verify_message(peer: Address, msg: &[u8], signature: Signature) -> Option
     let peer_pubkey = PublicKey::from_bytes(peer.as_bytes()).ok()?;
     peer_pubkey.verify(msg, &signature).ok()
 }
+
+#[test]
+fn sealed_chunk_roundtrip() {
+    let alice = Keypair::new(SecretKey::generate(&mut OsRng {}));
+    let bob = Keypair::new(SecretKey::generate(&mut OsRng {}));
+    let alice_addr = Address::from_bytes(alice.inner.public.as_bytes());
+    let bob_addr = Address::from_bytes(bob.inner.public.as_bytes());
+
+    let sender_key = diffie_hellman(&alice, bob_addr).unwrap();
+    let recipient_key = diffie_hellman(&bob, alice_addr).unwrap();
+
+    let recipient = Recipient::Address(bob_addr);
+    let seq_id = SequenceIdV1 {
+        hash: Ident32::random(),
+        num: 3,
+        max: 7,
+    };
+
+    let chunk = vec![42; 1024];
+    let mut sealed = chunk.clone();
+    let seal = seal_chunk(&sender_key, alice_addr, recipient, seq_id, &mut sealed).unwrap();
+    assert_ne!(sealed, chunk);
+
+    // A single flipped bit must be detected
+    let mut tampered = sealed.clone();
+    tampered[17] ^= 1;
+    assert!(matches!(
+        open_chunk(
+            &recipient_key,
+            alice_addr,
+            recipient,
+            seq_id,
+            seal,
+            &mut tampered
+        ),
+        Err(RatmanError::Block(BlockError::Unauthenticated {
+            num: 3,
+            ..
+        }))
+    ));
+
+    // Moving the chunk to a different sequence position must be detected
+    let mut moved = sealed.clone();
+    let moved_seq_id = SequenceIdV1 { num: 4, ..seq_id };
+    assert!(open_chunk(
+        &recipient_key,
+        alice_addr,
+        recipient,
+        moved_seq_id,
+        seal,
+        &mut moved
+    )
+    .is_err());
+
+    open_chunk(
+        &recipient_key,
+        alice_addr,
+        recipient,
+        seq_id,
+        seal,
+        &mut sealed,
+    )
+    .unwrap();
+    assert_eq!(sealed, chunk);
+}
+
+#[cfg(test)]
+use libratman::tokio;
+
+#[libratman::tokio::test]
+async fn only_owners_manage_namespaces() {
+    let db = fjall::Keyspace::open(fjall::Config::new(
+        tempdir::TempDir::new("crypto").unwrap().into_path(),
+    ))
+    .unwrap();
+    let meta_db = Arc::new(MetadataDb::new(db).unwrap());
+    let (owner, other) = (Address::random(), Address::random());
+
+    let namespace = generate_namespace(&meta_db, owner, None).await.unwrap();
+    assert!(check_namespace_owner(&meta_db, namespace, owner)
+        .await
+        .is_ok());
+    assert!(matches!(
+        check_namespace_owner(&meta_db, namespace, other).await,
+        Err(RatmanError::ClientApi(ClientError::InvalidAuth))
+    ));
+
+    // The owner is kept when the key is rotated
+    let rotated = rotate_namespace(&meta_db, owner, namespace).await.unwrap();
+    assert!(check_namespace_owner(&meta_db, rotated, owner)
+        .await
+        .is_ok());
+    assert!(check_namespace_owner(&meta_db, rotated, other)
+        .await
+        .is_err());
+    assert!(matches!(
+        check_namespace_owner(&meta_db, namespace, owner).await,
+        Err(RatmanError::ClientApi(ClientError::NoAddress))
+    ));
+
+    // Namespaces without a recorded owner are claimed on first use
+    meta_db
+        .space_owners
+        .remove(rotated.to_string())
+        .await
+        .unwrap();
+    assert!(check_namespace_owner(&meta_db, rotated, other)
+        .await
+        .is_ok());
+    assert!(check_namespace_owner(&meta_db, rotated, owner)
+        .await
+        .is_err());
+}
@@ -16,7 +16,8 @@
 //! - In-flight frames: these are individual packets that couldn't yet be
 //! delivired to their recipient, either originating on the local node or some
 //! remote.  Cached frames are explicitly not able to assemble into a full block
-//! and are deleted first in case of storage quota limitations.
+//! and are deleted first in case of storage quota limitations (see
+//! [`quota`](self::quota)).
 //!
 //! - ERIS blocks: these are encrypted content blocks for messages that are
 //! either still assembling or are being cached for a remote network
@@ -29,25 +30,34 @@
 //! - Known frame IDs: keep track of known frame IDs to avoid re-broadcasting
 //! the same messages infinitely.
 //!
+//! - Parked blocks: complete sealed blocks for local addresses that are
+//! offline, which can only be opened once their recipient comes online (see
+//! [`parked`](self::parked)).
+//!
 
 use self::{
     page::{CachePage, SerdeFrameType},
+    parked::{ParkedBlock, ParkedIndex},
+    quota::{page_usage, UsageMeter},
     types::{BlockData, FrameData, ManifestData},
 };
 use crate::storage::route::RouteData;
 
 use fjall::{compaction::SizeTiered, Keyspace, PartitionCreateOptions, PartitionHandle};
 use libratman::{
-    frame::{carrier::ManifestFrame, FrameParser},
-    tokio::task::spawn_blocking,
-};
-use libratman::{
+    api::types::JournalStatus,
     types::{Ident32, InMemoryEnvelope},
     Result,
 };
-use std::{marker::PhantomData, sync::Arc};
+use libratman::{
+    frame::{carrier::ManifestFrame, FrameParser},
+    tokio::task::spawn_blocking,
+};
+use std::sync::{Arc, Mutex};
 
 pub mod page;
+pub mod parked;
+pub mod quota;
 pub mod types;
 
 #[cfg(test)]
@@ -63,8 +73,8 @@ mod test;
 /// Warning: if a later read depends on the immediate availability of a previous
 /// insert it is highly recommended not to use the dispatch queue.
 pub struct Journal {
-    #[allow(unused)]
-    db: Keyspace,
+    /// The keyspace is only kept to be closed on shutdown
+    db: Mutex<Option<Keyspace>>,
     /// Single cached frames that haven't yet been delivired
     pub frames: CachePage<FrameData>,
     /// Fully cached blocks that may already have been delivered
@@ -73,16 +83,22 @@ pub struct Journal {
     pub manifests: CachePage<ManifestData>,
     /// A simple lookup set for known frame IDs
     pub seen_frames: CachePage<bool>,
+    /// Sealed blocks that are waiting for their recipient to come online
+    pub parked: CachePage<ParkedBlock>,
+    /// Recipient and size of each parked block
+    parked_index: Mutex<ParkedIndex>,
     /// Route metadata table
     pub routes: CachePage<RouteData>,
     // /// Message stream metadata table
     // pub links: CachePage<LinkData>,
+    /// Storage budget and use of frames, blocks, and manifests
+    meter: Arc<UsageMeter>,
 }
 
 fn setup_hot_partition(name: &str, db: &Keyspace) -> Result<PartitionHandle> {
     let part = db.open_partition(name, options())?;
     part.set_compaction_strategy(Arc::new(SizeTiered::default()));
-    part.set_max_memtable_size(512 * 1024 * 1024); // 512 MB of write cache
+    part.set_max_memtable_size(16 * 1024 * 1024); // 16 MB of write cache
     Ok(part)
 }
 
@@ -94,27 +110,49 @@ fn options() -> PartitionCreateOptions {
 }
 
 impl Journal {
-    pub fn new(db: Keyspace) -> Result<Self> {
-        let frames = CachePage(setup_hot_partition("frame_data", &db)?, PhantomData);
-        let blocks = CachePage(setup_hot_partition("block_data", &db)?, PhantomData);
-        let manifests = CachePage(
-            db.open_partition("blocks_manifests", options())?,
-            PhantomData,
-        );
-        let seen_frames = CachePage(db.open_partition("frames_seen", options())?, PhantomData);
-        let routes = CachePage(db.open_partition("meta_routes", options())?, PhantomData);
+    /// Open the journal with a storage quota in bytes
+    pub fn new(db: Keyspace, quota: u64) -> Result<Self> {
+        let frames = CachePage::new(setup_hot_partition("frame_data", &db)?);
+        let blocks = CachePage::new(setup_hot_partition("block_data", &db)?);
+        let manifests = CachePage::new(db.open_partition("blocks_manifests", options())?);
+        let seen_frames = CachePage::new(db.open_partition("frames_seen", options())?);
+        let parked = CachePage::new(db.open_partition("frames_parked", options())?);
+        let parked_index = Mutex::new(parked::build_index(&parked.0));
+        let routes = CachePage::new(db.open_partition("meta_routes", options())?);
+
+        // Seed the usage meter with what's already on disk
+        let usage = [&frames.0, &parked.0, &blocks.0, &manifests.0]
+            .iter()
+            .map(|page| page_usage(page))
+            .sum();
+        let meter = UsageMeter::new(quota, usage);
+        let frames = frames.metered(Arc::clone(&meter));
+        let parked = parked.metered(Arc::clone(&meter));
+        let blocks = blocks.metered(Arc::clone(&meter));
+        let manifests = manifests.metered(Arc::clone(&meter));
 
         Ok(Self {
-            db,
+            db: Mutex::new(Some(db)),
             frames,
             blocks,
             manifests,
             seen_frames,
+            parked,
+            parked_index,
             routes,
             // links,
+            meter,
         })
     }
 
+    /// Close the keyspace and wait for its flush and compaction threads
+    ///
+    /// This must happen before the storage directory is removed.  Anything
+    /// written to the journal afterwards is not persisted.
+    pub fn close(&self) {
+        drop(self.db.lock().unwrap().take());
+    }
+
     pub async fn is_unknown(&self, frame_id: &Ident32) -> Result<bool> {
         self.seen_frames
             .get(&frame_id.to_string())
@@ -131,6 +169,27 @@ impl Journal {
         Ok(spawn_blocking(move || this.blocks.0.len().map(|l| l as u64)).await??)
     }
 
+    /// Count the journal contents and their storage use
+    pub async fn status(self: &Arc<Self>) -> Result<JournalStatus> {
+        let this = Arc::clone(self);
+        let (frames, blocks, manifests) = spawn_blocking(move || -> Result<_> {
+            Ok((
+                this.frames.len()? as u64,
+                this.blocks.len()? as u64,
+                this.manifests.len()? as u64,
+            ))
+        })
+        .await??;
+
+        Ok(JournalStatus {
+            frames,
+            blocks,
+            manifests,
+            usage: self.usage(),
+            quota: self.meter.quota(),
+        })
+    }
+
     /// Store a frame in the journal
     ///
     /// Frame keys are composed of the block ID and the number in sequence
@@ -2,7 +2,10 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
 
-use crate::{journal::types::BlockData, storage::block::StorageBlock};
+use crate::{
+    journal::{quota::UsageMeter, types::BlockData},
+    storage::block::StorageBlock,
+};
 use async_eris::{Block, BlockReference, BlockStorage};
 use async_trait::async_trait;
 use fjall::{Keyspace, PartitionCreateOptions, PartitionHandle};
@@ -12,26 +15,71 @@ use libratman::{
     EncodingError, RatmanError, Result,
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use std::{io::Result as IoResult, marker::PhantomData};
+use std::{io::Result as IoResult, marker::PhantomData, sync::Arc};
 
 /// Represent a single logical page in the fjall database
 ///
 /// A cache page has an associated Rust type which it will serialize and
 /// deserialize for storage. Each page can be configured with a custom block
 /// size if that is desired.
-pub struct CachePage<T: Serialize + DeserializeOwned>(pub PartitionHandle, pub PhantomData<T>);
+///
+/// Pages that count towards the journal quota share a [`UsageMeter`], which
+/// is updated on every insert and removal.
+pub struct CachePage<T: Serialize + DeserializeOwned>(
+    pub PartitionHandle,
+    pub PhantomData<T>,
+    Option<Arc<UsageMeter>>,
+);
 
 impl<T: Serialize + DeserializeOwned> CachePage<T> {
+    pub fn new(part: PartitionHandle) -> Self {
+        Self(part, PhantomData, None)
+    }
+
+    /// Count the size of all entries on this page with a usage meter
+    pub fn metered(self, meter: Arc<UsageMeter>) -> Self {
+        Self(self.0, self.1, Some(meter))
+    }
+
     pub async fn insert(&self, key: String, value: &T) -> Result<()> {
         let bin = bincode::serialize(value)?;
         let handle = self.0.clone();
-        spawn_blocking(move || handle.insert(key, bin)).await??;
+        let meter = self.2.clone();
+        spawn_blocking(move || -> Result<()> {
+            let size = (key.len() + bin.len()) as u64;
+            match meter {
+                Some(meter) => {
+                    let prev = handle
+                        .get(&key)?
+                        .map(|prev| (key.len() + prev.len()) as u64);
+                    handle.insert(key, bin)?;
+                    meter.replace(prev.unwrap_or(0), size);
+                }
+                None => handle.insert(key, bin)?,
+            }
+            Ok(())
+        })
+        .await??;
         Ok(())
     }
 
     pub async fn remove(&self, key: String) -> Result<()> {
         let handle = self.0.clone();
-        spawn_blocking(move || handle.remove(key)).await??;
+        let meter = self.2.clone();
+        spawn_blocking(move || -> Result<()> {
+            match meter {
+                Some(meter) => {
+                    let prev = handle
+                        .get(&key)?
+                        .map(|prev| (key.len() + prev.len()) as u64);
+                    handle.remove(key)?;
+                    meter.replace(prev.unwrap_or(0), 0);
+                }
+                None => handle.remove(key)?,
+            }
+            Ok(())
+        })
+        .await??;
         Ok(())
     }
 
@@ -44,6 +92,13 @@ impl<T: Serialize + DeserializeOwned> CachePage<T> {
             .map(|bin_data| bincode::deserialize(&*bin_data).expect("failed to decode data")))
     }
 
+    /// Check whether a key exists without decoding its value
+    pub async fn contains(&self, key: &str) -> Result<bool> {
+        let handle = self.0.clone();
+        let key = key.to_owned();
+        Ok(spawn_blocking(move || handle.contains_key(key)).await??)
+    }
+
     /// Perform a prefix key search and filter out invalid entries
     pub fn prefix<'key>(
         &'key self,
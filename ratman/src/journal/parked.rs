@@ -0,0 +1,213 @@
+// SPDX-FileCopyrightText: 2024 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+//! Sealed blocks for offline addresses
+//!
+//! Sealed blocks can only be opened with the key of their (local) recipient,
+//! which is only available while the address is online.  Until then their
+//! chunks are kept on the `frames_parked` journal page, keyed by the time they
+//! were parked, their recipient, and their block ID.  This means they survive
+//! a router restart.
+//!
+//! Each address, and all addresses together, can only park a limited amount
+//! of data, so that remote peers can't exhaust the router's storage by sending
+//! sealed blocks to an address that never comes online.  When a limit is
+//! exceeded the oldest blocks are dropped first.
+
+use super::{page::SerdeFrameType, types::FrameData, Journal};
+use chrono::Utc;
+use libratman::{
+    frame::carrier::CarrierFrameHeader,
+    types::{Address, Ident32, InMemoryEnvelope},
+    Result,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, convert::TryFrom};
+
+/// How much data a single address can park (16 MB)
+pub const MAX_PARKED_PER_ADDRESS: u64 = 16 * 1024 * 1024;
+
+/// How much data all addresses together can park (128 MB)
+pub const MAX_PARKED: u64 = 128 * 1024 * 1024;
+
+/// The complete chunk sequence of a sealed block
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ParkedBlock {
+    pub chunks: Vec<FrameData>,
+}
+
+/// The recipient and storage size of every parked block, by key
+///
+/// Keys start with the time a block was parked, so iterating the index
+/// yields the oldest blocks first.
+pub(crate) type ParkedIndex = BTreeMap<String, (Address, u64)>;
+
+/// Parse the recipient from a parked block key
+fn key_recipient(key: &str) -> Option<Address> {
+    let id = Ident32::try_from(key.split("::").nth(1)?).ok()?;
+    Some(Address::from_bytes(id.as_bytes()))
+}
+
+/// Rebuild the index from the contents of the `frames_parked` page
+pub(crate) fn build_index(page: &fjall::PartitionHandle) -> ParkedIndex {
+    page.iter()
+        .filter_map(|item| item.ok())
+        .filter_map(|(key, value)| {
+            let size = (key.len() + value.len()) as u64;
+            let key = String::from_utf8(key.to_vec()).ok()?;
+            key_recipient(&key).map(|recipient| (key, (recipient, size)))
+        })
+        .collect()
+}
+
+/// Remove the oldest blocks from the index until both limits are met
+///
+/// Returns the keys of all removed blocks.
+pub(crate) fn evict_parked(
+    index: &mut ParkedIndex,
+    recipient: Address,
+    per_address: u64,
+    total: u64,
+) -> Vec<String> {
+    let mut evicted = vec![];
+
+    let mut usage: u64 = index
+        .values()
+        .filter(|(addr, _)| *addr == recipient)
+        .map(|(_, size)| size)
+        .sum();
+    while usage > per_address {
+        let (key, size) = match index.iter().find(|(_, (addr, _))| *addr == recipient) {
+            Some((key, (_, size))) => (key.clone(), *size),
+            None => break,
+        };
+        index.remove(&key);
+        usage -= size;
+        evicted.push(key);
+    }
+
+    let mut usage: u64 = index.values().map(|(_, size)| size).sum();
+    while usage > total {
+        let (key, (_, size)) = match index.pop_first() {
+            Some(entry) => entry,
+            None => break,
+        };
+        usage -= size;
+        evicted.push(key);
+    }
+
+    evicted
+}
+
+impl Journal {
+    /// Keep the chunks of a sealed block until its recipient comes online
+    pub async fn park_block(
+        &self,
+        recipient: Address,
+        chunks: Vec<InMemoryEnvelope>,
+    ) -> Result<()> {
+        let block_id = chunks
+            .first()
+            .and_then(|chunk| chunk.header.get_seq_id())
+            .map(|seq_id| seq_id.hash)
+            .unwrap_or_else(Ident32::random);
+        let key = format!(
+            "{:016x}::{recipient}::{block_id}",
+            Utc::now().timestamp_millis()
+        );
+        let block = ParkedBlock {
+            chunks: chunks
+                .into_iter()
+                .map(|InMemoryEnvelope { header, buffer }| FrameData {
+                    header: SerdeFrameType::from(header),
+                    payload: buffer,
+                })
+                .collect(),
+        };
+        let size = key.len() as u64 + bincode::serialized_size(&block)?;
+        self.parked.insert(key.clone(), &block).await?;
+
+        let evicted = {
+            let mut index = self.parked_index.lock().unwrap();
+            index.insert(key, (recipient, size));
+            evict_parked(&mut index, recipient, MAX_PARKED_PER_ADDRESS, MAX_PARKED)
+        };
+        if !evicted.is_empty() {
+            warn!(
+                "Too many sealed blocks are waiting for offline addresses: dropped {} of them",
+                evicted.len()
+            );
+        }
+        for key in evicted {
+            self.parked.remove(key).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Remove and return all parked blocks for an address, oldest first
+    pub async fn unpark_blocks(&self, recipient: Address) -> Result<Vec<Vec<InMemoryEnvelope>>> {
+        let keys: Vec<_> = {
+            let mut index = self.parked_index.lock().unwrap();
+            let keys: Vec<_> = index
+                .iter()
+                .filter(|(_, (addr, _))| *addr == recipient)
+                .map(|(key, _)| key.clone())
+                .collect();
+            keys.iter().for_each(|key| {
+                index.remove(key);
+            });
+            keys
+        };
+
+        let mut blocks = vec![];
+        for key in keys {
+            if let Some(ParkedBlock { chunks }) = self.parked.get(&key).await? {
+                match chunks
+                    .into_iter()
+                    .map(|FrameData { header, payload }| {
+                        header
+                            .maybe_inner()
+                            .map(|header: CarrierFrameHeader| InMemoryEnvelope {
+                                header,
+                                buffer: payload,
+                            })
+                    })
+                    .collect::<Result<Vec<_>>>()
+                {
+                    Ok(chunks) => blocks.push(chunks),
+                    Err(e) => warn!("failed to decode parked block {key}: {e}"),
+                }
+            }
+            self.parked.remove(key).await?;
+        }
+
+        Ok(blocks)
+    }
+}
+
+#[test]
+fn evict_oldest_parked_blocks() {
+    let (a, b) = (Address::random(), Address::random());
+    let mut index = ParkedIndex::new();
+    for (time, addr) in [a, b, a, a, b].iter().enumerate() {
+        index.insert(
+            format!("{time:016x}::{addr}::{}", Ident32::random()),
+            (*addr, 10),
+        );
+    }
+    assert_eq!(key_recipient(index.keys().next().unwrap()), Some(a));
+
+    // The oldest block of the address that went over its limit goes first
+    let evicted = evict_parked(&mut index, a, 20, 100);
+    assert_eq!(evicted.len(), 1);
+    assert!(evicted[0].starts_with(&format!("{:016x}", 0)));
+
+    // Then the oldest blocks of any address
+    let evicted = evict_parked(&mut index, a, 20, 20);
+    assert_eq!(evicted.len(), 2);
+    assert!(evicted[0].starts_with(&format!("{:016x}", 1)));
+    assert!(evicted[1].starts_with(&format!("{:016x}", 2)));
+    assert_eq!(index.len(), 2);
+}
@@ -0,0 +1,317 @@
+// SPDX-FileCopyrightText: 2024 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+//! Journal storage quotas
+//!
+//! The journal is given a storage budget, which is shared between in-flight
+//! frames, parked sealed blocks, ERIS blocks, and stream manifests.  When the
+//! budget is exhausted data is evicted in that order: first frames, then
+//! blocks that aren't part of a linked stream, and finally manifests.  Streams
+//! that have link data in the metadata database (which clients attach via the
+//! `STREAM MODIFY` API command) are never evicted.
+//!
+//! The size of each journal page is measured by the size of its keys and
+//! values, because deleted entries only free disk space once the storage
+//! backend gets around to compacting them.  The pages share a [`UsageMeter`],
+//! which is counted up on every insert and down on every removal, and which
+//! wakes the quota task as soon as an insert exceeds the budget.
+
+use super::Journal;
+use crate::{config::SubConfig, storage::MetadataDb};
+use async_eris::{Block, BlockKey, BlockReference, BlockStorage, ReadCapability};
+use fjall::PartitionHandle;
+use libratman::{
+    frame::carrier::ManifestFrame,
+    tokio::{select, spawn, sync::Notify, time::sleep},
+    types::Ident32,
+    Result,
+};
+use std::{
+    collections::{BTreeSet, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tripwire::Tripwire;
+
+/// Default journal storage budget (512 MB)
+pub const DEFAULT_QUOTA: u64 = 512 * 1024 * 1024;
+
+/// How long to let inserts settle after evicting data, before evicting again
+pub const QUOTA_SETTLE: Duration = Duration::from_secs(1);
+
+/// Read the `journal_quota_mb` setting from the `ratmand` configuration tree
+///
+/// A missing or invalid value uses the default quota.
+pub(crate) fn quota_from_config(ratmand: &SubConfig) -> u64 {
+    ratmand
+        .get_number_value("journal_quota_mb")
+        .filter(|mb| *mb > 0)
+        .map(|mb| mb as u64 * 1024 * 1024)
+        .unwrap_or(DEFAULT_QUOTA)
+}
+
+/// A running count of the bytes stored on the journal pages with a quota
+pub struct UsageMeter {
+    quota: u64,
+    usage: AtomicU64,
+    /// Notified whenever an insert exceeds the quota
+    exceeded: Notify,
+}
+
+impl UsageMeter {
+    pub fn new(quota: u64, usage: u64) -> Arc<Self> {
+        Arc::new(Self {
+            quota,
+            usage: AtomicU64::new(usage),
+            exceeded: Notify::new(),
+        })
+    }
+
+    pub fn quota(&self) -> u64 {
+        self.quota
+    }
+
+    pub fn usage(&self) -> u64 {
+        self.usage.load(Ordering::Relaxed)
+    }
+
+    /// Account for an entry of `prev` bytes that was replaced by `next` bytes
+    pub(crate) fn replace(&self, prev: u64, next: u64) {
+        let _ = self
+            .usage
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |usage| {
+                Some(usage.saturating_sub(prev).saturating_add(next))
+            });
+
+        if next > prev && self.usage() > self.quota {
+            self.exceeded.notify_one();
+        }
+    }
+}
+
+/// The number of entries removed from each journal page by an eviction run
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Eviction {
+    pub frames: usize,
+    pub blocks: usize,
+    pub manifests: usize,
+}
+
+impl Eviction {
+    pub fn is_empty(&self) -> bool {
+        self.frames == 0 && self.blocks == 0 && self.manifests == 0
+    }
+}
+
+/// Blocks and streams that must not be evicted
+#[derive(Default)]
+struct Protected {
+    /// Linked stream (manifest) IDs
+    streams: BTreeSet<String>,
+    /// Block keys of all linked streams
+    blocks: BTreeSet<String>,
+    /// The same blocks, but in the key format used for frames
+    frames: BTreeSet<String>,
+}
+
+/// Get the key of every entry on a journal page
+fn page_keys(page: &PartitionHandle) -> Vec<String> {
+    page.iter()
+        .filter_map(|item| item.ok())
+        .filter_map(|(key, _)| String::from_utf8(key.to_vec()).ok())
+        .collect()
+}
+
+/// Measure the size of all entries on a journal page
+pub(crate) fn page_usage(page: &PartitionHandle) -> u64 {
+    page.iter()
+        .filter_map(|item| item.ok())
+        .map(|(key, value)| (key.len() + value.len()) as u64)
+        .sum()
+}
+
+impl Journal {
+    /// How many bytes the journal currently uses
+    pub fn usage(&self) -> u64 {
+        self.meter.usage()
+    }
+
+    /// The remaining storage budget
+    pub fn remaining_buffer(&self) -> u64 {
+        self.meter.quota.saturating_sub(self.meter.usage())
+    }
+
+    /// Evict data from the journal until it fits into its storage budget
+    pub async fn enforce_quota(&self, meta_db: &MetadataDb) -> Result<Eviction> {
+        let quota = self.meter.quota;
+        let mut eviction = Eviction::default();
+        if self.usage() <= quota {
+            return Ok(eviction);
+        }
+
+        let protected = self.protected(meta_db).await;
+
+        // In-flight frames go first, then any block that isn't part of a
+        // linked stream, and finally the manifests themselves
+        for key in page_keys(&self.frames.0) {
+            if self.usage() <= quota {
+                break;
+            }
+
+            let block = key.split("::").next().unwrap_or_default();
+            if protected.frames.contains(block) {
+                continue;
+            }
+
+            self.frames.remove(key).await?;
+            eviction.frames += 1;
+        }
+
+        for key in page_keys(&self.parked.0) {
+            if self.usage() <= quota {
+                break;
+            }
+
+            self.parked_index.lock().unwrap().remove(&key);
+            self.parked.remove(key).await?;
+            eviction.frames += 1;
+        }
+
+        for key in page_keys(&self.blocks.0) {
+            if self.usage() <= quota {
+                break;
+            }
+            if protected.blocks.contains(&key) {
+                continue;
+            }
+
+            self.blocks.remove(key).await?;
+            eviction.blocks += 1;
+        }
+
+        for key in page_keys(&self.manifests.0) {
+            if self.usage() <= quota {
+                break;
+            }
+            if protected.streams.contains(&key) {
+                continue;
+            }
+
+            self.manifests.remove(key).await?;
+            eviction.manifests += 1;
+        }
+
+        let usage = self.usage();
+        if usage > quota {
+            warn!(
+                "Journal uses {:.1} MB after eviction, but its quota is {:.1} MB; remaining data belongs to linked streams",
+                usage as f64 / (1024.0 * 1024.0),
+                quota as f64 / (1024.0 * 1024.0),
+            );
+        }
+
+        Ok(eviction)
+    }
+
+    /// Evict data from the journal whenever an insert exceeds its quota
+    pub(crate) fn start_quota_task(self: &Arc<Self>, meta_db: Arc<MetadataDb>, tripwire: Tripwire) {
+        let this = Arc::clone(self);
+        spawn(async move {
+            loop {
+                match this.enforce_quota(&meta_db).await {
+                    Ok(eviction) if !eviction.is_empty() => info!(
+                        "Journal quota exceeded: evicted {} frames, {} blocks, and {} manifests",
+                        eviction.frames, eviction.blocks, eviction.manifests
+                    ),
+                    Ok(_) => {}
+                    Err(e) => error!("failed to enforce journal quota: {e}"),
+                }
+
+                select! {
+                    biased;
+                    _ = tripwire.clone() => break,
+                    _ = this.meter.exceeded.notified() => {}
+                }
+
+                // Don't re-scan the journal for every insert of a burst
+                select! {
+                    biased;
+                    _ = tripwire.clone() => break,
+                    _ = sleep(QUOTA_SETTLE) => {}
+                }
+            }
+        });
+    }
+
+    /// Collect all streams with link data and the blocks that belong to them
+    async fn protected(&self, meta_db: &MetadataDb) -> Protected {
+        let mut protected = Protected::default();
+        let mut blocks = BTreeSet::new();
+
+        for (stream_id, _) in meta_db.links.iter() {
+            let read_cap = match self.manifests.get(&stream_id).await {
+                Ok(Some(manifest)) => match manifest.manifest.maybe_inner() {
+                    Ok(ManifestFrame::V1(v1)) => Into::<Result<ReadCapability>>::into(v1).ok(),
+                    Err(_) => None,
+                },
+                _ => None,
+            };
+
+            match read_cap {
+                Some(rc) if rc.block_size == 1024 => {
+                    self.stream_blocks::<1024>(rc, &mut blocks).await
+                }
+                Some(rc) => self.stream_blocks::<32768>(rc, &mut blocks).await,
+                None => {}
+            }
+
+            protected.streams.insert(stream_id);
+        }
+
+        for block in blocks {
+            protected
+                .frames
+                .insert(Ident32::from_bytes(block.as_slice()).to_string());
+            protected.blocks.insert(block.to_string());
+        }
+
+        protected
+    }
+
+    /// Walk the block tree of a stream and collect all references that exist
+    async fn stream_blocks<const L: usize>(
+        &self,
+        read_cap: ReadCapability,
+        blocks: &mut BTreeSet<BlockReference>,
+    ) {
+        let mut subtrees = VecDeque::new();
+        subtrees.push_back(read_cap);
+
+        while let Some(tree) = subtrees.pop_front() {
+            let mut block: Block<L> = match self.blocks.fetch(&tree.root_reference).await {
+                Ok(Some(block)) => block,
+                _ => continue,
+            };
+            blocks.insert(tree.root_reference);
+
+            if tree.level == 0 {
+                continue;
+            }
+
+            block.chacha20(&tree.root_key);
+            for rk_pair_raw in block.chunks_exact(64) {
+                if rk_pair_raw.iter().any(|x| *x != 0) {
+                    let rk_pair = (
+                        BlockReference::from_bytes(&rk_pair_raw[..32]).unwrap(),
+                        BlockKey::from_bytes(&rk_pair_raw[32..]).unwrap(),
+                    );
+                    subtrees.push_back(ReadCapability::from_rk_pair(rk_pair, tree.level - 1, L));
+                }
+            }
+        }
+    }
+}
@@ -9,7 +9,7 @@ use libratman::{
 };
 use tempdir::TempDir;
 
-use super::{types::FrameData, Journal};
+use super::{quota::DEFAULT_QUOTA, types::FrameData, Journal};
 
 fn setup_db() -> Keyspace {
     Keyspace::open(Config::new(
@@ -26,7 +26,7 @@ use libratman::tokio;
 #[tokio::test]
 async fn insert_get_frames() {
     let db = setup_db();
-    let journal = Journal::new(db).unwrap();
+    let journal = Journal::new(db, DEFAULT_QUOTA).unwrap();
 
     let header = CarrierFrameHeader::V1(CarrierFrameHeaderV1::new(
         modes::DATA,
@@ -62,3 +62,210 @@ async fn insert_get_frames() {
     let recovered_event = journal.frames.get(&frame_id.to_string()).await.unwrap();
     assert_eq!(Some(frame_data), recovered_event);
 }
+
+/// Encode a random message stream into the journal and store its manifest
+async fn insert_stream(journal: &Journal) -> (Ident32, async_eris::ReadCapability) {
+    use super::{page::SerdeFrameType, types::ManifestData};
+    use libratman::{
+        frame::carrier::{ManifestFrame, ManifestFrameV1},
+        types::LetterheadV1,
+    };
+    use rand::{rngs::OsRng, RngCore};
+
+    let mut content = vec![0; 32 * 1024];
+    OsRng.fill_bytes(&mut content);
+    let read_cap = async_eris::encode(
+        &mut content.as_slice(),
+        &rand::random(),
+        async_eris::BlockSize::_1K,
+        &journal.blocks,
+    )
+    .await
+    .unwrap();
+
+    let letterhead = LetterheadV1 {
+        from: Address::random(),
+        to: Recipient::Address(Address::random()),
+        stream_size: content.len() as u64,
+        auxiliary_data: vec![],
+    };
+    let stream_id = Ident32::random();
+    journal
+        .manifests
+        .insert(
+            stream_id.to_string(),
+            &ManifestData {
+                sender: letterhead.from,
+                recipient: letterhead.to,
+                manifest: SerdeFrameType::from(ManifestFrame::V1(ManifestFrameV1::from((
+                    read_cap, letterhead,
+                )))),
+                forwarded: false,
+            },
+        )
+        .await
+        .unwrap();
+
+    (stream_id, read_cap)
+}
+
+#[tokio::test]
+async fn evict_unlinked_data() {
+    use crate::storage::{link::LinkData, MetadataDb};
+
+    let meta_db = MetadataDb::new(setup_db()).unwrap();
+    let journal = Journal::new(setup_db(), 1).unwrap();
+
+    let (linked_id, linked_cap) = insert_stream(&journal).await;
+    let (unlinked_id, _) = insert_stream(&journal).await;
+    meta_db
+        .links
+        .insert(linked_id.to_string(), &LinkData::default())
+        .await
+        .unwrap();
+
+    for num in 0..4 {
+        journal
+            .frames
+            .insert(
+                format!("{}::{num}", Ident32::random()),
+                &FrameData {
+                    header: CarrierFrameHeader::new_announce_frame(Address::random(), 0).into(),
+                    payload: vec![0; 512],
+                },
+            )
+            .await
+            .unwrap();
+    }
+
+    let blocks_before = journal.blocks.len().unwrap();
+    let eviction = journal.enforce_quota(&meta_db).await.unwrap();
+    assert_eq!(eviction.frames, 4);
+    assert_eq!(eviction.manifests, 1);
+    assert_eq!(
+        journal.blocks.len().unwrap(),
+        blocks_before - eviction.blocks
+    );
+    assert_eq!(journal.remaining_buffer(), 0);
+
+    // Only the linked stream remains, and it can still be decoded
+    assert!(journal
+        .manifests
+        .get(&unlinked_id.to_string())
+        .await
+        .unwrap()
+        .is_none());
+    assert!(journal
+        .manifests
+        .get(&linked_id.to_string())
+        .await
+        .unwrap()
+        .is_some());
+    let mut decoded = vec![];
+    async_eris::decode(&mut decoded, &linked_cap, &journal.blocks)
+        .await
+        .unwrap();
+    assert_eq!(decoded.len(), 32 * 1024);
+
+    // A journal within its quota isn't touched
+    let journal = Journal::new(setup_db(), DEFAULT_QUOTA).unwrap();
+    insert_stream(&journal).await;
+    assert!(journal.enforce_quota(&meta_db).await.unwrap().is_empty());
+    let usage = journal.usage();
+    assert!(usage > 32 * 1024);
+    assert_eq!(journal.remaining_buffer(), DEFAULT_QUOTA - usage);
+}
+
+#[tokio::test]
+async fn enforce_quota_on_insert() {
+    use super::quota::page_usage;
+    use crate::storage::MetadataDb;
+    use libratman::tokio::time::{sleep, timeout};
+    use std::{sync::Arc, time::Duration};
+    use tripwire::Tripwire;
+
+    let meta_db = Arc::new(MetadataDb::new(setup_db()).unwrap());
+    let journal = Arc::new(Journal::new(setup_db(), 4096).unwrap());
+    let frame = |len| FrameData {
+        header: CarrierFrameHeader::new_announce_frame(Address::random(), 0).into(),
+        payload: vec![0; len],
+    };
+
+    // The usage counter follows inserts, replacements, and removals
+    let (a, b) = (
+        format!("{}::0", Ident32::random()),
+        format!("{}::0", Ident32::random()),
+    );
+    journal.frames.insert(a.clone(), &frame(512)).await.unwrap();
+    journal.frames.insert(a, &frame(1024)).await.unwrap();
+    journal.frames.insert(b.clone(), &frame(512)).await.unwrap();
+    assert_eq!(journal.usage(), page_usage(&journal.frames.0));
+    journal.frames.remove(b).await.unwrap();
+    assert_eq!(journal.usage(), page_usage(&journal.frames.0));
+
+    // Going over the quota evicts data right away
+    let (tripwire, _worker, _tx) = Tripwire::new_simple();
+    journal.start_quota_task(meta_db, tripwire);
+    for num in 0..8 {
+        journal
+            .frames
+            .insert(format!("{}::{num}", Ident32::random()), &frame(1024))
+            .await
+            .unwrap();
+    }
+    timeout(Duration::from_secs(5), async {
+        while journal.usage() > 4096 {
+            sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(journal.usage(), page_usage(&journal.frames.0));
+}
+
+#[tokio::test]
+async fn parked_blocks_survive_restart() {
+    use libratman::types::InMemoryEnvelope;
+
+    let path = TempDir::new("journal")
+        .unwrap()
+        .into_path()
+        .join("test.jrnl");
+    let recipient = Address::random();
+    let chunk = |num| {
+        let header = CarrierFrameHeader::new_blockdata_frame(
+            Address::random(),
+            Recipient::Address(recipient),
+            SequenceIdV1 {
+                hash: Ident32::random(),
+                num,
+                max: 1,
+            },
+            4,
+        );
+        InMemoryEnvelope::from_header_and_payload(header, vec![num; 4]).unwrap()
+    };
+
+    {
+        let journal =
+            Journal::new(Keyspace::open(Config::new(&path)).unwrap(), DEFAULT_QUOTA).unwrap();
+        journal
+            .park_block(recipient, vec![chunk(0), chunk(1)])
+            .await
+            .unwrap();
+        journal
+            .park_block(Address::random(), vec![chunk(0)])
+            .await
+            .unwrap();
+    }
+
+    let journal = Journal::new(Keyspace::open(Config::new(&path)).unwrap(), DEFAULT_QUOTA).unwrap();
+    let blocks = journal.unpark_blocks(recipient).await.unwrap();
+    assert_eq!(blocks.len(), 1);
+    assert_eq!(blocks[0].len(), 2);
+    assert_eq!(blocks[0][1].get_payload_slice(), [1; 4]);
+
+    // Blocks are only handed out once
+    assert!(journal.unpark_blocks(recipient).await.unwrap().is_empty());
+    assert_eq!(journal.parked.len().unwrap(), 1);
+}
@@ -32,14 +32,14 @@ mod journal;
 mod links;
 mod procedures;
 mod protocol;
-mod routes;
 mod storage;
 
-// #[cfg(feature = "dashboard")]
-// mod web;
+#[cfg(feature = "dashboard")]
+mod web;
 
 pub mod config;
 pub mod context;
+pub mod routes;
 pub mod util;
 
 #[cfg(test)]
@@ -57,7 +57,21 @@ pub fn start_with_configuration(cfg: config::ConfigTree, state_path: PathBuf) {
     // what we want to do is listen to various signals here and
     // respond to them.
 
+    start_with_scorers(cfg, state_path, routes::ScorerRegistry::default())
+}
+
+/// Start a new Ratman router instance with additional route scorers
+///
+/// Scorers added to the registry can be selected by name via the
+/// `scorers` list in the `ratmand` configuration tree.
+pub fn start_with_scorers(
+    cfg: config::ConfigTree,
+    state_path: PathBuf,
+    scorers: routes::ScorerRegistry,
+) {
     eprintln!("Pass launch configuration to core async system...");
     let system = AsyncSystem::new("ratmand-core".to_owned(), 8);
-    system.exec(context::RatmanContext::start(cfg, state_path));
+    system.exec(context::RatmanContext::start_with_scorers(
+        cfg, state_path, scorers,
+    ));
 }
@@ -2,7 +2,10 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
 
-use libratman::{endpoint::EndpointExt, tokio::sync::RwLock};
+use libratman::{
+    endpoint::EndpointExt,
+    tokio::sync::{Notify, RwLock},
+};
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
     Arc,
@@ -16,17 +19,17 @@ pub(crate) type GenericEndpoint = dyn EndpointExt + 'static + Send + Sync;
 /// This way, when remove an interface, the ID's of other interfaces
 /// don't have have to be updated or mapped, because their place in the list doesn't change.
 enum EpWrap {
-    Used(String, Arc<GenericEndpoint>),
-    #[allow(unused)]
+    /// The notifier is used to stop the switch of a removed endpoint
+    Used(String, Arc<GenericEndpoint>, Arc<Notify>),
     Void,
 }
 type EpVec = Vec<EpWrap>;
 
 /// A map of available endpoint drivers
 ///
-/// Currently the removing of drivers isn't supported, but it's
-/// possible to have the same endpoint in the map multiple times, with
-/// unique IDs.
+/// It's possible to have the same endpoint in the map multiple times,
+/// with unique IDs.  Removed endpoints leave a gap in the map, and
+/// their IDs are never handed out again.
 #[derive(Default)]
 pub(crate) struct LinksMap {
     curr: AtomicUsize,
@@ -42,23 +45,42 @@ impl LinksMap {
     pub(crate) async fn add(&self, name: String, ep: Arc<GenericEndpoint>) -> usize {
         let mut map = self.map.write().await;
         let curr = self.curr.fetch_add(1, Ordering::SeqCst);
-        map.push(EpWrap::Used(name.clone(), ep));
+        map.push(EpWrap::Used(name.clone(), ep, Arc::new(Notify::new())));
         curr
     }
 
     /// Remove an endpoint from the list
-    #[allow(unused)]
-    pub(crate) async fn remove(&self, id: usize) {
+    ///
+    /// Anyone waiting via `removed()` is notified.  Returns `None` if
+    /// the ID is unknown or was already removed.
+    pub(crate) async fn remove(&self, id: usize) -> Option<(String, Arc<GenericEndpoint>)> {
         let mut map = self.map.write().await;
-        std::mem::swap(&mut map[id], &mut EpWrap::Void);
+        match std::mem::replace(map.get_mut(id)?, EpWrap::Void) {
+            EpWrap::Used(name, ep, notify) => {
+                notify.notify_one();
+                Some((name, ep))
+            }
+            EpWrap::Void => None,
+        }
+    }
+
+    /// Wait until the endpoint with the given ID is removed
+    pub(crate) async fn removed(&self, id: usize) {
+        let notify = match self.map.read().await.get(id) {
+            Some(EpWrap::Used(_, _, notify)) => Arc::clone(notify),
+            _ => return,
+        };
+        notify.notified().await
     }
 
     /// Get access to an endpoint via an Arc wrapper
-    pub(crate) async fn get(&self, id: usize) -> (String, Arc<GenericEndpoint>) {
+    ///
+    /// Returns `None` if the endpoint was removed in the meantime.
+    pub(crate) async fn get(&self, id: usize) -> Option<(String, Arc<GenericEndpoint>)> {
         let map = self.map.read().await;
-        match map[id] {
-            EpWrap::Used(ref name, ref ep) => (name.clone(), Arc::clone(ep)),
-            EpWrap::Void => panic!("Trying to use a removed endpoint!"),
+        match map.get(id)? {
+            EpWrap::Used(ref name, ref ep, _) => Some((name.clone(), Arc::clone(ep))),
+            EpWrap::Void => None,
         }
     }
 
@@ -67,7 +89,7 @@ impl LinksMap {
         let map = self.map.read().await;
         map.iter()
             .filter_map(|entry| match entry {
-                EpWrap::Used(ep_name, ep) if ep_name == name => Some(Arc::clone(&ep)),
+                EpWrap::Used(ep_name, ep, _) if ep_name == name => Some(Arc::clone(&ep)),
                 _ => None,
             })
             .next()
@@ -78,7 +100,7 @@ impl LinksMap {
         let map = self.map.read().await;
         map.iter()
             .filter_map(|ep| match ep {
-                EpWrap::Used(ref name, ref ep) => Some((name.clone(), Arc::clone(ep))),
+                EpWrap::Used(ref name, ref ep, _) => Some((name.clone(), Arc::clone(ep))),
                 _ => None,
             })
             .collect()
@@ -90,9 +112,41 @@ impl LinksMap {
         map.iter()
             .enumerate()
             .filter_map(|(i, ep)| match ep {
-                EpWrap::Used(ref name, ref ep) => Some((name.clone(), Arc::clone(ep), i)),
+                EpWrap::Used(ref name, ref ep, _) => Some((name.clone(), Arc::clone(ep), i)),
                 _ => None,
             })
             .collect()
     }
 }
+
+#[cfg(test)]
+use libratman::tokio;
+
+#[libratman::tokio::test]
+async fn remove_link() {
+    use libratman::{tokio::spawn, types::Ident32};
+    use netmod_inet::{InetEndpoint, PeerPolicy};
+
+    let links = LinksMap::new();
+    let inet = InetEndpoint::start("[::1]:0", Ident32::random(), PeerPolicy::default())
+        .await
+        .unwrap();
+    let id = links.add("inet".into(), inet).await;
+
+    let waiter = spawn({
+        let links = Arc::clone(&links);
+        async move { links.removed(id).await }
+    });
+    assert!(links.remove(id).await.is_some());
+    waiter.await.unwrap();
+
+    assert!(links.get(id).await.is_none());
+    assert!(links.remove(id).await.is_none());
+    assert!(links.get_all().await.is_empty());
+
+    // IDs of removed links are not handed out again
+    let inet = InetEndpoint::start("[::1]:0", Ident32::random(), PeerPolicy::default())
+        .await
+        .unwrap();
+    assert_eq!(links.add("inet".into(), inet).await, id + 1);
+}
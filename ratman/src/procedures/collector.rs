@@ -4,27 +4,33 @@
 
 use crate::{
     context::RatmanContext,
+    crypto,
     journal::{types::BlockData, Journal},
-    procedures::BlockNotifier,
+    procedures::{BlockNotifier, SubsManager},
+    protocol::Protocol,
+    routes::RouteTable,
     storage::{
         block::{IncompleteBlockData, StorageBlock},
         MetadataDb,
     },
 };
+use async_eris::BlockReference;
 use libratman::{
+    frame::carrier::schemes,
     tokio::{
         select,
         sync::{
-            broadcast::Sender as BcastSender,
+            broadcast::{Receiver as BcastReceiver, Sender as BcastSender},
             mpsc::{channel, Receiver, Sender},
             RwLock,
         },
         task::{self},
     },
-    types::{Ident32, InMemoryEnvelope, SequenceIdV1},
-    EncodingError, RatmanError, Result,
+    types::{Address, Ident32, InMemoryEnvelope, SequenceIdV1},
+    BlockError, ClientError, EncodingError, RatmanError, Result,
 };
 use std::{collections::BTreeMap, convert::TryFrom, sync::Arc};
+use x25519_dalek::SharedSecret;
 
 type EnvSender = Sender<(SequenceIdV1, InMemoryEnvelope)>;
 type EnvReceiver = Receiver<(SequenceIdV1, InMemoryEnvelope)>;
@@ -62,13 +68,131 @@ pub struct BlockCollectorWorker {
     senders: SenderStore,
     journal: Arc<Journal>,
     meta_db: Arc<MetadataDb>,
+    protocol: Arc<Protocol>,
+    routes: Arc<RouteTable>,
+    subs: Arc<SubsManager>,
+}
+
+/// The error to report to the recipient of a block that was rejected
+fn rejection(e: &RatmanError) -> Option<ClientError> {
+    match e {
+        RatmanError::Block(BlockError::Unauthenticated { hash, num }) => {
+            Some(ClientError::Unauthenticated {
+                hash: *hash,
+                num: *num,
+            })
+        }
+        RatmanError::Block(BlockError::Unsealed { hash, num }) => Some(ClientError::Unsealed {
+            hash: *hash,
+            num: *num,
+        }),
+        RatmanError::Block(BlockError::NoChunkKey(addr)) => Some(ClientError::NoChunkKey(*addr)),
+        _ => None,
+    }
 }
 
 impl BlockCollectorWorker {
+    /// Find the local recipient of a sealed block, if it is offline
+    ///
+    /// Its key is needed to open the block, so the block has to wait
+    /// until the address comes online.
+    async fn offline_recipient(&self, chunks: &[InMemoryEnvelope]) -> Option<Address> {
+        let addr = chunks
+            .iter()
+            .find(|chunk| chunk.header.get_chunk_seal().is_some())?
+            .header
+            .get_recipient()?
+            .inner_address();
+
+        match self.routes.is_local(addr).await {
+            Ok(true) if self.protocol.get_online_auth(addr).await.is_none() => Some(addr),
+            _ => None,
+        }
+    }
+
+    /// Concatenate the payloads of a complete chunk sequence
+    ///
+    /// Sealed chunks are opened with the shared secret between the sender and
+    /// the (local) recipient address, which must be online for its key to be
+    /// available.  Unsealed chunks are only accepted from senders that don't
+    /// advertise a sealing scheme, so that relays can't strip the seal.  Any
+    /// chunk that fails authentication invalidates the whole block.
+    async fn open_chunks(&self, chunks: Vec<InMemoryEnvelope>) -> Result<Vec<u8>> {
+        let mut block = vec![];
+        let mut shared_key: Option<SharedSecret> = None;
+
+        for mut chunk in chunks {
+            let sender = chunk.header.get_sender();
+            let seq_id = chunk.header.get_seq_id().ok_or_else(|| {
+                EncodingError::Parsing("Mandatory field 'sequence_id' was missing!".to_string())
+            })?;
+
+            let seal = match chunk.header.get_chunk_seal() {
+                None if self.routes.get_seal_scheme(sender).await != schemes::NONE => {
+                    return Err(BlockError::Unsealed {
+                        hash: seq_id.hash,
+                        num: seq_id.num,
+                    }
+                    .into());
+                }
+                // todo: can we avoid copying here?
+                None => {
+                    block.extend_from_slice(chunk.get_payload_slice());
+                    continue;
+                }
+                Some(seal) => seal?,
+            };
+
+            let recipient = chunk.header.get_recipient().ok_or_else(|| {
+                EncodingError::Parsing("Mandatory field 'recipient' was missing!".to_string())
+            })?;
+
+            if shared_key.is_none() {
+                let local_addr = recipient.inner_address();
+                let auth = self
+                    .protocol
+                    .get_online_auth(local_addr)
+                    .await
+                    .ok_or(BlockError::NoChunkKey(local_addr))?;
+                let local_key = crypto::get_addr_key(&self.meta_db, local_addr, auth).await?;
+                shared_key = Some(crypto::diffie_hellman(&local_key, sender).ok_or(
+                    EncodingError::Encryption("failed to compute diffie-hellman".into()),
+                )?);
+            }
+
+            crypto::open_chunk(
+                shared_key.as_ref().unwrap(),
+                sender,
+                recipient,
+                seq_id,
+                seal,
+                chunk.mut_payload_slice(),
+            )?;
+            block.extend_from_slice(chunk.get_payload_slice());
+        }
+
+        Ok(block)
+    }
+
     /// Spawn this!
     pub async fn run(mut self, mut recv: EnvReceiver, block_bcast: BcastSender<BlockNotifier>) {
         let this = &mut self;
         while let Some((seq_id, envelope)) = recv.recv().await {
+            // Retransmitted blocks may deliver chunks that we already have
+            if this.buffer.iter().any(|env| {
+                env.header
+                    .get_seq_id()
+                    .map(|seq| seq.num == seq_id.num)
+                    .unwrap_or(false)
+            }) {
+                trace!(
+                    "Ignore duplicate chunk {} in sequence {}",
+                    seq_id.num,
+                    seq_id.hash
+                );
+                continue;
+            }
+
             let insert_at_end = seq_id.num as usize >= this.buffer.len();
             trace!(
                 "Insert chunk {} in sequence {} to index {}/{}",
@@ -100,20 +224,35 @@ impl BlockCollectorWorker {
                 this.senders.write().await.remove(&seq_id.hash);
 
                 // Re-assemble the block
-                let mut block = vec![];
-                core::mem::replace(&mut this.buffer, Default::default())
-                    .into_iter()
-                    // todo: can we avoid copying here?
-                    .for_each(|chunk| {
-                        let pl = chunk.get_payload_slice();
-                        block.extend_from_slice(pl);
-                    });
-
-                trace!("Reconstructing {} byte-sized block", block.len());
+                let mut chunks = core::mem::replace(&mut this.buffer, Default::default());
+                chunks.sort_by_key(|env| env.header.get_seq_id().map(|seq| seq.num));
+                let recipient = chunks
+                    .first()
+                    .and_then(|chunk| chunk.header.get_recipient());
+                let block = match this.offline_recipient(&chunks).await {
+                    Some(addr) => {
+                        debug!(
+                            "Keep sealed block {} until {} comes online",
+                            seq_id.hash,
+                            addr.pretty_string()
+                        );
+                        if let Err(e) = this.journal.park_block(addr, chunks).await {
+                            error!("failed to keep sealed block {}: {e}", seq_id.hash);
+                        }
+                        None
+                    }
+                    None => Some(this.open_chunks(chunks).await),
+                };
 
                 // Then offer the finished block up to the block god
-                match StorageBlock::reconstruct_from_vec(block) {
-                    Ok(block) => {
+                match block.map(|block| {
+                    block.and_then(|block| {
+                        trace!("Reconstructing {} byte-sized block", block.len());
+                        StorageBlock::reconstruct_from_vec(block)
+                    })
+                }) {
+                    None => {}
+                    Some(Ok(block)) => {
                         let journal = Arc::clone(&self.journal);
                         journal
                             .blocks
@@ -127,7 +266,14 @@ impl BlockCollectorWorker {
                             .await
                             .expect("failed to insert block into journal!")
                     }
-                    Err(e) => warn!("failed to reconstruct block: {e:?}"),
+                    Some(Err(e)) => {
+                        warn!("failed to reconstruct block {}: {e}", seq_id.hash);
+
+                        // Let the receiving client know why its stream won't arrive
+                        if let (Some(recipient), Some(error)) = (recipient, rejection(&e)) {
+                            this.subs.stream_failed(recipient, error);
+                        }
+                    }
                 }
 
                 // Notify all current stream re-assemblers
@@ -161,6 +307,10 @@ pub struct BlockCollector {
     inner: SenderStore,
     journal: Arc<Journal>,
     meta_db: Arc<MetadataDb>,
+    protocol: Arc<Protocol>,
+    routes: Arc<RouteTable>,
+    subs: Arc<SubsManager>,
+    block_bcast: BcastSender<BlockNotifier>,
 }
 
 impl BlockCollector {
@@ -171,12 +321,19 @@ impl BlockCollector {
     pub async fn restore(
         journal: Arc<Journal>,
         meta_db: Arc<MetadataDb>,
+        protocol: Arc<Protocol>,
+        routes: Arc<RouteTable>,
+        subs: Arc<SubsManager>,
         block_bcast: BcastSender<BlockNotifier>,
     ) -> Result<Arc<Self>> {
         let this = Arc::new(Self {
             inner: Default::default(),
             journal,
             meta_db,
+            protocol,
+            routes,
+            subs,
+            block_bcast: block_bcast.clone(),
         });
 
         // Restore existing workers for blocks that were still being assembled
@@ -211,6 +368,45 @@ impl BlockCollector {
         Ok(this)
     }
 
+    /// Collect the sealed blocks that were kept for an address again
+    ///
+    /// This is called when an address comes online, so that its key is
+    /// available to open them.
+    pub async fn unpark(self: &Arc<Self>, addr: Address) {
+        let blocks = match self.journal.unpark_blocks(addr).await {
+            Ok(blocks) => blocks,
+            Err(e) => {
+                error!(
+                    "failed to load sealed blocks for {}: {e}",
+                    addr.pretty_string()
+                );
+                return;
+            }
+        };
+        for chunk in blocks.into_iter().flatten() {
+            if let Err(e) = self.queue_and_spawn(chunk, self.block_bcast.clone()).await {
+                warn!("failed to collect sealed block: {e}");
+            }
+        }
+    }
+
+    /// Get notified about every block that finished collection
+    pub(crate) fn subscribe_blocks(&self) -> BcastReceiver<BlockNotifier> {
+        self.block_bcast.subscribe()
+    }
+
+    async fn has_block(&self, block_id: Ident32) -> bool {
+        match BlockReference::from_bytes(block_id.as_bytes()) {
+            Ok(reference) => self
+                .journal
+                .blocks
+                .contains(&reference.to_string())
+                .await
+                .unwrap_or(false),
+            Err(_) => false,
+        }
+    }
+
     /// Queue a new frame and spawn a collection worker if none exists yet
     pub async fn queue_and_spawn(
         self: &Arc<Self>,
@@ -225,6 +421,16 @@ impl BlockCollector {
                 )))?;
         let max_num = sequence_id.max;
 
+        // Blocks can be sent more than once when they are requested again, or
+        // fetched from several neighbours.  Late chunks of a block we already
+        // have don't need a new worker
+        if !self.inner.read().await.contains_key(&sequence_id.hash)
+            && self.has_block(sequence_id.hash).await
+        {
+            trace!("Ignore chunk of known block {}", sequence_id.hash);
+            return Ok(());
+        }
+
         if let Ok(Some(mut block_meta)) = self
             .meta_db
             .incomplete
@@ -282,6 +488,9 @@ impl BlockCollector {
                         buffer: vec![],
                         journal: Arc::clone(&self.journal),
                         meta_db: Arc::clone(&self.meta_db),
+                        protocol: Arc::clone(&self.protocol),
+                        routes: Arc::clone(&self.routes),
+                        subs: Arc::clone(&self.subs),
                     }
                     .run(rx, block_bcast.clone()),
                 );
@@ -296,3 +505,128 @@ impl BlockCollector {
         Ok(())
     }
 }
+
+#[cfg(test)]
+use libratman::tokio;
+
+#[cfg(test)]
+fn test_worker() -> (BlockCollectorWorker, Arc<RouteTable>) {
+    use crate::journal::quota::DEFAULT_QUOTA;
+
+    let setup_db = || {
+        fjall::Keyspace::open(fjall::Config::new(
+            tempdir::TempDir::new("collector").unwrap().into_path(),
+        ))
+        .unwrap()
+    };
+    let meta_db = Arc::new(MetadataDb::new(setup_db()).unwrap());
+    let routes = RouteTable::new(
+        Arc::clone(&meta_db),
+        vec![],
+        Default::default(),
+        Default::default(),
+        Default::default(),
+    );
+    let worker = BlockCollectorWorker {
+        max_num: 0,
+        buffer: vec![],
+        senders: Default::default(),
+        journal: Arc::new(Journal::new(setup_db(), DEFAULT_QUOTA).unwrap()),
+        meta_db: Arc::clone(&meta_db),
+        protocol: Protocol::new(),
+        routes: Arc::clone(&routes),
+        subs: SubsManager::new(&meta_db),
+    };
+
+    (worker, routes)
+}
+
+#[libratman::tokio::test]
+async fn reject_stripped_seal() {
+    use libratman::{frame::carrier::CarrierFrameHeader, types::Recipient};
+
+    let (worker, routes) = test_worker();
+
+    let sender = Address::random();
+    let chunk = || {
+        let seq_id = SequenceIdV1 {
+            hash: Ident32::random(),
+            num: 0,
+            max: 0,
+        };
+        let header = CarrierFrameHeader::new_blockdata_frame(
+            sender,
+            Recipient::Address(Address::random()),
+            seq_id,
+            4,
+        );
+        InMemoryEnvelope::from_header_and_payload(header, vec![1, 2, 3, 4]).unwrap()
+    };
+
+    // Senders which don't seal chunks
+    assert_eq!(
+        worker.open_chunks(vec![chunk()]).await.unwrap(),
+        [1, 2, 3, 4]
+    );
+
+    // A relay stripped the seal of a sender which seals its chunks
+    routes.set_seal_scheme(sender, schemes::LATEST).await;
+    assert!(matches!(
+        worker.open_chunks(vec![chunk()]).await,
+        Err(RatmanError::Block(BlockError::Unsealed { num: 0, .. }))
+    ));
+}
+
+#[libratman::tokio::test]
+async fn keep_sealed_blocks_for_offline_recipients() {
+    use libratman::{
+        frame::carrier::{CarrierFrameHeader, ChunkSeal},
+        types::Recipient,
+    };
+
+    let (worker, routes) = test_worker();
+    let chunk = |recipient: Address, seal: Option<ChunkSeal>| {
+        let seq_id = SequenceIdV1 {
+            hash: Ident32::random(),
+            num: 0,
+            max: 0,
+        };
+        let recipient = Recipient::Address(recipient);
+        let header = match seal {
+            Some(seal) => CarrierFrameHeader::new_sealed_blockdata_frame(
+                Address::random(),
+                recipient,
+                seq_id,
+                seal,
+                4,
+            )
+            .unwrap(),
+            None => {
+                CarrierFrameHeader::new_blockdata_frame(Address::random(), recipient, seq_id, 4)
+            }
+        };
+        InMemoryEnvelope::from_header_and_payload(header, vec![1, 2, 3, 4]).unwrap()
+    };
+    let seal = || ChunkSeal::XChaCha20Poly1305 {
+        nonce: [1; 24],
+        tag: [2; 16],
+    };
+
+    let local = Address::random();
+    routes.register_local_route(local).await.unwrap();
+
+    // Only sealed blocks for local addresses need their key
+    assert_eq!(
+        worker
+            .offline_recipient(&[chunk(local, Some(seal()))])
+            .await,
+        Some(local)
+    );
+    assert_eq!(worker.offline_recipient(&[chunk(local, None)]).await, None);
+    assert_eq!(
+        worker
+            .offline_recipient(&[chunk(Address::random(), Some(seal()))])
+            .await,
+        None
+    );
+}
@@ -5,7 +5,7 @@
 use crate::context::RatmanContext;
 use async_eris::ReadCapability;
 use libratman::{
-    api::socket_v2::RawSocketHandle,
+    api::{socket_v2::RawSocketHandle, types::ServerPing},
     frame::{
         carrier::{ManifestFrame, ManifestFrameV1},
         micro::MicroframeHeader,
@@ -14,18 +14,24 @@ use libratman::{
         fs::OpenOptions,
         select,
         sync::{
-            broadcast::{Receiver as BcastReceiver, Sender as BcastSender},
+            broadcast::{error::RecvError, Receiver as BcastReceiver, Sender as BcastSender},
             mpsc::Receiver,
         },
         task::spawn,
+        time::{sleep, sleep_until, Instant},
     },
     tokio_util::compat::TokioAsyncReadCompatExt,
-    types::{AddrAuth, Ident32, LetterheadV1},
-    NonfatalError, RatmanError, Result,
+    types::{AddrAuth, Address, Ident32, LetterheadV1, Recipient},
+    ClientError, NonfatalError, RatmanError, Result,
 };
 use std::sync::Arc;
 use tripwire::Tripwire;
 
+use super::{
+    retransmit::{block_census, request_blocks},
+    StreamFailure,
+};
+
 /// Notify the ingress system of a new manifest in the journal
 pub(crate) struct MessageNotifier(pub Ident32);
 
@@ -63,7 +69,7 @@ pub async fn exec_ingress_system(
                 let ctx = Arc::clone(&ctx);
                 let tripwire = ctx.tripwire.clone();
                 spawn(async move {
-                    if let Err(e) = reassemble_message_stream(ctx, manifest_notifier.unwrap(), tripwire, block_notifier_tx.subscribe()).await {
+                    if let Err(e) = reassemble_message_stream(ctx, manifest_notifier.unwrap(), tripwire, block_notifier_tx).await {
                         error!("message stream stuck: {e}");
                         return;
                     }
@@ -82,8 +88,10 @@ async fn reassemble_message_stream(
     ctx: Arc<RatmanContext>,
     manifest: MessageNotifier,
     tripwire: Tripwire,
-    mut block_notify: BcastReceiver<BlockNotifier>,
+    block_notify_tx: BcastSender<BlockNotifier>,
 ) -> Result<()> {
+    let mut block_notify = block_notify_tx.subscribe();
+    let stream_id = manifest.0;
     let manifest = ctx
         .journal
         .manifests
@@ -101,6 +109,15 @@ async fn reassemble_message_stream(
         ),
     };
 
+    // Missing blocks are requested from the sender with a backoff for as long
+    // as the stream keeps making progress
+    let policy = ctx.retransmit;
+    let mut backoff = policy.backoff();
+    let mut last_progress = Instant::now();
+    let mut next_request = last_progress + backoff.next();
+    let mut known_blocks = 0;
+    let mut attempt: u8 = 0;
+
     loop {
         let null_file = OpenOptions::new()
             .create(false)
@@ -117,10 +134,63 @@ async fn reassemble_message_stream(
                 let tw = tripwire.clone();
                 debug!("Couldn't re-assemble stream ({e}); wait for block notifier");
                 drop(compat_null);
+
+                // Sealed blocks for local addresses that are offline are kept
+                // by the collector until the address comes online, so there is
+                // nothing to request or give up on until then
+                if recipient_offline(&ctx, letterhead.to).await {
+                    last_progress = Instant::now();
+                    backoff.reset();
+                    next_request = last_progress + backoff.next();
+
+                    select! {
+                        biased;
+                        _ = tw => return Ok(()),
+                        _ = block_notify.recv() => continue,
+                        _ = sleep(policy.initial_delay) => continue,
+                    }
+                }
+
+                let census = block_census(&ctx.journal, &read_cap).await?;
+                if census.present > known_blocks {
+                    known_blocks = census.present;
+                    last_progress = Instant::now();
+                    backoff.reset();
+                    next_request = last_progress + backoff.next();
+                }
+
+                let deadline = last_progress + policy.stream_timeout;
+                if Instant::now() >= deadline {
+                    ctx.subs
+                        .stream_failed(letterhead.to, ClientError::StreamTimeout(stream_id));
+                    return Err(ClientError::StreamTimeout(stream_id).into());
+                }
+
                 select! {
                     biased;
                     _ = tw => return Ok(()),
-                    _ = block_notify.recv() => continue
+                    _ = block_notify.recv() => continue,
+                    _ = sleep_until(next_request.min(deadline)) => {
+                        if Instant::now() < next_request {
+                            continue;
+                        }
+
+                        attempt = attempt.wrapping_add(1);
+                        if let Err(e) = request_blocks(
+                            &ctx,
+                            stream_id,
+                            &letterhead,
+                            &read_cap,
+                            &census.missing,
+                            attempt,
+                            block_notify_tx.clone(),
+                        )
+                        .await
+                        {
+                            warn!("failed to request missing blocks for {stream_id}: {e}");
+                        }
+                        next_request = Instant::now() + backoff.next();
+                    }
                 }
             }
         }
@@ -128,47 +198,116 @@ async fn reassemble_message_stream(
 
     info!("Stream from {} passed re-assembly check!", letterhead.from);
 
-    match ctx
+    if letterhead.wants_receipt() {
+        if let Err(e) =
+            super::send_receipt(&ctx, stream_id, &letterhead, block_notify_tx.clone()).await
+        {
+            warn!("failed to send delivery receipt for {stream_id}: {e}");
+        }
+    }
+
+    // Notify the subscription for this recipient, or otherwise an active
+    // sync listener, if they exist
+    let sub_id = ctx
         .subs
         .recipients
         .lock()
         .await
         .get(&manifest.recipient)
-        .ok_or(RatmanError::Nonfatal(NonfatalError::NoStream))
-    {
-        Ok(sub_id) => {
-            // Notify all listening subscription streams
-            if let Ok(bcast_tx) = ctx.get_active_listener(Some(*sub_id), letterhead.to).await {
-                debug!("Notify subscription {sub_id}");
-                bcast_tx.send((letterhead.clone(), read_cap)).unwrap();
-            }
-
-            Ok(())
+        .copied();
+    let delivered = match ctx.get_active_listener(sub_id, letterhead.to).await {
+        Ok(bcast_tx) => {
+            debug!("Notify receivers of stream {stream_id}");
+            bcast_tx.send((letterhead.clone(), read_cap)).is_ok()
         }
-        Err(e) => {
-            // Then notify all active sync listeners, if they exist
-            if let Ok(bcast_tx) = ctx.get_active_listener(None, letterhead.to).await {
-                debug!("Notify sync message receiver");
-                bcast_tx.send((letterhead, read_cap)).unwrap();
-                Ok(())
-            } else {
-                warn!(
-                    "Stream from {} is stuck: no active receivers found",
-                    letterhead.from
-                );
-                Err(e)
-            }
+        Err(_) => false,
+    };
+
+    if !delivered {
+        info!(
+            "No active receivers for stream {stream_id}: keeping it until {:?} is listening",
+            letterhead.to
+        );
+        let to = letterhead.to;
+        ctx.meta_db
+            .available_streams
+            .insert(stream_id.to_string(), &letterhead)
+            .await?;
+
+        // A sync listener may have shown up in the meantime
+        if let Ok(bcast_tx) = ctx.get_active_listener(None, to).await {
+            replay_pending_stream(&ctx, to, &bcast_tx).await?;
         }
     }
+
+    Ok(())
+}
+
+/// Check whether a stream is addressed to a local address that is offline
+async fn recipient_offline(ctx: &RatmanContext, to: Recipient) -> bool {
+    let addr = to.inner_address();
+    matches!(ctx.routes.is_local(addr).await, Ok(true))
+        && ctx.protocol.get_online_auth(addr).await.is_none()
 }
 
+/// Hand a stream that arrived while nobody was listening to a new receiver
+///
+/// At most one stream is handed over per call.  Returns whether a stream was
+/// waiting for the recipient.
+pub(crate) async fn replay_pending_stream(
+    ctx: &Arc<RatmanContext>,
+    to: Recipient,
+    bcast_tx: &BcastSender<(LetterheadV1, ReadCapability)>,
+) -> Result<bool> {
+    let (stream_id, letterhead) = match ctx
+        .meta_db
+        .available_streams
+        .iter()
+        .into_iter()
+        .find(|(_, letterhead)| letterhead.to == to)
+    {
+        Some(pending) => pending,
+        None => return Ok(false),
+    };
+    ctx.meta_db
+        .available_streams
+        .remove(stream_id.clone())
+        .await?;
+
+    let manifest = ctx
+        .journal
+        .manifests
+        .get(&stream_id)
+        .await?
+        .ok_or(RatmanError::Nonfatal(NonfatalError::NoStream))?;
+    let read_cap = match manifest.manifest.maybe_inner()? {
+        ManifestFrame::V1(v1) => <ManifestFrameV1 as Into<Result<ReadCapability>>>::into(v1)?,
+    };
+
+    debug!("Hand over stream {stream_id} that was kept for {to:?}");
+    bcast_tx
+        .send((letterhead, read_cap))
+        .map_err(|_| RatmanError::Nonfatal(NonfatalError::NoStream))?;
+    Ok(true)
+}
+
+/// Relay incoming streams and delivery receipts to a subscription socket
+///
+/// Receipts are only relayed for streams that were sent by `addr`.  Incoming
+/// streams for this subscription that had to be abandoned, and blocks that
+/// failed authentication, are reported as a `ClientError`.
 pub async fn handle_subscription_socket(
     ctx: Arc<RatmanContext>,
     mut rx: BcastReceiver<(LetterheadV1, ReadCapability)>,
     mut client_socket: RawSocketHandle,
+    addr: Address,
     auth: AddrAuth,
     sub_id: Ident32,
 ) {
+    use libratman::frame::micro::client_modes as cm;
+    let mut receipts_rx = ctx.receipts.subscribe();
+    let mut failures_rx = ctx.subs.failures();
+
     loop {
         let tw = ctx.tripwire.clone();
 
@@ -176,13 +315,57 @@ pub async fn handle_subscription_socket(
             biased;
             _ = tw => break,
             item = rx.recv() => item,
+            receipt = receipts_rx.recv() => {
+                match receipt {
+                    Ok(receipt) if receipt.to == addr => {
+                        if let Err(e) = client_socket
+                            .write_microframe(
+                                MicroframeHeader {
+                                    modes: cm::make(cm::SUB, cm::RECEIPT),
+                                    auth: Some(auth),
+                                    payload_size: 0,
+                                },
+                                receipt,
+                            )
+                            .await
+                        {
+                            error!("failed to send delivery receipt: {e}");
+                            break;
+                        }
+                    }
+                    Err(RecvError::Lagged(n)) => warn!("Subscription {sub_id} missed {n} receipts"),
+                    _ => {}
+                }
+                continue;
+            }
+            failure = failures_rx.recv() => {
+                match failure {
+                    Ok(StreamFailure { recipient, error })
+                        if ctx.subs.recipients.lock().await.get(&recipient) == Some(&sub_id) =>
+                    {
+                        if let Err(e) = client_socket
+                            .write_microframe(
+                                MicroframeHeader::intrinsic_auth(auth),
+                                ServerPing::Error(error),
+                            )
+                            .await
+                        {
+                            error!("failed to report stream failure: {e}");
+                            break;
+                        }
+                    }
+                    Err(RecvError::Lagged(n)) => {
+                        warn!("Subscription {sub_id} missed {n} stream failures")
+                    }
+                    _ => {}
+                }
+                continue;
+            }
         };
 
         match item {
             Err(_) => break,
             Ok((letterhead, read_cap)) => {
-                use libratman::frame::micro::client_modes as cm;
-
                 if let Err(e) = client_socket
                     .write_microframe(
                         MicroframeHeader {
@@ -4,14 +4,23 @@
 
 mod collector;
 mod ingress;
+mod receipt;
+mod retransmit;
 mod send;
 mod slicer;
 mod subs_man;
 mod switch;
 
 pub(crate) use collector::{exec_block_collector_system, BlockCollector};
-pub(crate) use ingress::{exec_ingress_system, handle_subscription_socket, BlockNotifier};
-pub(crate) use send::{dispatch_frame, exec_sender_system, flood_frame, SenderSystem};
+pub(crate) use ingress::{
+    exec_ingress_system, handle_subscription_socket, replay_pending_stream, BlockNotifier,
+    MessageNotifier,
+};
+pub(crate) use receipt::{send_receipt, verify_receipt, DeliveryReceipts};
+pub(crate) use retransmit::{exec_retransmit_system, fetch_stream, RetransmitPolicy};
+pub(crate) use send::{
+    dispatch_frame, exec_sender_system, flood_frame, select_block_size, SenderSystem,
+};
 pub(crate) use slicer::BlockWorker;
-pub(crate) use subs_man::SubsManager;
+pub(crate) use subs_man::{StreamFailure, SubsManager};
 pub(crate) use switch::exec_switching_batch;
@@ -0,0 +1,232 @@
+// SPDX-FileCopyrightText: 2024 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+//! End-to-end delivery receipts
+//!
+//! A stream letterhead can request a delivery receipt.  Once the recipient
+//! router has reassembled such a stream, it signs the stream ID with the key of
+//! the receiving address and sends a receipt frame back to the stream sender.
+//! The sending router verifies the signature and hands the receipt to clients
+//! that are waiting for it, either via `send_to_confirmed` or a subscription.
+
+use crate::{context::RatmanContext, crypto, procedures};
+use chrono::Utc;
+use ed25519_dalek::{Signature, Signer};
+use libratman::{
+    api::types::DeliveryReceipt,
+    frame::{
+        carrier::{CarrierFrameHeader, ReceiptFrame, ReceiptFrameV1},
+        FrameGenerator,
+    },
+    tokio::{
+        sync::{
+            broadcast::{channel as bcast_channel, error::RecvError, Receiver, Sender},
+            Mutex,
+        },
+        time::timeout,
+    },
+    types::{Address, Ident32, InMemoryEnvelope, LetterheadV1, Recipient},
+    Result,
+};
+use std::{collections::VecDeque, sync::Arc, time::Duration};
+
+use super::BlockNotifier;
+
+/// How many receipts are kept around for clients that start waiting late
+const RECENT_RECEIPTS: usize = 256;
+
+/// Keep track of delivery receipts for locally sent streams
+pub(crate) struct DeliveryReceipts {
+    recent: Mutex<VecDeque<DeliveryReceipt>>,
+    notify: Sender<DeliveryReceipt>,
+}
+
+impl DeliveryReceipts {
+    pub(crate) fn new() -> Arc<Self> {
+        let (notify, _) = bcast_channel(32);
+        Arc::new(Self {
+            recent: Mutex::new(VecDeque::new()),
+            notify,
+        })
+    }
+
+    /// Store a verified receipt and notify all waiting clients
+    pub(crate) async fn insert(&self, receipt: DeliveryReceipt) {
+        debug!(
+            "Stream {} was delivered to {}",
+            receipt.stream_id.pretty_string(),
+            receipt.from.pretty_string()
+        );
+
+        let mut recent = self.recent.lock().await;
+        if recent.len() >= RECENT_RECEIPTS {
+            recent.pop_front();
+        }
+        recent.push_back(receipt.clone());
+
+        // Nobody may be listening, which is fine
+        let _ = self.notify.send(receipt);
+    }
+
+    /// Get notified of all new receipts
+    pub(crate) fn subscribe(&self) -> Receiver<DeliveryReceipt> {
+        self.notify.subscribe()
+    }
+
+    async fn find(&self, stream_id: Ident32, sender: Address) -> Option<DeliveryReceipt> {
+        self.recent
+            .lock()
+            .await
+            .iter()
+            .find(|r| r.stream_id == stream_id && r.to == sender)
+            .cloned()
+    }
+
+    /// Wait for the receipt of a stream sent by `sender`
+    ///
+    /// Returns `None` if no receipt arrived within the given time limit.
+    pub(crate) async fn wait_for(
+        &self,
+        stream_id: Ident32,
+        sender: Address,
+        limit: Duration,
+    ) -> Option<DeliveryReceipt> {
+        // Subscribe first so no receipt can slip through between checking the
+        // recent receipts and waiting for new ones
+        let mut rx = self.subscribe();
+        if let Some(receipt) = self.find(stream_id, sender).await {
+            return Some(receipt);
+        }
+
+        timeout(limit, async {
+            loop {
+                match rx.recv().await {
+                    Ok(r) if r.stream_id == stream_id && r.to == sender => break Some(r),
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(_)) => match self.find(stream_id, sender).await {
+                        Some(r) => break Some(r),
+                        None => continue,
+                    },
+                    Err(RecvError::Closed) => break None,
+                }
+            }
+        })
+        .await
+        .ok()
+        .flatten()
+    }
+}
+
+/// Sign and send a receipt for a stream that passed re-assembly
+///
+/// Receipts can only be signed while the receiving address is online, since
+/// its key is needed.  Otherwise no receipt is sent.
+pub(crate) async fn send_receipt(
+    ctx: &Arc<RatmanContext>,
+    stream_id: Ident32,
+    letterhead: &LetterheadV1,
+    block_notify_tx: Sender<BlockNotifier>,
+) -> Result<()> {
+    let recipient = letterhead.to.inner_address();
+    let auth = match ctx.protocol.get_online_auth(recipient).await {
+        Some(auth) => auth,
+        None => {
+            debug!(
+                "Can't sign receipt for stream {}: {} is offline",
+                stream_id.pretty_string(),
+                recipient.pretty_string()
+            );
+            return Ok(());
+        }
+    };
+
+    let key = crypto::get_addr_key(&ctx.meta_db, recipient, auth).await?;
+    let delivered_at = Utc::now();
+    let signed_data = ReceiptFrameV1::signed_data(stream_id, letterhead.from, delivered_at)?;
+    let signature = key.inner.sign(&signed_data).to_bytes();
+
+    // If the sender is on this router we don't need to go via the network
+    if let Ok(true) = ctx.routes.is_local(letterhead.from).await {
+        ctx.receipts
+            .insert(DeliveryReceipt {
+                stream_id,
+                from: recipient,
+                to: letterhead.from,
+                delivered_at,
+            })
+            .await;
+        return Ok(());
+    }
+
+    let mut payload = vec![];
+    ReceiptFrame::V1(ReceiptFrameV1 {
+        stream_id,
+        delivered_at,
+        signature,
+    })
+    .generate(&mut payload)?;
+
+    let payload_len = payload.len();
+    let header = CarrierFrameHeader::new_receipt_frame(
+        recipient,
+        Recipient::Address(letterhead.from),
+        stream_id,
+        payload_len as u16,
+    );
+
+    procedures::dispatch_frame(
+        &ctx.routes,
+        &ctx.links,
+        &ctx.collector,
+        block_notify_tx,
+        InMemoryEnvelope::from_header_and_payload(header, payload)?,
+        payload_len,
+    )
+    .await
+}
+
+/// Check the signature of a receipt sent by `from` to `to`
+pub(crate) fn verify_receipt(
+    from: Address,
+    to: Address,
+    receipt: &ReceiptFrameV1,
+) -> Option<DeliveryReceipt> {
+    let signed_data =
+        ReceiptFrameV1::signed_data(receipt.stream_id, to, receipt.delivered_at).ok()?;
+    let signature = Signature::from_bytes(&receipt.signature).ok()?;
+    crypto::verify_message(from, &signed_data, signature)?;
+
+    Some(DeliveryReceipt {
+        stream_id: receipt.stream_id,
+        from,
+        to,
+        delivered_at: receipt.delivered_at,
+    })
+}
+
+#[test]
+fn verify_receipt_signature() {
+    use ed25519_dalek::SecretKey;
+    use rand::rngs::OsRng;
+
+    let key = crypto::Keypair::new(SecretKey::generate(&mut OsRng {}));
+    let from = Address::from_bytes(key.inner.public.as_bytes());
+    let to = Address::random();
+    let stream_id = Ident32::random();
+    let delivered_at = Utc::now();
+
+    let signed_data = ReceiptFrameV1::signed_data(stream_id, to, delivered_at).unwrap();
+    let receipt = ReceiptFrameV1 {
+        stream_id,
+        delivered_at,
+        signature: key.inner.sign(&signed_data).to_bytes(),
+    };
+    assert!(verify_receipt(from, to, &receipt).is_some());
+
+    // Somebody else claiming to have received the stream
+    assert!(verify_receipt(Address::random(), to, &receipt).is_none());
+
+    // A receipt replayed towards a different sender
+    assert!(verify_receipt(from, Address::random(), &receipt).is_none());
+}
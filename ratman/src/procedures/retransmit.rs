@@ -0,0 +1,648 @@
+// SPDX-FileCopyrightText: 2024 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+//! Missing block retransmission
+//!
+//! When a message stream can't be re-assembled because some of its blocks
+//! never arrived, the ingress system walks the block tree of the stream to
+//! find the missing block references and asks the stream sender to send them
+//! again.  Requests are repeated with an exponential backoff for as long as
+//! new blocks keep arriving.  If the stream makes no progress for longer than
+//! the configured stream timeout, re-assembly is aborted and the failure is
+//! reported to the subscription of the recipient.
+//!
+//! The sending router answers a request by slicing the requested blocks from
+//! its journal into data frames, the same way the sender system does.  Since
+//! blocks are content-addressed, any other router that has them in its journal
+//! can answer too.  When the sender doesn't respond, missing blocks are
+//! additionally fetched from direct neighbours with `BLOCK_FETCH` frames.
+//!
+//! A sender only answers requests from the recipient of a stream, and every
+//! requester can only make a limited number of retransmission and fetch
+//! requests in a short time.  Fetched blocks are sealed for the requester
+//! with a separate address key of the router.
+
+use crate::{config::SubConfig, context::RatmanContext, crypto, journal::Journal, procedures};
+use async_eris::{Block, BlockKey, BlockReference, BlockStorage, ReadCapability};
+use libratman::{
+    frame::{
+        carrier::{
+            modes::BLOCK_FETCH, schemes, BlockFetchFrame, BlockFetchFrameV1, CarrierFrameHeader,
+            RetransmitFrame, RetransmitFrameV1, MAX_FETCH_BLOCKS, MAX_RETRANSMIT_BLOCKS,
+        },
+        FrameGenerator, FrameParser,
+    },
+    tokio::{
+        select,
+        sync::{broadcast::Sender as BcastSender, mpsc::Receiver},
+        task::{spawn, yield_now},
+        time::{sleep_until, Instant},
+    },
+    types::{Address, Ident32, InMemoryEnvelope, LetterheadV1, Recipient},
+    EncodingError, Result,
+};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
+use x25519_dalek::SharedSecret;
+
+use super::{slicer::BlockSlicer, BlockNotifier};
+
+/// Decide how often and for how long missing blocks are requested
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RetransmitPolicy {
+    /// How long to wait for missing blocks before the first request
+    pub initial_delay: Duration,
+    /// The longest delay between two requests for the same stream
+    pub max_delay: Duration,
+    /// Give up on a stream if no new blocks arrived for this long
+    pub stream_timeout: Duration,
+}
+
+impl Default for RetransmitPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(5),
+            max_delay: Duration::from_secs(60),
+            stream_timeout: Duration::from_secs(10 * 60),
+        }
+    }
+}
+
+impl RetransmitPolicy {
+    /// Read the `retransmit` block from the `ratmand` configuration tree
+    ///
+    /// Missing or invalid values use the default policy.
+    pub(crate) fn from_config(ratmand: &SubConfig) -> Self {
+        let default = Self::default();
+        let tree = match ratmand.get_subtree("retransmit") {
+            Some(tree) => tree,
+            None => return default,
+        };
+        let seconds = |key| {
+            tree.get_number_value(key)
+                .filter(|value| *value > 0)
+                .map(|value| Duration::from_secs(value as u64))
+        };
+
+        Self {
+            initial_delay: seconds("delay_secs").unwrap_or(default.initial_delay),
+            max_delay: seconds("max_delay_secs").unwrap_or(default.max_delay),
+            stream_timeout: seconds("stream_timeout_secs").unwrap_or(default.stream_timeout),
+        }
+    }
+
+    /// Create a new backoff timer for a single stream
+    pub(crate) fn backoff(&self) -> Backoff {
+        Backoff {
+            initial: self.initial_delay,
+            max: self.max_delay.max(self.initial_delay),
+            current: self.initial_delay,
+        }
+    }
+}
+
+/// Exponential backoff between retransmission requests
+pub(crate) struct Backoff {
+    initial: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    /// Start again from the initial delay
+    pub(crate) fn reset(&mut self) {
+        self.current = self.initial;
+    }
+
+    /// Get the next delay and double it for the time after
+    pub(crate) fn next(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+        delay
+    }
+}
+
+/// How many block requests a single address can make in one `REQUEST_WINDOW`
+const MAX_REQUESTS: u32 = 32;
+const REQUEST_WINDOW: Duration = Duration::from_secs(10);
+
+/// Count block requests to limit how often each address can make them
+pub(crate) struct RequestLimiter {
+    max: u32,
+    window: Duration,
+    requests: BTreeMap<Address, (Instant, u32)>,
+}
+
+impl Default for RequestLimiter {
+    fn default() -> Self {
+        Self {
+            max: MAX_REQUESTS,
+            window: REQUEST_WINDOW,
+            requests: BTreeMap::new(),
+        }
+    }
+}
+
+impl RequestLimiter {
+    /// Record a request and check whether it should be answered
+    pub(crate) fn allow(&mut self, requester: Address, now: Instant) -> bool {
+        let window = self.window;
+        self.requests
+            .retain(|_, (start, _)| now.duration_since(*start) < window);
+
+        let (_, count) = self.requests.entry(requester).or_insert((now, 0));
+        *count += 1;
+        *count <= self.max
+    }
+}
+
+/// The blocks of a stream that are (not) in the journal yet
+#[derive(Default)]
+pub(crate) struct BlockCensus {
+    /// Number of blocks that are available
+    pub(crate) present: usize,
+    /// References of blocks that are needed but haven't arrived
+    pub(crate) missing: Vec<Ident32>,
+}
+
+/// Walk the block tree of a stream and find all missing blocks
+///
+/// Children of a missing internal block can't be known yet, so only the
+/// current "frontier" of the tree is returned.
+pub(crate) async fn block_census(
+    journal: &Journal,
+    read_cap: &ReadCapability,
+) -> Result<BlockCensus> {
+    match read_cap.block_size {
+        1024 => census::<1024>(journal, *read_cap).await,
+        _ => census::<32768>(journal, *read_cap).await,
+    }
+}
+
+async fn census<const L: usize>(
+    journal: &Journal,
+    read_cap: ReadCapability,
+) -> Result<BlockCensus> {
+    let mut result = BlockCensus::default();
+    let mut subtrees = VecDeque::new();
+    subtrees.push_back(read_cap);
+
+    while let Some(tree) = subtrees.pop_front() {
+        let mut block: Block<L> = match journal.blocks.fetch(&tree.root_reference).await? {
+            Some(block) => block,
+            None => {
+                result
+                    .missing
+                    .push(Ident32::from_bytes(tree.root_reference.as_slice()));
+                continue;
+            }
+        };
+        result.present += 1;
+
+        if tree.level == 0 {
+            continue;
+        }
+
+        block.chacha20(&tree.root_key);
+        for rk_pair_raw in block.chunks_exact(64) {
+            if rk_pair_raw.iter().any(|x| *x != 0) {
+                let rk_pair = (
+                    BlockReference::from_bytes(&rk_pair_raw[..32]).unwrap(),
+                    BlockKey::from_bytes(&rk_pair_raw[32..]).unwrap(),
+                );
+                subtrees.push_back(ReadCapability::from_rk_pair(rk_pair, tree.level - 1, L));
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Ask the sender of a stream to send a set of missing blocks again
+///
+/// Large sets of blocks are split across several request frames.  If the
+/// sender can't be reached, or didn't answer the first request, the blocks
+/// are also requested from all direct neighbours.
+pub(crate) async fn request_blocks(
+    ctx: &Arc<RatmanContext>,
+    stream_id: Ident32,
+    letterhead: &LetterheadV1,
+    read_cap: &ReadCapability,
+    missing: &[Ident32],
+    attempt: u8,
+    block_notify_tx: BcastSender<BlockNotifier>,
+) -> Result<()> {
+    // Local streams never have to leave the journal
+    if missing.is_empty() || ctx.routes.is_local(letterhead.from).await? {
+        return Ok(());
+    }
+
+    debug!(
+        "Request {} missing blocks for stream {} from {}",
+        missing.len(),
+        stream_id.pretty_string(),
+        letterhead.from.pretty_string()
+    );
+
+    let requester = letterhead.to.inner_address();
+    let block_size = (read_cap.block_size / 1024) as u8;
+    let mut sender_reached = true;
+
+    for blocks in missing.chunks(MAX_RETRANSMIT_BLOCKS) {
+        let mut payload = vec![];
+        RetransmitFrame::V1(RetransmitFrameV1 {
+            stream_id,
+            block_size,
+            blocks: blocks.to_vec(),
+        })
+        .generate(&mut payload)?;
+
+        let payload_len = payload.len();
+        let header = CarrierFrameHeader::new_retransmit_frame(
+            requester,
+            Recipient::Address(letterhead.from),
+            stream_id,
+            attempt,
+            payload_len as u16,
+        );
+
+        if let Err(e) = procedures::dispatch_frame(
+            &ctx.routes,
+            &ctx.links,
+            &ctx.collector,
+            block_notify_tx.clone(),
+            InMemoryEnvelope::from_header_and_payload(header, payload)?,
+            payload_len,
+        )
+        .await
+        {
+            debug!(
+                "Can't reach stream sender {}: {e}",
+                letterhead.from.pretty_string()
+            );
+            sender_reached = false;
+            break;
+        }
+    }
+
+    if attempt > 1 || !sender_reached {
+        fetch_from_neighbours(ctx, requester, block_size, missing).await?;
+    }
+
+    Ok(())
+}
+
+/// Ask all direct neighbours for a set of blocks
+///
+/// Any neighbour that has one of the blocks in its journal sends it to the
+/// `requester` address, which must be local.
+pub(crate) async fn fetch_from_neighbours(
+    ctx: &Arc<RatmanContext>,
+    requester: Address,
+    block_size: u8,
+    blocks: &[Ident32],
+) -> Result<()> {
+    for blocks in blocks.chunks(MAX_FETCH_BLOCKS) {
+        let mut payload = vec![];
+        BlockFetchFrame::V1(BlockFetchFrameV1 {
+            block_size,
+            blocks: blocks.to_vec(),
+        })
+        .generate(&mut payload)?;
+
+        let header = CarrierFrameHeader::new_block_fetch_frame(requester, payload.len() as u16);
+        procedures::flood_frame(
+            &ctx.routes,
+            &ctx.links,
+            InMemoryEnvelope::from_header_and_payload(header, payload)?,
+            None,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Collect all blocks of a stream from direct neighbours
+///
+/// Blocks that aren't in the journal yet are fetched on behalf of the
+/// `requester` address, which must be local.  Requests are repeated with
+/// a backoff until the stream is complete, or `timeout` has passed.
+/// Returns whether the stream is complete.
+pub(crate) async fn fetch_stream(
+    ctx: &Arc<RatmanContext>,
+    requester: Address,
+    read_cap: &ReadCapability,
+    timeout: Duration,
+) -> Result<bool> {
+    let mut block_notify = ctx.collector.subscribe_blocks();
+    let block_size = (read_cap.block_size / 1024) as u8;
+    let deadline = Instant::now() + timeout;
+    let mut backoff = ctx.retransmit.backoff();
+    let mut next_request = Instant::now();
+
+    loop {
+        let census = block_census(&ctx.journal, read_cap).await?;
+        if census.missing.is_empty() {
+            return Ok(true);
+        }
+
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(false);
+        }
+
+        if now >= next_request {
+            debug!(
+                "Fetch {} missing blocks from neighbours",
+                census.missing.len()
+            );
+            fetch_from_neighbours(ctx, requester, block_size, &census.missing).await?;
+            next_request = now + backoff.next();
+        }
+
+        select! {
+            biased;
+            _ = ctx.tripwire.clone() => return Ok(false),
+            _ = block_notify.recv() => {},
+            _ = sleep_until(next_request.min(deadline)) => {},
+        }
+    }
+}
+
+/// Answer retransmission and block fetch requests from the local journal
+pub(crate) async fn exec_retransmit_system(
+    ctx: Arc<RatmanContext>,
+    mut rx: Receiver<InMemoryEnvelope>,
+    block_notify_tx: BcastSender<BlockNotifier>,
+) {
+    let mut retransmits = RequestLimiter::default();
+    let mut fetches = RequestLimiter::default();
+    loop {
+        let tripwire = ctx.tripwire.clone();
+        let envelope = select! {
+            biased;
+            _ = tripwire => break,
+            env = rx.recv() => match env {
+                Some(env) => env,
+                None => break,
+            }
+        };
+
+        let requester = envelope.header.get_sender();
+        let limiter = match envelope.header.get_modes() {
+            BLOCK_FETCH => &mut fetches,
+            _ => &mut retransmits,
+        };
+        if !limiter.allow(requester, Instant::now()) {
+            debug!(
+                "Ignore block request from {}: too many requests",
+                requester.pretty_string()
+            );
+            continue;
+        }
+
+        let ctx = Arc::clone(&ctx);
+        let block_notify_tx = block_notify_tx.clone();
+        spawn(async move {
+            let result = match envelope.header.get_modes() {
+                BLOCK_FETCH => answer_fetch(ctx, envelope, block_notify_tx).await,
+                _ => answer_retransmit(ctx, envelope, block_notify_tx).await,
+            };
+
+            if let Err(e) = result {
+                warn!("failed to answer block request: {e}");
+            }
+        });
+    }
+
+    info!("Retransmission system shut down");
+}
+
+async fn answer_retransmit(
+    ctx: Arc<RatmanContext>,
+    envelope: InMemoryEnvelope,
+    block_notify_tx: BcastSender<BlockNotifier>,
+) -> Result<()> {
+    let requester = envelope.header.get_sender();
+    let local = envelope
+        .header
+        .get_recipient()
+        .ok_or_else(|| {
+            EncodingError::Parsing("Mandatory field 'recipient' was missing!".to_string())
+        })?
+        .inner_address();
+
+    let request = match RetransmitFrame::parse(envelope.get_payload_slice()) {
+        Ok((_, request)) => request?,
+        Err(e) => {
+            return Err(EncodingError::Parsing(format!("invalid retransmit frame: {e}")).into())
+        }
+    };
+    let RetransmitFrame::V1(request) = request;
+
+    debug!(
+        "{} requested {} blocks of stream {}",
+        requester.pretty_string(),
+        request.blocks.len(),
+        request.stream_id.pretty_string()
+    );
+
+    // Only the recipient of a stream we sent may ask for its blocks again
+    match ctx
+        .journal
+        .manifests
+        .get(&request.stream_id.to_string())
+        .await?
+    {
+        Some(manifest)
+            if manifest.sender == local && manifest.recipient.inner_address() == requester => {}
+        _ => {
+            debug!(
+                "Refuse to retransmit stream {} to {}: not its recipient",
+                request.stream_id.pretty_string(),
+                requester.pretty_string()
+            );
+            return Ok(());
+        }
+    }
+
+    // Chunks can only be sealed while the local address is online.  Block
+    // references are content hashes, so an unsealed block still can't be
+    // tampered with on the way.
+    let seal_key = match ctx.routes.get_seal_scheme(requester).await {
+        schemes::NONE => None,
+        _ => match ctx.protocol.get_online_auth(local).await {
+            Some(auth) => {
+                let key = crypto::get_addr_key(&ctx.meta_db, local, auth).await?;
+                crypto::diffie_hellman(&key, requester)
+            }
+            None => None,
+        },
+    };
+
+    let blocks = BlockRequest {
+        local,
+        requester,
+        block_size: request.block_size,
+        blocks: &request.blocks,
+    };
+    blocks.answer(&ctx, seal_key, block_notify_tx).await
+}
+
+async fn answer_fetch(
+    ctx: Arc<RatmanContext>,
+    envelope: InMemoryEnvelope,
+    block_notify_tx: BcastSender<BlockNotifier>,
+) -> Result<()> {
+    let requester = envelope.header.get_sender();
+    let request = match BlockFetchFrame::parse(envelope.get_payload_slice()) {
+        Ok((_, request)) => request?,
+        Err(e) => {
+            return Err(EncodingError::Parsing(format!("invalid block fetch frame: {e}")).into())
+        }
+    };
+    let BlockFetchFrame::V1(request) = request;
+
+    trace!(
+        "{} asked for {} blocks",
+        requester.pretty_string(),
+        request.blocks.len()
+    );
+
+    // We don't own either side of the stream, so blocks are sent on behalf
+    // of the router itself.  They are still sealed for the requester, so that
+    // relays can't tell which blocks it was looking for
+    let keypair = crypto::fetch_keypair(&ctx.meta_db);
+    let local = Address::from_bytes(keypair.inner.public.as_bytes());
+    let seal_key = match ctx.routes.get_seal_scheme(requester).await {
+        schemes::NONE => None,
+        _ => crypto::diffie_hellman(&keypair, requester),
+    };
+
+    let blocks = BlockRequest {
+        local,
+        requester,
+        block_size: request.block_size,
+        blocks: &request.blocks,
+    };
+    blocks.answer(&ctx, seal_key, block_notify_tx).await
+}
+
+/// A set of blocks to send from the local journal
+struct BlockRequest<'r> {
+    local: Address,
+    requester: Address,
+    block_size: u8,
+    blocks: &'r [Ident32],
+}
+
+impl BlockRequest<'_> {
+    async fn answer(
+        &self,
+        ctx: &Arc<RatmanContext>,
+        seal_key: Option<SharedSecret>,
+        block_notify_tx: BcastSender<BlockNotifier>,
+    ) -> Result<()> {
+        match self.block_size {
+            1 => self.send::<1024>(ctx, seal_key, block_notify_tx).await,
+            32 => self.send::<32768>(ctx, seal_key, block_notify_tx).await,
+            size => Err(EncodingError::Parsing(format!("invalid block size {size}")).into()),
+        }
+    }
+
+    async fn send<const L: usize>(
+        &self,
+        ctx: &Arc<RatmanContext>,
+        seal_key: Option<SharedSecret>,
+        block_notify_tx: BcastSender<BlockNotifier>,
+    ) -> Result<()> {
+        let path_mtu = ctx.routes.path_mtu(self.requester).await;
+        for block_id in self.blocks.iter().take(MAX_RETRANSMIT_BLOCKS) {
+            let reference = BlockReference::from_bytes(block_id.as_bytes())
+                .map_err(libratman::BlockError::Eris)?;
+            let block: Block<L> = match ctx.journal.blocks.fetch(&reference).await? {
+                Some(block) => block,
+                None => {
+                    trace!("Don't have requested block {}", block_id.pretty_string());
+                    continue;
+                }
+            };
+
+            let frames = BlockSlicer
+                .produce_frames(
+                    block,
+                    self.local,
+                    Recipient::Address(self.requester),
+                    seal_key.as_ref(),
+                    path_mtu,
+                )
+                .await?;
+
+            for envelope in frames {
+                let frame_len = envelope.buffer.len();
+                if let Err(e) = procedures::dispatch_frame(
+                    &ctx.routes,
+                    &ctx.links,
+                    &ctx.collector,
+                    block_notify_tx.clone(),
+                    envelope,
+                    frame_len,
+                )
+                .await
+                {
+                    error!("failed to dispatch requested block frame: {e}");
+                    return Ok(());
+                }
+
+                yield_now().await;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn backoff_doubles_until_limit() {
+    let policy = RetransmitPolicy {
+        initial_delay: Duration::from_secs(5),
+        max_delay: Duration::from_secs(30),
+        stream_timeout: Duration::from_secs(600),
+    };
+    let mut backoff = policy.backoff();
+
+    assert_eq!(backoff.next(), Duration::from_secs(5));
+    assert_eq!(backoff.next(), Duration::from_secs(10));
+    assert_eq!(backoff.next(), Duration::from_secs(20));
+    assert_eq!(backoff.next(), Duration::from_secs(30));
+    assert_eq!(backoff.next(), Duration::from_secs(30));
+
+    backoff.reset();
+    assert_eq!(backoff.next(), Duration::from_secs(5));
+}
+
+#[test]
+fn limit_requests_per_address() {
+    let mut limiter = RequestLimiter {
+        max: 2,
+        window: Duration::from_secs(10),
+        requests: BTreeMap::new(),
+    };
+    let (a, b) = (Address::random(), Address::random());
+    let now = Instant::now();
+
+    assert!(limiter.allow(a, now));
+    assert!(limiter.allow(a, now));
+    assert!(!limiter.allow(a, now + Duration::from_secs(1)));
+
+    // Other requesters have their own budget
+    assert!(limiter.allow(b, now + Duration::from_secs(1)));
+
+    // The budget is renewed after the window passed
+    assert!(limiter.allow(a, now + Duration::from_secs(10)));
+}
@@ -14,11 +14,11 @@ use crate::{
     links::LinksMap,
     routes::{EpNeighbourPair, RouteTable},
 };
-use async_eris::{Block, ReadCapability};
+use async_eris::{Block, BlockSize, ReadCapability};
 use colored::Colorize;
 use libratman::{
     frame::{
-        carrier::{CarrierFrameHeader, ManifestFrame, ManifestFrameV1},
+        carrier::{schemes, CarrierFrameHeader, ManifestFrame, ManifestFrameV1},
         FrameGenerator,
     },
     rt::new_async_thread,
@@ -35,10 +35,31 @@ use libratman::{
 };
 use std::sync::Arc;
 use tripwire::Tripwire;
+use x25519_dalek::SharedSecret;
+
+/// A block stream to send, along with the sender/recipient shared secret
+pub type SendJob = (ReadCapability, LetterheadV1, SharedSecret);
+
+/// Smallest path MTU on which streams are sent with 32K blocks
+///
+/// On smaller links a 32K block would be cut into a huge number of frames,
+/// each of which can get lost on the way.
+const LARGE_BLOCK_MTU: usize = 1024;
+
+/// Choose the block size for a stream
+///
+/// Small streams, and streams over a path with a small MTU use 1K blocks.
+pub(crate) fn select_block_size(stream_size: u64, path_mtu: Option<usize>) -> BlockSize {
+    match path_mtu {
+        _ if stream_size < 8 * 1024 => BlockSize::_1K,
+        Some(mtu) if mtu < LARGE_BLOCK_MTU => BlockSize::_1K,
+        _ => BlockSize::_32K,
+    }
+}
 
 pub struct SenderSystem {
-    pub tx_1k: Sender<(ReadCapability, LetterheadV1)>,
-    pub tx_32k: Sender<(ReadCapability, LetterheadV1)>,
+    pub tx_1k: Sender<SendJob>,
+    pub tx_32k: Sender<SendJob>,
 }
 
 pub(crate) async fn exec_sender_system<const L: usize>(
@@ -49,132 +70,139 @@ pub(crate) async fn exec_sender_system<const L: usize>(
     block_bcast: BcastSender<BlockNotifier>,
     ingress_tx: Sender<MessageNotifier>,
     tripwire: Tripwire,
-) -> Sender<(ReadCapability, LetterheadV1)> {
+) -> Sender<SendJob> {
     let (tx_l, mut rx_l) = channel(32);
     {
         let journal = Arc::clone(journal);
         let routes = Arc::clone(routes);
         let drivers = Arc::clone(drivers);
         let collector = Arc::clone(&collector);
-        new_async_thread(
-            format!("sender-system-{}k", L / 1024),
-            1024 * 8,
-            async move {
-                debug!("Setup sender system for {}kB blocks", L / 1024);
-                loop {
-                    let routes = Arc::clone(&routes);
-                    let drivers = Arc::clone(&drivers);
-                    let collector = Arc::clone(&collector);
-                    let block_bcast = block_bcast.clone();
-
-                    let tw = tripwire.clone();
-                    let (read_cap, letterhead): (ReadCapability, LetterheadV1) = select! {
-                        _ = tw => break,
-                        i = rx_l.recv() => {
-                            match i {
-                                Some(i) => i,
-                                None => break,
-                            }
+        new_async_thread(format!("sender-system-{}k", L / 1024), 8, async move {
+            debug!("Setup sender system for {}kB blocks", L / 1024);
+            loop {
+                let routes = Arc::clone(&routes);
+                let drivers = Arc::clone(&drivers);
+                let collector = Arc::clone(&collector);
+                let block_bcast = block_bcast.clone();
+
+                let tw = tripwire.clone();
+                let (read_cap, letterhead, shared_key): SendJob = select! {
+                    _ = tw => break,
+                    i = rx_l.recv() => {
+                        match i {
+                            Some(i) => i,
+                            None => break,
+                        }
+                    }
+                };
+
+                debug!(
+                    "Got block stream to handle, (to: {}, stream_len: {})",
+                    letterhead.to.inner_address().pretty_string(),
+                    letterhead.stream_size
+                );
+
+                // Only seal chunks if the recipient has told us it can open them
+                let seal_key = match routes.get_seal_scheme(letterhead.to.inner_address()).await {
+                    schemes::NONE => None,
+                    _ => Some(&shared_key),
+                };
+
+                // Slice frames to fit the smallest link on the way
+                let path_mtu = routes.path_mtu(letterhead.to.inner_address()).await;
+
+                let (local_tx, mut local_rx) = channel::<(Block<L>, LetterheadV1)>(1);
+                let manifest = ManifestFrame::V1(ManifestFrameV1::from((
+                    read_cap.clone(),
+                    letterhead.clone(),
+                )));
+
+                send_manifest(
+                    manifest,
+                    letterhead.clone(),
+                    &read_cap,
+                    &routes,
+                    &journal,
+                    &ingress_tx,
+                    &block_bcast,
+                    &drivers,
+                    &collector,
+                )
+                .await?;
+
+                let journal = Arc::clone(&journal);
+                spawn(BlockWorker { read_cap }.traverse_block_tree::<L>(
+                    Arc::clone(&journal),
+                    letterhead.clone(),
+                    local_tx,
+                ));
+
+                while let Some((block, letterhead)) = local_rx.recv().await {
+                    let bid = block.reference();
+
+                    let frame_buf = match BlockSlicer
+                        .produce_frames(block, letterhead.from, letterhead.to, seal_key, path_mtu)
+                        .await
+                    {
+                        Ok(buf) => buf,
+                        Err(e) => {
+                            error!("failed to slice block to frames: {e}");
+                            continue;
                         }
                     };
 
-                    debug!(
-                        "Got block stream to handle, (to: {}, stream_len: {})",
-                        letterhead.to.inner_address().pretty_string(),
-                        letterhead.stream_size
-                    );
-                    let (local_tx, mut local_rx) = channel::<(Block<L>, LetterheadV1)>(1);
-                    let manifest = ManifestFrame::V1(ManifestFrameV1::from((
-                        read_cap.clone(),
-                        letterhead.clone(),
-                    )));
-
-                    send_manifest(
-                        manifest,
-                        letterhead.clone(),
-                        &read_cap,
-                        &routes,
-                        &journal,
-                        &ingress_tx,
-                        &block_bcast,
-                        &drivers,
-                        &collector,
-                    )
-                    .await?;
-
-                    let journal = Arc::clone(&journal);
-                    spawn(BlockWorker { read_cap }.traverse_block_tree::<L>(
-                        Arc::clone(&journal),
-                        letterhead.clone(),
-                        local_tx,
-                    ));
-
-                    while let Some((block, letterhead)) = local_rx.recv().await {
-                        let bid = block.reference();
-
-                        let frame_buf = match BlockSlicer
-                            .produce_frames(block, letterhead.from, letterhead.to)
-                            .await
-                        {
-                            Ok(buf) => buf,
-                            Err(e) => {
-                                error!("failed to slice block to frames: {e}");
-                                continue;
-                            }
-                        };
+                    let frame_count = frame_buf.get(0).unwrap().buffer.len();
 
-                        let frame_count = frame_buf.get(0).unwrap().buffer.len();
+                    let bid32 = Ident32::from_bytes(bid.as_slice()).pretty_string();
 
-                        let bid32 = Ident32::from_bytes(bid.as_slice()).pretty_string();
+                    trace!(
+                        "Block {} turned into {}x {:.1}kB frames",
+                        bid32,
+                        frame_buf.len(),
+                        frame_count as f32 / 1024.0,
+                    );
+
+                    for envelope in frame_buf {
+                        if envelope.header.get_seq_id().is_none() {
+                            error!("{:?}", envelope.header);
+                            panic!(
+                                "WAS ABOUT TO SEND OFF A DATA FRAME WITHOUT SEQUENCE ID
+WHAT THE FUCK"
+                            );
+                        }
 
                         trace!(
-                            "Block {} turned into {}x {:.1}kB frames",
+                            "Dispatching {} byte frame {}/{}",
+                            envelope.buffer.len(),
                             bid32,
-                            frame_buf.len(),
-                            frame_count as f32 / 1024.0,
+                            envelope.header.get_seq_id().unwrap().num
                         );
 
-                        for envelope in frame_buf {
-                            if envelope.header.get_seq_id().is_none() {
-                                error!("{:?}", envelope.header);
-                                panic!(
-                                    "WAS ABOUT TO SEND OFF A DATA FRAME WITHOUT SEQUENCE ID
-WHAT THE FUCK"
-                                );
-                            }
-
-                            trace!(
-                                "Dispatching {} byte frame {}/{}",
-                                envelope.buffer.len(),
-                                bid32,
-                                envelope.header.get_seq_id().unwrap().num
-                            );
-
-                            if let Err(e) = dispatch_frame(
-                                &routes,
-                                &drivers,
-                                &collector,
-                                block_bcast.clone(),
-                                envelope,
-                            )
-                            .await
-                            {
-                                error!("failed to dispatch frame: {e}");
-                            }
-
-                            // Yield before sending the next frame
-                            yield_now().await;
+                        if let Err(e) = dispatch_frame(
+                            &routes,
+                            &drivers,
+                            &collector,
+                            block_bcast.clone(),
+                            envelope,
+                            letterhead.stream_size as usize,
+                        )
+                        .await
+                        {
+                            error!("failed to dispatch frame: {e}");
                         }
-                    }
 
-                    // Yield before starting the next stream
-                    yield_now().await;
+                        // Yield before sending the next frame
+                        yield_now().await;
+                    }
                 }
 
-                //
-                Ok(())
-            },
-        );
+                // Yield before starting the next stream
+                yield_now().await;
+            }
+
+            //
+            Ok(())
+        });
     }
 
     tx_l
@@ -216,8 +244,11 @@ async fn send_manifest(
         buffer: full_buf,
     };
 
+    // The manifest also records who the stream was sent to, so that only its
+    // recipient can request missing blocks again
+    journal.queue_manifest(envelope.clone()).await.unwrap();
+
     if let Ok(true) = routes.is_local(letterhead.to.inner_address()).await {
-        journal.queue_manifest(envelope.clone()).await.unwrap();
         if let Err(e) = ingress_tx
             .send(MessageNotifier(envelope.header.get_seq_id().unwrap().hash))
             .await
@@ -233,6 +264,7 @@ async fn send_manifest(
             &Arc::clone(&collector),
             block_bcast,
             envelope,
+            letterhead.stream_size as usize,
         )
         .await
         {
@@ -245,13 +277,16 @@ async fn send_manifest(
 
 /// Resolve the target address and dispatch the frame
 ///
-/// Returns an error if resolving or sending failed
+/// `stream_size` is the size of the stream this frame is part of, if it is
+/// known, or the size of the frame itself otherwise.  Returns an error if
+/// resolving or sending failed
 pub(crate) async fn dispatch_frame(
     routes: &Arc<RouteTable>,
     drivers: &Arc<LinksMap>,
     collector: &Arc<BlockCollector>,
     block_bcast: BcastSender<BlockNotifier>,
     envelope: InMemoryEnvelope,
+    stream_size: usize,
 ) -> Result<()> {
     trace!(
         "Dispatch frame in sequence {}",
@@ -275,7 +310,8 @@ pub(crate) async fn dispatch_frame(
         return Ok(());
     }
 
-    let EpNeighbourPair(epid, nb) = match routes.resolve(drivers, target_address).await {
+    let EpNeighbourPair(epid, nb) = match routes.resolve(drivers, target_address, stream_size).await
+    {
         // Return the endpoint/target ID pair from the resolver
         Ok(resolve) => resolve,
         Err(_) => {
@@ -290,7 +326,10 @@ pub(crate) async fn dispatch_frame(
         }
     };
 
-    let (_, ep) = drivers.get(epid as usize).await;
+    let (_, ep) = drivers
+        .get(epid as usize)
+        .await
+        .ok_or(RatmanError::Nonfatal(NonfatalError::NoAvailableRoute))?;
     ep.send(envelope, Neighbour::Single(nb), None).await
 }
 
@@ -320,6 +359,23 @@ pub(crate) async fn flood_frame(
     Ok(())
 }
 
+#[test]
+fn block_size_from_path_mtu() {
+    assert!(matches!(select_block_size(1024, None), BlockSize::_1K));
+    assert!(matches!(
+        select_block_size(1024 * 1024, None),
+        BlockSize::_32K
+    ));
+    assert!(matches!(
+        select_block_size(1024 * 1024, Some(1452)),
+        BlockSize::_32K
+    ));
+    assert!(matches!(
+        select_block_size(1024 * 1024, Some(246)),
+        BlockSize::_1K
+    ));
+}
+
 // #[cfg(feature = "dashboard")]
 // mod metrics {
 //     use libratman::types::{Address, ApiRecipient};
@@ -5,7 +5,7 @@
 
 //! Slices `Message` into a series of Frames
 
-use crate::journal::Journal;
+use crate::{crypto, journal::Journal};
 use async_eris::{Block, BlockKey, BlockReference, BlockStorage, ReadCapability};
 
 use libratman::tokio::sync::mpsc::Sender;
@@ -15,12 +15,13 @@ use libratman::{
     types::{Address, Ident32, InMemoryEnvelope, Recipient, SequenceIdV1},
     Result,
 };
-use libratman::{BlockError, RatmanError};
+use libratman::{BlockError, NonfatalError, RatmanError};
 
 use std::collections::VecDeque;
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 
 use std::sync::Arc;
+use x25519_dalek::SharedSecret;
 
 pub struct BlockWorker {
     pub read_cap: ReadCapability,
@@ -94,6 +95,7 @@ async fn test_block_walker() {
     let j = Arc::new(
         Journal::new(
             Keyspace::open(Config::new(td.into_path().join("test_block_walker.jfall"))).unwrap(),
+            crate::journal::quota::DEFAULT_QUOTA,
         )
         .unwrap(),
     );
@@ -164,19 +166,36 @@ async fn test_block_walker() {
     assert_eq!(*original_blocks, traversed_blocks);
 }
 
+/// Largest frame size used on paths without a known MTU
+pub const DEFAULT_FRAME_SIZE: usize = 1300;
+
 pub struct BlockSlicer;
 
 impl BlockSlicer {
+    /// Slice a block into data frames
+    ///
+    /// If a shared secret is provided, every chunk is sealed before being
+    /// packed into its frame.  Frames are never larger than the path MTU, if
+    /// one is known.
     pub async fn produce_frames<const L: usize>(
         self,
         b: Block<L>,
         sender: Address,
         recipient: Recipient,
+        seal_key: Option<&SharedSecret>,
+        path_mtu: Option<usize>,
     ) -> Result<Vec<InMemoryEnvelope>> {
         let mut buf = vec![];
-        let header_size = CarrierFrameHeader::get_blockdata_size(sender, recipient);
+        let header_size = match seal_key {
+            Some(_) => CarrierFrameHeader::get_sealed_blockdata_size(sender, recipient),
+            None => CarrierFrameHeader::get_blockdata_size(sender, recipient),
+        };
         trace!("Slice block with header size {header_size}");
-        let max_transfer_size = 1300; // fixme: /o\
+        let max_transfer_size =
+            path_mtu.map_or(DEFAULT_FRAME_SIZE, |mtu| mtu.min(DEFAULT_FRAME_SIZE));
+        if max_transfer_size <= header_size + 4 {
+            return Err(RatmanError::Nonfatal(NonfatalError::MtuTooSmallForFrame));
+        }
 
         let block_ref = Ident32::from_bytes(b.reference().as_slice());
 
@@ -188,8 +207,10 @@ impl BlockSlicer {
 
         // The length of the block divided by the MTU - what is
         // required for the header
-        let max_payload_size = max_transfer_size as usize - header_size - 4;
+        let max_payload_size = max_transfer_size - header_size - 4;
         let num_chunks = b.as_slice().len() / max_payload_size;
+        let max = u8::try_from(num_chunks)
+            .map_err(|_| RatmanError::Nonfatal(NonfatalError::MtuTooSmallForFrame))?;
 
         trace!("Selected data chunk size {max_payload_size}");
         trace!(
@@ -200,26 +221,36 @@ impl BlockSlicer {
         for chunk in b.as_slice().chunks(max_payload_size as usize) {
             assert!(ctr as usize <= num_chunks);
 
-            use std::convert::TryFrom;
             let seq_id = SequenceIdV1 {
                 hash: block_ref,
                 num: ctr,
-                max: u8::try_from(num_chunks).expect("maximum frame number too large!"),
+                max,
             };
 
             // Create a header and encode it into an InMemoryEnvelope
-            let header = CarrierFrameHeader::new_blockdata_frame(
-                sender,
-                recipient,
-                seq_id,
-                chunk.len() as u16,
-            );
+            let mut payload = chunk.to_vec();
+            let header = match seal_key {
+                Some(shared_key) => {
+                    let seal =
+                        crypto::seal_chunk(shared_key, sender, recipient, seq_id, &mut payload)?;
+                    CarrierFrameHeader::new_sealed_blockdata_frame(
+                        sender,
+                        recipient,
+                        seq_id,
+                        seal,
+                        payload.len() as u16,
+                    )?
+                }
+                None => CarrierFrameHeader::new_blockdata_frame(
+                    sender,
+                    recipient,
+                    seq_id,
+                    payload.len() as u16,
+                ),
+            };
 
             // Push the header + chunk data to the output buffer
-            buf.push(InMemoryEnvelope::from_header_and_payload(
-                header,
-                chunk.to_vec(),
-            )?);
+            buf.push(InMemoryEnvelope::from_header_and_payload(header, payload)?);
 
             // Increment sequence counter
             ctr += 1;
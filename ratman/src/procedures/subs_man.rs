@@ -12,10 +12,21 @@ use std::{collections::BTreeMap, sync::Arc};
 
 type Locked<K, V> = Mutex<BTreeMap<K, V>>;
 
+/// An incoming stream or block had to be abandoned
+///
+/// Either because blocks were missing (`ClientError::StreamTimeout`), or
+/// because a block failed authentication.
+#[derive(Clone, Debug)]
+pub struct StreamFailure {
+    pub recipient: Recipient,
+    pub error: ClientError,
+}
+
 pub struct SubsManager {
     meta_db: Arc<MetadataDb>,
     pub(crate) recipients: Locked<Recipient, Ident32>,
     pub(crate) active_listeners: Locked<Ident32, Sender<(LetterheadV1, ReadCapability)>>,
+    failures: Sender<StreamFailure>,
 }
 
 impl SubsManager {
@@ -36,9 +47,21 @@ impl SubsManager {
             meta_db: Arc::clone(meta_db),
             recipients: Locked::new(recipients),
             active_listeners: Locked::default(),
+            failures: channel(16).0,
         })
     }
 
+    /// Notify subscriptions that an incoming stream has failed
+    pub fn stream_failed(self: &Arc<Self>, recipient: Recipient, error: ClientError) {
+        // Nobody may be subscribed, which is fine
+        let _ = self.failures.send(StreamFailure { recipient, error });
+    }
+
+    /// Get notified of all failed incoming streams
+    pub fn failures(self: &Arc<Self>) -> Receiver<StreamFailure> {
+        self.failures.subscribe()
+    }
+
     async fn sub_listener(
         self: &Arc<Self>,
         sub_id: Ident32,
@@ -11,10 +11,12 @@ use crate::{
 };
 use chrono::Utc;
 use libratman::{
-    frame::carrier::modes::{self as fmodes, DATA, MANIFEST, NAMESPACE_ANYCAST},
+    frame::carrier::modes::{
+        self as fmodes, DATA, MANIFEST, NAMESPACE_ANYCAST, RECEIPT, RETRANSMIT,
+    },
     frame::{
-        carrier::{AnnounceFrame, CarrierFrameHeader},
-        FrameParser,
+        carrier::{AnnounceFrame, CarrierFrameHeader, ReceiptFrame},
+        FrameGenerator, FrameParser,
     },
     types::{InMemoryEnvelope, Recipient},
     NetmodError, RatmanError,
@@ -27,10 +29,10 @@ use libratman::{
     },
     types::RouterMeta,
 };
-use std::sync::Arc;
+use std::{convert::TryInto, sync::Arc};
 use tripwire::Tripwire;
 
-use super::{ingress::MessageNotifier, BlockCollector, BlockNotifier};
+use super::{ingress::MessageNotifier, BlockCollector, BlockNotifier, DeliveryReceipts};
 
 /// Run a batch of receive jobs for a given endpoint and state context
 pub(crate) async fn exec_switching_batch(
@@ -51,6 +53,8 @@ pub(crate) async fn exec_switching_batch(
     collector: &Arc<BlockCollector>,
     // Reference to protocol tracker
     protocol: &Arc<Protocol>,
+    // Hand verified delivery receipts to waiting clients
+    receipts: &Arc<DeliveryReceipts>,
     // Allow the switch to shut down gracefully
     tripwire: Tripwire,
     // The netmod driver endpoint to switch messages for
@@ -58,6 +62,8 @@ pub(crate) async fn exec_switching_batch(
     // Control flow endpoint to send signals to this switch between batches
     ingress_tx: Sender<MessageNotifier>,
     collector_tx: Sender<InMemoryEnvelope>,
+    // Requests for missing blocks that can be answered from the journal
+    retransmit_tx: Sender<InMemoryEnvelope>,
     // We only take the sender because we can spawn receivers from it
     // with .subscribe()
     block_notify_tx: BcastSender<BlockNotifier>,
@@ -135,10 +141,36 @@ pub(crate) async fn exec_switching_batch(
                     let announce_buf = &buffer.as_slice()[payload_slice];
 
                     match AnnounceFrame::parse(announce_buf) {
-                        Ok((remainder, Ok(announce_frame))) => {
+                        Ok((remainder, Ok(mut announce_frame))) => {
                             // fixme: fail softly ;-;
                             assert!(remainder.len() == 0);
 
+                            let AnnounceFrame::V1(ref v1) = announce_frame;
+                            let seal_scheme = header.get_announced_seal_scheme();
+                            if let Err(reason) = routes
+                                .verify_announcement(header.get_sender(), v1, seal_scheme)
+                                .await
+                            {
+                                // Rejected announcements are neither used nor
+                                // flooded any further
+                                warn!(
+                                    "Rejected announcement for {} ({reason})",
+                                    header.get_sender().pretty_string()
+                                );
+                                continue;
+                            }
+
+                            // The route data isn't signed, so we lower the
+                            // path MTU to what this link can carry
+                            let AnnounceFrame::V1(ref mut v1) = announce_frame;
+                            v1.route
+                                .limit_mtu(ep.size_hint().try_into().unwrap_or(u32::MAX));
+
+                            routes
+                                .set_seal_scheme(header.get_sender(), seal_scheme)
+                                .await;
+                            routes.notify_scorers(&announce_frame).await;
+
                             // Update the routing table and re-flood the announcement
                             if let Err(e) = routes
                                 .update(
@@ -154,10 +186,22 @@ pub(crate) async fn exec_switching_batch(
                                 continue;
                             }
 
+                            let mut payload = vec![];
+                            let envelope =
+                                match announce_frame.generate(&mut payload).and_then(|_| {
+                                    InMemoryEnvelope::from_header_and_payload(header, payload)
+                                }) {
+                                    Ok(envelope) => envelope,
+                                    Err(e) => {
+                                        error!("failed to re-encode announcement frame: {e}");
+                                        continue;
+                                    }
+                                };
+
                             if let Err(e) = procedures::flood_frame(
                                 &routes,
                                 &links,
-                                InMemoryEnvelope { header, buffer },
+                                envelope,
                                 neighbour.maybe_single(),
                             )
                             .await
@@ -177,6 +221,18 @@ pub(crate) async fn exec_switching_batch(
                 }
             }
             //
+            // Neighbours asking for blocks are answered from the local
+            // journal.  These requests are never forwarded
+            (fmodes::BLOCK_FETCH, _) => {
+                trace!("Received block fetch request from {}", header.get_sender());
+                if let Err(e) = retransmit_tx
+                    .send(InMemoryEnvelope { header, buffer })
+                    .await
+                {
+                    error!("failed to queue block fetch request: {e}");
+                }
+            }
+            //
             // Handle anycast requests
             (mode, Some(Recipient::Namespace(namespace))) if mode == NAMESPACE_ANYCAST => {
                 let now = Utc::now();
@@ -200,6 +256,7 @@ pub(crate) async fn exec_switching_batch(
                             vec![],
                         )
                         .expect("failed to encode anycast probe response"),
+                        0,
                     )
                     .await
                     {
@@ -212,19 +269,24 @@ pub(crate) async fn exec_switching_batch(
             ///////////////////////////////////////////////////////////
             //
             // Any frame that's addressed to an address
-            (mode, Some(Recipient::Address(address))) if mode == DATA || mode == MANIFEST => {
+            (mode, Some(Recipient::Address(address)))
+                if mode == DATA || mode == MANIFEST || mode == RECEIPT || mode == RETRANSMIT =>
+            {
                 trace!("Received [mode:{mode}] frame for {address}");
 
                 // Check if the target address is "reachable"
                 match routes.reachable(address).await {
                     // Any frame for a reachable remote address will be forwarded
                     Some(_) => {
+                        let buffer_len = buffer.len();
                         match procedures::dispatch_frame(
                             routes,
                             links,
                             collector,
                             block_notify_tx.clone(),
                             InMemoryEnvelope { header, buffer },
+                            // Forwarded frames don't tell us how large their stream is
+                            buffer_len,
                         )
                         .await
                         {
@@ -243,8 +305,38 @@ pub(crate) async fn exec_switching_batch(
                     }
                     // A route entry with actual route data is a local address
                     None => {
+                        // Receipts are verified and handed to waiting clients
+                        if mode == RECEIPT {
+                            let payload_buf = &buffer.as_slice()[payload_slice];
+                            match ReceiptFrame::parse(payload_buf) {
+                                Ok((_, Ok(ReceiptFrame::V1(ref v1)))) => {
+                                    match procedures::verify_receipt(
+                                        header.get_sender(),
+                                        address,
+                                        v1,
+                                    ) {
+                                        Some(receipt) => receipts.insert(receipt).await,
+                                        None => warn!(
+                                            "Rejected receipt with invalid signature from {}",
+                                            header.get_sender().pretty_string()
+                                        ),
+                                    }
+                                }
+                                Ok((_, Err(e))) => warn!("Failed to parse receipt: {e}"),
+                                Err(e) => warn!("Completely failed receipt parsing: {e}"),
+                            }
+                        }
+                        // Block requests are answered from the local journal
+                        else if mode == RETRANSMIT {
+                            if let Err(e) = retransmit_tx
+                                .send(InMemoryEnvelope { header, buffer })
+                                .await
+                            {
+                                error!("failed to queue retransmission request: {e}");
+                            }
+                        }
                         // If it's a manifest we queue that and start the ingress machine
-                        if mode == MANIFEST {
+                        else if mode == MANIFEST {
                             let manifest_id = header.get_seq_id().unwrap().hash;
 
                             if let Err(e) = journal
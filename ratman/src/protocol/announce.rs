@@ -1,8 +1,10 @@
-use crate::{context::RatmanContext, crypto, procedures, storage::MetadataDb};
+use crate::{context::RatmanContext, crypto, procedures, routes::verify, storage::MetadataDb};
 use ed25519_dalek::ed25519::signature::SignerMut;
 use libratman::{
     frame::{
-        carrier::{AnnounceFrame, AnnounceFrameV1, CarrierFrameHeader, OriginDataV1, RouteDataV1},
+        carrier::{
+            schemes, AnnounceFrame, AnnounceFrameV1, CarrierFrameHeader, OriginDataV1, RouteDataV1,
+        },
         FrameGenerator,
     },
     tokio::time,
@@ -35,8 +37,8 @@ impl AddressAnnouncer {
     pub(crate) async fn generate_announce(&self) -> Result<AnnounceFrame> {
         let origin = OriginDataV1::now();
         let origin_signature = {
-            let mut origin_buf = vec![];
-            origin.clone().generate(&mut origin_buf).unwrap();
+            // The header of every announcement advertises the newest scheme
+            let origin_buf = verify::signed_origin(&origin, schemes::LATEST);
             let mut key = crypto::get_addr_key(&self.db, self.addr, self.auth).await?;
 
             // return signature
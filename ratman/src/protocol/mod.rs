@@ -28,7 +28,7 @@ pub(crate) use router::RouterAnnouncement;
 /// Provide a builder API to construct different types of Messages
 #[derive(Default)]
 pub(crate) struct Protocol {
-    online: Mutex<BTreeMap<Address, oneshot::Sender<()>>>,
+    online: Mutex<BTreeMap<Address, (AddrAuth, oneshot::Sender<()>)>>,
     anycasts: Mutex<BTreeMap<Namespace, MpscSender<(Address, Duration)>>>,
     online_namespaces: Mutex<BTreeMap<Namespace, Vec<Address>>>,
     #[cfg(feature = "dashboard")]
@@ -87,6 +87,19 @@ impl Protocol {
         Ok(())
     }
 
+    /// Move all listeners of a namespace to a new (rotated) namespace key
+    pub(crate) async fn move_namespace(&self, from: Namespace, to: Namespace) {
+        let mut namespaces = self.online_namespaces.lock().await;
+        if let Some(listeners) = namespaces.remove(&from) {
+            namespaces.entry(to).or_default().extend(listeners);
+        }
+    }
+
+    /// Mark a namespace as down for all of its listeners
+    pub(crate) async fn remove_namespace(&self, namespace: Namespace) {
+        self.online_namespaces.lock().await.remove(&namespace);
+    }
+
     pub(crate) async fn get_namespace_listeners(
         self: &Arc<Self>,
         namespace: Namespace,
@@ -109,15 +122,11 @@ impl Protocol {
         let mut map = self.online.lock().await;
 
         let (tx, mut rx) = oneshot::channel::<()>();
-        map.insert(address, tx);
-        let announce_delay = (&ctx)
-            .config
-            .get_subtree("ratmand")
-            .and_then(|subtree| subtree.get_number_value("announce_delay"))
-            .unwrap_or_else(|| {
-                debug!("ratmand/announce_delay was not set, assuming default of 2 seconds");
-                2
-            }) as u16;
+        map.insert(address, (auth, tx));
+        drop(map);
+
+        // Open sealed blocks that arrived while the address was offline
+        ctx.collector.unpark(address).await;
 
         spawn(async move {
             // Split into a separate function to make tracing it easier
@@ -132,6 +141,8 @@ impl Protocol {
 
             loop {
                 let ctx = Arc::clone(&ctx);
+                // Read the delay every time, so configuration reloads apply
+                let announce_delay = announce_delay(&ctx);
                 select! {
                     biased;
                     _ = ctx.tripwire.clone() => break,
@@ -154,6 +165,14 @@ impl Protocol {
         Ok(())
     }
 
+    /// Get the client authenticator of an address that is currently online
+    ///
+    /// This is used to unlock address keys on the receive path, even when no
+    /// client is connected for the address.
+    pub(crate) async fn get_online_auth(&self, addr: Address) -> Option<AddrAuth> {
+        self.online.lock().await.get(&addr).map(|(auth, _)| *auth)
+    }
+
     pub(crate) async fn offline(&self, addr: Address) -> Result<()> {
         info!("Setting address {} to 'offline'", addr.pretty_string());
         self.online
@@ -191,6 +210,14 @@ impl Protocol {
     }
 }
 
+/// Get the configured delay between address announcements
+fn announce_delay(ctx: &RatmanContext) -> u16 {
+    ctx.config()
+        .get_subtree("ratmand")
+        .and_then(|subtree| subtree.get_number_value("announce_delay"))
+        .unwrap_or(2) as u16
+}
+
 #[cfg(feature = "dashboard")]
 mod metrics {
     use prometheus_client::{metrics::counter::Counter, registry::Registry};
@@ -17,19 +17,23 @@ impl RouterAnnouncement {
         spawn(async move {
             debug!("Start router announcer with key_id={}", self.key_id);
 
-            // hardcoded 512MB of buffer because we don't have any quotas yet.
-            let remaining = /* 512 MB */ (512 * 1024 * 1024) - ctx.journal.frames.0.disk_space();
-
             loop {
                 let ctx = Arc::clone(&ctx);
                 select! {
                     biased;
                     _ = ctx.tripwire.clone() => break,
                     _ = sleep(Duration::from_secs(30))  => {
+                        let known_peers = match ctx.routes.list_remote().await {
+                            Ok(peers) => peers.len() as u32,
+                            Err(e) => {
+                                warn!("failed to count known peers: {e}");
+                                0
+                            }
+                        };
                         let router_announce = RouterMeta {
                             key_id: self.key_id,
-                            available_buffer: remaining,
-                            known_peers: 0,
+                            available_buffer: ctx.journal.remaining_buffer(),
+                            known_peers,
                         };
 
                         let announce_buffer = {
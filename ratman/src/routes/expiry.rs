@@ -0,0 +1,197 @@
+// SPDX-FileCopyrightText: 2024 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+//! Routing table expiry policy
+//!
+//! Links that haven't delivered an announcement for a while are first marked
+//! `idle` by the route activity tasks.  When they stay silent for longer than
+//! the policy allows they are considered `lost` and dropped from the route.
+//! The most recently seen link of a peer is kept around (in the `lost` state)
+//! so that data can still be handed to it via store & forward, until the peer
+//! itself hasn't been seen for long enough to be forgotten entirely.
+
+use crate::{
+    config::SubConfig,
+    storage::route::{RouteData, RouteState},
+};
+use chrono::{DateTime, Duration, Utc};
+
+/// Decide when links and peers are removed from the routing table
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ExpiryPolicy {
+    /// Inactive links that haven't been seen for this long are lost
+    pub lost_link_after: Duration,
+    /// Peers that haven't been seen on any link for this long are forgotten
+    pub forget_peer_after: Duration,
+    /// How often the routing table is pruned automatically
+    pub interval: std::time::Duration,
+}
+
+impl Default for ExpiryPolicy {
+    fn default() -> Self {
+        Self {
+            lost_link_after: Duration::hours(24),
+            forget_peer_after: Duration::days(30),
+            interval: std::time::Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+/// The result of applying an expiry policy to a single route
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Expiry {
+    /// Nothing about this route has changed
+    Keep,
+    /// The route was changed and some number of links were dropped
+    Update { dropped_links: u32 },
+    /// The peer should be removed from the routing table
+    Forget,
+}
+
+impl ExpiryPolicy {
+    /// Read the `route_expiry` block from the `ratmand` configuration tree
+    ///
+    /// Missing or invalid values use the default policy.
+    pub(crate) fn from_config(ratmand: &SubConfig) -> Self {
+        let default = Self::default();
+        let tree = match ratmand.get_subtree("route_expiry") {
+            Some(tree) => tree,
+            None => return default,
+        };
+        let positive = |key| tree.get_number_value(key).filter(|value| *value > 0);
+
+        Self {
+            lost_link_after: positive("lost_link_hours")
+                .map(Duration::hours)
+                .unwrap_or(default.lost_link_after),
+            forget_peer_after: positive("forget_peer_days")
+                .map(Duration::days)
+                .unwrap_or(default.forget_peer_after),
+            interval: positive("interval_minutes")
+                .map(|minutes| std::time::Duration::from_secs(minutes as u64 * 60))
+                .unwrap_or(default.interval),
+        }
+    }
+
+    /// Apply this policy to a remote route
+    pub(crate) fn apply(&self, route: &mut RouteData, now: DateTime<Utc>) -> Expiry {
+        // Local addresses don't have any links and never expire
+        let newest = match route
+            .link_data
+            .iter()
+            .max_by_key(|(_, entry)| entry.last_seen)
+        {
+            Some((nb, entry)) => (*nb, entry.last_seen),
+            None => return Expiry::Keep,
+        };
+
+        if now - newest.1 > self.forget_peer_after {
+            return Expiry::Forget;
+        }
+
+        let mut changed = false;
+        let mut dropped_links = 0;
+        route.link_data.retain(|nb, entry| {
+            if entry.state == RouteState::Active || now - entry.last_seen <= self.lost_link_after {
+                return true;
+            }
+
+            // Keep the newest link so that the peer stays reachable via
+            // store & forward until it is forgotten
+            if *nb == newest.0 {
+                changed |= entry.state != RouteState::Lost;
+                entry.state = RouteState::Lost;
+                true
+            } else {
+                dropped_links += 1;
+                false
+            }
+        });
+        let link_data = &route.link_data;
+        route.link_id.retain(|nb| link_data.contains_key(nb));
+
+        if changed || dropped_links > 0 {
+            Expiry::Update { dropped_links }
+        } else {
+            Expiry::Keep
+        }
+    }
+}
+
+#[test]
+fn expire_links_and_peers() {
+    use crate::{routes::EpNeighbourPair, storage::route::RouteEntry};
+    use libratman::{
+        frame::carrier::RouteDataV1,
+        types::{Address, Ident32},
+    };
+
+    let now = Utc::now();
+    let policy = ExpiryPolicy::default();
+    let mut route = RouteData::local(Address::random());
+    assert_eq!(policy.apply(&mut route, now), Expiry::Keep);
+
+    // One active link, and two links that went idle two and three days ago
+    for (idx, (state, age)) in [
+        (RouteState::Active, Duration::zero()),
+        (RouteState::Idle, Duration::days(2)),
+        (RouteState::Idle, Duration::days(3)),
+    ]
+    .iter()
+    .enumerate()
+    {
+        let nb = EpNeighbourPair(idx, Ident32::random());
+        route.link_id.push(nb);
+        route.link_data.insert(
+            nb,
+            RouteEntry {
+                data: RouteDataV1 { available_mtu: 0 },
+                state: *state,
+                ping: std::time::Duration::from_millis(10),
+                first_seen: now - Duration::days(7),
+                last_seen: now - *age,
+            },
+        );
+    }
+
+    assert_eq!(
+        policy.apply(&mut route, now),
+        Expiry::Update { dropped_links: 2 }
+    );
+    assert_eq!(route.link_id.len(), 1);
+    assert_eq!(policy.apply(&mut route, now), Expiry::Keep);
+
+    // Without the active link, the newest link is kept as lost
+    let mut route = RouteData::local(route.peer);
+    for (idx, age) in [Duration::days(2), Duration::days(3)].iter().enumerate() {
+        let nb = EpNeighbourPair(idx, Ident32::random());
+        route.link_id.push(nb);
+        route.link_data.insert(
+            nb,
+            RouteEntry {
+                data: RouteDataV1 { available_mtu: 0 },
+                state: RouteState::Idle,
+                ping: std::time::Duration::from_millis(10),
+                first_seen: now - Duration::days(7),
+                last_seen: now - *age,
+            },
+        );
+    }
+
+    assert_eq!(
+        policy.apply(&mut route, now),
+        Expiry::Update { dropped_links: 1 }
+    );
+    assert_eq!(route.link_data.len(), 1);
+    assert_eq!(
+        route.link_data.values().next().unwrap().state,
+        RouteState::Lost
+    );
+
+    // Eventually the peer is forgotten
+    assert_eq!(
+        policy.apply(&mut route, now + Duration::days(31)),
+        Expiry::Forget
+    );
+}
@@ -34,8 +34,24 @@
 //! This module consists of two main parts: the main routing table type
 //! utilities, and route scoring, which is how Ratman decides on a route if
 //! multiple active options exist.
+//!
+//! Route scorers can be selected and ordered via the `scorers` list in the
+//! `ratmand` configuration tree.  Applications embedding ratmand can provide
+//! their own scorers via a [`ScorerRegistry`].
 
 mod scoring;
+pub use scoring::{
+    BoxedScorer, DefaultScorer, MultiPathScorer, RouteScorer, ScorerConfiguration, ScorerRegistry,
+    StoreForwardScorer, TRUST_NEUTRAL,
+};
 
 mod table;
-pub(crate) use table::{EpNeighbourPair, RouteTable};
+pub use crate::storage::route::{RouteData, RouteEntry, RouteState};
+
+mod expiry;
+pub use expiry::ExpiryPolicy;
+
+pub(crate) mod verify;
+pub use table::EpNeighbourPair;
+pub(crate) use table::RouteTable;
+pub use verify::{AnnounceRejection, AnnounceWindow, MAX_ANNOUNCE_AGE, MAX_CLOCK_SKEW};
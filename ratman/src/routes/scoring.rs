@@ -7,14 +7,26 @@ use libratman::{
     endpoint::NeighbourMetrics, frame::carrier::AnnounceFrame, types::Address, NonfatalError,
     RatmanError, Result,
 };
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use super::EpNeighbourPair;
 
+/// The trust weight of any address that wasn't explicitly configured
+pub const TRUST_NEUTRAL: u32 = 100;
+
 #[derive(Default)]
 pub struct ScorerConfiguration {
-    /// Calculate trust scores for an address
-    #[allow(unused)]
+    /// Operator-provided trust weights for an address
+    ///
+    /// Use [`trust_weight`](Self::trust_weight) to also take the default
+    /// weight into account.
     pub trust: BTreeMap<Address, u32>,
     /// Available measured bandwidth for a connection
     pub available_bw: BTreeMap<EpNeighbourPair, NeighbourMetrics>,
@@ -22,6 +34,93 @@ pub struct ScorerConfiguration {
     pub available_buffer: BTreeMap<EpNeighbourPair, u64>,
 }
 
+impl ScorerConfiguration {
+    /// Get the trust weight for an address
+    ///
+    /// A weight of `0` means that no route to this address should ever be
+    /// selected.  Addresses without a configured weight are considered
+    /// neutral ([`TRUST_NEUTRAL`]).
+    pub fn trust_weight(&self, addr: &Address) -> u32 {
+        self.trust.get(addr).copied().unwrap_or(TRUST_NEUTRAL)
+    }
+}
+
+/// A route scorer that can be owned by the routing table
+pub type BoxedScorer = Box<dyn RouteScorer + Send + Sync + 'static>;
+
+/// A set of named route scorers that can be selected via the configuration
+///
+/// The built-in scorers are always available as `default` and
+/// `store-forward`.  Applications embedding ratmand can register
+/// additional scorers, which can then be selected and ordered via the
+/// `scorers` list in the `ratmand` configuration tree.
+pub struct ScorerRegistry {
+    scorers: BTreeMap<String, BoxedScorer>,
+}
+
+impl Default for ScorerRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScorerRegistry {
+    /// Name of the [`DefaultScorer`]
+    pub const DEFAULT: &'static str = "default";
+    /// Name of the [`StoreForwardScorer`]
+    pub const STORE_FORWARD: &'static str = "store-forward";
+    /// Name of the [`MultiPathScorer`]
+    pub const MULTI_PATH: &'static str = "multi-path";
+
+    /// Create a new registry with all built-in scorers
+    pub fn new() -> Self {
+        Self {
+            scorers: BTreeMap::new(),
+        }
+        .register(Self::DEFAULT, DefaultScorer)
+        .register(Self::STORE_FORWARD, StoreForwardScorer)
+        .register(Self::MULTI_PATH, MultiPathScorer::default())
+    }
+
+    /// Add a new scorer, replacing any existing scorer with the same name
+    pub fn register(
+        mut self,
+        name: impl Into<String>,
+        scorer: impl RouteScorer + Send + Sync + 'static,
+    ) -> Self {
+        self.scorers.insert(name.into(), Box::new(scorer));
+        self
+    }
+
+    /// Take scorers out of the registry in the order they were selected
+    ///
+    /// Unknown (or repeated) names are logged and skipped.  If no valid
+    /// scorer was selected, the built-in order of `default` and
+    /// `store-forward` is used instead.
+    pub(crate) fn select(mut self, names: &[String]) -> Vec<BoxedScorer> {
+        let selected: Vec<_> = names
+            .iter()
+            .filter_map(|name| match self.scorers.remove(name) {
+                Some(scorer) => Some(scorer),
+                None => {
+                    warn!("Route scorer '{name}' is unknown or was already selected; skipping");
+                    None
+                }
+            })
+            .collect();
+
+        if selected.is_empty() {
+            if !names.is_empty() {
+                warn!("No valid route scorers were selected: using built-in defaults");
+            }
+
+            vec![Box::new(DefaultScorer), Box::new(StoreForwardScorer)]
+        } else {
+            selected
+        }
+    }
+}
+
 #[async_trait]
 pub trait RouteScorer {
     /// Provide a mechanism to pre-configure a route scoring module
@@ -60,7 +159,8 @@ pub trait RouteScorer {
 ///
 /// This strategy mainly uses the captured ping time to a given address, with
 /// the available bandwidth as a tie-breaker for connections that have very
-/// similar pings (~10% of each other).
+/// similar pings (~10% of the fastest one).  Links that aren't active are
+/// ignored; if no link is active we must fail-over into the next scorer.
 pub struct DefaultScorer;
 
 #[async_trait]
@@ -73,124 +173,367 @@ impl RouteScorer for DefaultScorer {
         cfg: &ScorerConfiguration,
         meta: &RouteData,
     ) -> Result<EpNeighbourPair> {
-        // If there's no route available we return an error.  This case SHOULD
-        // never occur but you never know
-        if meta.link_id.len() == 0 {
-            return Err(RatmanError::Nonfatal(NonfatalError::NoAvailableRoute));
-        }
+        let active: Vec<_> = meta
+            .link_id
+            .iter()
+            .filter_map(|nb| match meta.link_data.get(nb) {
+                Some(entry) if entry.state == RouteState::Active => Some((nb, entry.ping)),
+                _ => None,
+            })
+            .collect();
 
-        if meta.link_id.len() == 1 {
-            // We know there is at least one available link, but we need to check if
-            // it's active.  If not we must fail-over into the next scorer
-            let link = meta.link_id.get(0).unwrap();
-            let link_data = meta.link_data.get(link).unwrap();
+        let fastest = active
+            .iter()
+            .map(|(_, ping)| ping.as_millis())
+            .min()
+            .ok_or(RatmanError::Nonfatal(NonfatalError::NoAvailableRoute))?;
 
-            if link_data.state == RouteState::Active {
-                return Ok(*link);
-            } else {
-                return Err(RatmanError::Nonfatal(NonfatalError::NoAvailableRoute));
-            }
-        }
-        // There are at least two links, so we look at both, compare the ping
-        // times and use measured bandwidth as a tie-breaker
-        else {
-            let nb_a = meta.link_id.get(0).unwrap();
-            let nb_b = meta.link_id.get(1).unwrap();
-
-            let link_a = meta.link_data.get(nb_a).unwrap();
-            let link_b = meta.link_data.get(nb_b).unwrap();
-
-            // Check that both links are, in fact, active
-            match (link_a.state, link_b.state) {
-                (RouteState::Idle, _)
-                | (_, RouteState::Idle)
-                | (RouteState::Lost, _)
-                | (_, RouteState::Lost) => {
-                    return Err(RatmanError::Nonfatal(NonfatalError::NoAvailableRoute));
-                }
-                _ => {}
-            }
+        // Links within a 10% bound around the fastest ping are compared by
+        // their measured bandwidth.  If any of them wasn't measured, or the
+        // bandwidths are within 2% of each other, the ping decides.
+        let mut close: Vec<_> = active
+            .into_iter()
+            .filter(|(_, ping)| ping.as_millis() * 100 <= fastest * 110)
+            .map(|(nb, ping)| {
+                let bw = cfg.available_bw.get(nb).map(|m| m.write_bandwidth);
+                (nb, ping, bw.filter(|bw| *bw > 0))
+            })
+            .collect();
+        close.sort_by_key(|(_, ping, _)| *ping);
 
-            // Calculate a 10% bound around the ping time in ms.  If the values
-            // are close, the available bandwidth is used as a tie-breaker
-            if link_b.ping.as_millis() >= (link_a.ping.as_millis() * 90 / 100)
-                && link_b.ping.as_millis() <= (link_a.ping.as_millis() * 110 / 100)
-            {
-                let link_a_bw = cfg.available_bw.get(nb_a);
-                let link_b_bw = cfg.available_bw.get(nb_b);
-
-                match (link_a_bw, link_b_bw) {
-                    (Some(a_bw), Some(b_bw)) if b_bw.write_bandwidth > a_bw.write_bandwidth => {
-                        Ok(*nb_b)
-                    }
-                    _ => Ok(*nb_a),
-                }
-            }
-            // Otherwise: just return the first link
-            else {
-                Ok(*nb_a)
-            }
+        let measured: Option<Vec<u64>> = close.iter().map(|(_, _, bw)| *bw).collect();
+        let (nb, _, _) = match measured.and_then(|bws| bws.into_iter().max()) {
+            Some(max_bw) => close
+                .into_iter()
+                .find(|(_, _, bw)| bw.unwrap_or(0) as u128 * 100 >= max_bw as u128 * 98),
+            None => close.into_iter().next(),
         }
+        .ok_or(RatmanError::Nonfatal(NonfatalError::NoAvailableRoute))?;
+        Ok(*nb)
     }
 }
 
 /// A fallback route selection strategy for when no live link exists
 ///
-/// This strategy uses the available bandwdith as well as advertised available
-/// storage space in neighbouring nodes to select the next connection.  It is
-/// possible that this scorer fails to determine a route, if the next hop is
-/// full, at which point routing is paused until a link can be established
-/// again.
+/// This strategy ranks every known link by the same cost as the
+/// [`MultiPathScorer`] (the last measured ping plus the time it takes to
+/// transfer the stream with the measured bandwidth), and picks the cheapest
+/// link whose neighbour has advertised enough storage space for the stream.
+/// It is possible that this scorer fails to determine a route, if the next
+/// hop is full, at which point routing is paused until a link can be
+/// established again.
+///
+/// Data for addresses with a trust weight below [`TRUST_NEUTRAL`] is never
+/// handed to store & forward.
 pub struct StoreForwardScorer;
 
 #[async_trait]
 impl RouteScorer for StoreForwardScorer {
     async fn compute(
         &self,
-        // Currently unused
-        _stream_size: usize,
+        stream_size: usize,
         cfg: &ScorerConfiguration,
         meta: &RouteData,
     ) -> Result<EpNeighbourPair> {
-        // If there's no route available we return an error.  This case SHOULD
-        // never occur but you never know
-        if meta.link_id.len() == 0 {
+        if cfg.trust_weight(&meta.peer) < TRUST_NEUTRAL {
             return Err(RatmanError::Nonfatal(NonfatalError::NoAvailableRoute));
         }
 
-        if meta.link_id.len() == 1 {
-            // We know there is at least one available link, but we need to check if
-            // it's active.  If not we must fail-over into the next scorer
-            let link = meta.link_id.get(0).unwrap();
-            return Ok(*link);
-        } else {
-            let nb_a = meta.link_id.get(0).unwrap();
-            let nb_b = meta.link_id.get(1).unwrap();
-
-            let link_a_buffer = cfg.available_buffer.get(&nb_a);
-            let link_b_buffer = cfg.available_buffer.get(&nb_b);
-
-            let link_a_bw = cfg.available_bw.get(&nb_a);
-            let link_b_bw = cfg.available_bw.get(&nb_b);
-
-            match (link_a_buffer, link_b_buffer, link_a_bw, link_b_bw) {
-                (_, _, Some(_), None) => Ok(*nb_a),
-                (_, _, None, Some(_)) => Ok(*nb_b),
-                (Some(ba), Some(bb), Some(bwa), Some(bwb)) => {
-                    // If the bandwidth is within 10% of each other
-                    if bwb.write_bandwidth >= (bwa.write_bandwidth * 90 / 100)
-                        && bwb.write_bandwidth <= (bwa.write_bandwidth * 110 / 100)
-                        // And the buffer for b is bigger than for a
-                        && bb > ba
-                    {
-                        Ok(*nb_b)
-                    } else {
-                        Ok(*nb_a)
-                    }
-                }
-                // If we couldn't measure anything useful, use the first link
-                _ => Ok(*nb_a),
-            }
+        // None of the links are active, so their pings may be stale, but it's
+        // the best guess we have
+        rank_links(stream_size, cfg, meta, |nb| {
+            Some(
+                meta.link_data
+                    .get(nb)
+                    .map(|entry| entry.ping)
+                    .unwrap_or_default(),
+            )
+        })
+        .into_iter()
+        .find(|(_, low_buffer, _)| !low_buffer)
+        .map(|(nb, _, _)| nb)
+        .ok_or(RatmanError::Nonfatal(NonfatalError::NoAvailableRoute))
+    }
+}
+
+/// Rank the links of a route by the estimated cost of sending a stream
+///
+/// Only links for which `ping` returns a value are considered.  Each entry
+/// contains the link, whether its neighbour advertised less buffer space than
+/// the stream needs, and the cost in microseconds.  Links with too little
+/// buffer space are sorted last, regardless of their cost.
+fn rank_links(
+    stream_size: usize,
+    cfg: &ScorerConfiguration,
+    meta: &RouteData,
+    ping: impl Fn(&EpNeighbourPair) -> Option<Duration>,
+) -> Vec<(EpNeighbourPair, bool, u128)> {
+    let mut ranked: Vec<_> = meta
+        .link_id
+        .iter()
+        .filter_map(|nb| {
+            let ping = ping(nb)?;
+            let bandwidth = cfg
+                .available_bw
+                .get(nb)
+                .map(|m| m.write_bandwidth)
+                .filter(|bw| *bw > 0)
+                .unwrap_or(MultiPathScorer::FALLBACK_BANDWIDTH);
+            let transfer = stream_size as u128 * 1_000_000 / bandwidth as u128;
+
+            let enough_buffer = cfg
+                .available_buffer
+                .get(nb)
+                .is_none_or(|buf| *buf >= stream_size as u64);
+
+            Some((*nb, !enough_buffer, ping.as_micros() + transfer))
+        })
+        .collect();
+
+    ranked.sort_by_key(|(_, low_buffer, cost)| (*low_buffer, *cost));
+    ranked
+}
+
+/// A route selection strategy that considers every available link
+///
+/// Each active link is given a cost, which is the estimated time it takes to
+/// deliver a stream over it: the last measured ping plus the stream size
+/// divided by the measured bandwidth.  Links that have advertised less buffer
+/// space than the stream needs are only used if nothing else is available.
+///
+/// Streams of at least [`SPREAD_THRESHOLD`](Self::SPREAD_THRESHOLD) bytes are
+/// spread across all links that are at most twice as expensive as the best
+/// one.  Frames carry their own sequence numbers so the receiving side
+/// re-assembles blocks regardless of which link they arrived on.
+#[derive(Default)]
+pub struct MultiPathScorer {
+    next: AtomicUsize,
+}
+
+impl MultiPathScorer {
+    /// Minimum stream size in bytes to spread frames across several links
+    pub const SPREAD_THRESHOLD: usize = 256 * 1024;
+
+    /// Assumed write bandwidth (bytes per second) for unmeasured links
+    pub const FALLBACK_BANDWIDTH: u64 = 64 * 1024;
+
+    /// Rank all active links for a route, from cheapest to most expensive
+    ///
+    /// The cost of each link is given in microseconds.  Links that have
+    /// advertised less buffer space than the stream needs are sorted last.
+    pub fn rank(
+        stream_size: usize,
+        cfg: &ScorerConfiguration,
+        meta: &RouteData,
+    ) -> Vec<(EpNeighbourPair, u128)> {
+        rank_links(stream_size, cfg, meta, |nb| {
+            meta.link_data
+                .get(nb)
+                .filter(|entry| entry.state == RouteState::Active)
+                .map(|entry| entry.ping)
+        })
+        .into_iter()
+        .map(|(nb, _, cost)| (nb, cost))
+        .collect()
+    }
+}
+
+#[async_trait]
+impl RouteScorer for MultiPathScorer {
+    async fn compute(
+        &self,
+        stream_size: usize,
+        cfg: &ScorerConfiguration,
+        meta: &RouteData,
+    ) -> Result<EpNeighbourPair> {
+        let ranked = Self::rank(stream_size, cfg, meta);
+        let (best, best_cost) = *ranked
+            .first()
+            .ok_or(RatmanError::Nonfatal(NonfatalError::NoAvailableRoute))?;
+
+        if stream_size < Self::SPREAD_THRESHOLD {
+            return Ok(best);
         }
+
+        let spread: Vec<_> = ranked
+            .iter()
+            .take_while(|(_, cost)| *cost <= best_cost.saturating_mul(2))
+            .collect();
+        let idx = self.next.fetch_add(1, Ordering::Relaxed) % spread.len();
+        Ok(spread[idx].0)
+    }
+}
+
+#[cfg(test)]
+fn test_route(pings: &[u64]) -> RouteData {
+    use crate::storage::route::RouteEntry;
+    use chrono::Utc;
+    use libratman::{frame::carrier::RouteDataV1, types::Ident32};
+
+    let mut route = RouteData::local(Address::random());
+    for (idx, ping) in pings.iter().enumerate() {
+        let nb = EpNeighbourPair(idx, Ident32::random());
+        route.link_id.push(nb);
+        route.link_data.insert(
+            nb,
+            RouteEntry {
+                data: RouteDataV1 {
+                    available_mtu: 1200,
+                },
+                state: RouteState::Active,
+                ping: Duration::from_millis(*ping),
+                first_seen: Utc::now(),
+                last_seen: Utc::now(),
+            },
+        );
     }
+    route
+}
+
+#[test]
+fn multi_path_rank_all_links() {
+    let route = test_route(&[40, 30, 10]);
+    let mut cfg = ScorerConfiguration::default();
+
+    // With tiny streams the ping dominates, so the third link wins
+    let ranked = MultiPathScorer::rank(0, &cfg, &route);
+    assert_eq!(ranked.len(), 3);
+    assert_eq!(ranked[0].0, route.link_id[2]);
+
+    // A large stream prefers the link with the highest bandwidth
+    cfg.available_bw.insert(
+        route.link_id[0],
+        NeighbourMetrics {
+            write_bandwidth: 10 * 1024 * 1024,
+            read_bandwidth: 0,
+        },
+    );
+    let ranked = MultiPathScorer::rank(1024 * 1024, &cfg, &route);
+    assert_eq!(ranked[0].0, route.link_id[0]);
+
+    // Unless that link doesn't have enough buffer space
+    cfg.available_buffer.insert(route.link_id[0], 1024);
+    let ranked = MultiPathScorer::rank(1024 * 1024, &cfg, &route);
+    assert_eq!(ranked[2].0, route.link_id[0]);
+}
+
+#[cfg(test)]
+use libratman::tokio;
+
+#[libratman::tokio::test]
+async fn default_rank_all_links() {
+    let scorer = DefaultScorer;
+    let mut cfg = ScorerConfiguration::default();
+
+    // Routes without any (known) links don't panic
+    let mut route = test_route(&[]);
+    assert!(scorer.compute(0, &cfg, &route).await.is_err());
+    route
+        .link_id
+        .push(EpNeighbourPair(0, libratman::types::Ident32::random()));
+    assert!(scorer.compute(0, &cfg, &route).await.is_err());
+
+    // The fastest link wins, even if it isn't one of the first two
+    let mut route = test_route(&[40, 30, 10, 11]);
+    assert_eq!(
+        scorer.compute(0, &cfg, &route).await.unwrap(),
+        route.link_id[2]
+    );
+
+    // Bandwidth breaks ties between similar pings, if it was measured
+    let bw = |write_bandwidth| NeighbourMetrics {
+        write_bandwidth,
+        read_bandwidth: 0,
+    };
+    cfg.available_bw.insert(route.link_id[3], bw(1024));
+    assert_eq!(
+        scorer.compute(0, &cfg, &route).await.unwrap(),
+        route.link_id[2]
+    );
+    cfg.available_bw.insert(route.link_id[2], bw(1010));
+    assert_eq!(
+        scorer.compute(0, &cfg, &route).await.unwrap(),
+        route.link_id[2]
+    );
+    cfg.available_bw.insert(route.link_id[2], bw(512));
+    assert_eq!(
+        scorer.compute(0, &cfg, &route).await.unwrap(),
+        route.link_id[3]
+    );
+
+    // Inactive links are ignored
+    route.link_data.get_mut(&route.link_id[3]).unwrap().state = RouteState::Lost;
+    route.link_data.get_mut(&route.link_id[2]).unwrap().state = RouteState::Idle;
+    assert_eq!(
+        scorer.compute(0, &cfg, &route).await.unwrap(),
+        route.link_id[1]
+    );
+}
+
+#[libratman::tokio::test]
+async fn store_forward_rank_all_links() {
+    let scorer = StoreForwardScorer;
+    let mut cfg = ScorerConfiguration::default();
+
+    // Routes without any links have no store & forward route either
+    assert!(scorer.compute(0, &cfg, &test_route(&[])).await.is_err());
+
+    // None of the links are active, but the fastest one is still preferred
+    let mut route = test_route(&[40, 30, 10, 20]);
+    for entry in route.link_data.values_mut() {
+        entry.state = RouteState::Idle;
+    }
+    assert_eq!(
+        scorer.compute(0, &cfg, &route).await.unwrap(),
+        route.link_id[2]
+    );
+
+    // A large stream prefers the link with the highest bandwidth
+    let stream_size = 1024 * 1024;
+    cfg.available_bw.insert(
+        route.link_id[3],
+        NeighbourMetrics {
+            write_bandwidth: 10 * 1024 * 1024,
+            read_bandwidth: 0,
+        },
+    );
+    assert_eq!(
+        scorer.compute(stream_size, &cfg, &route).await.unwrap(),
+        route.link_id[3]
+    );
+
+    // Unless its neighbour doesn't have enough buffer space for the stream
+    cfg.available_buffer.insert(route.link_id[3], 1024);
+    assert_eq!(
+        scorer.compute(stream_size, &cfg, &route).await.unwrap(),
+        route.link_id[2]
+    );
+
+    // If every neighbour is full the stream has to wait
+    for nb in &route.link_id {
+        cfg.available_buffer.insert(*nb, 0);
+    }
+    assert!(scorer.compute(stream_size, &cfg, &route).await.is_err());
+}
+
+#[libratman::tokio::test]
+async fn multi_path_spread_large_streams() {
+    let route = test_route(&[10, 10, 10]);
+    let cfg = ScorerConfiguration::default();
+    let scorer = MultiPathScorer::default();
+
+    let mut used = std::collections::BTreeSet::new();
+    for _ in 0..3 {
+        used.insert(
+            scorer
+                .compute(MultiPathScorer::SPREAD_THRESHOLD, &cfg, &route)
+                .await
+                .unwrap(),
+        );
+    }
+    assert_eq!(used.len(), 3);
+
+    // Small streams always use the cheapest link
+    let first = scorer.compute(0, &cfg, &route).await.unwrap();
+    assert_eq!(scorer.compute(0, &cfg, &route).await.unwrap(), first);
 }
@@ -9,15 +9,20 @@ use crate::{
         route::{RouteData, RouteEntry, RouteState},
         MetadataDb,
     },
-    util::IoPair,
 };
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use libratman::{
-    api::types::PeerEntry,
-    frame::carrier::AnnounceFrameV1,
+    api::types::{PeerEntry, PeerEvent, PrunedPeer},
+    frame::carrier::{schemes, AnnounceFrame, AnnounceFrameV1},
     tokio::{
-        sync::{mpsc::channel, RwLock},
-        task::{spawn_blocking, spawn_local},
+        select,
+        sync::{
+            broadcast::{
+                channel as bcast_channel, Receiver as BcastReceiver, Sender as BcastSender,
+            },
+            Mutex, RwLock,
+        },
+        task::{spawn, spawn_blocking, spawn_local},
         time::sleep,
     },
     types::{Address, Ident32, Neighbour},
@@ -29,34 +34,71 @@ use std::{
     sync::Arc,
     time::Duration,
 };
+use tripwire::Tripwire;
 
-use super::scoring::{DefaultScorer, RouteScorer, ScorerConfiguration, StoreForwardScorer};
+use super::{
+    expiry::{Expiry, ExpiryPolicy},
+    scoring::{BoxedScorer, ScorerConfiguration},
+    verify::{check_origin, AnnounceRejection, AnnounceWindow},
+};
+use crate::context::RatmanContext;
 
 /// Main Ratman routing table
 ///
 /// It keeps track of available addresses and their types (i.e. remote
-/// or local, and an address key or a namespace key).  New addresses
-/// can be polled via the `new` announce channel.
+/// or local, and an address key or a namespace key).  Changes to the
+/// table can be followed via [`subscribe`](Self::subscribe).
 pub(crate) struct RouteTable {
     meta_db: Arc<MetadataDb>,
+    /// Serialises read-modify-write cycles on the stored routes
+    ///
+    /// Announcements, activity checks, and pruning all load a route,
+    /// change it, and write it back.  Without this lock one of them can
+    /// overwrite the changes of another.
+    write_lock: Mutex<()>,
     activity_tasks: Arc<RwLock<BTreeSet<Address>>>,
-    pub(crate) solvers: Vec<Box<dyn RouteScorer + Send + Sync + 'static>>,
+    pub(crate) solvers: Vec<BoxedScorer>,
     pub(crate) solver_state: RwLock<ScorerConfiguration>,
-    #[allow(unused)]
-    new: IoPair<Address>,
+    /// Newest chunk sealing scheme advertised by each remote address
+    ///
+    /// This is refreshed by every announcement and thus doesn't need to
+    /// be persisted between sessions.
+    seal_schemes: RwLock<BTreeMap<Address, u8>>,
+    /// Origin timestamp of the last accepted announcement for each address
+    ///
+    /// Used to reject replayed announcements.  Announcements outside of
+    /// the `announce_window` are rejected anyway, so this doesn't need to
+    /// be persisted between sessions either.
+    origins: RwLock<BTreeMap<Address, DateTime<Utc>>>,
+    /// The configured range of accepted announcement timestamps
+    pub(crate) announce_window: AnnounceWindow,
+    /// The configured policy for dropping stale links and peers
+    pub(crate) expiry: ExpiryPolicy,
+    events: BcastSender<PeerEvent>,
     #[allow(unused)]
     #[cfg(feature = "dashboard")]
     metrics: metrics::RouteTableMetrics,
 }
 
 impl RouteTable {
-    pub(crate) fn new(meta_db: Arc<MetadataDb>) -> Arc<Self> {
+    pub(crate) fn new(
+        meta_db: Arc<MetadataDb>,
+        solvers: Vec<BoxedScorer>,
+        solver_state: ScorerConfiguration,
+        expiry: ExpiryPolicy,
+        announce_window: AnnounceWindow,
+    ) -> Arc<Self> {
         let this = Arc::new(Self {
             meta_db,
+            write_lock: Default::default(),
             activity_tasks: Default::default(),
-            solvers: vec![Box::new(DefaultScorer), Box::new(StoreForwardScorer)],
-            solver_state: RwLock::new(ScorerConfiguration::default()),
-            new: channel(1),
+            solvers,
+            solver_state: RwLock::new(solver_state),
+            seal_schemes: Default::default(),
+            origins: Default::default(),
+            announce_window,
+            expiry,
+            events: bcast_channel(64).0,
             #[cfg(feature = "dashboard")]
             metrics: metrics::RouteTableMetrics::default(),
         });
@@ -89,7 +131,7 @@ impl RouteTable {
 
 /// A netmod endpoint ID and an endpoint target ID
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
-pub(crate) struct EpNeighbourPair(pub(crate) usize, pub(crate) Ident32);
+pub struct EpNeighbourPair(pub usize, pub Ident32);
 
 /// An ephemeral routing table
 ///
@@ -104,10 +146,55 @@ impl RouteTable {
         self.metrics.register(registry);
     }
 
+    /// Verify the origin of an announcement before it is used
+    ///
+    /// The origin signature must match the sender address and cover the
+    /// advertised seal scheme, and the origin timestamp must be recent and
+    /// newer than the last accepted announcement from this sender.
+    /// Rejections are counted in the route table metrics.
+    pub(crate) async fn verify_announcement(
+        &self,
+        sender: Address,
+        announce: &AnnounceFrameV1,
+        seal_scheme: u8,
+    ) -> std::result::Result<(), AnnounceRejection> {
+        let mut origins = self.origins.write().await;
+        let result = check_origin(
+            sender,
+            announce,
+            seal_scheme,
+            origins.get(&sender).copied(),
+            &self.announce_window,
+            Utc::now(),
+        );
+
+        match result {
+            Ok(()) => {
+                origins.insert(sender, announce.origin.timestamp());
+            }
+            Err(_reason) => {
+                #[cfg(feature = "dashboard")]
+                self.metrics
+                    .announcements_rejected
+                    .get_or_create(&metrics::RejectLabels {
+                        reason: _reason.label().to_owned(),
+                    })
+                    .inc();
+            }
+        }
+
+        result
+    }
+
+    /// Subscribe to changes in the routing table
+    pub(crate) fn subscribe(&self) -> BcastReceiver<PeerEvent> {
+        self.events.subscribe()
+    }
+
     /// Update or add an IDs entry in the routing table
     ///
-    /// If the Id was not previously known to the router, it is queued
-    /// to the `new` set which can be polled by calling `discovered().await`.
+    /// If the Id was not previously known to the router, a
+    /// [`PeerEvent::Discovered`] is sent to all subscribers.
     pub(crate) async fn update(
         self: &Arc<Self>,
         ep_neighbour: EpNeighbourPair,
@@ -117,6 +204,9 @@ impl RouteTable {
         let peer_ping = announce_f.origin.elapsed();
         let newly_active;
         let new_route;
+        let discovered;
+
+        let _write = self.write_lock.lock().await;
 
         match self.meta_db.routes.get(&peer_addr.to_string()).await? {
             Some(RouteData {
@@ -131,6 +221,7 @@ impl RouteTable {
                     .iter()
                     .find(|(_, entry)| entry.state == RouteState::Active)
                     .is_none();
+                discovered = false;
 
                 // Update the peer ping for this neighbour
                 match link_data.get_mut(&ep_neighbour) {
@@ -173,6 +264,7 @@ impl RouteTable {
             None => {
                 info!("Discovered new address: {}", peer_addr.pretty_string());
                 newly_active = true;
+                discovered = true;
                 new_route = RouteData {
                     peer: peer_addr,
                     link_id: vec![ep_neighbour],
@@ -201,6 +293,12 @@ impl RouteTable {
             .routes
             .insert(peer_addr.to_string(), &new_route)
             .await?;
+        drop(_write);
+
+        // Sending only fails if nobody is subscribed
+        if discovered {
+            let _ = self.events.send(PeerEvent::Discovered(peer_addr));
+        }
 
         // If a route is declared active we want to keep checking whether it is
         // still active.  Inactive addresses don't need this since any new
@@ -232,6 +330,7 @@ impl RouteTable {
                 // DOWN and end this task
                 let check = Utc::now();
                 sleep(Duration::from_secs(sleep_time)).await;
+                let _write = self.write_lock.lock().await;
                 match self.meta_db.routes.get(&peer_addr.to_string()).await {
                     Ok(Some(mut entry)) => {
                         // Iterate over all endpoints and mark those that
@@ -276,6 +375,17 @@ impl RouteTable {
     }
 
     pub(crate) async fn list_remote(self: &Arc<Self>) -> Result<Vec<PeerEntry>> {
+        Ok(self
+            .list_remote_routes()
+            .await?
+            .iter()
+            // Construct a PeerEntry type from the available data
+            .map(|entry| entry.make_peer_entry())
+            .collect())
+    }
+
+    /// List the stored route data for all remote addresses
+    pub(crate) async fn list_remote_routes(self: &Arc<Self>) -> Result<Vec<RouteData>> {
         let this = Arc::clone(&self);
         spawn_blocking(move || {
             Ok(this
@@ -287,13 +397,93 @@ impl RouteTable {
                 .into_iter()
                 // Any entry that has link_data is remote
                 .filter(|(_, entry)| !entry.link_data.is_empty())
-                // Then construct a PeerEntry type from the available data
-                .map(|(_, entry)| entry.make_peer_entry())
+                .map(|(_, entry)| entry)
                 .collect())
         })
         .await?
     }
 
+    /// Drop stale links and forget peers according to an expiry policy
+    ///
+    /// Returns the set of peers that were affected, which are also sent
+    /// to all subscribers as [`PeerEvent::Pruned`].
+    pub(crate) async fn prune(self: &Arc<Self>, policy: &ExpiryPolicy) -> Result<Vec<PrunedPeer>> {
+        let _write = self.write_lock.lock().await;
+        let this = Arc::clone(self);
+        let routes = spawn_blocking(move || this.meta_db.routes.iter()).await?;
+
+        let now = Utc::now();
+        let mut pruned = vec![];
+        for (_, mut route) in routes {
+            let peer = route.peer;
+            match policy.apply(&mut route, now) {
+                Expiry::Keep => continue,
+                Expiry::Update { dropped_links } => {
+                    self.meta_db.routes.insert(peer.to_string(), &route).await?;
+                    if dropped_links == 0 {
+                        continue;
+                    }
+
+                    info!(
+                        "Dropped {dropped_links} lost link(s) to {}",
+                        peer.pretty_string()
+                    );
+                    pruned.push(PrunedPeer {
+                        addr: peer,
+                        dropped_links,
+                        forgotten: false,
+                    });
+                }
+                Expiry::Forget => {
+                    self.meta_db.routes.remove(peer.to_string()).await?;
+                    self.seal_schemes.write().await.remove(&peer);
+                    self.origins.write().await.remove(&peer);
+
+                    info!(
+                        "Forgot peer {}: not seen for more than {} days",
+                        peer.pretty_string(),
+                        policy.forget_peer_after.num_days()
+                    );
+                    pruned.push(PrunedPeer {
+                        addr: peer,
+                        dropped_links: route.link_data.len() as u32,
+                        forgotten: true,
+                    });
+                }
+            }
+
+            let last = pruned.last().unwrap();
+            #[cfg(feature = "dashboard")]
+            {
+                self.metrics.links_pruned.inc_by(last.dropped_links as u64);
+                if last.forgotten {
+                    self.metrics.peers_forgotten.inc();
+                }
+            }
+            let _ = self.events.send(PeerEvent::Pruned(last.clone()));
+        }
+
+        Ok(pruned)
+    }
+
+    /// Periodically prune the routing table with the configured policy
+    pub(crate) fn start_expiry_task(self: &Arc<Self>, tripwire: Tripwire) {
+        let this = Arc::clone(self);
+        spawn(async move {
+            loop {
+                select! {
+                    biased;
+                    _ = tripwire.clone() => break,
+                    _ = sleep(this.expiry.interval) => {
+                        if let Err(e) = this.prune(&this.expiry).await {
+                            error!("failed to prune routing table: {e}");
+                        }
+                    }
+                }
+            }
+        });
+    }
+
     pub(crate) async fn register_local_route(&self, local: Address) -> Result<()> {
         let local_addr = RouteData::local(local);
         self.meta_db
@@ -322,12 +512,16 @@ impl RouteTable {
     }
 
     /// Get the endpoint and target ID for a peer's address
+    ///
+    /// `stream_size` is a hint for the size (in bytes) of the stream that the
+    /// resolved route will be used for.
     pub(crate) async fn resolve(
         &self,
         links: &Arc<LinksMap>,
         peer_addr: Address,
+        stream_size: usize,
     ) -> Result<EpNeighbourPair> {
-        let route_data = self
+        let mut route_data = self
             .meta_db
             .routes
             .get(&peer_addr.to_string())
@@ -338,27 +532,44 @@ impl RouteTable {
 
         let mut scorer_state = self.solver_state.write().await;
 
+        // Operators can block routing to an address entirely
+        if scorer_state.trust_weight(&peer_addr) == 0 {
+            debug!(
+                "Refusing to route to untrusted address {}",
+                peer_addr.pretty_string()
+            );
+            return Err(RatmanError::Nonfatal(NonfatalError::NoAvailableRoute));
+        }
+
         // We know what neighbours we are considering, and we have access to the
         // links map here.  So we fill in the available bandwidth metrics into
         // the scorer state here.
-        for EpNeighbourPair(ref link_id, ref neighbour_id) in &route_data.link_id {
-            match links
-                .get(*link_id)
-                .await
-                .1
-                .metrics_for_neighbour(Neighbour::Single(*neighbour_id))
+        //
+        // Links that were removed at runtime can't be used anymore, so they
+        // are not considered by the scorers.
+        let mut available = Vec::with_capacity(route_data.link_id.len());
+        for EpNeighbourPair(link_id, neighbour_id) in route_data.link_id.drain(..) {
+            let ep = match links.get(link_id).await {
+                Some((_, ep)) => ep,
+                None => continue,
+            };
+            available.push(EpNeighbourPair(link_id, neighbour_id));
+
+            match ep
+                .metrics_for_neighbour(Neighbour::Single(neighbour_id))
                 .await
             {
                 Ok(metrics) => {
                     scorer_state
                         .available_bw
-                        .insert(EpNeighbourPair(*link_id, *neighbour_id), metrics);
+                        .insert(EpNeighbourPair(link_id, neighbour_id), metrics);
                 }
                 Err(e) => {
                     warn!("couldn't collect bandwidth metrics for {link_id}:{neighbour_id}: {e}");
                 }
             }
         }
+        route_data.link_id = available;
 
         // Now we iterate all the available solvers in the order they were added
         // to the route table and pass the available route data and scorer state
@@ -369,7 +580,7 @@ impl RouteTable {
                 .solvers
                 .get(idx)
                 .unwrap()
-                .compute(0, &scorer_state, &route_data)
+                .compute(stream_size, &scorer_state, &route_data)
                 .await
             {
                 Ok(ep) => return Ok(ep),
@@ -383,6 +594,91 @@ impl RouteTable {
         Err(RatmanError::Nonfatal(NonfatalError::NoAvailableRoute))
     }
 
+    /// Let every route scorer initialise its state
+    ///
+    /// Scorers that fail to configure themselves are kept, but their error
+    /// is logged.
+    pub(crate) async fn configure_scorers(&self, ctx: &Arc<RatmanContext>) {
+        let mut scorer_state = self.solver_state.write().await;
+        for (idx, solver) in self.solvers.iter().enumerate() {
+            if let Err(e) = solver.configure(ctx, &mut scorer_state).await {
+                warn!("failed to configure route scorer id={idx}: {e}");
+            }
+        }
+    }
+
+    /// Pass a live announcement to every route scorer
+    pub(crate) async fn notify_scorers(&self, announce: &AnnounceFrame) {
+        let mut scorer_state = self.solver_state.write().await;
+        for (idx, solver) in self.solvers.iter().enumerate() {
+            if let Err(e) = solver
+                .irq_live_announcement(announce, &mut scorer_state)
+                .await
+            {
+                debug!("route scorer id={idx} failed to handle announcement: {e}");
+            }
+        }
+    }
+
+    /// Remember the chunk sealing scheme advertised by a remote address
+    ///
+    /// The scheme must come from a verified announcement (see
+    /// [`verify_announcement`](Self::verify_announcement)).
+    pub(crate) async fn set_seal_scheme(&self, peer_addr: Address, scheme: u8) {
+        self.seal_schemes.write().await.insert(peer_addr, scheme);
+    }
+
+    /// Select the chunk sealing scheme to use when sending to an address
+    ///
+    /// Local addresses always use the newest scheme.  Remote addresses use
+    /// whatever they last advertised, and no sealing at all if they haven't
+    /// announced themselves with a newer router version yet.
+    pub(crate) async fn get_seal_scheme(&self, addr: Address) -> u8 {
+        if let Ok(true) = self.is_local(addr).await {
+            return schemes::LATEST;
+        }
+
+        self.seal_schemes
+            .read()
+            .await
+            .get(&addr)
+            .map(|scheme| (*scheme).min(schemes::LATEST))
+            .unwrap_or(schemes::NONE)
+    }
+
+    /// Find the smallest MTU on the known paths to an address
+    ///
+    /// Only active links are considered, unless there are none.  Returns
+    /// `None` for local addresses, and if no announcement for this address
+    /// carried an MTU.
+    pub(crate) async fn path_mtu(&self, peer_addr: Address) -> Option<usize> {
+        let route_data = self
+            .meta_db
+            .routes
+            .get(&peer_addr.to_string())
+            .await
+            .ok()
+            .flatten()?;
+
+        let active: Vec<_> = route_data
+            .link_data
+            .values()
+            .filter(|entry| entry.state == RouteState::Active)
+            .collect();
+        let entries = if active.is_empty() {
+            route_data.link_data.values().collect()
+        } else {
+            active
+        };
+
+        entries
+            .into_iter()
+            .map(|entry| entry.data.available_mtu)
+            .filter(|mtu| *mtu != 0)
+            .min()
+            .map(|mtu| mtu as usize)
+    }
+
     /// Check if an ID is reachable via currently known routes
     ///
     /// - `Some(State)` indicates a remote address with a particular connection
@@ -407,13 +703,73 @@ impl RouteTable {
     }
 }
 
+#[cfg(test)]
+use libratman::tokio;
+
+#[libratman::tokio::test]
+async fn prune_publishes_events() {
+    use libratman::frame::carrier::RouteDataV1;
+
+    let meta_db = Arc::new(
+        MetadataDb::new(
+            fjall::Config::new(tempdir::TempDir::new("routes").unwrap().into_path())
+                .open()
+                .unwrap(),
+        )
+        .unwrap(),
+    );
+    let routes = RouteTable::new(
+        Arc::clone(&meta_db),
+        vec![],
+        Default::default(),
+        Default::default(),
+        Default::default(),
+    );
+    let mut events = routes.subscribe();
+
+    // A peer that was last seen on a single link two months ago
+    let now = Utc::now();
+    let mut route = RouteData::local(Address::random());
+    let nb = EpNeighbourPair(0, Ident32::random());
+    route.link_id.push(nb);
+    route.link_data.insert(
+        nb,
+        RouteEntry {
+            data: RouteDataV1 { available_mtu: 0 },
+            state: RouteState::Lost,
+            ping: Duration::from_millis(10),
+            first_seen: now - chrono::Duration::days(90),
+            last_seen: now - chrono::Duration::days(60),
+        },
+    );
+    meta_db
+        .routes
+        .insert(route.peer.to_string(), &route)
+        .await
+        .unwrap();
+
+    let pruned = routes.prune(&routes.expiry).await.unwrap();
+    assert_eq!(pruned.len(), 1);
+    assert!(pruned[0].forgotten);
+    assert_eq!(
+        events.try_recv().unwrap(),
+        PeerEvent::Pruned(pruned[0].clone())
+    );
+    assert!(meta_db
+        .routes
+        .get(&route.peer.to_string())
+        .await
+        .unwrap()
+        .is_none());
+}
+
 #[cfg(feature = "dashboard")]
 mod metrics {
     //! Metric helpers.
 
     use prometheus_client::{
         encoding::text::Encode,
-        metrics::{family::Family, gauge::Gauge},
+        metrics::{counter::Counter, family::Family, gauge::Gauge},
         registry::Registry,
     };
 
@@ -422,9 +778,17 @@ mod metrics {
         pub kind: String,
     }
 
+    #[derive(Clone, Hash, PartialEq, Eq, Encode)]
+    pub(super) struct RejectLabels {
+        pub reason: String,
+    }
+
     #[derive(Default)]
     pub(super) struct RouteTableMetrics {
         pub routes_count: Family<RouteLabels, Gauge>,
+        pub announcements_rejected: Family<RejectLabels, Counter>,
+        pub links_pruned: Counter,
+        pub peers_forgotten: Counter,
     }
 
     impl RouteTableMetrics {
@@ -434,6 +798,21 @@ mod metrics {
                 "Number of routes currently in the table",
                 Box::new(self.routes_count.clone()),
             );
+            registry.register(
+                "ratman_routes_announcements_rejected",
+                "Total number of announcements rejected during verification",
+                Box::new(self.announcements_rejected.clone()),
+            );
+            registry.register(
+                "ratman_routes_links_pruned",
+                "Total number of lost links dropped from the table",
+                Box::new(self.links_pruned.clone()),
+            );
+            registry.register(
+                "ratman_routes_peers_forgotten",
+                "Total number of peers removed from the table",
+                Box::new(self.peers_forgotten.clone()),
+            );
         }
     }
 }
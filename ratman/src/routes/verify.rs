@@ -0,0 +1,285 @@
+// SPDX-FileCopyrightText: 2024 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+//! Announcement origin verification
+//!
+//! Every announcement carries origin data (currently only a timestamp), which
+//! is signed with the key of the announced address, together with the chunk
+//! sealing scheme advertised in the announcement header.  Before an announcement
+//! is allowed to change the routing table its signature is checked against
+//! the sender address, and its timestamp must be recent and newer than the
+//! last accepted announcement from the same address.  This prevents other
+//! nodes from forging announcements, replaying old ones to pull routes
+//! towards themselves, or downgrading an address to unsealed chunks.
+
+use crate::{config::SubConfig, crypto};
+use chrono::{DateTime, Duration, Utc};
+use ed25519_dalek::Signature;
+use libratman::{
+    frame::{
+        carrier::{schemes, AnnounceFrameV1, OriginDataV1},
+        FrameGenerator,
+    },
+    types::Address,
+};
+use std::fmt;
+
+/// Announcements older than this are rejected as stale, by default
+pub const MAX_ANNOUNCE_AGE: Duration = Duration::seconds(60);
+
+/// Announcements further in the future than this are rejected, by default
+///
+/// This leaves some room for clock drift between different routers.
+pub const MAX_CLOCK_SKEW: Duration = Duration::seconds(30);
+
+/// The range of origin timestamps that announcements are accepted in
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AnnounceWindow {
+    /// Announcements older than this are rejected as stale
+    pub max_age: Duration,
+    /// Announcements further in the future than this are rejected
+    pub max_clock_skew: Duration,
+}
+
+impl Default for AnnounceWindow {
+    fn default() -> Self {
+        Self {
+            max_age: MAX_ANNOUNCE_AGE,
+            max_clock_skew: MAX_CLOCK_SKEW,
+        }
+    }
+}
+
+impl AnnounceWindow {
+    /// Read `max_announce_age_secs` and `max_clock_skew_secs` from the
+    /// `ratmand` configuration tree
+    ///
+    /// Missing or invalid values use the defaults.
+    pub(crate) fn from_config(ratmand: &SubConfig) -> Self {
+        let default = Self::default();
+        let positive = |key| ratmand.get_number_value(key).filter(|value| *value > 0);
+
+        Self {
+            max_age: positive("max_announce_age_secs")
+                .map(Duration::seconds)
+                .unwrap_or(default.max_age),
+            max_clock_skew: positive("max_clock_skew_secs")
+                .map(Duration::seconds)
+                .unwrap_or(default.max_clock_skew),
+        }
+    }
+}
+
+/// Reasons why an announcement was rejected
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AnnounceRejection {
+    /// The origin signature doesn't match the sender address
+    InvalidSignature,
+    /// The origin timestamp is older than the announce window allows
+    Stale,
+    /// The origin timestamp is further ahead than the announce window allows
+    FromFuture,
+    /// An announcement with the same or a newer timestamp was already accepted
+    Replayed,
+}
+
+impl AnnounceRejection {
+    /// A short name for logs and metric labels
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::InvalidSignature => "invalid_signature",
+            Self::Stale => "stale",
+            Self::FromFuture => "from_future",
+            Self::Replayed => "replayed",
+        }
+    }
+}
+
+impl fmt::Display for AnnounceRejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.label())
+    }
+}
+
+/// Encode the data that an announcement signature covers
+///
+/// The seal scheme is only appended when it isn't `NONE`, so stripping it
+/// from the announcement header invalidates the signature.
+pub(crate) fn signed_origin(origin: &OriginDataV1, seal_scheme: u8) -> Vec<u8> {
+    let mut buf = vec![];
+    (*origin)
+        .generate(&mut buf)
+        .expect("failed to encode origin data");
+    if seal_scheme != schemes::NONE {
+        buf.push(seal_scheme);
+    }
+    buf
+}
+
+/// Check an announcement's origin signature and timestamp
+///
+/// `seal_scheme` is the sealing scheme advertised in the announcement header.
+/// `last_seen` is the timestamp of the last announcement accepted from the
+/// same sender, if any.
+pub(crate) fn check_origin(
+    sender: Address,
+    announce: &AnnounceFrameV1,
+    seal_scheme: u8,
+    last_seen: Option<DateTime<Utc>>,
+    window: &AnnounceWindow,
+    now: DateTime<Utc>,
+) -> Result<(), AnnounceRejection> {
+    let origin_buf = signed_origin(&announce.origin, seal_scheme);
+
+    Signature::from_bytes(&announce.origin_signature)
+        .ok()
+        .and_then(|signature| crypto::verify_message(sender, &origin_buf, signature))
+        .ok_or(AnnounceRejection::InvalidSignature)?;
+
+    let timestamp = announce.origin.timestamp();
+    if timestamp < now - window.max_age {
+        return Err(AnnounceRejection::Stale);
+    }
+    if timestamp > now + window.max_clock_skew {
+        return Err(AnnounceRejection::FromFuture);
+    }
+    if last_seen.is_some_and(|last| timestamp <= last) {
+        return Err(AnnounceRejection::Replayed);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+fn signed_announce(key: &crypto::Keypair, timestamp: DateTime<Utc>) -> (Address, AnnounceFrameV1) {
+    use ed25519_dalek::Signer;
+    use libratman::frame::carrier::RouteDataV1;
+
+    let origin = OriginDataV1::from_timestamp(timestamp);
+    let origin_buf = signed_origin(&origin, schemes::LATEST);
+
+    (
+        Address::from_bytes(key.inner.public.as_bytes()),
+        AnnounceFrameV1 {
+            origin,
+            origin_signature: key.inner.sign(&origin_buf).to_bytes(),
+            route: RouteDataV1 { available_mtu: 0 },
+        },
+    )
+}
+
+#[test]
+fn verify_announce_signature() {
+    use ed25519_dalek::SecretKey;
+    use rand::rngs::OsRng;
+
+    let now = Utc::now();
+    let window = AnnounceWindow::default();
+    let key = crypto::Keypair::new(SecretKey::generate(&mut OsRng {}));
+    let (addr, announce) = signed_announce(&key, now);
+    assert_eq!(
+        check_origin(addr, &announce, schemes::LATEST, None, &window, now),
+        Ok(())
+    );
+
+    // Somebody else claiming to be this address
+    assert_eq!(
+        check_origin(
+            Address::random(),
+            &announce,
+            schemes::LATEST,
+            None,
+            &window,
+            now
+        ),
+        Err(AnnounceRejection::InvalidSignature)
+    );
+
+    // A tampered signature
+    let mut forged = announce;
+    forged.origin_signature[0] ^= 0xFF;
+    assert_eq!(
+        check_origin(addr, &forged, schemes::LATEST, None, &window, now),
+        Err(AnnounceRejection::InvalidSignature)
+    );
+
+    // A relay removing the seal scheme from the header
+    assert_eq!(
+        check_origin(addr, &announce, schemes::NONE, None, &window, now),
+        Err(AnnounceRejection::InvalidSignature)
+    );
+}
+
+#[test]
+fn verify_announce_replay_window() {
+    use ed25519_dalek::SecretKey;
+    use rand::rngs::OsRng;
+
+    let now = Utc::now();
+    let window = AnnounceWindow::default();
+    let key = crypto::Keypair::new(SecretKey::generate(&mut OsRng {}));
+
+    let (addr, old) = signed_announce(&key, now - MAX_ANNOUNCE_AGE - Duration::seconds(1));
+    assert_eq!(
+        check_origin(addr, &old, schemes::LATEST, None, &window, now),
+        Err(AnnounceRejection::Stale)
+    );
+
+    let (_, future) = signed_announce(&key, now + MAX_CLOCK_SKEW + Duration::seconds(1));
+    assert_eq!(
+        check_origin(addr, &future, schemes::LATEST, None, &window, now),
+        Err(AnnounceRejection::FromFuture)
+    );
+
+    let (_, current) = signed_announce(&key, now - Duration::seconds(5));
+    assert_eq!(
+        check_origin(addr, &current, schemes::LATEST, None, &window, now),
+        Ok(())
+    );
+    assert_eq!(
+        check_origin(
+            addr,
+            &current,
+            schemes::LATEST,
+            Some(current.origin.timestamp()),
+            &window,
+            now
+        ),
+        Err(AnnounceRejection::Replayed)
+    );
+}
+
+#[test]
+fn announce_window_config() {
+    use crate::config::ConfigTree;
+    use ed25519_dalek::SecretKey;
+    use rand::rngs::OsRng;
+
+    let cfg = ConfigTree::default_in_memory()
+        .patch("ratmand/max_announce_age_secs", 300)
+        .patch("ratmand/max_clock_skew_secs", 0);
+    let window = AnnounceWindow::from_config(&cfg.get_subtree("ratmand").unwrap());
+    assert_eq!(window.max_age, Duration::seconds(300));
+    assert_eq!(window.max_clock_skew, MAX_CLOCK_SKEW);
+
+    // An announcement that is stale by default is accepted
+    let now = Utc::now();
+    let key = crypto::Keypair::new(SecretKey::generate(&mut OsRng {}));
+    let (addr, old) = signed_announce(&key, now - Duration::seconds(120));
+    assert_eq!(
+        check_origin(addr, &old, schemes::LATEST, None, &window, now),
+        Ok(())
+    );
+    assert_eq!(
+        check_origin(
+            addr,
+            &old,
+            schemes::LATEST,
+            None,
+            &AnnounceWindow::default(),
+            now
+        ),
+        Err(AnnounceRejection::Stale)
+    );
+}
@@ -0,0 +1,202 @@
+//! Per-address contact book storage
+//!
+//! Entries are keyed by `<owner address>/<contact id>`, so that all
+//! contacts of a single local address can be found with a prefix search.
+
+use crate::storage::MetadataDb;
+use libratman::{
+    api::types::{ContactEntry, ContactFilter, TRUST_MAX, TRUST_MIN},
+    types::{apply_simple_modify, error::UserError, Address, Ident32, Modify},
+    ClientError, Result,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::Arc};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ContactData {
+    pub addr: Address,
+    pub note: Option<String>,
+    pub tags: BTreeMap<String, String>,
+    pub trust: u8,
+}
+
+impl ContactData {
+    fn matches(&self, filter: &ContactFilter) -> bool {
+        (filter.addrs.is_empty() || filter.addrs.contains(&self.addr))
+            && filter.note.as_ref().is_none_or(|note_filter| {
+                self.note
+                    .as_ref()
+                    .is_some_and(|note| note.contains(&*note_filter.to_string_lossy()))
+            })
+            && filter.tags.iter().all(|(k, v)| {
+                self.tags.get(&*k.to_string_lossy()).map(String::as_str)
+                    == Some(&*v.to_string_lossy())
+            })
+    }
+
+    fn into_entry(self, id: Ident32) -> ContactEntry {
+        ContactEntry {
+            id,
+            addr: self.addr,
+            note: self.note,
+            tags: self.tags,
+            trust: self.trust,
+        }
+    }
+}
+
+fn contact_key(owner: Address, id: Ident32) -> String {
+    format!("{}/{}", owner, id)
+}
+
+fn contact_id(key: &str) -> Ident32 {
+    Ident32::from_string(&key.rsplit('/').next().unwrap_or_default().to_string())
+}
+
+fn check_trust(trust: u8) -> Result<()> {
+    if (TRUST_MIN..=TRUST_MAX).contains(&trust) {
+        Ok(())
+    } else {
+        Err(ClientError::User(UserError::InvalidInput(
+            format!("trust level {trust}"),
+            Some(format!("{TRUST_MIN}-{TRUST_MAX}")),
+        ))
+        .into())
+    }
+}
+
+/// Find all contacts of a local address that match a filter
+pub fn list_contacts(
+    meta_db: &Arc<MetadataDb>,
+    owner: Address,
+    filter: &ContactFilter,
+) -> Vec<ContactEntry> {
+    let prefix = format!("{}/", owner);
+    meta_db
+        .contacts
+        .prefix(&prefix)
+        .filter(|(_, data)| data.matches(filter))
+        .map(|(key, data)| data.into_entry(contact_id(&key)))
+        .collect()
+}
+
+/// Add a new contact to the contact book of a local address
+pub async fn add_contact(
+    meta_db: &Arc<MetadataDb>,
+    owner: Address,
+    data: ContactData,
+) -> Result<ContactEntry> {
+    check_trust(data.trust)?;
+
+    let id = Ident32::random();
+    meta_db
+        .contacts
+        .insert(contact_key(owner, id), &data)
+        .await?;
+    Ok(data.into_entry(id))
+}
+
+/// Apply a change to all contacts selected by the filter
+///
+/// Returns the modified entries.
+pub async fn modify_contacts(
+    meta_db: &Arc<MetadataDb>,
+    owner: Address,
+    filter: &ContactFilter,
+    note_modify: Modify<String>,
+    tags_modify: Modify<(String, String)>,
+    new_trust: Option<u8>,
+) -> Result<Vec<ContactEntry>> {
+    if let Some(trust) = new_trust {
+        check_trust(trust)?;
+    }
+
+    let mut modified = vec![];
+    for mut entry in list_contacts(meta_db, owner, filter) {
+        apply_simple_modify(&mut entry.note, note_modify.clone());
+        match tags_modify.clone() {
+            Modify::Keep => {}
+            Modify::Change((k, v)) => {
+                entry.tags.insert(k, v);
+            }
+            Modify::DeleteOne((k, _)) => {
+                entry.tags.remove(&k);
+            }
+            Modify::DeleteAll => entry.tags.clear(),
+        }
+        if let Some(trust) = new_trust {
+            entry.trust = trust;
+        }
+
+        meta_db
+            .contacts
+            .insert(
+                contact_key(owner, entry.id),
+                &ContactData {
+                    addr: entry.addr,
+                    note: entry.note.clone(),
+                    tags: entry.tags.clone(),
+                    trust: entry.trust,
+                },
+            )
+            .await?;
+        modified.push(entry);
+    }
+
+    Ok(modified)
+}
+
+/// Delete all contact entries for an address from a contact book
+pub async fn delete_contacts(
+    meta_db: &Arc<MetadataDb>,
+    owner: Address,
+    addr: Address,
+) -> Result<()> {
+    let filter = ContactFilter {
+        addrs: vec![addr],
+        ..Default::default()
+    };
+
+    let ids = list_contacts(meta_db, owner, &filter);
+    if ids.is_empty() {
+        return Err(ClientError::NoAddress.into());
+    }
+
+    for entry in ids {
+        meta_db
+            .contacts
+            .remove(contact_key(owner, entry.id))
+            .await?;
+    }
+    Ok(())
+}
+
+#[test]
+fn contact_filter_matches() {
+    use libratman::types::to_cstring;
+
+    let addr = Address::random();
+    let data = ContactData {
+        addr,
+        note: Some("met at the hackspace".into()),
+        tags: vec![("group".to_string(), "friends".to_string())]
+            .into_iter()
+            .collect(),
+        trust: 5,
+    };
+
+    assert!(data.matches(&ContactFilter::default()));
+    assert!(data.matches(&ContactFilter {
+        addrs: vec![addr],
+        note: Some(to_cstring(&"hackspace".into())),
+        tags: vec![(to_cstring(&"group".into()), to_cstring(&"friends".into()))],
+    }));
+    assert!(!data.matches(&ContactFilter {
+        addrs: vec![Address::random()],
+        ..Default::default()
+    }));
+    assert!(!data.matches(&ContactFilter {
+        tags: vec![(to_cstring(&"group".into()), to_cstring(&"family".into()))],
+        ..Default::default()
+    }));
+}
@@ -1,9 +1,15 @@
-use crate::journal::page::SerdeFrameType;
-use libratman::{frame::carrier::PeerDataV1, types::Ident32};
+use libratman::types::Ident32;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Associations and tags for a single message stream
+///
+/// Link data is keyed by the stream (manifest) ID.  Any stream that has link
+/// data is protected from being evicted from the journal when its storage
+/// quota is exhausted.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct LinkData {
-    link_id: Ident32,
-    neighbour_data: SerdeFrameType<PeerDataV1>,
+    /// Other message streams that are associated with this one
+    pub streams: Vec<Ident32>,
+    /// Free-form tags attached to this stream
+    pub tags: Vec<String>,
 }
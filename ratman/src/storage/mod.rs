@@ -12,21 +12,24 @@
 use crate::{
     journal::page::CachePage,
     storage::{
-        addr_key::AddressData, block::IncompleteBlockData, link::LinkData, route::RouteData,
-        subs::SubscriptionData,
+        addr_key::AddressData, block::IncompleteBlockData, contact::ContactData, link::LinkData,
+        peer::PeerData, route::RouteData, subs::SubscriptionData,
     },
 };
-use fjall::{Keyspace, PartitionCreateOptions};
+use fjall::{Keyspace, PartitionCreateOptions, PartitionHandle};
 use libratman::{
     tokio::task::block_in_place,
-    types::{Ident32, LetterheadV1},
+    types::{Address, Ident32, LetterheadV1},
     Result,
 };
-use std::marker::PhantomData;
+use std::sync::Mutex;
+use x25519_dalek::{PublicKey, StaticSecret};
 
 pub mod addr_key;
 pub mod block;
+pub mod contact;
 pub mod link;
+pub mod peer;
 pub mod route;
 pub mod subs;
 
@@ -37,9 +40,15 @@ pub mod subs;
 ///
 /// - Registered addresses and their encrypted private key information
 ///
+/// - Contact books: per-address notes, tags, and trust levels for other
+/// addresses on the network
+///
 /// - Routing table: keep track of known peers via their links and various
 /// metrics like MTU, uptime, and average ping.
 ///
+/// - Peers: neighbours that were added at runtime, and should be re-connected
+/// after a restart
+///
 /// - Link metadata: certain message streams have associations between them, or
 /// can be tagged with additional information for importance to prevent them
 /// from being cleaned from the journal in case of storage quota limits.
@@ -47,66 +56,134 @@ pub mod subs;
 // though there's no real reason for this.  Maybe move this code to the crypto
 // module?
 pub struct MetadataDb {
-    pub db: Keyspace,
+    /// The keyspace is only kept to be closed on shutdown
+    db: Mutex<Option<Keyspace>>,
+    /// Router keys, see [`meta_key`](Self::meta_key)
+    meta: PartitionHandle,
     pub addrs: CachePage<AddressData>,
     pub routes: CachePage<RouteData>,
     pub links: CachePage<LinkData>,
     pub incomplete: CachePage<IncompleteBlockData>,
     pub available_streams: CachePage<LetterheadV1>,
     pub subscriptions: CachePage<SubscriptionData>,
+    pub contacts: CachePage<ContactData>,
+    pub peers: CachePage<PeerData>,
+    /// The address that created each namespace, by namespace address
+    pub space_owners: CachePage<Address>,
 }
 
 impl MetadataDb {
+    /// The public identity of this router
+    ///
+    /// This is the public key of [`router_key`](Self::router_key),
+    /// which netmods that authenticate their peers (for example inet)
+    /// use as their static key.  Peers can therefore pin a router by
+    /// its id.
     pub fn router_id(&self) -> Ident32 {
-        let part = self
-            .db
-            .open_partition("meta_meta", PartitionCreateOptions::default())
-            .expect("failed to open meta_meta partition; can't generate router id key! :(");
+        let secret = StaticSecret::from(self.router_key().slice());
+        Ident32::from_bytes(PublicKey::from(&secret).as_bytes())
+    }
+
+    /// The private key this router authenticates itself to peers with
+    ///
+    /// It is generated on first use.
+    pub fn router_key(&self) -> Ident32 {
+        self.meta_key("router.key")
+    }
 
-        if let Some(router_key_id) = part.get("router.key_id").unwrap() {
-            Ident32::from_bytes(&*router_key_id)
+    /// The secret of the address this router answers block fetches from
+    ///
+    /// It is generated on first use.  Unlike the router key this is an
+    /// address key, so that fetched blocks can be sealed.
+    pub fn fetch_key(&self) -> Ident32 {
+        self.meta_key("router.fetch_key")
+    }
+
+    /// The symmetric key used to encrypt address secrets at rest
+    ///
+    /// It is generated on first use and never leaves the router.
+    pub fn storage_key(&self) -> Ident32 {
+        // Before router ids were derived from the router key this
+        // random value was the router id
+        self.meta_key("router.key_id")
+    }
+
+    /// Load a random key from the meta partition, or generate it
+    fn meta_key(&self, name: &str) -> Ident32 {
+        if let Some(key) = self.meta.get(name).unwrap() {
+            Ident32::from_bytes(&key)
         } else {
-            let router_key_id = Ident32::random();
-            part.insert("router.key_id", router_key_id.as_bytes())
-                .expect("failed to insert router id");
-            router_key_id
+            let key = Ident32::random();
+            self.meta
+                .insert(name, key.as_bytes())
+                .unwrap_or_else(|e| panic!("failed to insert {}: {}", name, e));
+            key
         }
     }
 
+    /// Close the keyspace and wait for its flush and compaction threads
+    ///
+    /// This must happen before the storage directory is removed.  Anything
+    /// written to the database afterwards is not persisted.
+    pub fn close(&self) {
+        drop(self.db.lock().unwrap().take());
+    }
+
     pub fn new(db: Keyspace) -> Result<Self> {
-        let addrs = CachePage(
-            db.open_partition("meta_addrs", PartitionCreateOptions::default())?,
-            PhantomData,
-        );
-        let routes = CachePage(
-            db.open_partition("meta_routes", PartitionCreateOptions::default())?,
-            PhantomData,
-        );
-        let links = CachePage(
-            db.open_partition("meta_links", PartitionCreateOptions::default())?,
-            PhantomData,
-        );
-        let incomplete = CachePage(
+        let meta = db.open_partition("meta_meta", PartitionCreateOptions::default())?;
+        let addrs =
+            CachePage::new(db.open_partition("meta_addrs", PartitionCreateOptions::default())?);
+        let routes =
+            CachePage::new(db.open_partition("meta_routes", PartitionCreateOptions::default())?);
+        let links =
+            CachePage::new(db.open_partition("meta_links", PartitionCreateOptions::default())?);
+        let incomplete = CachePage::new(
             db.open_partition("meta_incomplete", PartitionCreateOptions::default())?,
-            PhantomData,
         );
-        let available_streams = CachePage(
+        let available_streams = CachePage::new(
             db.open_partition("meta_available_streams", PartitionCreateOptions::default())?,
-            PhantomData,
         );
-        let subscriptions = CachePage(
+        let subscriptions = CachePage::new(
             db.open_partition("meta_subscriptions", PartitionCreateOptions::default())?,
-            PhantomData,
+        );
+
+        let contacts =
+            CachePage::new(db.open_partition("meta_contacts", PartitionCreateOptions::default())?);
+        let peers =
+            CachePage::new(db.open_partition("meta_peers", PartitionCreateOptions::default())?);
+        let space_owners = CachePage::new(
+            db.open_partition("meta_space_owners", PartitionCreateOptions::default())?,
         );
 
         Ok(Self {
-            db,
+            db: Mutex::new(Some(db)),
+            meta,
             addrs,
             routes,
             links,
             incomplete,
             available_streams,
             subscriptions,
+            contacts,
+            peers,
+            space_owners,
         })
     }
 }
+
+#[test]
+fn router_id_is_public_router_key() {
+    let db = fjall::Keyspace::open(fjall::Config::new(
+        tempdir::TempDir::new("meta").unwrap().into_path(),
+    ))
+    .unwrap();
+    let meta_db = MetadataDb::new(db).unwrap();
+
+    let router_id = meta_db.router_id();
+    assert_eq!(router_id, meta_db.router_id());
+    assert_ne!(router_id, meta_db.router_key());
+    assert_ne!(router_id, meta_db.storage_key());
+
+    let secret = StaticSecret::from(meta_db.router_key().slice());
+    assert_eq!(router_id.as_bytes(), PublicKey::from(&secret).as_bytes());
+}
@@ -0,0 +1,15 @@
+//! Storage for peers that were added at runtime
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A peer that was added via the client API
+///
+/// Peer data is keyed by the peer line (`<netmod>:<address>`).  Peers
+/// from the configuration file are not stored, since they are attached
+/// on every start anyway.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PeerData {
+    /// When the peer was added
+    pub added: DateTime<Utc>,
+}
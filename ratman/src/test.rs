@@ -3,7 +3,7 @@ use std::{thread, time::Duration};
 use crate::config::ConfigTree;
 use libratman::{
     api::{RatmanIpc, RatmanIpcExtV1},
-    tokio::{runtime::Runtime, time::sleep},
+    tokio::{self, runtime::Runtime, time::sleep},
 };
 
 #[test]
@@ -29,3 +29,262 @@ fn create_address() {
 
     std::process::exit(0);
 }
+
+#[tokio::test]
+async fn ephemeral_storage() {
+    use crate::{context::RatmanContext, routes::ScorerRegistry};
+    use libratman::tokio::sync::broadcast::channel;
+
+    let state = tempdir::TempDir::new("ephemeral-state").unwrap();
+    let (block_notify_tx, _) = channel(8);
+
+    // Two ephemeral routers can run side by side without touching the
+    // state directory
+    let cfg = || ConfigTree::default_in_memory().patch("ratmand/ephemeral", true);
+    let a = RatmanContext::new(
+        cfg(),
+        state.path().to_path_buf(),
+        block_notify_tx.clone(),
+        ScorerRegistry::default(),
+    )
+    .await
+    .unwrap();
+    let b = RatmanContext::new(
+        cfg(),
+        state.path().to_path_buf(),
+        block_notify_tx,
+        ScorerRegistry::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(std::fs::read_dir(state.path()).unwrap().count(), 0);
+
+    let dir_a = a.ephemeral_dir().unwrap();
+    assert_ne!(Some(&dir_a), b.ephemeral_dir().as_ref());
+    assert!(dir_a.join("journal.fjall").exists());
+
+    a.wipe_ephemeral_state();
+    assert!(!dir_a.exists());
+    assert!(b.ephemeral_dir().unwrap().exists());
+}
+
+#[tokio::test]
+async fn config_reload() {
+    use crate::{context::RatmanContext, routes::ScorerRegistry};
+    use libratman::{
+        api::types::{ChangeOutcome, ConfigChange},
+        tokio::sync::broadcast::channel,
+    };
+
+    let dir = tempdir::TempDir::new("config-reload").unwrap();
+    let path = dir.path().join("ratmand.kdl");
+    let write = |cfg: &str| std::fs::write(&path, cfg).unwrap();
+    write(
+        r#"settings "ratmand" {
+            ephemeral false
+            api_bind "localhost:5852"
+        }
+        settings "inet" {
+            enable false
+            bind "[::]:5860"
+        }"#,
+    );
+
+    // Overrides from the command-line survive a reload
+    let cfg = ConfigTree::load_path(&path)
+        .await
+        .unwrap()
+        .patch("ratmand/ephemeral", true);
+    let (block_notify_tx, _) = channel(8);
+    let ctx = RatmanContext::new(
+        cfg,
+        dir.path().to_path_buf(),
+        block_notify_tx,
+        ScorerRegistry::default(),
+    )
+    .await
+    .unwrap();
+
+    write(
+        r#"settings "ratmand" {
+            ephemeral false
+            api_bind "localhost:9000"
+            announce_delay 5
+        }
+        settings "inet" {
+            enable false
+            bind "[::]:9001"
+        }"#,
+    );
+    let change = |key: &str, outcome| ConfigChange {
+        key: key.into(),
+        outcome,
+    };
+    assert_eq!(
+        ctx.reload_config().await.unwrap(),
+        vec![
+            change("inet/bind", ChangeOutcome::Applied),
+            change("ratmand/announce_delay", ChangeOutcome::Applied),
+            change("ratmand/api_bind", ChangeOutcome::NeedsRestart),
+        ]
+    );
+
+    let ratmand = |ctx: &RatmanContext| {
+        let cfg = ctx.config();
+        let ratmand = cfg.get_subtree("ratmand").unwrap();
+        (
+            ratmand.get_number_value("announce_delay"),
+            ratmand.get_bool_value("ephemeral"),
+        )
+    };
+    assert_eq!(ratmand(&ctx), (Some(5), Some(true)));
+
+    // An invalid configuration is rejected and the old one is kept
+    write(r#"settings "ratmand" { announce_delay "#);
+    assert!(ctx.reload_config().await.is_err());
+    assert_eq!(ratmand(&ctx), (Some(5), Some(true)));
+
+    ctx.wipe_ephemeral_state();
+}
+
+#[tokio::test]
+async fn config_reload_retry_failed() {
+    use crate::{context::RatmanContext, routes::ScorerRegistry};
+    use libratman::{api::types::ChangeOutcome, tokio::sync::broadcast::channel};
+
+    let dir = tempdir::TempDir::new("config-reload-retry").unwrap();
+    let path = dir.path().join("ratmand.kdl");
+    let write = |cfg: &str| std::fs::write(&path, cfg).unwrap();
+    let inet = |enable: bool, port: u16| {
+        format!(
+            r#"settings "ratmand" {{ ephemeral true; }}
+            settings "inet" {{ enable {}; bind "[::1]:{}"; }}"#,
+            enable, port
+        )
+    };
+
+    // Keep the port busy, so that the inet netmod fails to start
+    let busy = std::net::TcpListener::bind("[::1]:0").unwrap();
+    let port = busy.local_addr().unwrap().port();
+    write(&inet(false, port));
+
+    let (block_notify_tx, _) = channel(8);
+    let ctx = RatmanContext::new(
+        ConfigTree::load_path(&path).await.unwrap(),
+        dir.path().to_path_buf(),
+        block_notify_tx,
+        ScorerRegistry::default(),
+    )
+    .await
+    .unwrap();
+
+    write(&inet(true, port));
+    let changes = ctx.reload_config().await.unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].key, "inet/enable");
+    assert!(matches!(changes[0].outcome, ChangeOutcome::Failed(_)));
+    assert!(ctx.link_list().await.is_empty());
+
+    // The failed setting is retried, even though the file didn't change
+    drop(busy);
+    let changes = ctx.reload_config().await.unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].outcome, ChangeOutcome::Applied);
+    assert_eq!(ctx.link_list().await.len(), 1);
+
+    ctx.wipe_ephemeral_state();
+}
+
+#[tokio::test]
+async fn report_tampered_block() {
+    use crate::{
+        context::RatmanContext, crypto, procedures::handle_subscription_socket,
+        routes::ScorerRegistry,
+    };
+    use libratman::{
+        api::{socket_v2::RawSocketHandle, types::ServerPing},
+        frame::carrier::CarrierFrameHeader,
+        tokio::{net::TcpListener, sync::broadcast::channel, time::timeout},
+        types::{Ident32, InMemoryEnvelope, Recipient, SequenceIdV1},
+        ClientError,
+    };
+    use std::sync::Arc;
+
+    let state = tempdir::TempDir::new("tampered-block").unwrap();
+    let (block_notify_tx, _) = channel(8);
+    let ctx = RatmanContext::new(
+        ConfigTree::default_in_memory().patch("ratmand/ephemeral", true),
+        state.path().to_path_buf(),
+        block_notify_tx.clone(),
+        ScorerRegistry::default(),
+    )
+    .await
+    .unwrap();
+
+    // A local address that is online and has a subscription
+    let (addr, auth) = crypto::create_addr_key(&ctx.meta_db, None).await.unwrap();
+    ctx.routes.register_local_route(addr).await.unwrap();
+    Arc::clone(&ctx.protocol)
+        .online(addr, auth, Arc::clone(&ctx))
+        .await
+        .unwrap();
+    let recipient = Recipient::Address(addr);
+    let (sub_id, rx) = ctx.subs.create_subscription(addr, recipient).await.unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut client = RawSocketHandle::new(
+        libratman::tokio::net::TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap(),
+    );
+    let (stream, _) = listener.accept().await.unwrap();
+    tokio::spawn(handle_subscription_socket(
+        Arc::clone(&ctx),
+        rx,
+        RawSocketHandle::new(stream),
+        addr,
+        auth,
+        sub_id,
+    ));
+
+    // Seal a chunk for the address, then flip a bit of it in transit
+    let (sender, sender_auth) = crypto::create_addr_key(&ctx.meta_db, None).await.unwrap();
+    let sender_key = crypto::get_addr_key(&ctx.meta_db, sender, sender_auth)
+        .await
+        .unwrap();
+    let shared_key = crypto::diffie_hellman(&sender_key, addr).unwrap();
+    let seq_id = SequenceIdV1 {
+        hash: Ident32::random(),
+        num: 0,
+        max: 0,
+    };
+    let mut chunk = vec![42; 1024];
+    let seal = crypto::seal_chunk(&shared_key, sender, recipient, seq_id, &mut chunk).unwrap();
+    chunk[17] ^= 1;
+    let header =
+        CarrierFrameHeader::new_sealed_blockdata_frame(sender, recipient, seq_id, seal, 1024)
+            .unwrap();
+    Arc::clone(&ctx.collector)
+        .queue_and_spawn(
+            InMemoryEnvelope::from_header_and_payload(header, chunk).unwrap(),
+            block_notify_tx,
+        )
+        .await
+        .unwrap();
+
+    let (_, ping) = timeout(
+        Duration::from_secs(10),
+        client.read_microframe::<ServerPing>(),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    match ping.unwrap() {
+        ServerPing::Error(ClientError::Unauthenticated { hash, num: 0 }) => {
+            assert_eq!(hash, seq_id.hash)
+        }
+        ping => panic!("unexpected subscription event: {:?}", ping),
+    }
+
+    ctx.wipe_ephemeral_state();
+}
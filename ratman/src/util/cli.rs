@@ -38,8 +38,7 @@ If you encounter a reproducible issue, \
                 .short("v")
                 .long("verbosity")
                 .possible_values(&["trace", "debug", "info", "warn", "error", "fatal"])
-                .default_value("info")
-                .help("Specify the verbosity level at which ratmand logs interactions"),
+                .help("Specify the verbosity level at which ratmand logs interactions (overrides 'ratmand/verbosity')"),
         )
         .arg(
             Arg::with_name("CONFIG")
@@ -55,6 +54,11 @@ If you encounter a reproducible issue, \
                 .long("dir")
                 .help("Set the state directory where ratmand keeps its databases")
         )
+        .arg(
+            Arg::with_name("EPHEMERAL")
+                .long("ephemeral")
+                .help("Keep all state in a temporary directory that is removed when ratmand shuts down")
+        )
         .arg(
             Arg::with_name("DAEMONIZE")
                 .long("daemonize")
@@ -11,14 +11,11 @@ pub(crate) mod pidfile;
 
 use crate::config::{ConfigTree, SubConfig};
 use colored::CustomColor;
-use libratman::tokio::sync::mpsc::{Receiver, Sender};
 use nix::unistd::Uid;
+use std::sync::OnceLock;
 
 use tracing_subscriber::{filter::LevelFilter, fmt, EnvFilter};
 
-/// A convevient Sender/Receiver pair for a type
-pub(crate) type IoPair<T> = (Sender<T>, Receiver<T>);
-
 /// Setup a very verbose output for test environments
 pub fn setup_test_logging() {
     let cfg = ConfigTree::default_in_memory().patch("ratmand/verbosity", "trace");
@@ -31,50 +28,94 @@ pub const SOFT_WARN_COLOR: CustomColor = CustomColor {
     b: 83,
 };
 
-/// Setup default logging output with a configuration
-pub fn setup_logging(ratmand_config: &SubConfig) {
-    let lvl = ratmand_config
-        .get_string_value("verbosity")
-        .unwrap_or_else(|| "debug".into());
-    let syslog = ratmand_config.get_bool_value("use_syslog").unwrap_or(false);
+/// Replaces the log filter of the active logger
+type ReloadFilter = Box<dyn Fn(EnvFilter) -> Result<(), String> + Send + Sync>;
 
+/// Set when the logger is initialised
+///
+/// The reload handle type depends on the log writer, so it is kept
+/// behind a closure.
+static RELOAD_FILTER: OnceLock<ReloadFilter> = OnceLock::new();
+
+/// Build a log filter from a `ratmand/verbosity` value
+///
+/// The value is either a single level, or a list of comma-separated
+/// tracing directives (for example `info,ratmand=trace`).
+fn log_filter(lvl: &str) -> Result<EnvFilter, String> {
     let filter = if lvl.contains(|x| x == ',') {
         lvl.split(|x| x == ',')
-            .fold(EnvFilter::default(), |filter, lvl| {
-                filter.add_directive(lvl.parse().unwrap())
-            })
+            .try_fold(EnvFilter::default(), |filter, lvl| {
+                lvl.parse()
+                    .map(|directive| filter.add_directive(directive))
+                    .map_err(|e| format!("invalid log directive '{lvl}': {e}"))
+            })?
     } else {
-        EnvFilter::default().add_directive(match lvl.as_str() {
+        EnvFilter::default().add_directive(match lvl {
             "trace" => LevelFilter::TRACE.into(),
             "debug" => LevelFilter::DEBUG.into(),
             "info" => LevelFilter::INFO.into(),
             "warn" => LevelFilter::WARN.into(),
             "error" => LevelFilter::ERROR.into(),
-            _ => unreachable!(),
+            _ => return Err(format!("invalid log level '{lvl}'")),
         })
-    }
-    .add_directive("tokio=error".parse().unwrap())
-    .add_directive("mio=error".parse().unwrap())
-    .add_directive("polling=error".parse().unwrap())
-    .add_directive("trust_dns_proto=error".parse().unwrap())
-    .add_directive("lsm_tree=warn".parse().unwrap())
-    .add_directive("fjall=info".parse().unwrap())
-    .add_directive("trust_dns_resolver=warn".parse().unwrap());
-
-    // Initialise the logger
+    };
+
+    Ok(filter
+        .add_directive("tokio=error".parse().unwrap())
+        .add_directive("mio=error".parse().unwrap())
+        .add_directive("polling=error".parse().unwrap())
+        .add_directive("trust_dns_proto=error".parse().unwrap())
+        .add_directive("lsm_tree=warn".parse().unwrap())
+        .add_directive("fjall=info".parse().unwrap())
+        .add_directive("trust_dns_resolver=warn".parse().unwrap()))
+}
+
+/// Setup default logging output with a configuration
+pub fn setup_logging(ratmand_config: &SubConfig) {
+    let lvl = ratmand_config
+        .get_string_value("verbosity")
+        .unwrap_or_else(|| "debug".into());
+    let syslog = ratmand_config.get_bool_value("use_syslog").unwrap_or(false);
+
+    let filter = match log_filter(&lvl) {
+        Ok(filter) => filter,
+        Err(e) => {
+            eprintln!("ratmand/verbosity is invalid: {}", e);
+            std::process::exit(codes::INVALID_CONFIG as i32);
+        }
+    };
+
+    // Initialise the logger.  When several routers run in the same process
+    // only the first one sets it up
     if syslog {
         let identity = std::ffi::CStr::from_bytes_with_nul(b"ratmand\0").unwrap();
         let facility = Default::default();
         let syslog =
             tracing_syslog::Syslog::new(identity, tracing_syslog::Options::LOG_PID, facility);
-        fmt()
+        let builder = fmt()
             .with_ansi(false)
             .with_env_filter(filter)
             .with_writer(syslog)
-            .init();
+            .with_filter_reloading();
+        let handle = builder.reload_handle();
+        if builder.try_init().is_err() {
+            return;
+        }
+        let _ = RELOAD_FILTER.set(Box::new(move |filter| {
+            handle.reload(filter).map_err(|e| e.to_string())
+        }));
     } else {
         #[cfg(not(feature = "android"))]
-        fmt().with_env_filter(filter).init();
+        {
+            let builder = fmt().with_env_filter(filter).with_filter_reloading();
+            let handle = builder.reload_handle();
+            if builder.try_init().is_err() {
+                return;
+            }
+            let _ = RELOAD_FILTER.set(Box::new(move |filter| {
+                handle.reload(filter).map_err(|e| e.to_string())
+            }));
+        }
     }
 
     info!("Initialised logger: welcome to ratmand!");
@@ -84,3 +125,16 @@ pub fn setup_logging(ratmand_config: &SubConfig) {
         std::env::args()
     );
 }
+
+/// Apply a new `ratmand/verbosity` value to the active logger
+pub(crate) fn reload_logging(ratmand_config: &SubConfig) -> Result<(), String> {
+    let lvl = ratmand_config
+        .get_string_value("verbosity")
+        .unwrap_or_else(|| "debug".into());
+    let filter = log_filter(&lvl)?;
+
+    match RELOAD_FILTER.get() {
+        Some(reload) => reload(filter),
+        None => Err("the logger was not set up by this router".into()),
+    }
+}
@@ -4,8 +4,9 @@
 // SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
 
 use crate::context::RatmanContext;
+use libratman::tokio::runtime::Handle;
 use prometheus_client::registry::Registry;
-use std::{path::Path, sync::Arc};
+use std::{borrow::Cow, future::Future, path::Path, sync::Arc};
 use tide::{http::mime, prelude::*, utils::After, Request, Response};
 
 pub mod middleware;
@@ -16,12 +17,45 @@ pub type State = Arc<StateData>;
 pub struct StateData {
     pub router: Arc<RatmanContext>,
     pub registry: Registry,
+    /// The router runtime
+    ///
+    /// The web server runs on async-std, but most router state can
+    /// only be queried from inside the tokio runtime.
+    pub runtime: Handle,
 }
 
+impl StateData {
+    /// Run a router query on the router runtime
+    pub async fn query<F, T>(&self, f: F) -> tide::Result<T>
+    where
+        F: Future<Output = libratman::Result<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.runtime
+            .spawn(f)
+            .await
+            .map_err(|e| tide::Error::from_str(500, e))?
+            .map_err(|e| tide::Error::from_str(500, e))
+    }
+}
+
+#[cfg(not(dashboard_placeholder))]
 #[derive(rust_embed::RustEmbed)]
 #[folder = "dashboard/dist"]
 struct DashboardAssets;
 
+#[cfg(not(dashboard_placeholder))]
+fn dashboard_asset(path: &str) -> Option<Cow<'static, [u8]>> {
+    DashboardAssets::get(path).map(|file| file.data)
+}
+
+/// Only the placeholder page that `build.rs` generated is available
+#[cfg(dashboard_placeholder)]
+fn dashboard_asset(path: &str) -> Option<Cow<'static, [u8]>> {
+    const PLACEHOLDER: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/placeholder.html"));
+    (path == "index.html").then_some(Cow::Borrowed(PLACEHOLDER))
+}
+
 async fn serve_dashboard(req: Request<State>) -> tide::Result {
     let path = {
         let path = req.url().path();
@@ -31,7 +65,7 @@ async fn serve_dashboard(req: Request<State>) -> tide::Result {
             path.strip_prefix('/').unwrap_or(path)
         }
     };
-    let (asset, mtype) = DashboardAssets::get(path)
+    let (asset, mtype) = dashboard_asset(path)
         .map(|ass| {
             let mtype = mime::Mime::from_extension(
                 Path::new(&path)
@@ -43,11 +77,11 @@ async fn serve_dashboard(req: Request<State>) -> tide::Result {
             .unwrap_or(mime::PLAIN);
             (ass, mtype)
         })
-        .or_else(|| DashboardAssets::get("index.html").map(|ass| (ass, mime::HTML)))
+        .or_else(|| dashboard_asset("index.html").map(|ass| (ass, mime::HTML)))
         .ok_or_else(|| tide::Error::from_str(404, format!("not found: /{:}", path)))?;
     Ok(Response::builder(200)
         .content_type(mtype)
-        .body(&asset.data[..])
+        .body(&asset[..])
         .build())
 }
 
@@ -61,17 +95,18 @@ async fn serve_metrics(req: Request<State>) -> tide::Result {
     Ok(response)
 }
 
-pub async fn start(
-    router: Arc<RatmanContext>,
-    mut registry: Registry,
-    bind_addr: String,
-) -> tide::Result<()> {
+/// Create the web server with all API routes and the dashboard
+fn server(router: Arc<RatmanContext>, mut registry: Registry) -> tide::Server<State> {
     // Metrics and logging for HTTP requests.
     let instrument = middleware::Instrument::default();
     instrument.register_metrics(&mut registry);
 
     // Create a new application with state
-    let mut app = tide::with_state(Arc::new(StateData { router, registry }));
+    let mut app = tide::with_state(Arc::new(StateData {
+        router,
+        registry,
+        runtime: Handle::current(),
+    }));
     app.with(instrument);
 
     // Convert errors into a form Ember.js can understand.
@@ -90,15 +125,28 @@ pub async fn start(
     // Attach some routes to it.
     app.at("/api/v1/openapi.json").get(v1::get_openapi);
     app.at("/api/v1/addrs").get(v1::get_addrs);
+    app.at("/api/v1/peers").get(v1::get_peers);
+    app.at("/api/v1/links").get(v1::get_links);
+    app.at("/api/v1/routes").get(v1::get_routes);
+    app.at("/api/v1/subscriptions").get(v1::get_subscriptions);
+    app.at("/api/v1/journal").get(v1::get_journal);
+    app.at("/api/v1/namespaces").get(v1::get_namespaces);
 
     app.at("/_/metrics").get(serve_metrics);
 
     // Let the dashboard handle any routes we don't recognise.
     app.at("/").get(serve_dashboard);
     app.at("/*").get(serve_dashboard);
+    app
+}
 
+pub async fn start(
+    router: Arc<RatmanContext>,
+    registry: Registry,
+    bind_addr: String,
+) -> tide::Result<()> {
     // Then asynchronously run the web server
-    let fut = app.listen(bind_addr);
+    let fut = server(router, registry).listen(bind_addr);
     async_std::task::spawn(async move {
         match fut.await {
             Ok(_) => {}
@@ -111,3 +159,50 @@ pub async fn start(
 
     Ok(())
 }
+
+#[cfg(test)]
+use libratman::tokio;
+
+#[libratman::tokio::test]
+async fn api_handlers() {
+    use crate::{config::ConfigTree, routes::ScorerRegistry};
+    use libratman::tokio::sync::broadcast::channel;
+    use tide::http::{Method, Request as HttpRequest, Response as HttpResponse, Url};
+
+    let state = tempdir::TempDir::new("web-api").unwrap();
+    let (block_notify_tx, _) = channel(8);
+    let router = RatmanContext::new(
+        ConfigTree::default_in_memory().patch("ratmand/ephemeral", true),
+        state.path().to_path_buf(),
+        block_notify_tx,
+        ScorerRegistry::default(),
+    )
+    .await
+    .unwrap();
+    let app = server(router, Registry::default());
+
+    let get = |path: &str| {
+        let url = Url::parse("http://localhost").unwrap().join(path).unwrap();
+        app.respond::<_, HttpResponse>(HttpRequest::new(Method::Get, url))
+    };
+
+    let mut res = get("/api/v1/journal").await.unwrap();
+    assert_eq!(res.status(), 200);
+    let body: serde_json::Value = res.body_json().await.unwrap();
+    assert_eq!(body["journal"]["blocks"], 0);
+
+    for path in ["/api/v1/addrs", "/api/v1/peers", "/api/v1/links"] {
+        let mut res = get(path).await.unwrap();
+        assert_eq!(res.status(), 200, "{}", path);
+        let body: serde_json::Value = res.body_json().await.unwrap();
+        assert!(
+            body.as_object().unwrap().values().all(|v| v.is_array()),
+            "{}",
+            path
+        );
+    }
+
+    // Unknown paths are left to the dashboard
+    let res = get("/some/dashboard/route").await.unwrap();
+    assert_eq!(res.status(), 200);
+}
@@ -1,8 +1,16 @@
-use openapi_type::OpenapiType;
+use crate::{crypto, storage::route::RouteState};
+use libratman::{
+    api::types::{JournalStatus, LinkEntry, PeerEntry},
+    types::Recipient,
+};
+use openapi_type::{openapiv3::OpenAPI, OpenapiType};
 use serde::Serialize;
+use std::sync::Arc;
 use tide::http::mime;
 use tide::{prelude::*, Request, Response};
 
+mod openapi;
+
 #[derive(Debug, Serialize, OpenapiType)]
 /// A network address.
 struct Addr {
@@ -20,75 +28,432 @@ struct GetAddrsResponse {
     pub addrs: Vec<Addr>,
 }
 
+#[derive(Debug, Serialize, OpenapiType)]
+/// A remote address that the router has a route to.
+struct Peer {
+    /// The address of the peer.
+    pub id: String,
+
+    /// When the peer was first seen, in RFC 3339 format.
+    pub first_connection: String,
+
+    /// When the peer was last seen, in RFC 3339 format.
+    pub last_connection: String,
+
+    /// Is the peer currently reachable via any link?
+    pub active: bool,
+}
+
+impl From<PeerEntry> for Peer {
+    fn from(entry: PeerEntry) -> Self {
+        Self {
+            id: entry.addr.to_string(),
+            first_connection: entry.first_connection.to_rfc3339(),
+            last_connection: entry.last_connection.to_rfc3339(),
+            active: entry.active,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, OpenapiType)]
+struct GetPeersResponse {
+    /// An array of all known peers.
+    pub peers: Vec<Peer>,
+}
+
+#[derive(Debug, Serialize, OpenapiType)]
+/// A netmod instance which is attached to the router.
+struct Link {
+    /// The link ID, which is also used in routes.
+    pub id: u32,
+
+    /// The name of the netmod driver, for example `inet`.
+    pub name: String,
+
+    /// The instance identifier reported by the driver.
+    pub identifier: String,
+
+    /// Largest frame this link can carry, 0 if there's no limit.
+    pub mtu: u32,
+
+    /// One of `unknown`, `up`, `degraded`, or `down`.
+    pub state: String,
+
+    /// Number of currently connected peers.
+    pub peers: u32,
+
+    /// Bytes sent since the link was started.
+    pub tx_bytes: u64,
+
+    /// Bytes received since the link was started.
+    pub rx_bytes: u64,
+
+    /// Incoming peers that were rejected since the link was started.
+    pub rejected: u64,
+
+    /// The most recent error this link encountered.
+    pub last_error: Option<String>,
+}
+
+impl From<LinkEntry> for Link {
+    fn from(entry: LinkEntry) -> Self {
+        Self {
+            id: entry.id,
+            name: entry.name,
+            identifier: entry.identifier,
+            mtu: entry.mtu,
+            state: entry.status.state.to_string(),
+            peers: entry.status.peers,
+            tx_bytes: entry.status.tx_bytes,
+            rx_bytes: entry.status.rx_bytes,
+            rejected: entry.status.rejected,
+            last_error: entry.status.last_error,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, OpenapiType)]
+struct GetLinksResponse {
+    /// An array of all attached links.
+    pub links: Vec<Link>,
+}
+
+#[derive(Debug, Serialize, OpenapiType)]
+/// A way to reach a peer via a neighbour on a particular link.
+struct RouteLink {
+    /// The ID of the link.
+    pub link: u64,
+
+    /// The neighbour on the link that announced the peer.
+    pub neighbour: String,
+
+    /// One of `active`, `idle`, or `lost`.
+    pub state: String,
+
+    /// Round-trip time via this route, in milliseconds.
+    pub ping_ms: u64,
+
+    /// Largest frame that can be sent along the route, 0 if there's no limit.
+    pub available_mtu: u32,
+
+    /// When the route was first seen, in RFC 3339 format.
+    pub first_seen: String,
+
+    /// When the route was last seen, in RFC 3339 format.
+    pub last_seen: String,
+}
+
+#[derive(Debug, Serialize, OpenapiType)]
+/// All the known ways to reach a single peer.
+struct Route {
+    /// The address of the peer.
+    pub id: String,
+
+    pub links: Vec<RouteLink>,
+}
+
+#[derive(Debug, Serialize, OpenapiType)]
+struct GetRoutesResponse {
+    /// An array of routes for all known peers.
+    pub routes: Vec<Route>,
+}
+
+#[derive(Debug, Serialize, OpenapiType)]
+/// A stream subscription on the router.
+struct Subscription {
+    /// The subscription ID.
+    pub id: String,
+
+    /// The address or namespace that is subscribed to.
+    pub recipient: String,
+
+    /// Is the recipient a namespace, as opposed to an address?
+    pub is_namespace: bool,
+
+    /// Local addresses listening to this subscription.
+    pub listeners: Vec<String>,
+
+    /// Streams that arrived while no listener was connected.
+    pub missed_items: u64,
+}
+
+#[derive(Debug, Serialize, OpenapiType)]
+struct GetSubscriptionsResponse {
+    /// An array of all subscriptions.
+    pub subscriptions: Vec<Subscription>,
+}
+
+#[derive(Debug, Serialize, OpenapiType)]
+/// Storage use of the router journal.
+struct Journal {
+    /// Frames which haven't been collected into blocks yet.
+    pub frames: u64,
+
+    pub blocks: u64,
+
+    pub manifests: u64,
+
+    /// Storage used, in bytes, as of the last quota check.
+    pub usage: u64,
+
+    /// Storage budget, in bytes.
+    pub quota: u64,
+}
+
+impl From<JournalStatus> for Journal {
+    fn from(status: JournalStatus) -> Self {
+        Self {
+            frames: status.frames,
+            blocks: status.blocks,
+            manifests: status.manifests,
+            usage: status.usage,
+            quota: status.quota,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, OpenapiType)]
+struct GetJournalResponse {
+    pub journal: Journal,
+}
+
+#[derive(Debug, Serialize, OpenapiType)]
+/// A namespace that this router holds the key for.
+struct Namespace {
+    /// The namespace address.
+    pub id: String,
+
+    /// The name the namespace was created with.
+    pub name: Option<String>,
+
+    /// Local addresses which are currently listening on the namespace.
+    pub listeners: Vec<String>,
+}
+
+#[derive(Debug, Serialize, OpenapiType)]
+struct GetNamespacesResponse {
+    /// An array of all namespaces.
+    pub namespaces: Vec<Namespace>,
+}
+
+fn json_response(body: impl Serialize) -> tide::Result {
+    Ok(Response::builder(200)
+        .content_type(mime::JSON)
+        .body(json!(body))
+        .build())
+}
+
 pub async fn get_addrs(req: Request<super::State>) -> tide::Result {
-    let addrs = req
+    let router = Arc::clone(&req.state().router);
+    let (local, remote) = req
         .state()
-        .router
-        .core
-        .all_known_addresses()
-        .await
+        .query(async move {
+            Ok((
+                crypto::list_addr_keys(&router.meta_db),
+                router.routes.list_remote().await?,
+            ))
+        })
+        .await?;
+
+    let local = local.into_iter().map(|addr| Addr {
+        id: addr.to_string(),
+        is_local: true,
+    });
+    let remote = remote.into_iter().map(|entry| Addr {
+        id: entry.addr.to_string(),
+        is_local: false,
+    });
+    json_response(GetAddrsResponse {
+        addrs: local.chain(remote).collect(),
+    })
+}
+
+pub async fn get_peers(req: Request<super::State>) -> tide::Result {
+    let router = Arc::clone(&req.state().router);
+    let peers = req
+        .state()
+        .query(async move { router.routes.list_remote().await })
+        .await?;
+    json_response(GetPeersResponse {
+        peers: peers.into_iter().map(Peer::from).collect(),
+    })
+}
+
+pub async fn get_links(req: Request<super::State>) -> tide::Result {
+    let router = Arc::clone(&req.state().router);
+    let links = req
+        .state()
+        .query(async move { Ok(router.link_list().await) })
+        .await?;
+    json_response(GetLinksResponse {
+        links: links.into_iter().map(Link::from).collect(),
+    })
+}
+
+pub async fn get_routes(req: Request<super::State>) -> tide::Result {
+    let router = Arc::clone(&req.state().router);
+    let routes = req
+        .state()
+        .query(async move { router.routes.list_remote_routes().await })
+        .await?
         .into_iter()
-        .map(|(addr, is_local)| Addr {
-            id: format!("{:}", addr),
-            is_local,
+        .map(|data| Route {
+            id: data.peer.to_string(),
+            links: data
+                .link_data
+                .iter()
+                .map(|(pair, entry)| RouteLink {
+                    link: pair.0 as u64,
+                    neighbour: pair.1.to_string(),
+                    state: match entry.state {
+                        RouteState::Active => "active",
+                        RouteState::Idle => "idle",
+                        RouteState::Lost => "lost",
+                    }
+                    .into(),
+                    ping_ms: entry.ping.as_millis() as u64,
+                    available_mtu: entry.data.available_mtu,
+                    first_seen: entry.first_seen.to_rfc3339(),
+                    last_seen: entry.last_seen.to_rfc3339(),
+                })
+                .collect(),
         })
-        .collect::<Vec<Addr>>();
-    Ok(Response::builder(200)
-        .content_type(mime::JSON)
-        .body(json!(GetAddrsResponse { addrs }))
-        .build())
+        .collect();
+    json_response(GetRoutesResponse { routes })
+}
+
+pub async fn get_subscriptions(req: Request<super::State>) -> tide::Result {
+    let router = Arc::clone(&req.state().router);
+    let subscriptions = req
+        .state()
+        .query(async move { Ok(router.meta_db.subscriptions.iter()) })
+        .await?
+        .into_iter()
+        .map(|(id, data)| Subscription {
+            id,
+            recipient: data.recipient.inner_address().to_string(),
+            is_namespace: matches!(data.recipient, Recipient::Namespace(_)),
+            listeners: data.listeners.iter().map(ToString::to_string).collect(),
+            missed_items: data.missed_items.values().map(Vec::len).sum::<usize>() as u64,
+        })
+        .collect();
+    json_response(GetSubscriptionsResponse { subscriptions })
+}
+
+pub async fn get_journal(req: Request<super::State>) -> tide::Result {
+    let router = Arc::clone(&req.state().router);
+    let status = req
+        .state()
+        .query(async move { router.journal.status().await })
+        .await?;
+    json_response(GetJournalResponse {
+        journal: status.into(),
+    })
+}
+
+pub async fn get_namespaces(req: Request<super::State>) -> tide::Result {
+    let router = Arc::clone(&req.state().router);
+    let namespaces = req
+        .state()
+        .query(async move {
+            let mut namespaces = vec![];
+            for (addr, name) in crypto::list_namespaces(&router.meta_db) {
+                namespaces.push(Namespace {
+                    id: addr.to_string(),
+                    name: name.map(|name| name.to_string_lossy().into_owned()),
+                    listeners: router
+                        .protocol
+                        .get_namespace_listeners(addr)
+                        .await
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect(),
+                });
+            }
+            Ok(namespaces)
+        })
+        .await?;
+    json_response(GetNamespacesResponse { namespaces })
+}
+
+/// Describe all endpoints of the v1 API
+fn openapi() -> OpenAPI {
+    let mut spec = openapi::Spec::default();
+    spec.tag("addr", "Addresses")
+        .tag("network", "Network state")
+        .tag("router", "Router state")
+        .get::<GetAddrsResponse>("/addrs", "addr", "List known addresses", "getAddrs")
+        .get::<GetNamespacesResponse>(
+            "/namespaces",
+            "addr",
+            "List namespaces and their listeners",
+            "getNamespaces",
+        )
+        .get::<GetPeersResponse>("/peers", "network", "List known peers", "getPeers")
+        .get::<GetLinksResponse>("/links", "network", "List attached links", "getLinks")
+        .get::<GetRoutesResponse>(
+            "/routes",
+            "network",
+            "List the routes to all known peers",
+            "getRoutes",
+        )
+        .get::<GetSubscriptionsResponse>(
+            "/subscriptions",
+            "router",
+            "List stream subscriptions",
+            "getSubscriptions",
+        )
+        .get::<GetJournalResponse>(
+            "/journal",
+            "router",
+            "Show the journal storage use",
+            "getJournal",
+        );
+    spec.build()
 }
 
 pub async fn get_openapi(_req: Request<super::State>) -> tide::Result {
-    // I would like there to be a better way to generate this JSON blob.
-    //
-    // Unfortunately, the structs in the `openapiv3` crate are quite extensive, and don't
-    // have builders, so constructing them by hand makes this function many times longer,
-    // with nested, verbose `let mut x = X::default(); x.y = Some(y); x.z = "Z".into();`
-    // boilerplate, and much harder to understand or work with for everyone involved.
-    //
-    // Until we can figure out something less painful, let's just construct JSON by hand.
-    Ok(Response::builder(200)
-        .content_type(mime::JSON)
-        .body(json!({
-            "paths": {
-
-                "/addrs": {
-                    "get": {
-                        "tags": ["addr"],
-                        "summary": "List known addresses",
-                        "operationId": "getAddrs",
-                        "responses": {
-                            "200": {
-                                "description": "Success.",
-                                "content": {
-                                    "application/json": {
-                                        "schema": GetAddrsResponse::schema().schema,
-                                    },
-                                },
-                            },
-                        },
-                    },
-                },
-
-            },
-            "components": {
-                "schemas": {
-                    "Addr": Addr::schema().schema,
-                },
-            },
-
-            "tags": [{
-                "name": "addr",
-                "description": "Addresses",
-            }],
-
-            "info": {
-                "title": "ratmand",
-                "version": "1.0",
-            },
-            "servers": [{ "url": "/api/v1" }],
-            "openapi": "3.0.2",
-        }))
-        .build())
+    json_response(openapi())
+}
+
+#[test]
+fn openapi_references_resolve() {
+    let spec = serde_json::to_value(openapi()).unwrap();
+
+    for path in [
+        "/addrs",
+        "/namespaces",
+        "/peers",
+        "/links",
+        "/routes",
+        "/subscriptions",
+        "/journal",
+    ] {
+        assert!(
+            spec["paths"][path]["get"].is_object(),
+            "{} is missing",
+            path
+        );
+    }
+
+    // Every referenced schema must be part of the document
+    fn check_refs(spec: &serde_json::Value, value: &serde_json::Value) {
+        match value {
+            serde_json::Value::Object(map) => {
+                if let Some(reference) = map.get("$ref").and_then(|r| r.as_str()) {
+                    let name = reference.strip_prefix("#/components/schemas/").unwrap();
+                    assert!(
+                        spec["components"]["schemas"][name].is_object(),
+                        "{} is missing",
+                        reference
+                    );
+                }
+                map.values().for_each(|v| check_refs(spec, v));
+            }
+            serde_json::Value::Array(list) => list.iter().for_each(|v| check_refs(spec, v)),
+            _ => {}
+        }
+    }
+    check_refs(&spec, &spec);
+    assert!(spec["components"]["schemas"]["RouteLink"].is_object());
 }
@@ -0,0 +1,124 @@
+// SPDX-FileCopyrightText: 2024 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+//! Generate the OpenAPI document from the API response types
+
+use openapi_type::{
+    indexmap::IndexMap,
+    openapiv3::{
+        Components, Info, MediaType, OpenAPI, Operation, PathItem, Paths, ReferenceOr, Response,
+        Responses, Schema, Server, StatusCode, Tag,
+    },
+    OpenapiSchema, OpenapiType,
+};
+use std::iter;
+
+/// Collects the operations of the API and the schemas they use
+#[derive(Default)]
+pub struct Spec {
+    paths: Paths,
+    schemas: IndexMap<String, ReferenceOr<Schema>>,
+    tags: Vec<Tag>,
+}
+
+impl Spec {
+    /// Describe a group of operations
+    pub fn tag(&mut self, name: &str, description: &str) -> &mut Self {
+        self.tags.push(Tag {
+            name: name.into(),
+            description: Some(description.into()),
+            ..Default::default()
+        });
+        self
+    }
+
+    /// Describe a GET operation which responds with a `T`
+    pub fn get<T: OpenapiType>(
+        &mut self,
+        path: &str,
+        tag: &str,
+        summary: &str,
+        operation_id: &str,
+    ) -> &mut Self {
+        let response = Response {
+            description: "Success.".into(),
+            content: iter::once((
+                "application/json".into(),
+                MediaType {
+                    schema: Some(self.add_schema(T::schema())),
+                    ..Default::default()
+                },
+            ))
+            .collect(),
+            ..Default::default()
+        };
+
+        let operation = Operation {
+            tags: vec![tag.into()],
+            summary: Some(summary.into()),
+            operation_id: Some(operation_id.into()),
+            responses: Responses {
+                responses: iter::once((StatusCode::Code(200), ReferenceOr::Item(response)))
+                    .collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        self.paths.paths.insert(
+            path.into(),
+            ReferenceOr::Item(PathItem {
+                get: Some(operation),
+                ..Default::default()
+            }),
+        );
+        self
+    }
+
+    /// Add a schema and its dependencies to the components
+    ///
+    /// Named schemas are referenced, anything else is used in-line.
+    fn add_schema(&mut self, schema: OpenapiSchema) -> ReferenceOr<Schema> {
+        self.add_dependencies(schema.dependencies);
+        let schema = schema.schema;
+
+        match schema.schema_data.title.clone() {
+            Some(name) => {
+                let reference = format!("#/components/schemas/{name}");
+                self.schemas.insert(name, ReferenceOr::Item(schema));
+                ReferenceOr::Reference { reference }
+            }
+            None => ReferenceOr::Item(schema),
+        }
+    }
+
+    fn add_dependencies(&mut self, dependencies: IndexMap<String, OpenapiSchema>) {
+        for (name, dep) in dependencies {
+            self.add_dependencies(dep.dependencies);
+            self.schemas.insert(name, ReferenceOr::Item(dep.schema));
+        }
+    }
+
+    pub fn build(self) -> OpenAPI {
+        OpenAPI {
+            openapi: "3.0.2".into(),
+            info: Info {
+                title: "ratmand".into(),
+                version: env!("CARGO_PKG_VERSION").into(),
+                ..Default::default()
+            },
+            servers: vec![Server {
+                url: "/api/v1".into(),
+                ..Default::default()
+            }],
+            paths: self.paths,
+            components: Some(Components {
+                schemas: self.schemas,
+                ..Default::default()
+            }),
+            tags: self.tags,
+            ..Default::default()
+        }
+    }
+}
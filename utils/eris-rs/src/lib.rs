@@ -255,6 +255,13 @@ impl ReadCapability {
     pub fn urn(&self) -> String {
         format!("urn:erisx2:{}", &display_base32(&self.binary()))
     }
+
+    /// Parse a read capability from its URN representation
+    pub fn from_urn(urn: &str) -> Option<ReadCapability> {
+        let encoded = urn.strip_prefix("urn:erisx2:")?;
+        let buf = base32::decode(base32::Alphabet::RFC4648 { padding: false }, encoded)?;
+        Self::from_binary(&buf)
+    }
 }
 
 impl<const BS: usize> Block<BS> {
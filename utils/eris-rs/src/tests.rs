@@ -155,6 +155,12 @@ async fn run_test_for_vector(path: &Path, tx: tokio::sync::mpsc::Sender<()>) {
     // set of blocks as in the test harness file!
     assert!(verify_input_content(&harness).await);
 
+    assert_eq!(
+        ReadCapability::from_urn(&harness._test.urn),
+        Some(harness.read_cap)
+    );
+    assert_eq!(harness.read_cap.urn(), harness._test.urn);
+
     // If we reach this point this vector was successfully parsed,
     // decoded, and re-encoded.
     tx.send(()).await.unwrap();
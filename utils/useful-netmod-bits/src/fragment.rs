@@ -0,0 +1,316 @@
+// SPDX-FileCopyrightText: 2024 Katharina Fey <kookie@spacekookie.de>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-AppStore
+
+//! Link-layer fragmentation for netmods with tiny MTUs
+//!
+//! Some links (for example LoRa radios) can only carry a few hundred bytes at
+//! a time, which isn't enough for a single carrier frame.  The `Fragmenter`
+//! splits an encoded frame into numbered fragments that fit into the link MTU,
+//! and the `Reassembler` on the other side puts them back together.
+//!
+//! Each message gets a 32-bit ID, which is unique per sender and very unlikely
+//! to collide between different senders on a shared medium.  When a message
+//! stays incomplete for too long the reassembler produces an `RtxRequest`
+//! listing the missing fragments.  The sender keeps its most recent messages
+//! around so it can answer these requests.
+//!
+//! Neither type does any I/O, so they can be used by any `EndpointExt`
+//! implementation, regardless of async runtime.
+
+use libratman::{
+    types::{Ident32, InMemoryEnvelope},
+    EncodingError, NetmodError, RatmanError, Result,
+};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    convert::TryInto,
+    time::{Duration, Instant},
+};
+
+/// Size of the header in front of every fragment
+pub const FRAGMENT_HEADER_SIZE: usize = 6;
+
+/// A single piece of an encoded carrier frame
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fragment {
+    /// Message this fragment belongs to
+    pub msg_id: u32,
+    /// Position of this fragment in the message
+    pub index: u8,
+    /// Total number of fragments in the message
+    pub count: u8,
+    pub data: Vec<u8>,
+}
+
+impl Fragment {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(FRAGMENT_HEADER_SIZE + self.data.len());
+        buf.extend_from_slice(&self.msg_id.to_be_bytes());
+        buf.push(self.index);
+        buf.push(self.count);
+        buf.extend_from_slice(&self.data);
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        if buf.len() < FRAGMENT_HEADER_SIZE {
+            return Err(EncodingError::Parsing("fragment header too short".into()).into());
+        }
+
+        let (index, count) = (buf[4], buf[5]);
+        if index >= count {
+            return Err(EncodingError::Parsing(format!("invalid fragment {index}/{count}")).into());
+        }
+
+        Ok(Self {
+            msg_id: u32::from_be_bytes(buf[..4].try_into().unwrap()),
+            index,
+            count,
+            data: buf[FRAGMENT_HEADER_SIZE..].to_vec(),
+        })
+    }
+}
+
+/// Ask the sender of a message to send some of its fragments again
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RtxRequest {
+    pub msg_id: u32,
+    /// Indices of the missing fragments
+    pub missing: Vec<u8>,
+}
+
+impl RtxRequest {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(5 + self.missing.len());
+        buf.extend_from_slice(&self.msg_id.to_be_bytes());
+        buf.push(self.missing.len() as u8);
+        buf.extend_from_slice(&self.missing);
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        let len = *buf
+            .get(4)
+            .ok_or_else(|| EncodingError::Parsing("retransmit request too short".into()))?
+            as usize;
+        let missing = buf
+            .get(5..5 + len)
+            .ok_or_else(|| EncodingError::Parsing("retransmit request too short".into()))?;
+
+        Ok(Self {
+            msg_id: u32::from_be_bytes(buf[..4].try_into().unwrap()),
+            missing: missing.to_vec(),
+        })
+    }
+}
+
+/// Split frames into fragments and remember them for retransmission
+pub struct Fragmenter {
+    /// Number of frame bytes that fit into a single fragment
+    chunk_size: usize,
+    next_id: u32,
+    /// How many sent messages are kept around for retransmission
+    history: usize,
+    sent: VecDeque<(u32, Vec<Fragment>)>,
+}
+
+impl Fragmenter {
+    /// Create a fragmenter for a link with the given MTU
+    ///
+    /// The last `history` messages are kept for retransmission requests.
+    pub fn new(mtu: usize, history: usize) -> Self {
+        assert!(mtu > FRAGMENT_HEADER_SIZE, "MTU is too small to fragment");
+
+        // Start at a random point so different senders are unlikely to
+        // produce the same message IDs
+        let seed = Ident32::random();
+        Self {
+            chunk_size: mtu - FRAGMENT_HEADER_SIZE,
+            next_id: u32::from_be_bytes(seed.as_bytes()[..4].try_into().unwrap()),
+            history,
+            sent: VecDeque::new(),
+        }
+    }
+
+    /// Split an envelope into fragments that each fit into the link MTU
+    pub fn split(&mut self, envelope: &InMemoryEnvelope) -> Result<Vec<Fragment>> {
+        let chunks: Vec<_> = envelope.buffer.chunks(self.chunk_size).collect();
+        let count: u8 = chunks
+            .len()
+            .try_into()
+            .map_err(|_| RatmanError::Netmod(NetmodError::FrameTooLarge))?;
+
+        let msg_id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+
+        let fragments: Vec<_> = chunks
+            .into_iter()
+            .enumerate()
+            .map(|(index, data)| Fragment {
+                msg_id,
+                index: index as u8,
+                count,
+                data: data.to_vec(),
+            })
+            .collect();
+
+        if self.sent.len() >= self.history {
+            self.sent.pop_front();
+        }
+        self.sent.push_back((msg_id, fragments.clone()));
+
+        Ok(fragments)
+    }
+
+    /// Look up fragments for a retransmission request
+    ///
+    /// Returns nothing if the message wasn't sent by us, or is too old.
+    pub fn retransmit(&self, req: &RtxRequest) -> Vec<Fragment> {
+        self.sent
+            .iter()
+            .find(|(msg_id, _)| *msg_id == req.msg_id)
+            .map(|(_, fragments)| {
+                fragments
+                    .iter()
+                    .filter(|f| req.missing.contains(&f.index))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+struct Partial {
+    count: u8,
+    fragments: BTreeMap<u8, Vec<u8>>,
+    last_seen: Instant,
+    requests: u8,
+}
+
+/// How many completed message IDs are remembered to ignore late fragments
+const COMPLETED_HISTORY: usize = 64;
+
+/// Put fragments back together and detect lost ones
+pub struct Reassembler {
+    partial: BTreeMap<u32, Partial>,
+    completed: VecDeque<u32>,
+    /// How long an incomplete message may be idle before requesting fragments
+    rtx_after: Duration,
+    /// How many retransmission requests are sent before giving up
+    max_requests: u8,
+}
+
+impl Reassembler {
+    pub fn new(rtx_after: Duration, max_requests: u8) -> Self {
+        Self {
+            partial: BTreeMap::new(),
+            completed: VecDeque::new(),
+            rtx_after,
+            max_requests,
+        }
+    }
+
+    /// Add a fragment, returning the envelope once it is complete
+    pub fn insert(&mut self, fragment: Fragment) -> Option<Result<InMemoryEnvelope>> {
+        // Retransmitted fragments may arrive after the message was completed
+        if self.completed.contains(&fragment.msg_id) {
+            return None;
+        }
+
+        let partial = self.partial.entry(fragment.msg_id).or_insert(Partial {
+            count: fragment.count,
+            fragments: BTreeMap::new(),
+            last_seen: Instant::now(),
+            requests: 0,
+        });
+
+        // Ignore fragments that don't agree on the message length
+        if partial.count != fragment.count {
+            return None;
+        }
+
+        partial.last_seen = Instant::now();
+        partial.fragments.insert(fragment.index, fragment.data);
+        if partial.fragments.len() < partial.count as usize {
+            return None;
+        }
+
+        let partial = self.partial.remove(&fragment.msg_id)?;
+        if self.completed.len() >= COMPLETED_HISTORY {
+            self.completed.pop_front();
+        }
+        self.completed.push_back(fragment.msg_id);
+
+        let buffer = partial.fragments.into_values().flatten().collect();
+        Some(InMemoryEnvelope::parse_from_buffer(buffer))
+    }
+
+    /// Find incomplete messages that haven't made progress in a while
+    ///
+    /// Each returned request is counted towards the retransmission limit of
+    /// its message, and messages that reached the limit are dropped.
+    pub fn lost(&mut self, now: Instant) -> Vec<RtxRequest> {
+        let (rtx_after, max_requests) = (self.rtx_after, self.max_requests);
+        self.partial
+            .retain(|_, p| p.requests < max_requests || now - p.last_seen < rtx_after);
+
+        self.partial
+            .iter_mut()
+            .filter(|(_, p)| now - p.last_seen >= rtx_after)
+            .map(|(msg_id, p)| {
+                p.requests += 1;
+                p.last_seen = now;
+                RtxRequest {
+                    msg_id: *msg_id,
+                    missing: (0..p.count)
+                        .filter(|idx| !p.fragments.contains_key(idx))
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn fragment_roundtrip_with_loss() {
+    use libratman::{
+        frame::carrier::CarrierFrameHeader,
+        types::{Address, Recipient, SequenceIdV1},
+    };
+
+    let header = CarrierFrameHeader::new_blockdata_frame(
+        Address::random(),
+        Recipient::Address(Address::random()),
+        SequenceIdV1 {
+            hash: Ident32::random(),
+            num: 0,
+            max: 0,
+        },
+        1024,
+    );
+    let envelope = InMemoryEnvelope::from_header_and_payload(header, vec![7; 1024]).unwrap();
+
+    let mut fragmenter = Fragmenter::new(252, 4);
+    let mut reassembler = Reassembler::new(Duration::from_millis(0), 2);
+
+    let fragments = fragmenter.split(&envelope).unwrap();
+    assert!(fragments.iter().all(|f| f.encode().len() <= 252));
+
+    // Lose the second fragment on the way
+    for f in fragments.iter().filter(|f| f.index != 1) {
+        let f = Fragment::decode(&f.encode()).unwrap();
+        assert!(reassembler.insert(f).is_none());
+    }
+
+    let requests = reassembler.lost(Instant::now());
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].missing, vec![1]);
+
+    let req = RtxRequest::decode(&requests[0].encode()).unwrap();
+    let resent = fragmenter.retransmit(&req);
+    assert_eq!(resent.len(), 1);
+
+    let complete = reassembler.insert(resent[0].clone()).unwrap().unwrap();
+    assert_eq!(complete.buffer, envelope.buffer);
+}
@@ -3,5 +3,7 @@
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
 pub mod addrs;
+pub mod fragment;
 pub mod framing;
 pub mod metrics;
+pub mod status;
@@ -0,0 +1,96 @@
+//! Status reporting for netmod instances
+//!
+//! Netmods count their traffic and remember the last error they
+//! encountered via a `StatusTracker`, which then creates the status
+//! digest that is reported to the router.
+
+use libratman::types::{CurrentStatus, LinkState};
+use std::{
+    fmt::Display,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// How long a netmod is considered degraded after an error
+pub const DEGRADED_AFTER_ERROR: Duration = Duration::from_secs(60);
+
+#[derive(Default)]
+pub struct StatusTracker {
+    tx_bytes: AtomicU64,
+    rx_bytes: AtomicU64,
+    rejected: AtomicU64,
+    down: AtomicBool,
+    last_error: Mutex<Option<(Instant, String)>>,
+}
+
+impl StatusTracker {
+    /// Count bytes that were sent
+    pub fn sent(&self, bytes: usize) {
+        self.tx_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Count bytes that were received
+    pub fn received(&self, bytes: usize) {
+        self.rx_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Count an incoming peer that was rejected
+    pub fn rejected(&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Remember an error, which marks the netmod as degraded for a while
+    pub fn error(&self, e: impl Display) {
+        *self.last_error.lock().unwrap() = Some((Instant::now(), e.to_string()));
+    }
+
+    /// Mark the netmod as no longer being able to send or receive
+    pub fn set_down(&self) {
+        self.down.store(true, Ordering::Release);
+    }
+
+    /// Create a status digest for the router
+    ///
+    /// Netmods that need more specific rules for their state (for
+    /// example missing peers) can adjust the state afterwards.
+    pub fn snapshot(&self, peers: u32) -> CurrentStatus {
+        let last_error = self.last_error.lock().unwrap().clone();
+        let state = match last_error {
+            _ if self.down.load(Ordering::Acquire) => LinkState::Down,
+            Some((at, _)) if at.elapsed() < DEGRADED_AFTER_ERROR => LinkState::Degraded,
+            _ => LinkState::Up,
+        };
+
+        CurrentStatus {
+            state,
+            peers,
+            tx_bytes: self.tx_bytes.load(Ordering::Relaxed),
+            rx_bytes: self.rx_bytes.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            last_error: last_error.map(|(_, e)| e),
+        }
+    }
+}
+
+#[test]
+fn error_degrades_status() {
+    let tracker = StatusTracker::default();
+    tracker.sent(128);
+    tracker.received(64);
+    tracker.rejected();
+    assert_eq!(tracker.snapshot(1).state, LinkState::Up);
+
+    tracker.error("connection refused");
+    let status = tracker.snapshot(1);
+    assert_eq!(status.state, LinkState::Degraded);
+    assert_eq!(status.tx_bytes, 128);
+    assert_eq!(status.rx_bytes, 64);
+    assert_eq!(status.rejected, 1);
+    assert_eq!(status.last_error.as_deref(), Some("connection refused"));
+
+    tracker.set_down();
+    assert_eq!(tracker.snapshot(0).state, LinkState::Down);
+}